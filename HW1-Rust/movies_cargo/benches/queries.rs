@@ -0,0 +1,63 @@
+/*
+ * Criterion benchmarks for CSV parsing and the main queries.
+ *
+ * Synthetic files of 10k, 100k, and 1M rows are generated once per run from a
+ * fixed seed. Run with `cargo bench`.
+ */
+
+ use std::hint::black_box;
+ use std::io;
+ use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+ use movies_cargo::{
+     highest_rated_per_year, read_csv, show_movies_by_language, show_movies_by_year, ParseOptions, RatingSource,
+     YearOrder,
+ };
+ 
+ #[path = "../tests/support/mod.rs"]
+ mod support;
+ 
+ /// The number of rows in each generated file.
+ const SIZES: [usize; 3] = [10_000, 100_000, 1_000_000];
+ /// The seed every generated file is built from.
+ const SEED: u64 = 42;
+ 
+ fn bench_read_csv(c: &mut Criterion) {
+     let dir = tempfile::tempdir().unwrap();
+     let options = ParseOptions::default();
+     let mut group = c.benchmark_group("read_csv");
+     group.sample_size(10);
+     for size in SIZES {
+         let path = support::write_synthetic_csv(dir.path(), size, SEED);
+         let path = path.to_str().unwrap();
+         group.throughput(Throughput::Elements(size as u64));
+         group.bench_with_input(BenchmarkId::from_parameter(size), path, |b, path| {
+             b.iter(|| read_csv(path, &options).unwrap())
+         });
+     }
+     group.finish();
+ }
+ 
+ fn bench_queries(c: &mut Criterion) {
+     let dir = tempfile::tempdir().unwrap();
+     let options = ParseOptions::default();
+     for size in SIZES {
+         let path = support::write_synthetic_csv(dir.path(), size, SEED);
+         let movies = read_csv(path.to_str().unwrap(), &options).unwrap();
+ 
+         let mut group = c.benchmark_group(format!("queries/{}", size));
+         group.throughput(Throughput::Elements(size as u64));
+         group.bench_function("year lookup", |b| {
+             b.iter(|| show_movies_by_year(&mut io::sink(), &movies, black_box(2000), None).unwrap())
+         });
+         group.bench_function("language lookup", |b| {
+             b.iter(|| show_movies_by_language(&mut io::sink(), &movies, black_box("French"), None).unwrap())
+         });
+         group.bench_function("highest rated per year", |b| {
+             b.iter(|| highest_rated_per_year(&movies, black_box(YearOrder::Ascending), RatingSource::Rating).len())
+         });
+         group.finish();
+     }
+ }
+ 
+ criterion_group!(benches, bench_read_csv, bench_queries);
+ criterion_main!(benches);
@@ -0,0 +1,236 @@
+/*
+ * Binary cache of the parsed movie collection.
+ *
+ * Parsing a large CSV file takes a long time, so after a successful parse the
+ * collection is written next to it as `<file>.cache` with bincode, together with
+ * the source file's size and modification time. On the next launch the cache is
+ * used instead of reparsing as long as that metadata still matches.
+ */
+
+ use std::collections::LinkedList;
+ use std::error::Error;
+ use std::fs::{self, File};
+ use std::io::{BufReader, BufWriter};
+ use std::time::SystemTime;
+ use serde::{Deserialize, Serialize};
+ use crate::{read_csv, LanguageTable, Movie, ParseOptions};
+ 
+ /// Bumped whenever the cached layout of `Movie` or the parse rules change so stale
+ /// caches are rebuilt.
+ const CACHE_VERSION: u32 = 8;
+ 
+ /// Identifies the exact source file and parse options a cache was built from.
+ #[derive(Serialize, Deserialize, PartialEq, Debug)]
+ struct CacheHeader {
+     version: u32,
+     size: u64,
+     modified: SystemTime,
+     options: ParseOptions,
+ }
+ 
+ /// How the movies were obtained by [`load_movies`].
+ #[derive(Debug, PartialEq)]
+ pub enum CacheStatus {
+     /// The cache matched the source file and was used.
+     Hit,
+     /// There was no usable cache, or the source changed; the file was parsed.
+     Miss,
+     /// The cache could not be decoded; the file was parsed and the cache rebuilt.
+     Corrupted(String),
+     /// Caching was turned off with `--no-cache`.
+     Disabled,
+ }
+ 
+ /// Returns the path of the cache file for `filename`.
+ pub fn cache_path(filename: &str) -> String {
+     format!("{}.cache", filename)
+ }
+ 
+ /// Reads the size and modification time of the source file.
+ fn source_header(filename: &str, options: &ParseOptions) -> std::io::Result<CacheHeader> {
+     let metadata = fs::metadata(filename)?;
+     Ok(CacheHeader {
+         version: CACHE_VERSION,
+         size: metadata.len(),
+         modified: metadata.modified()?,
+         options: options.clone(),
+     })
+ }
+ 
+ /// Loads the movies from `filename`, using its cache when it is up to date.
+ ///
+ /// The file is parsed with `options`; a cache built with different options
+ /// counts as stale. When `use_cache` is true and the cache is missing,
+ /// stale, or corrupted the file is parsed and the cache rewritten. Failing to
+ /// write the cache is only a warning, since the parsed movies are still usable.
+ ///
+ /// # Returns
+ ///
+ /// * `Result<(LinkedList<Movie>, CacheStatus), Box<dyn Error>>` - The movies and
+ ///   how they were obtained, or an error if the CSV file could not be parsed.
+ pub fn load_movies(
+     filename: &str,
+     options: &ParseOptions,
+     use_cache: bool,
+ ) -> Result<(LinkedList<Movie>, CacheStatus), Box<dyn Error>> {
+     if !use_cache {
+         return Ok((read_csv(filename, options)?, CacheStatus::Disabled));
+     }
+ 
+     let header = source_header(filename, options)?;
+     let status = match read_cache(filename, &header) {
+         Ok(Some(movies)) => return Ok((movies, CacheStatus::Hit)),
+         Ok(None) => CacheStatus::Miss,
+         Err(e) => {
+             eprintln!("Warning: ignoring corrupted cache {}: {}", cache_path(filename), e);
+             CacheStatus::Corrupted(e.to_string())
+         }
+     };
+ 
+     let movies = read_csv(filename, options)?;
+     if let Err(e) = write_cache(filename, &header, &movies) {
+         eprintln!("Warning: could not write cache {}: {}", cache_path(filename), e);
+     }
+     Ok((movies, status))
+ }
+ 
+ /// Reads the cache for `filename` if it exists and was built from `header`.
+ ///
+ /// # Returns
+ ///
+ /// * `Ok(None)` if there is no cache or it belongs to a different version of the file.
+ /// * `Err` if the cache exists but cannot be decoded.
+ fn read_cache(filename: &str, header: &CacheHeader) -> Result<Option<LinkedList<Movie>>, bincode::Error> {
+     let file = match File::open(cache_path(filename)) {
+         Ok(file) => file,
+         Err(_) => return Ok(None),
+     };
+     let mut reader = BufReader::new(file);
+ 
+     let cached: CacheHeader = bincode::deserialize_from(&mut reader)?;
+     if cached != *header {
+         return Ok(None);
+     }
+     let mut movies: LinkedList<Movie> = bincode::deserialize_from(&mut reader)?;
+ 
+     // Decoding allocates every language separately, so intern them again
+     let mut language_table = LanguageTable::new();
+     for movie in &mut movies {
+         for language in &mut movie.languages {
+             *language = language_table.intern(language);
+         }
+     }
+     Ok(Some(movies))
+ }
+ 
+ /// Writes `movies` to the cache for `filename`, tagged with the source `header`.
+ fn write_cache(filename: &str, header: &CacheHeader, movies: &LinkedList<Movie>) -> Result<(), bincode::Error> {
+     let mut writer = BufWriter::new(File::create(cache_path(filename))?);
+     bincode::serialize_into(&mut writer, header)?;
+     bincode::serialize_into(&mut writer, movies)?;
+     Ok(())
+ }
+ 
+ #[cfg(test)]
+ mod tests {
+     use super::*;
+     use std::sync::Arc;
+     use std::time::Duration;
+     use crate::EmptyLanguages;
+ 
+     /// Copies the sample CSV into a temporary directory and returns its path.
+     fn copy_fixture(dir: &tempfile::TempDir) -> String {
+         let path = dir.path().join("movies_sample_1.csv");
+         fs::copy("movies_sample_1.csv", &path).unwrap();
+         path.to_str().unwrap().to_string()
+     }
+ 
+     #[test]
+     fn test_cache_hit() {
+         let dir = tempfile::tempdir().unwrap();
+         let filename = copy_fixture(&dir);
+ 
+         let (parsed, status) = load_movies(&filename, &ParseOptions::default(), true).unwrap();
+         assert_eq!(status, CacheStatus::Miss);
+         let (cached, status) = load_movies(&filename, &ParseOptions::default(), true).unwrap();
+         assert_eq!(status, CacheStatus::Hit);
+ 
+         let titles = |movies: &LinkedList<Movie>| movies.iter().map(|m| m.title.clone()).collect::<Vec<_>>();
+         assert_eq!(titles(&parsed), titles(&cached));
+         // Languages loaded from the cache are interned again
+         let english: Vec<&Arc<str>> = cached
+             .iter()
+             .flat_map(|m| m.languages.iter())
+             .filter(|lang| &***lang == "English")
+             .collect();
+         assert!(english.windows(2).all(|pair| Arc::ptr_eq(pair[0], pair[1])));
+     }
+ 
+     #[test]
+     fn test_cache_miss_after_modification() {
+         let dir = tempfile::tempdir().unwrap();
+         let filename = copy_fixture(&dir);
+         load_movies(&filename, &ParseOptions::default(), true).unwrap();
+ 
+         let mut contents = fs::read_to_string(&filename).unwrap();
+         contents.push_str("Extra Movie,2001,[English],6.5\n");
+         fs::write(&filename, contents).unwrap();
+         let file = File::options().write(true).open(&filename).unwrap();
+         file.set_modified(SystemTime::now() + Duration::from_secs(5)).unwrap();
+ 
+         let (movies, status) = load_movies(&filename, &ParseOptions::default(), true).unwrap();
+         assert_eq!(status, CacheStatus::Miss);
+         assert_eq!(movies.len(), 25);
+         assert_eq!(load_movies(&filename, &ParseOptions::default(), true).unwrap().1, CacheStatus::Hit);
+     }
+ 
+     #[test]
+     fn test_corrupted_cache_falls_back_to_parsing() {
+         let dir = tempfile::tempdir().unwrap();
+         let filename = copy_fixture(&dir);
+         load_movies(&filename, &ParseOptions::default(), true).unwrap();
+ 
+         // Keep a valid header but truncate the movie data
+         let cache = fs::read(cache_path(&filename)).unwrap();
+         fs::write(cache_path(&filename), &cache[..cache.len() / 2]).unwrap();
+ 
+         let (movies, status) = load_movies(&filename, &ParseOptions::default(), true).unwrap();
+         assert!(matches!(status, CacheStatus::Corrupted(_)));
+         assert_eq!(movies.len(), 24);
+         // The rebuilt cache is valid again
+         assert_eq!(load_movies(&filename, &ParseOptions::default(), true).unwrap().1, CacheStatus::Hit);
+     }
+ 
+     #[test]
+     fn test_cache_miss_after_language_map_change() {
+         let dir = tempfile::tempdir().unwrap();
+         let filename = copy_fixture(&dir);
+         load_movies(&filename, &ParseOptions::default(), true).unwrap();
+ 
+         let mut options = ParseOptions::default();
+         options.language_map.insert("british", "English").unwrap();
+         assert_eq!(load_movies(&filename, &options, true).unwrap().1, CacheStatus::Miss);
+         assert_eq!(load_movies(&filename, &options, true).unwrap().1, CacheStatus::Hit);
+     }
+ 
+     #[test]
+     fn test_cache_miss_after_parse_option_change() {
+         let dir = tempfile::tempdir().unwrap();
+         let filename = copy_fixture(&dir);
+         load_movies(&filename, &ParseOptions::default(), true).unwrap();
+ 
+         let options = ParseOptions { empty_languages: EmptyLanguages::Skip, ..ParseOptions::default() };
+         assert_eq!(load_movies(&filename, &options, true).unwrap().1, CacheStatus::Miss);
+     }
+ 
+     #[test]
+     fn test_no_cache_bypasses_cache() {
+         let dir = tempfile::tempdir().unwrap();
+         let filename = copy_fixture(&dir);
+ 
+         let (movies, status) = load_movies(&filename, &ParseOptions::default(), false).unwrap();
+         assert_eq!(status, CacheStatus::Disabled);
+         assert_eq!(movies.len(), 24);
+         assert!(!std::path::Path::new(&cache_path(&filename)).exists());
+     }
+ }
@@ -0,0 +1,197 @@
+/*
+ * Indexed movie collection.
+ *
+ * MovieCollection owns a list of movies together with indexes from release year
+ * and from language to the movies that have them, so those lookups do not scan
+ * every movie. The indexes are updated on every insertion, whether the movies
+ * arrive through push, collect, or extend, so they always agree with the list.
+ */
+
+ use std::collections::{BTreeMap, HashMap};
+ use std::slice;
+ use std::sync::Arc;
+ use std::vec;
+ use crate::Movie;
+ 
+ /// Movies in insertion order, indexed by year and by language.
+ ///
+ /// A collection can be built from any iterator of movies and queried through the
+ /// usual iterator adapters or its indexes:
+ ///
+ /// ```
+ /// use std::sync::Arc;
+ /// use movies_cargo::collection::MovieCollection;
+ /// use movies_cargo::Movie;
+ /// # fn movie(title: &str, year: i32, languages: &[&str], rating: f32) -> Movie {
+ /// #     Movie {
+ /// #         title: title.to_string(),
+ /// #         year,
+ /// #         languages: languages.iter().map(|&language| Arc::from(language)).collect(),
+ /// #         genres: Vec::new(),
+ /// #         rating: Some(rating),
+ /// #         invalid_rating: None,
+ /// #         runtime: None,
+ /// #         critic_rating: None,
+ /// #         audience_rating: None,
+ /// #     }
+ /// # }
+ ///
+ /// let mut collection: MovieCollection = vec![
+ ///     movie("Heat", 1995, &["English", "Spanish"], 8.3),
+ ///     movie("Se7en", 1995, &["English"], 8.6),
+ ///     movie("Amélie", 2001, &["French"], 8.3),
+ /// ]
+ /// .into_iter()
+ /// .collect();
+ /// assert_eq!(collection.len(), 3);
+ ///
+ /// let titles: Vec<&str> = collection.in_year(1995).map(|movie| movie.title.as_str()).collect();
+ /// assert_eq!(titles, ["Heat", "Se7en"]);
+ ///
+ /// // Extending keeps the indexes up to date
+ /// collection.extend([movie("Spirited Away", 2001, &["Japanese"], 8.6)]);
+ /// assert_eq!(collection.in_year(2001).count(), 2);
+ /// assert_eq!(collection.in_language("Japanese").next().unwrap().title, "Spirited Away");
+ ///
+ /// let acclaimed = collection.iter().filter(|movie| movie.rating >= Some(8.5)).count();
+ /// assert_eq!(acclaimed, 2);
+ /// ```
+ #[derive(Default)]
+ pub struct MovieCollection {
+     movies: Vec<Movie>,
+     by_year: BTreeMap<i32, Vec<usize>>,
+     by_language: HashMap<Arc<str>, Vec<usize>>,
+ }
+ 
+ impl MovieCollection {
+     /// Creates an empty collection.
+     pub fn new() -> Self {
+         Self::default()
+     }
+ 
+     /// Adds `movie` at the end of the collection and to the indexes.
+     pub fn push(&mut self, movie: Movie) {
+         let position = self.movies.len();
+         self.by_year.entry(movie.year).or_default().push(position);
+         for language in &movie.languages {
+             let positions = self.by_language.entry(Arc::clone(language)).or_default();
+             // A language listed twice by one movie indexes it once
+             if positions.last() != Some(&position) {
+                 positions.push(position);
+             }
+         }
+         self.movies.push(movie);
+     }
+ 
+     /// Returns the number of movies in the collection.
+     pub fn len(&self) -> usize {
+         self.movies.len()
+     }
+ 
+     /// Returns true if the collection has no movies.
+     pub fn is_empty(&self) -> bool {
+         self.movies.is_empty()
+     }
+ 
+     /// Iterates over the movies in insertion order.
+     pub fn iter(&self) -> slice::Iter<'_, Movie> {
+         self.movies.iter()
+     }
+ 
+     /// Iterates over the movies released in `year`, in insertion order.
+     pub fn in_year(&self, year: i32) -> impl Iterator<Item = &Movie> {
+         self.indexed(self.by_year.get(&year))
+     }
+ 
+     /// Iterates over the movies listing `language` (exact, case-sensitive match),
+     /// in insertion order.
+     pub fn in_language(&self, language: &str) -> impl Iterator<Item = &Movie> {
+         self.indexed(self.by_language.get(language))
+     }
+ 
+     /// Returns every release year present, oldest first.
+     pub fn years(&self) -> impl Iterator<Item = i32> + '_ {
+         self.by_year.keys().copied()
+     }
+ 
+     /// Looks up the movies at the positions of one index entry.
+     fn indexed<'a>(&'a self, positions: Option<&'a Vec<usize>>) -> impl Iterator<Item = &'a Movie> {
+         positions.into_iter().flatten().map(|&position| &self.movies[position])
+     }
+ }
+ 
+ impl FromIterator<Movie> for MovieCollection {
+     fn from_iter<I: IntoIterator<Item = Movie>>(movies: I) -> Self {
+         let mut collection = MovieCollection::new();
+         collection.extend(movies);
+         collection
+     }
+ }
+ 
+ impl Extend<Movie> for MovieCollection {
+     fn extend<I: IntoIterator<Item = Movie>>(&mut self, movies: I) {
+         for movie in movies {
+             self.push(movie);
+         }
+     }
+ }
+ 
+ impl IntoIterator for MovieCollection {
+     type Item = Movie;
+     type IntoIter = vec::IntoIter<Movie>;
+ 
+     fn into_iter(self) -> Self::IntoIter {
+         self.movies.into_iter()
+     }
+ }
+ 
+ impl<'a> IntoIterator for &'a MovieCollection {
+     type Item = &'a Movie;
+     type IntoIter = slice::Iter<'a, Movie>;
+ 
+     fn into_iter(self) -> Self::IntoIter {
+         self.movies.iter()
+     }
+ }
+ 
+ #[cfg(test)]
+ mod tests {
+     use super::*;
+     use crate::{read_csv, ParseOptions};
+ 
+     fn titles<'a>(movies: impl Iterator<Item = &'a Movie>) -> Vec<&'a str> {
+         movies.map(|movie| movie.title.as_str()).collect()
+     }
+ 
+     #[test]
+     fn test_collection_from_csv_matches_linked_list() {
+         let movies = read_csv("movies_sample_1.csv", &ParseOptions::default()).unwrap();
+         let expected: Vec<String> = movies.iter().map(|movie| movie.title.clone()).collect();
+         let collection: MovieCollection = movies.into_iter().collect();
+         assert_eq!(collection.len(), expected.len());
+         assert_eq!(titles(collection.iter()), expected);
+         assert_eq!(titles(collection.in_year(2009)), ["Sherlock Holmes"]);
+         assert!(collection.in_year(1899).next().is_none());
+         assert!(collection.in_language("Klingon").next().is_none());
+         let english = collection.iter().filter(|movie| movie.has_language("English")).count();
+         assert_eq!(collection.in_language("English").count(), english);
+     }
+ 
+     #[test]
+     fn test_extend_keeps_indexes_consistent() {
+         let mut first = read_csv("movies_sample_1.csv", &ParseOptions::default()).unwrap();
+         let second = first.split_off(first.len() / 2);
+         let mut collection: MovieCollection = first.into_iter().collect();
+         collection.extend(second);
+ 
+         let whole = read_csv("movies_sample_1.csv", &ParseOptions::default()).unwrap();
+         assert_eq!(collection.len(), whole.len());
+         for year in collection.years() {
+             let expected: Vec<&str> = whole.iter().filter(|movie| movie.year == year).map(|movie| movie.title.as_str()).collect();
+             assert_eq!(titles(collection.in_year(year)), expected, "{}", year);
+         }
+         let years: Vec<i32> = collection.years().collect();
+         assert!(years.windows(2).all(|pair| pair[0] < pair[1]));
+         assert!(MovieCollection::new().is_empty());
+     }
+ }
@@ -0,0 +1,799 @@
+/*
+ * Non-interactive commands.
+ *
+ * A command is a short list of words such as `year 1994` or `highest report.csv`.
+ * The same parser serves the command given after the CSV file name on the command
+ * line and every line of a --script file, so both accept exactly the same commands.
+ */
+
+ use std::collections::LinkedList;
+ use std::error::Error;
+ use std::fs::File;
+ use std::io::{self, Write};
+ use std::ops::RangeInclusive;
+ use std::path::Path;
+ use clap::ValueEnum;
+ use movies_cargo::expr::{self, Expr};
+ use movies_cargo::languages::LanguageMap;
+ use movies_cargo::{filters, markdown, ndjson};
+ use movies_cargo::*;
+ #[cfg(feature = "sqlite")]
+ use movies_cargo::sqlite;
+ 
+ /// How non-interactive reports are rendered.
+ #[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+ pub enum ReportFormat {
+     /// The same plain text as the interactive menu.
+     #[default]
+     Text,
+     /// Comma-separated values with a header row.
+     Csv,
+     /// A Markdown table, for pasting into issues and documents.
+     Markdown,
+     /// One JSON object per line on stdout, with the row count on stderr.
+     Ndjson,
+ }
+ 
+ /// The command-line settings that shape the reports of non-interactive commands.
+ #[derive(Clone, Copy)]
+ pub struct ReportOptions {
+     /// The format of the report.
+     pub output: ReportFormat,
+     /// The year order of per-year reports.
+     pub order: YearOrder,
+     /// The number of decimal places ratings are shown with.
+     pub precision: usize,
+     /// The most rows a text report shows; other formats are never truncated.
+     pub limit: Option<usize>,
+     /// Whether exports may write into a directory that already has entries.
+     pub force: bool,
+     /// Whether title searches ignore diacritics.
+     pub fold_diacritics: bool,
+     /// The rating the highest-rated report compares and movie tables show.
+     pub rating_source: RatingSource,
+ }
+ 
+ /// The name, arguments, and description of a command.
+ pub struct CommandSpec {
+     /// The name the command is typed as.
+     pub name: &'static str,
+     /// Shorter names that are accepted too.
+     pub aliases: &'static [&'static str],
+     /// The arguments as shown in usage messages, e.g. `START END`.
+     pub args: &'static str,
+     /// The most arguments the command takes.
+     pub max_args: usize,
+     /// A one-line description for `help`.
+     pub summary: &'static str,
+ }
+ 
+ impl CommandSpec {
+     /// Returns the usage line of the command, such as `year-range START END`.
+     pub fn usage(&self) -> String {
+         format!("{} {}", self.name, self.args).trim_end().to_string()
+     }
+ }
+ 
+ /// Every command, in the order `help` lists them. The parser only accepts names
+ /// found here, so a command cannot be added without a description.
+ pub const COMMANDS: &[CommandSpec] = &[
+     CommandSpec { name: "year", aliases: &[], args: "YEAR", max_args: 1, summary: "Show the movies released in YEAR" },
+     CommandSpec {
+         name: "year-range",
+         aliases: &[],
+         args: "START END",
+         max_args: 2,
+         summary: "Show the movies released from START to END, grouped by year",
+     },
+     CommandSpec {
+         name: "language",
+         aliases: &["lang"],
+         args: "NAME",
+         max_args: usize::MAX,
+         summary: "Show the movies in a language; NAME may contain spaces",
+     },
+     CommandSpec {
+         name: "highest",
+         aliases: &[],
+         args: "[DEST]",
+         max_args: 1,
+         summary: "Show the highest-rated movie of each year, to DEST or `-` for stdout",
+     },
+     CommandSpec { name: "lowest", aliases: &[], args: "", max_args: 0, summary: "Show the lowest-rated movie of each year" },
+     CommandSpec { name: "top", aliases: &[], args: "N", max_args: 1, summary: "Show the N highest-rated movies" },
+     CommandSpec { name: "stats", aliases: &[], args: "", max_args: 0, summary: "Show rating statistics for each year" },
+     CommandSpec {
+         name: "dominant-language",
+         aliases: &[],
+         args: "",
+         max_args: 0,
+         summary: "Show the language listed by the most movies of each year",
+     },
+     CommandSpec {
+         name: "expr",
+         aliases: &[],
+         args: "EXPRESSION",
+         max_args: usize::MAX,
+         summary: "Show the movies matching an expression such as `year>=2000 and rating>8.5`",
+     },
+     CommandSpec { name: "filters", aliases: &[], args: "", max_args: 0, summary: "List the saved filters" },
+     CommandSpec {
+         name: "run-filter",
+         aliases: &[],
+         args: "NAME",
+         max_args: 1,
+         summary: "Show the movies matching the filter saved as NAME from the menu",
+     },
+     CommandSpec {
+         name: "save-filter",
+         aliases: &[],
+         args: "NAME [KEY=VALUE]...",
+         max_args: usize::MAX,
+         summary: "Save criteria such as `year=2000-2009 lang=English rating=8` as the filter NAME",
+     },
+     CommandSpec {
+         name: "export-sqlite",
+         aliases: &[],
+         args: "DB_PATH",
+         max_args: 1,
+         summary: "Write all movies into a SQLite database (requires the `sqlite` feature)",
+     },
+     CommandSpec {
+         name: "export-by-year",
+         aliases: &[],
+         args: "DIR",
+         max_args: 1,
+         summary: "Write each year's titles to DIR/YYYY.txt; DIR must be empty unless --force is given",
+     },
+     CommandSpec {
+         name: "help",
+         aliases: &["?"],
+         args: "[COMMAND]",
+         max_args: 1,
+         summary: "List the commands, or describe one; `help expr` shows the expression syntax",
+     },
+     CommandSpec { name: "quit", aliases: &["exit"], args: "", max_args: 0, summary: "Stop reading commands" },
+ ];
+ 
+ /// Writes every command with its arguments and description, one per line.
+ pub fn write_help(out: &mut impl Write) -> io::Result<()> {
+     let width = COMMANDS.iter().map(|spec| spec.usage().len()).max().unwrap_or(0);
+     for spec in COMMANDS {
+         writeln!(out, "  {:<width$}  {}", spec.usage(), spec.summary, width = width)?;
+     }
+     Ok(())
+ }
+ 
+ /// Writes the usage and description of the command named `name`, followed by the
+ /// expression grammar for `expr`.
+ pub fn write_command_help(out: &mut impl Write, name: &str) -> io::Result<()> {
+     if let Some(spec) = COMMANDS.iter().find(|spec| spec.name == name) {
+         writeln!(out, "Usage: {}", spec.usage())?;
+         writeln!(out, "{}", spec.summary)?;
+     }
+     if name == "expr" {
+         for line in expr::GRAMMAR {
+             writeln!(out, "{}", line)?;
+         }
+     }
+     Ok(())
+ }
+ 
+ /// Splits a command line into words at whitespace. Text in double quotes stays in
+ /// one word, so `language "Old English"` has two words.
+ ///
+ /// # Errors
+ ///
+ /// Returns a message quoting the rest of the line if a quote is never closed.
+ pub fn tokenize(line: &str) -> Result<Vec<String>, String> {
+     let mut words = Vec::new();
+     let mut word: Option<String> = None;
+     let mut quote_start = None;
+     for (index, c) in line.char_indices() {
+         match c {
+             '"' if quote_start.is_some() => quote_start = None,
+             '"' => {
+                 quote_start = Some(index);
+                 word.get_or_insert_with(String::new);
+             }
+             c if c.is_whitespace() && quote_start.is_none() => words.extend(word.take()),
+             c => word.get_or_insert_with(String::new).push(c),
+         }
+     }
+     if let Some(start) = quote_start {
+         return Err(format!("Unterminated quote in '{}'.", &line[start..]));
+     }
+     words.extend(word);
+     Ok(words)
+ }
+ 
+ /// A parsed non-interactive command. See [`COMMANDS`] for the syntax of each.
+ #[derive(Debug, PartialEq)]
+ pub enum Command {
+     Year(i32),
+     YearRange(RangeInclusive<i32>),
+     Language(String),
+     /// The highest-rated movie per year, written to a path or `-` for stdout.
+     Highest(String),
+     Lowest,
+     Top(usize),
+     Stats,
+     DominantLanguage,
+     /// The movies matching a query expression.
+     Expr(Expr),
+     ListFilters,
+     /// The movies matching the saved filter of the given name.
+     RunFilter(String),
+     /// Save the filter under the given name.
+     SaveFilter(String, MovieFilter),
+     /// Export to the SQLite database at the given path.
+     ExportSqlite(String),
+     /// Export per-year title files into the given directory.
+     ExportByYear(String),
+     /// The command list, or the description of the named command.
+     Help(Option<&'static str>),
+     Quit,
+ }
+ 
+ impl Command {
+     /// Parses a command from its words.
+     ///
+     /// # Errors
+     ///
+     /// Returns a message naming the offending word if the command is unknown or its
+     /// arguments are invalid.
+     pub fn parse(words: &[&str]) -> Result<Command, String> {
+         let (name, args) = words.split_first().ok_or("Missing command.")?;
+         let spec = find_command(name)?;
+         if let Some(extra) = args.get(spec.max_args) {
+             return Err(format!("Unexpected argument '{}'. Usage: {}", extra, spec.usage()));
+         }
+ 
+         match (spec.name, args) {
+             ("year", [year]) => {
+                 let year = year.parse().map_err(|_| format!("Invalid year '{}'.", year))?;
+                 let years = validate_year_range(year, year)?;
+                 Ok(Command::Year(*years.start()))
+             }
+             ("year-range", [start, end]) => {
+                 let start = start.parse().map_err(|_| format!("Invalid start year '{}'.", start))?;
+                 let end = end.parse().map_err(|_| format!("Invalid end year '{}'.", end))?;
+                 Ok(Command::YearRange(validate_year_range(start, end)?))
+             }
+             ("language", [_, ..]) => Ok(Command::Language(args.join(" "))),
+             ("highest", []) => Ok(Command::Highest("-".to_string())),
+             ("highest", [destination]) => Ok(Command::Highest(destination.to_string())),
+             ("lowest", []) => Ok(Command::Lowest),
+             ("top", [count]) => match count.parse() {
+                 Ok(count) if count > 0 => Ok(Command::Top(count)),
+                 _ => Err(format!("Invalid count '{}': expected a positive whole number.", count)),
+             },
+             ("stats", []) => Ok(Command::Stats),
+             ("dominant-language", []) => Ok(Command::DominantLanguage),
+             ("expr", [_, ..]) => match Expr::parse(&args.join(" ")) {
+                 Ok(expr) => Ok(Command::Expr(expr)),
+                 Err(e) => Err(format!("Invalid expression: {}.", e)),
+             },
+             ("filters", []) => Ok(Command::ListFilters),
+             ("run-filter", [name]) => {
+                 filters::validate_filter_name(name)?;
+                 Ok(Command::RunFilter(name.to_string()))
+             }
+             ("save-filter", [name, criteria @ ..]) => {
+                 filters::validate_filter_name(name)?;
+                 Ok(Command::SaveFilter(name.to_string(), filters::parse_criteria(criteria)?))
+             }
+             ("export-sqlite", [db_path]) => Ok(Command::ExportSqlite(db_path.to_string())),
+             ("export-by-year", [dir]) => Ok(Command::ExportByYear(dir.to_string())),
+             ("help", []) => Ok(Command::Help(None)),
+             ("help", [topic]) => Ok(Command::Help(Some(find_command(topic)?.name))),
+             ("quit", []) => Ok(Command::Quit),
+             _ => Err(format!("Usage: {}", spec.usage())),
+         }
+     }
+ }
+ 
+ /// Looks up a command by its name or one of its aliases.
+ fn find_command(name: &str) -> Result<&'static CommandSpec, String> {
+     COMMANDS
+         .iter()
+         .find(|spec| spec.name == name || spec.aliases.contains(&name))
+         .ok_or_else(|| format!("Unknown command '{}'.", name))
+ }
+ 
+ /// Loads the filter saved as `name`, with its language normalized through
+ /// `language_map` since the file may have been edited by hand.
+ fn saved_filter(name: &str, language_map: &LanguageMap) -> Result<MovieFilter, Box<dyn Error>> {
+     let saved = filters::load_filters(&filters::filters_path()?)?;
+     let mut filter = saved.get(name)?.clone();
+     filter.normalize_language(language_map);
+     Ok(filter)
+ }
+ 
+ /// Runs a command against `movies`, writing its output to `out`.
+ ///
+ /// The report settings in `report` come from the command-line options, and
+ /// language names are normalized through `language_map`.
+ ///
+ /// # Errors
+ ///
+ /// Returns an error if the output cannot be written or the command fails.
+ pub fn run_command(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     command: &Command,
+     report: ReportOptions,
+     language_map: &LanguageMap,
+ ) -> Result<(), Box<dyn Error>> {
+     let ReportOptions { output, order, precision, limit, rating_source, .. } = report;
+     match output {
+         ReportFormat::Markdown => return run_markdown(out, movies, command, report, language_map),
+         ReportFormat::Ndjson => return run_ndjson(out, movies, command, report, language_map),
+         ReportFormat::Text | ReportFormat::Csv => {}
+     }
+     match command {
+         Command::Year(year) => show_movies_by_year(out, movies, *year, limit)?,
+         Command::YearRange(years) => show_movies_in_year_range(out, movies, years.clone(), order, limit)?,
+         Command::Language(name) => show_movies_by_language(out, movies, language_map.canonical(name), limit)?,
+         Command::Highest(destination) => match output {
+             ReportFormat::Csv => export_highest_rated_csv(out, movies, destination, order, rating_source, precision)?,
+             _ if destination == "-" => show_highest_rated_movies(out, movies, order, rating_source, precision, limit)?,
+             _ => {
+                 let mut file = File::create(destination)?;
+                 show_highest_rated_movies(&mut file, movies, order, rating_source, precision, limit)?
+             }
+         },
+         Command::Lowest => show_lowest_rated_movies(out, movies, precision, limit)?,
+         Command::Top(count) => show_top_rated_movies(out, movies, *count, precision)?,
+         Command::Stats => show_rating_stats(out, movies, order, limit)?,
+         Command::DominantLanguage => show_dominant_languages(out, movies, limit)?,
+         Command::Expr(query) => {
+             let mut query = query.clone();
+             query.normalize_languages(language_map);
+             query.set_fold_diacritics(report.fold_diacritics);
+             expr::show_expr_matches(out, movies, &query, precision, limit)?;
+         }
+         Command::ListFilters => filters::show_saved_filters(out, &filters::load_filters(&filters::filters_path()?)?)?,
+         Command::RunFilter(name) => show_filtered_movies(out, movies, &saved_filter(name, language_map)?, precision, limit)?,
+         Command::SaveFilter(name, filter) => {
+             let mut filter = filter.clone().rating_source(rating_source);
+             filter.normalize_language(language_map);
+             let path = filters::filters_path()?;
+             filters::save_filter(&path, name, &filter)?;
+             writeln!(out, "Saved filter {} to {}", name, path.display())?;
+         }
+         #[cfg(feature = "sqlite")]
+         Command::ExportSqlite(db_path) => {
+             let count = sqlite::export_sqlite(movies, db_path)?;
+             writeln!(out, "Exported {} movies to {}", count, db_path)?;
+         }
+         #[cfg(not(feature = "sqlite"))]
+         Command::ExportSqlite(_) => {
+             return Err("SQLite export is not available; rebuild with `--features sqlite`.".into());
+         }
+         Command::ExportByYear(dir) => {
+             let export = export_titles_by_year(movies, Path::new(dir), report.force)?;
+             writeln!(out, "Wrote {} titles to {} files in {}", export.lines, export.files, dir)?;
+         }
+         Command::Help(None) => write_help(out)?,
+         Command::Help(Some(name)) => write_command_help(out, name)?,
+         // Only the prompt and scripts stop at `quit`
+         Command::Quit => {}
+     }
+     Ok(())
+ }
+ 
+ /// Returns the movies a movie query selects, in the order its text report lists
+ /// them, or `None` for a command that does not list movies.
+ ///
+ /// # Errors
+ ///
+ /// Returns an error if a saved filter cannot be loaded.
+ fn selected_movies<'a>(
+     movies: &'a LinkedList<Movie>,
+     command: &Command,
+     report: ReportOptions,
+     language_map: &LanguageMap,
+ ) -> Result<Option<Vec<&'a Movie>>, Box<dyn Error>> {
+     let selected = match command {
+         Command::Year(year) => MovieFilter::new().year(*year).apply(movies).collect(),
+         Command::YearRange(years) => movies_in_year_range(movies, *years.start(), *years.end(), report.order)
+             .into_iter()
+             .flat_map(|(_, group)| group)
+             .collect(),
+         Command::Language(name) => movies_by_language(movies, language_map.canonical(name)),
+         Command::Highest(_) => highest_rated_per_year(movies, report.order, report.rating_source)
+             .into_iter()
+             .map(|(_, movie)| movie)
+             .collect(),
+         Command::Lowest => lowest_rated_per_year(movies).into_values().flatten().collect(),
+         Command::Top(count) => top_rated_movies(movies, *count),
+         Command::Expr(query) => {
+             let mut query = query.clone();
+             query.normalize_languages(language_map);
+             query.set_fold_diacritics(report.fold_diacritics);
+             query.apply(movies).collect()
+         }
+         Command::RunFilter(name) => saved_filter(name, language_map)?.apply(movies).collect(),
+         Command::Stats
+         | Command::DominantLanguage
+         | Command::ListFilters
+         | Command::SaveFilter(..)
+         | Command::ExportSqlite(_)
+         | Command::ExportByYear(_)
+         | Command::Help(_)
+         | Command::Quit => return Ok(None),
+     };
+     Ok(Some(selected))
+ }
+ 
+ /// Runs a command with its result rendered as a Markdown table: the movies a query
+ /// selects for movie queries, or the statistics of each year for `stats`.
+ fn run_markdown(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     command: &Command,
+     report: ReportOptions,
+     language_map: &LanguageMap,
+ ) -> Result<(), Box<dyn Error>> {
+     let ReportOptions { order, precision, rating_source, .. } = report;
+     match (command, selected_movies(movies, command, report, language_map)?) {
+         (Command::Highest(destination), Some(selected)) if destination != "-" => {
+             markdown::write_movie_table(&mut File::create(destination)?, selected, rating_source, precision)?;
+         }
+         (_, Some(selected)) => markdown::write_movie_table(out, selected, rating_source, precision)?,
+         (Command::Stats, None) => markdown::write_rating_stats_table(out, &rating_stats_by_year(movies, order))?,
+         (_, None) => {
+             let text = ReportOptions { output: ReportFormat::Text, ..report };
+             return run_command(out, movies, command, text, language_map);
+         }
+     }
+     Ok(())
+ }
+ 
+ /// Runs a command with its result written as newline-delimited JSON: one object per
+ /// movie for movie queries, or per year for `stats`. The row count goes to stderr so
+ /// stdout holds nothing but JSON lines. Other commands report as text.
+ fn run_ndjson(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     command: &Command,
+     report: ReportOptions,
+     language_map: &LanguageMap,
+ ) -> Result<(), Box<dyn Error>> {
+     let rows = match (command, selected_movies(movies, command, report, language_map)?) {
+         (Command::Highest(destination), Some(selected)) if destination != "-" => {
+             ndjson::write_movies(&mut File::create(destination)?, selected)?;
+             return Ok(());
+         }
+         (_, Some(selected)) => footer(ndjson::write_movies(out, selected)?, "movie"),
+         (Command::Stats, None) => {
+             let stats = rating_stats_by_year(movies, report.order);
+             ndjson::write_rating_stats(out, &stats)?;
+             footer(stats.len(), "year")
+         }
+         (_, None) => {
+             let text = ReportOptions { output: ReportFormat::Text, ..report };
+             return run_command(out, movies, command, text, language_map);
+         }
+     };
+     eprintln!("{}", rows);
+     Ok(())
+ }
+ 
+ /// Runs every command of a script in order, writing each command's output to
+ /// `out` after a `== command ==` header.
+ ///
+ /// Lines are split into words by [`tokenize`]. Blank lines and lines starting with
+ /// `#` are skipped, and `quit` ends the script early. A line that fails to parse
+ /// or run is reported to `err` with its line number; the script then continues, or
+ /// stops when `strict` is true.
+ ///
+ /// # Returns
+ ///
+ /// * `Result<usize, String>` - The number of lines that failed, or the report of
+ ///   the first failure in strict mode.
+ pub fn run_script(
+     out: &mut impl Write,
+     err: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     script: &str,
+     report: ReportOptions,
+     language_map: &LanguageMap,
+     strict: bool,
+ ) -> Result<usize, String> {
+     let mut failures = 0;
+     for (index, line) in script.lines().enumerate() {
+         let line = line.trim();
+         if line.is_empty() || line.starts_with('#') {
+             continue;
+         }
+ 
+         writeln!(out, "== {} ==", line).map_err(|e| e.to_string())?;
+         let result = tokenize(line).and_then(|words| {
+             let words: Vec<&str> = words.iter().map(String::as_str).collect();
+             Command::parse(&words)
+         });
+         let result = match result {
+             Ok(Command::Quit) => break,
+             Ok(command) => run_command(out, movies, &command, report, language_map).map_err(|e| e.to_string()),
+             Err(message) => Err(message),
+         };
+         if let Err(message) = result {
+             let message = format!("line {}: {}", index + 1, message);
+             if strict {
+                 return Err(message);
+             }
+             writeln!(err, "Error: {}", message).map_err(|e| e.to_string())?;
+             failures += 1;
+         }
+     }
+     Ok(failures)
+ }
+ 
+ #[cfg(test)]
+ mod tests {
+     use super::*;
+ 
+     /// Loads the 24-movie sample file used by the command tests.
+     fn sample_movies() -> LinkedList<Movie> {
+         read_csv("movies_sample_1.csv", &ParseOptions::default()).unwrap()
+     }
+ 
+     /// Report settings with the default precision and no row limit.
+     fn report(output: ReportFormat, order: YearOrder) -> ReportOptions {
+         ReportOptions {
+             output,
+             order,
+             precision: RATING_PRECISION,
+             limit: None,
+             force: false,
+             fold_diacritics: true,
+             rating_source: RatingSource::Rating,
+         }
+     }
+ 
+     /// Parses and runs `words`, returning the output.
+     fn run(words: &[&str], report: ReportOptions) -> String {
+         let mut out = Vec::new();
+         let command = Command::parse(words).unwrap();
+         run_command(&mut out, &sample_movies(), &command, report, &LanguageMap::builtin()).unwrap();
+         String::from_utf8(out).unwrap()
+     }
+ 
+     #[test]
+     fn test_parse_commands() {
+         assert_eq!(Command::parse(&["year", "1994"]), Ok(Command::Year(1994)));
+         assert_eq!(Command::parse(&["year-range", "2008", "2009"]), Ok(Command::YearRange(2008..=2009)));
+         assert_eq!(Command::parse(&["language", "Old", "English"]), Ok(Command::Language("Old English".to_string())));
+         assert_eq!(Command::parse(&["highest"]), Ok(Command::Highest("-".to_string())));
+         assert_eq!(Command::parse(&["highest", "out.csv"]), Ok(Command::Highest("out.csv".to_string())));
+         assert_eq!(Command::parse(&["lowest"]), Ok(Command::Lowest));
+         assert_eq!(Command::parse(&["stats"]), Ok(Command::Stats));
+         assert_eq!(Command::parse(&["dominant-language"]), Ok(Command::DominantLanguage));
+         assert_eq!(Command::parse(&["export-sqlite", "movies.db"]), Ok(Command::ExportSqlite("movies.db".to_string())));
+         assert_eq!(Command::parse(&["top", "10"]), Ok(Command::Top(10)));
+         assert_eq!(Command::parse(&["export-by-year", "out"]), Ok(Command::ExportByYear("out".to_string())));
+         assert_eq!(Command::parse(&["filters"]), Ok(Command::ListFilters));
+         assert_eq!(Command::parse(&["run-filter", "favorites"]), Ok(Command::RunFilter("favorites".to_string())));
+         assert_eq!(
+             Command::parse(&["save-filter", "favorites", "year=2000-2009", "lang=English", "rating=8"]),
+             Ok(Command::SaveFilter(
+                 "favorites".to_string(),
+                 MovieFilter::new().years(2000..=2009).language("English").min_rating(8.0)
+             ))
+         );
+         assert_eq!(
+             Command::parse(&["save-filter", "dramas", "genre=Drama", "year=1994"]),
+             Ok(Command::SaveFilter("dramas".to_string(), MovieFilter::new().genre("Drama").year(1994)))
+         );
+         assert_eq!(Command::parse(&["save-filter", "all"]), Ok(Command::SaveFilter("all".to_string(), MovieFilter::new())));
+         assert_eq!(Command::parse(&["lang", "German"]), Ok(Command::Language("German".to_string())));
+         assert_eq!(Command::parse(&["help"]), Ok(Command::Help(None)));
+         assert_eq!(Command::parse(&["?", "lang"]), Ok(Command::Help(Some("language"))));
+         assert_eq!(
+             Command::parse(&["expr", "year=2009", "or", "lang='Old", "English'"]),
+             Ok(Command::Expr(Expr::parse("year=2009 or lang='Old English'").unwrap()))
+         );
+         assert_eq!(Command::parse(&["exit"]), Ok(Command::Quit));
+     }
+ 
+     #[test]
+     fn test_tokenize() {
+         assert_eq!(tokenize("  year   1994 "), Ok(vec!["year".to_string(), "1994".to_string()]));
+         assert_eq!(tokenize("lang \"Old English\""), Ok(vec!["lang".to_string(), "Old English".to_string()]));
+         assert_eq!(tokenize("highest \"\""), Ok(vec!["highest".to_string(), String::new()]));
+         assert_eq!(tokenize(""), Ok(vec![]));
+         assert_eq!(tokenize("lang \"Old English"), Err("Unterminated quote in '\"Old English'.".to_string()));
+     }
+ 
+     #[test]
+     fn test_help_lists_every_command() {
+         let mut out = Vec::new();
+         write_help(&mut out).unwrap();
+         let help = String::from_utf8(out).unwrap();
+         assert_eq!(help.lines().count(), COMMANDS.len());
+         for spec in COMMANDS {
+             assert!(help.contains(&format!("  {}", spec.usage())), "{} missing from help", spec.name);
+             // Every listed command must parse, so the registry and parser cannot drift apart
+             let error = Command::parse(&[spec.name]).err().unwrap_or_default();
+             assert!(!error.starts_with("Unknown command"), "{}", error);
+         }
+     }
+ 
+     #[test]
+     fn test_parse_invalid_commands() {
+         assert_eq!(Command::parse(&["year"]), Err("Usage: year YEAR".to_string()));
+         assert_eq!(Command::parse(&["year", "soon"]), Err("Invalid year 'soon'.".to_string()));
+         assert!(Command::parse(&["year", "1800"]).is_err());
+         assert_eq!(Command::parse(&["year-range", "2008"]), Err("Usage: year-range START END".to_string()));
+         assert!(Command::parse(&["year-range", "2009", "2008"]).is_err());
+         assert_eq!(Command::parse(&["language"]), Err("Usage: language NAME".to_string()));
+         assert_eq!(Command::parse(&["highest", "a", "b"]), Err("Unexpected argument 'b'. Usage: highest [DEST]".to_string()));
+         assert_eq!(Command::parse(&["stats", "now"]), Err("Unexpected argument 'now'. Usage: stats".to_string()));
+         assert_eq!(Command::parse(&["top", "ten"]), Err("Invalid count 'ten': expected a positive whole number.".to_string()));
+         assert!(Command::parse(&["top", "0"]).is_err());
+         assert!(Command::parse(&["run-filter", "my/filter"]).is_err());
+         assert_eq!(Command::parse(&["save-filter"]), Err("Usage: save-filter NAME [KEY=VALUE]...".to_string()));
+         assert!(Command::parse(&["save-filter", "my/filter", "year=1994"]).is_err());
+         assert_eq!(Command::parse(&["save-filter", "old", "year=1800"]), Err("Invalid year or range '1800'.".to_string()));
+         assert_eq!(Command::parse(&["save-filter", "good", "rating=high"]), Err("Invalid rating 'high'.".to_string()));
+         assert_eq!(Command::parse(&["save-filter", "any", "lang="]), Err("Missing value for 'lang'.".to_string()));
+         assert_eq!(
+             Command::parse(&["save-filter", "red", "colour=red"]),
+             Err("Unknown criterion 'colour': use year, lang, genre, or rating.".to_string())
+         );
+         assert_eq!(
+             Command::parse(&["save-filter", "recent", "2012"]),
+             Err("Invalid criterion '2012': expected KEY=VALUE.".to_string())
+         );
+         assert_eq!(Command::parse(&["shrug"]), Err("Unknown command 'shrug'.".to_string()));
+         assert_eq!(Command::parse(&["help", "shrug"]), Err("Unknown command 'shrug'.".to_string()));
+         assert_eq!(
+             Command::parse(&["expr", "year>=2000", "and"]),
+             Err("Invalid expression: unexpected end of expression at column 15.".to_string())
+         );
+         assert_eq!(Command::parse(&[]), Err("Missing command.".to_string()));
+     }
+ 
+     #[test]
+     fn test_save_filter_round_trip() {
+         let dir = tempfile::tempdir().unwrap();
+         let path = dir.path().join("filters.toml");
+         let Ok(Command::SaveFilter(name, filter)) = Command::parse(&["save-filter", "recent", "year=2008-2012", "lang=English", "rating=7.5"])
+         else {
+             panic!("save-filter did not parse");
+         };
+         filters::save_filter(&path, &name, &filter).unwrap();
+ 
+         // The saved filter reads back unchanged and selects what the criteria describe
+         let saved = filters::load_filters(&path).unwrap();
+         assert_eq!(saved.get("recent").unwrap(), &filter);
+         let movies = sample_movies();
+         let titles: Vec<&str> = saved.get("recent").unwrap().apply(&movies).map(|movie| movie.title.as_str()).collect();
+         assert_eq!(titles, ["Sherlock Holmes", "Iron Man", "The Avengers", "Anna Karenina"]);
+     }
+ 
+     #[test]
+     fn test_run_year_range() {
+         let output = run(&["year-range", "2008", "2009"], report(ReportFormat::Text, YearOrder::Ascending));
+         assert_eq!(output, "2008:\n  The Incredible Hulk\n  Iron Man\n2009:\n  Sherlock Holmes\n-- 3 movies --\n");
+     }
+ 
+     #[test]
+     fn test_run_dominant_language() {
+         let output = run(&["dominant-language"], report(ReportFormat::Text, YearOrder::Ascending));
+         assert!(output.starts_with("2003: English (1 movie)\n2008: English (2 movies)\n"), "{}", output);
+         assert!(output.contains("\n2012: English (3 movies)\n"), "{}", output);
+     }
+ 
+     #[test]
+     fn test_run_command_limit() {
+         let limited = ReportOptions { limit: Some(2), ..report(ReportFormat::Text, YearOrder::Ascending) };
+         let output = run(&["year-range", "2008", "2009"], limited);
+         assert_eq!(output, "2008:\n  The Incredible Hulk\n  Iron Man\n(+ 1 more)\n-- 3 movies --\n");
+ 
+         // CSV reports are never truncated
+         let limited = ReportOptions { limit: Some(2), ..report(ReportFormat::Csv, YearOrder::Ascending) };
+         let rows = run(&["highest"], limited).lines().count();
+         assert_eq!(rows, 1 + highest_rated_per_year(&sample_movies(), YearOrder::Ascending, RatingSource::Rating).len());
+     }
+ 
+     #[test]
+     fn test_run_command_highest_csv_to_stdout() {
+         let output = run(&["highest"], report(ReportFormat::Csv, YearOrder::Ascending));
+         assert!(output.starts_with("year,rating,title\n2003,6.6,Right on Track\n"));
+ 
+         let output = run(&["highest"], report(ReportFormat::Csv, YearOrder::Descending));
+         assert!(output.starts_with("year,rating,title\n2018,8.5,Avengers: Infinity War\n"));
+     }
+ 
+     #[test]
+     fn test_run_command_markdown() {
+         let markdown = report(ReportFormat::Markdown, YearOrder::Descending);
+         let output = run(&["highest"], markdown);
+         assert!(output.starts_with("| Title | Year | Rating | Languages |\n| --- | --- | --- | --- |\n"), "{}", output);
+         assert!(output.contains("\n| Avengers: Infinity War | 2018 | 8.5 | English |\n"), "{}", output);
+ 
+         let output = run(&["stats"], markdown);
+         assert!(output.starts_with("| Year | Min | Median | P90 | Max |\n"), "{}", output);
+     }
+ 
+     #[test]
+     fn test_run_expr_and_its_help() {
+         let text = report(ReportFormat::Text, YearOrder::Ascending);
+         let output = run(&["expr", "year>2008 and year<2010 or lang=ta"], text);
+         assert_eq!(output, "2009 7.6 Sherlock Holmes
+-- 1 movie --
+");
+ 
+         let output = run(&["help", "expr"], text);
+         assert!(output.starts_with("Usage: expr EXPRESSION\nShow the movies matching"), "{}", output);
+         assert!(output.ends_with(&format!("{}\n", expr::GRAMMAR.last().unwrap())), "{}", output);
+     }
+ 
+     #[test]
+     fn test_run_command_ndjson() {
+         let ndjson = report(ReportFormat::Ndjson, YearOrder::Ascending);
+         let output = run(&["year-range", "2008", "2009"], ndjson);
+         let titles: Vec<String> = output
+             .lines()
+             .map(|line| {
+                 let movie: Movie = serde_json::from_str(line).unwrap();
+                 movie.title
+             })
+             .collect();
+         assert_eq!(titles, ["The Incredible Hulk", "Iron Man", "Sherlock Holmes"]);
+         assert!(output.ends_with("}\n") && !output.contains("--"), "{}", output);
+ 
+         let output = run(&["stats"], ndjson);
+         for line in output.lines() {
+             let value: serde_json::Value = serde_json::from_str(line).unwrap();
+             assert!(value["year"].is_i64() && value["median"].is_f64(), "{}", line);
+         }
+     }
+ 
+     #[test]
+     fn test_run_language_uses_language_map() {
+         let output = run(&["language", "en"], report(ReportFormat::Text, YearOrder::Ascending));
+         assert!(output.starts_with("2003 Right on Track\n2008 Iron Man\n2008 The Incredible Hulk\n"), "{}", output);
+     }
+ 
+     #[test]
+     fn test_run_script() {
+         let script = "# nightly report\nyear 2012\n\nlanguage Tamil\nbogus 1\nyear-range 2008 2009\nquit\nstats\n";
+         let (mut out, mut err) = (Vec::new(), Vec::new());
+         let text = report(ReportFormat::Text, YearOrder::Ascending);
+         let failures = run_script(&mut out, &mut err, &sample_movies(), script, text, &LanguageMap::builtin(), false);
+         assert_eq!(failures, Ok(1));
+         assert_eq!(
+             String::from_utf8(out).unwrap(),
+             "== year 2012 ==\n\
+              The Avengers\n\
+              Rise of the Guardians\n\
+              Anna Karenina\n\
+              -- 3 movies --\n\
+              == language Tamil ==\n\
+              No movies found in Tamil\n\
+              == bogus 1 ==\n\
+              == year-range 2008 2009 ==\n\
+              2008:\n  The Incredible Hulk\n  Iron Man\n\
+              2009:\n  Sherlock Holmes\n\
+              -- 3 movies --\n\
+              == quit ==\n"
+         );
+         assert_eq!(String::from_utf8(err).unwrap(), "Error: line 5: Unknown command 'bogus'.\n");
+     }
+ 
+     #[test]
+     fn test_run_script_strict_stops_at_first_failure() {
+         let script = "year 2012\nyear 1800\nstats\n";
+         let (mut out, mut err) = (Vec::new(), Vec::new());
+         let text = report(ReportFormat::Text, YearOrder::Ascending);
+         let result = run_script(&mut out, &mut err, &sample_movies(), script, text, &LanguageMap::builtin(), true);
+         assert!(result.unwrap_err().starts_with("line 2: "));
+         let output = String::from_utf8(out).unwrap();
+         assert!(output.ends_with("== year 1800 ==\n"), "{}", output);
+         assert!(err.is_empty());
+     }
+ }
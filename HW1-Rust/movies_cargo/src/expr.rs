@@ -0,0 +1,497 @@
+/*
+ * Query expressions.
+ *
+ * A query expression such as `year>=2000 and (rating>8.5 or lang=French)` selects
+ * movies in one line instead of a series of prompts. Year ranges and languages are
+ * checked by the same MovieFilter the menu builds; ratings and title substrings are
+ * compared directly. Errors give the 1-based column of the offending token.
+ */
+
+ use std::collections::LinkedList;
+ use std::fmt;
+ use std::io::{self, Write};
+ use crate::languages::LanguageMap;
+ use crate::text::search_key;
+ use crate::{Listing, Movie, MovieFilter};
+ 
+ /// The grammar of query expressions, one line per entry, as shown by `help expr`.
+ pub const GRAMMAR: &[&str] = &[
+     "An expression joins comparisons with `and`, `or`, and parentheses; `and` binds",
+     "tighter than `or`. Field names and keywords ignore case.",
+     "  year OP YEAR       OP is one of = < <= > >=",
+     "  rating OP RATING   unrated movies never match",
+     "  lang = NAME        movies listing the language; aliases such as `en` work too",
+     "  title = TEXT       titles containing TEXT, ignoring case and accents",
+     "Quote a NAME or TEXT that contains spaces or operators: lang='Old English'.",
+     "Example: year>=2000 and (rating>8.5 or lang=French)",
+ ];
+ 
+ /// A syntax error in a query expression.
+ #[derive(Debug, Clone, PartialEq)]
+ pub struct ExprError {
+     /// The 1-based column, in characters, where the error was found.
+     pub column: usize,
+     /// What was wrong at that column.
+     pub message: String,
+ }
+ 
+ impl fmt::Display for ExprError {
+     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+         write!(f, "{} at column {}", self.message, self.column)
+     }
+ }
+ 
+ impl std::error::Error for ExprError {}
+ 
+ /// A comparison operator.
+ #[derive(Debug, Clone, Copy, PartialEq)]
+ pub enum Comparison {
+     Eq,
+     Lt,
+     Le,
+     Gt,
+     Ge,
+ }
+ 
+ impl Comparison {
+     /// Returns true if `left` compares to `right` as the operator requires.
+     pub fn holds<T: PartialOrd>(self, left: T, right: T) -> bool {
+         match self {
+             Comparison::Eq => left == right,
+             Comparison::Lt => left < right,
+             Comparison::Le => left <= right,
+             Comparison::Gt => left > right,
+             Comparison::Ge => left >= right,
+         }
+     }
+ }
+ 
+ /// A parsed query expression.
+ #[derive(Debug, Clone, PartialEq)]
+ pub enum Expr {
+     And(Box<Expr>, Box<Expr>),
+     Or(Box<Expr>, Box<Expr>),
+     /// A year range or language, checked by the menu's filter.
+     Filter(MovieFilter),
+     /// Rated movies whose rating compares to the value as the operator requires.
+     Rating(Comparison, f32),
+     /// Titles containing the text, ignoring case and, when `fold` is true, diacritics.
+     Title { text: String, fold: bool },
+ }
+ 
+ impl Expr {
+     /// Parses a query expression; see [`GRAMMAR`] for the syntax.
+     ///
+     /// # Errors
+     ///
+     /// Returns the column and a description of the first token that does not fit
+     /// the grammar.
+     pub fn parse(text: &str) -> Result<Expr, ExprError> {
+         let tokens = lex(text)?;
+         let mut parser = Parser { tokens, position: 0, end: text.chars().count() + 1 };
+         let expr = parser.or()?;
+         match parser.peek() {
+             Some(token) => Err(token.unexpected()),
+             None => Ok(expr),
+         }
+     }
+ 
+     /// Replaces every language name with its canonical name from `language_map`,
+     /// so `lang=en` matches English movies.
+     pub fn normalize_languages(&mut self, language_map: &LanguageMap) {
+         match self {
+             Expr::And(left, right) | Expr::Or(left, right) => {
+                 left.normalize_languages(language_map);
+                 right.normalize_languages(language_map);
+             }
+             Expr::Filter(filter) => filter.normalize_language(language_map),
+             Expr::Rating(..) | Expr::Title { .. } => {}
+         }
+     }
+ 
+     /// Sets whether title comparisons ignore diacritics; they do after parsing.
+     pub fn set_fold_diacritics(&mut self, fold: bool) {
+         match self {
+             Expr::And(left, right) | Expr::Or(left, right) => {
+                 left.set_fold_diacritics(fold);
+                 right.set_fold_diacritics(fold);
+             }
+             Expr::Title { fold: title_fold, .. } => *title_fold = fold,
+             Expr::Filter(_) | Expr::Rating(..) => {}
+         }
+     }
+ 
+     /// Returns true if `movie` satisfies the expression.
+     pub fn matches(&self, movie: &Movie) -> bool {
+         match self {
+             Expr::And(left, right) => left.matches(movie) && right.matches(movie),
+             Expr::Or(left, right) => left.matches(movie) || right.matches(movie),
+             Expr::Filter(filter) => filter.matches(movie),
+             Expr::Rating(comparison, value) => movie.rating.is_some_and(|rating| comparison.holds(rating, *value)),
+             Expr::Title { text, fold } => search_key(&movie.title, *fold).contains(&search_key(text, *fold)),
+         }
+     }
+ 
+     /// Returns the movies that satisfy the expression, in file order.
+     pub fn apply<'a, 'm: 'a>(&'a self, movies: &'m LinkedList<Movie>) -> impl Iterator<Item = &'m Movie> + 'a {
+         movies.iter().filter(|movie| self.matches(movie))
+     }
+ }
+ 
+ /// Displays the year, rating, and title of every movie satisfying `expr`.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `expr` - The expression movies must satisfy.
+ /// * `precision` - The number of decimal places ratings are shown with.
+ /// * `limit` - The most movies to list; the footer still counts all of them.
+ pub fn show_expr_matches(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     expr: &Expr,
+     precision: usize,
+     limit: Option<usize>,
+ ) -> io::Result<()> {
+     if expr.apply(movies).next().is_none() {
+         return writeln!(out, "No movies match the expression");
+     }
+     let mut listing = Listing::new(out, "movie", limit);
+     for movie in expr.apply(movies) {
+         listing.row(|out| writeln!(out, "{} {} {}", movie.year, movie.rating_label(precision), movie.title))?;
+     }
+     listing.finish()
+ }
+ 
+ /// The kinds of token in an expression.
+ #[derive(Debug, Clone, PartialEq)]
+ enum TokenKind {
+     /// A field name, keyword, or unquoted value.
+     Word(String),
+     /// A quoted value, without its quotes.
+     Quoted(String),
+     Op(Comparison),
+     Open,
+     Close,
+ }
+ 
+ /// A token and the column it starts at.
+ #[derive(Debug, Clone)]
+ struct Token {
+     kind: TokenKind,
+     column: usize,
+     text: String,
+ }
+ 
+ impl Token {
+     /// Returns the error for a token that is not allowed where it appears.
+     fn unexpected(&self) -> ExprError {
+         ExprError { column: self.column, message: format!("unexpected token '{}'", self.text) }
+     }
+ 
+     /// Returns the token as a keyword, lowercased, if it is an unquoted word.
+     fn keyword(&self) -> Option<String> {
+         match &self.kind {
+             TokenKind::Word(word) => Some(word.to_lowercase()),
+             _ => None,
+         }
+     }
+ }
+ 
+ /// Splits an expression into tokens.
+ fn lex(text: &str) -> Result<Vec<Token>, ExprError> {
+     let chars: Vec<char> = text.chars().collect();
+     let mut tokens = Vec::new();
+     let mut index = 0;
+     while index < chars.len() {
+         let start = index;
+         let c = chars[index];
+         index += 1;
+         let kind = match c {
+             c if c.is_whitespace() => continue,
+             '(' => TokenKind::Open,
+             ')' => TokenKind::Close,
+             '=' => TokenKind::Op(Comparison::Eq),
+             '<' | '>' => {
+                 let or_equal = chars.get(index) == Some(&'=');
+                 if or_equal {
+                     index += 1;
+                 }
+                 TokenKind::Op(match (c, or_equal) {
+                     ('<', false) => Comparison::Lt,
+                     ('<', true) => Comparison::Le,
+                     (_, false) => Comparison::Gt,
+                     (_, true) => Comparison::Ge,
+                 })
+             }
+             '\'' | '"' => {
+                 let length = chars[index..].iter().position(|&end| end == c).ok_or(ExprError {
+                     column: start + 1,
+                     message: "unterminated quote".to_string(),
+                 })?;
+                 let value = chars[index..index + length].iter().collect();
+                 index += length + 1;
+                 TokenKind::Quoted(value)
+             }
+             _ => {
+                 while index < chars.len() && !is_delimiter(chars[index]) {
+                     index += 1;
+                 }
+                 TokenKind::Word(chars[start..index].iter().collect())
+             }
+         };
+         tokens.push(Token { kind, column: start + 1, text: chars[start..index].iter().collect() });
+     }
+     Ok(tokens)
+ }
+ 
+ /// Returns true if `c` ends an unquoted word.
+ fn is_delimiter(c: char) -> bool {
+     c.is_whitespace() || matches!(c, '(' | ')' | '=' | '<' | '>' | '\'' | '"')
+ }
+ 
+ /// A recursive descent parser over the tokens of one expression.
+ struct Parser {
+     tokens: Vec<Token>,
+     position: usize,
+     /// The column just past the end of the expression, for errors at the end.
+     end: usize,
+ }
+ 
+ impl Parser {
+     fn peek(&self) -> Option<&Token> {
+         self.tokens.get(self.position)
+     }
+ 
+     /// Takes the next token, failing at the end of the expression.
+     fn next(&mut self) -> Result<Token, ExprError> {
+         let token = self.tokens.get(self.position).cloned().ok_or(ExprError {
+             column: self.end,
+             message: "unexpected end of expression".to_string(),
+         })?;
+         self.position += 1;
+         Ok(token)
+     }
+ 
+     /// Takes the next token if it is the keyword `keyword`.
+     fn keyword(&mut self, keyword: &str) -> bool {
+         let found = self.peek().and_then(Token::keyword).is_some_and(|word| word == keyword);
+         if found {
+             self.position += 1;
+         }
+         found
+     }
+ 
+     /// or := and ("or" and)*
+     fn or(&mut self) -> Result<Expr, ExprError> {
+         let mut expr = self.and()?;
+         while self.keyword("or") {
+             expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+         }
+         Ok(expr)
+     }
+ 
+     /// and := term ("and" term)*
+     fn and(&mut self) -> Result<Expr, ExprError> {
+         let mut expr = self.term()?;
+         while self.keyword("and") {
+             expr = Expr::And(Box::new(expr), Box::new(self.term()?));
+         }
+         Ok(expr)
+     }
+ 
+     /// term := "(" or ")" | FIELD OP VALUE
+     fn term(&mut self) -> Result<Expr, ExprError> {
+         let token = self.next()?;
+         if token.kind == TokenKind::Open {
+             let expr = self.or()?;
+             let close = self.next()?;
+             if close.kind != TokenKind::Close {
+                 return Err(close.unexpected());
+             }
+             return Ok(expr);
+         }
+         let field = token.keyword().ok_or_else(|| token.unexpected())?;
+         if field == "and" || field == "or" {
+             return Err(token.unexpected());
+         }
+         if !matches!(field.as_str(), "year" | "rating" | "lang" | "language" | "title") {
+             return Err(ExprError {
+                 column: token.column,
+                 message: format!("unknown field '{}'; expected year, rating, lang, or title", token.text),
+             });
+         }
+ 
+         let op_token = self.next()?;
+         let TokenKind::Op(op) = op_token.kind else {
+             return Err(op_token.unexpected());
+         };
+         let value_token = self.next()?;
+         let value = match &value_token.kind {
+             TokenKind::Word(value) | TokenKind::Quoted(value) => value.as_str(),
+             _ => return Err(value_token.unexpected()),
+         };
+         let invalid = |what: &str| ExprError {
+             column: value_token.column,
+             message: format!("invalid {} '{}'", what, value),
+         };
+ 
+         match field.as_str() {
+             "year" => {
+                 let year: i32 = value.parse().map_err(|_| invalid("year"))?;
+                 let years = match op {
+                     Comparison::Eq => year..=year,
+                     Comparison::Lt => i32::MIN..=year.saturating_sub(1),
+                     Comparison::Le => i32::MIN..=year,
+                     Comparison::Gt => year.saturating_add(1)..=i32::MAX,
+                     Comparison::Ge => year..=i32::MAX,
+                 };
+                 Ok(Expr::Filter(MovieFilter::new().years(years)))
+             }
+             "rating" => {
+                 let rating: f32 = value.parse().map_err(|_| invalid("rating"))?;
+                 if !rating.is_finite() {
+                     return Err(invalid("rating"));
+                 }
+                 Ok(Expr::Rating(op, rating))
+             }
+             _ if op != Comparison::Eq => Err(ExprError {
+                 column: op_token.column,
+                 message: format!("{} can only be compared with '='", field),
+             }),
+             "title" => Ok(Expr::Title { text: value.to_string(), fold: true }),
+             _ => Ok(Expr::Filter(MovieFilter::new().language(value))),
+         }
+     }
+ }
+ 
+ #[cfg(test)]
+ mod tests {
+     use super::*;
+     use crate::{read_csv, ParseOptions};
+ 
+     fn and(left: Expr, right: Expr) -> Expr {
+         Expr::And(Box::new(left), Box::new(right))
+     }
+ 
+     fn or(left: Expr, right: Expr) -> Expr {
+         Expr::Or(Box::new(left), Box::new(right))
+     }
+ 
+     fn years(years: std::ops::RangeInclusive<i32>) -> Expr {
+         Expr::Filter(MovieFilter::new().years(years))
+     }
+ 
+     fn lang(language: &str) -> Expr {
+         Expr::Filter(MovieFilter::new().language(language))
+     }
+ 
+     fn title(text: &str) -> Expr {
+         Expr::Title { text: text.to_string(), fold: true }
+     }
+ 
+     #[test]
+     fn test_parse_valid_expressions() {
+         let cases = [
+             ("year=1994", years(1994..=1994)),
+             ("year < 2000", years(i32::MIN..=1999)),
+             ("YEAR<=2000", years(i32::MIN..=2000)),
+             ("year>2000", years(2001..=i32::MAX)),
+             ("rating>=8.5", Expr::Rating(Comparison::Ge, 8.5)),
+             ("lang=English", lang("English")),
+             ("language = 'Old English'", lang("Old English")),
+             ("title=\"Iron Man\"", title("Iron Man")),
+             (
+                 "year>=2000 and rating>8.5 and lang=English",
+                 and(and(years(2000..=i32::MAX), Expr::Rating(Comparison::Gt, 8.5)), lang("English")),
+             ),
+             (
+                 "year=2012 or rating<5 and title=man",
+                 or(years(2012..=2012), and(Expr::Rating(Comparison::Lt, 5.0), title("man"))),
+             ),
+             (
+                 "((lang=French OR lang=German)) AND year>2000",
+                 and(or(lang("French"), lang("German")), years(2001..=i32::MAX)),
+             ),
+         ];
+         for (text, expected) in cases {
+             assert_eq!(Expr::parse(text), Ok(expected), "{}", text);
+         }
+     }
+ 
+     #[test]
+     fn test_parse_invalid_expressions() {
+         let cases = [
+             ("", 1, "unexpected end of expression"),
+             ("year>=", 7, "unexpected end of expression"),
+             ("year>=2000 and", 15, "unexpected end of expression"),
+             ("year>=2000 rating>8", 12, "unexpected token 'rating'"),
+             ("year>=2000 and and", 16, "unexpected token 'and'"),
+             ("year>=2000 and rating>>8", 23, "unexpected token '>'"),
+             ("(year=2000", 11, "unexpected end of expression"),
+             ("year=2000)", 10, "unexpected token ')'"),
+             ("(year=2000 lang=English)", 12, "unexpected token 'lang'"),
+             ("year 2000", 6, "unexpected token '2000'"),
+             ("'year'=2000", 1, "unexpected token ''year''"),
+             ("genre=Drama", 1, "unknown field 'genre'; expected year, rating, lang, or title"),
+             ("year=20x0", 6, "invalid year '20x0'"),
+             ("rating>=high", 9, "invalid rating 'high'"),
+             ("rating>NaN", 8, "invalid rating 'NaN'"),
+             ("lang<English", 5, "lang can only be compared with '='"),
+             ("title='Iron Man", 7, "unterminated quote"),
+         ];
+         for (text, column, message) in cases {
+             let expected = ExprError { column, message: message.to_string() };
+             assert_eq!(Expr::parse(text), Err(expected), "{}", text);
+         }
+         let error = Expr::parse("year>=2000 and x").unwrap_err();
+         assert_eq!(error.to_string(), "unknown field 'x'; expected year, rating, lang, or title at column 16");
+     }
+ 
+     #[test]
+     fn test_expr_matches_movies() {
+         let movies = read_csv("movies_sample_1.csv", &ParseOptions::default()).unwrap();
+         let titles = |text: &str| {
+             let mut expr = Expr::parse(text).unwrap();
+             expr.normalize_languages(&LanguageMap::builtin());
+             expr.apply(&movies).map(|movie| movie.title.as_str()).collect::<Vec<_>>()
+         };
+         assert_eq!(titles("year=2012 and rating>7.5"), ["The Avengers", "Anna Karenina"]);
+         assert_eq!(titles("title=CAPTAIN and year<2014"), ["Captain America: The First Avenger"]);
+         assert_eq!(
+             titles("year=2009 or (year=2018 and lang=en)"),
+             ["Sherlock Holmes", "Avengers: Infinity War", "Mary Queen of Scots"]
+         );
+         assert!(titles("rating>10").is_empty());
+ 
+ 
+         let mut out = Vec::new();
+         show_expr_matches(&mut out, &movies, &Expr::parse("year=2009").unwrap(), 1, None).unwrap();
+         assert_eq!(String::from_utf8(out).unwrap(), "2009 7.6 Sherlock Holmes\n-- 1 movie --\n");
+         let mut out = Vec::new();
+         show_expr_matches(&mut out, &movies, &Expr::parse("year=1901").unwrap(), 1, None).unwrap();
+         assert_eq!(String::from_utf8(out).unwrap(), "No movies match the expression\n");
+     }
+ 
+     #[test]
+     fn test_title_matching_folds_diacritics() {
+         let movie = Movie {
+             title: "Les Misérables".to_string(),
+             year: 2012,
+             languages: Vec::new(),
+             genres: Vec::new(),
+             rating: Some(7.6),
+             invalid_rating: None,
+             runtime: None,
+             critic_rating: None,
+             audience_rating: None,
+         };
+         let mut expr = Expr::parse("title='les miserables'").unwrap();
+         assert!(expr.matches(&movie));
+         assert!(Expr::parse("title=MISÉR").unwrap().matches(&movie));
+         expr.set_fold_diacritics(false);
+         assert!(!expr.matches(&movie));
+         assert!(Expr::parse("title=misér").unwrap().matches(&movie));
+     }
+ }
@@ -0,0 +1,330 @@
+/*
+ * Saved named filters.
+ *
+ * A movie filter can be saved under a name and run again in a later session. The
+ * filters live in `filters.toml` in the configuration directory, which is
+ * $MOVIES_CARGO_CONFIG_DIR when that is set and ~/.config/movies_cargo otherwise.
+ * Each filter is a table named after it:
+ *
+ *     [favorites]
+ *     years = { start = 2000, end = 2009 }
+ *     language = "English"
+ *     min_rating = 8.0
+ *
+ * An entry that cannot be read is reported by name and the others stay usable.
+ */
+
+ use std::collections::BTreeMap;
+ use std::error::Error;
+ use std::fs;
+ use std::io::{self, Write};
+ use std::path::{Path, PathBuf};
+ use crate::{footer, parse_year_range, validate_year_range, MovieFilter, RatingSource};
+ 
+ /// The environment variable that overrides the configuration directory.
+ pub const CONFIG_DIR_VAR: &str = "MOVIES_CARGO_CONFIG_DIR";
+ 
+ /// The name of the saved filters file inside the configuration directory.
+ const FILTERS_FILE: &str = "filters.toml";
+ 
+ /// Returns the path of the saved filters file.
+ ///
+ /// # Errors
+ ///
+ /// Returns a message if neither $MOVIES_CARGO_CONFIG_DIR nor $HOME is set.
+ pub fn filters_path() -> Result<PathBuf, String> {
+     let dir = match std::env::var_os(CONFIG_DIR_VAR) {
+         Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+         _ => match std::env::var_os("HOME") {
+             Some(home) => PathBuf::from(home).join(".config").join("movies_cargo"),
+             None => return Err(format!("Cannot locate the saved filters; set {} or HOME.", CONFIG_DIR_VAR)),
+         },
+     };
+     Ok(dir.join(FILTERS_FILE))
+ }
+ 
+ /// Checks that `name` can name a saved filter: 1 to 40 letters, digits, `-`, or `_`.
+ ///
+ /// # Errors
+ ///
+ /// Returns a message explaining what is wrong with the name.
+ pub fn validate_filter_name(name: &str) -> Result<(), String> {
+     if name.is_empty() || name.chars().count() > 40 {
+         return Err("Filter names must be 1 to 40 characters long.".to_string());
+     }
+     if !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+         return Err(format!("Invalid filter name '{}': use only letters, digits, - and _.", name));
+     }
+     Ok(())
+ }
+ 
+ /// The filters read from a saved filters file.
+ #[derive(Debug, Default)]
+ pub struct SavedFilters {
+     /// The valid filters by name.
+     pub filters: BTreeMap<String, MovieFilter>,
+     /// The names of the entries that could not be read, with the reason for each.
+     pub malformed: BTreeMap<String, String>,
+ }
+ 
+ impl SavedFilters {
+     /// Returns the filter saved as `name`.
+     ///
+     /// # Errors
+     ///
+     /// Returns a message if no filter has the name or its entry is malformed.
+     pub fn get(&self, name: &str) -> Result<&MovieFilter, String> {
+         if let Some(reason) = self.malformed.get(name) {
+             return Err(format!("Saved filter '{}' is malformed: {}", name, reason));
+         }
+         self.filters.get(name).ok_or_else(|| format!("No saved filter named '{}'.", name))
+     }
+ }
+ 
+ /// Reads the text of the filters file at `path` as a TOML table, or an empty table
+ /// if the file does not exist yet.
+ fn read_table(path: &Path) -> Result<toml::Table, Box<dyn Error>> {
+     match fs::read_to_string(path) {
+         Ok(text) => text.parse().map_err(|e| format!("{}: {}", path.display(), e).into()),
+         Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(toml::Table::new()),
+         Err(e) => Err(format!("{}: {}", path.display(), e).into()),
+     }
+ }
+ 
+ /// Converts one entry of the filters file to a filter, checking its values.
+ fn parse_entry(value: toml::Value) -> Result<MovieFilter, String> {
+     let filter: MovieFilter = value.try_into().map_err(|e: toml::de::Error| e.message().to_string())?;
+     if let Some(years) = &filter.years {
+         validate_year_range(*years.start(), *years.end())?;
+     }
+     if filter.min_rating.is_some_and(|rating| !rating.is_finite()) {
+         return Err("min_rating must be a number.".to_string());
+     }
+     if filter.language.as_deref().is_some_and(|language| language.trim().is_empty())
+         || filter.genre.as_deref().is_some_and(|genre| genre.trim().is_empty())
+     {
+         return Err("language and genre must not be empty.".to_string());
+     }
+     Ok(filter)
+ }
+ 
+ /// Builds a filter from criteria written as `KEY=VALUE` words, the form the
+ /// `save-filter` command takes them in: `year=YEAR` or `year=START-END`,
+ /// `lang=NAME`, `genre=NAME`, and `rating=MIN`. A key given twice keeps its last
+ /// value, and no criteria at all make a filter that matches every movie.
+ ///
+ /// # Errors
+ ///
+ /// Returns a message naming the criterion that has an unknown key or an invalid value.
+ pub fn parse_criteria(criteria: &[&str]) -> Result<MovieFilter, String> {
+     let mut filter = MovieFilter::new();
+     for criterion in criteria {
+         let (key, value) = criterion
+             .split_once('=')
+             .ok_or_else(|| format!("Invalid criterion '{}': expected KEY=VALUE.", criterion))?;
+         filter = match key {
+             "year" => filter.years(parse_year_range(value).ok_or_else(|| format!("Invalid year or range '{}'.", value))?),
+             "lang" | "genre" if value.trim().is_empty() => return Err(format!("Missing value for '{}'.", key)),
+             "lang" => filter.language(value.trim()),
+             "genre" => filter.genre(value.trim()),
+             "rating" => match value.parse::<f32>() {
+                 Ok(rating) if rating.is_finite() => filter.min_rating(rating),
+                 _ => return Err(format!("Invalid rating '{}'.", value)),
+             },
+             _ => return Err(format!("Unknown criterion '{}': use year, lang, genre, or rating.", key)),
+         };
+     }
+     Ok(filter)
+ }
+ 
+ /// Loads the saved filters from `path`. A missing file holds no filters.
+ ///
+ /// Each entry is checked on its own, so one with an unknown key, a value of the
+ /// wrong type, or an invalid year range lands in `malformed` under its name
+ /// without affecting the others.
+ ///
+ /// # Errors
+ ///
+ /// Returns an error if the file cannot be read or is not valid TOML.
+ pub fn load_filters(path: &Path) -> Result<SavedFilters, Box<dyn Error>> {
+     let mut saved = SavedFilters::default();
+     for (name, value) in read_table(path)? {
+         let entry = validate_filter_name(&name).and_then(|()| parse_entry(value));
+         match entry {
+             Ok(filter) => {
+                 saved.filters.insert(name, filter);
+             }
+             Err(reason) => {
+                 saved.malformed.insert(name, reason);
+             }
+         }
+     }
+     Ok(saved)
+ }
+ 
+ /// Saves `filter` as `name` in the filters file at `path`, replacing any filter of
+ /// the same name and creating the file and its directory if needed. The other
+ /// entries, including malformed ones, are kept as they are.
+ ///
+ /// # Errors
+ ///
+ /// Returns an error if the name is invalid, the existing file cannot be read or is
+ /// not valid TOML, or the file cannot be written.
+ pub fn save_filter(path: &Path, name: &str, filter: &MovieFilter) -> Result<(), Box<dyn Error>> {
+     validate_filter_name(name)?;
+     let mut table = read_table(path)?;
+     table.insert(name.to_string(), toml::Value::try_from(filter)?);
+     if let Some(dir) = path.parent() {
+         fs::create_dir_all(dir)?;
+     }
+     fs::write(path, toml::to_string(&table)?)?;
+     Ok(())
+ }
+ 
+ /// Describes the criteria of `filter`, such as
+ /// `years 2000-2009, language English, rating >= 8.0`.
+ pub fn describe_filter(filter: &MovieFilter) -> String {
+     let mut criteria = Vec::new();
+     if let Some(years) = &filter.years {
+         if years.start() == years.end() {
+             criteria.push(format!("year {}", years.start()));
+         } else {
+             criteria.push(format!("years {}-{}", years.start(), years.end()));
+         }
+     }
+     if let Some(language) = &filter.language {
+         criteria.push(format!("language {}", language));
+     }
+     if let Some(genre) = &filter.genre {
+         criteria.push(format!("genre {}", genre));
+     }
+     if let Some(rating) = filter.min_rating {
+         match filter.rating_source {
+             RatingSource::Rating => criteria.push(format!("rating >= {:.1}", rating)),
+             source => criteria.push(format!("{} rating >= {:.1}", source.name(), rating)),
+         }
+     }
+     if criteria.is_empty() {
+         return "all movies".to_string();
+     }
+     criteria.join(", ")
+ }
+ 
+ /// Lists each saved filter with its criteria, then each malformed entry with the
+ /// reason it was skipped.
+ pub fn show_saved_filters(out: &mut impl Write, saved: &SavedFilters) -> io::Result<()> {
+     if saved.filters.is_empty() && saved.malformed.is_empty() {
+         return writeln!(out, "No saved filters");
+     }
+     let width = saved.filters.keys().map(|name| name.chars().count()).max().unwrap_or(0);
+     for (name, filter) in &saved.filters {
+         writeln!(out, "{:<width$}  {}", name, describe_filter(filter), width = width)?;
+     }
+     for (name, reason) in &saved.malformed {
+         writeln!(out, "Skipped malformed filter '{}': {}", name, reason)?;
+     }
+     writeln!(out, "{}", footer(saved.filters.len(), "filter"))
+ }
+ 
+ #[cfg(test)]
+ mod tests {
+     use super::*;
+ 
+     #[test]
+     fn test_save_and_load_round_trip() {
+         let dir = tempfile::tempdir().unwrap();
+         let path = dir.path().join("config").join(FILTERS_FILE);
+         let favorites = MovieFilter::new().years(2000..=2009).language("English").min_rating(8.0);
+         let critics = MovieFilter::new().genre("Drama").min_rating(7.5).rating_source(RatingSource::Critic);
+ 
+         save_filter(&path, "favorites", &favorites).unwrap();
+         save_filter(&path, "critics", &critics).unwrap();
+         save_filter(&path, "everything", &MovieFilter::new()).unwrap();
+ 
+         let saved = load_filters(&path).unwrap();
+         assert!(saved.malformed.is_empty());
+         assert_eq!(saved.filters.len(), 3);
+         assert_eq!(saved.get("favorites").unwrap(), &favorites);
+         assert_eq!(saved.get("critics").unwrap(), &critics);
+         assert_eq!(saved.get("everything").unwrap(), &MovieFilter::new());
+ 
+         // Saving under an existing name replaces the filter
+         save_filter(&path, "favorites", &MovieFilter::new().year(1994)).unwrap();
+         assert_eq!(load_filters(&path).unwrap().get("favorites").unwrap(), &MovieFilter::new().year(1994));
+     }
+ 
+     #[test]
+     fn test_missing_file_has_no_filters() {
+         let dir = tempfile::tempdir().unwrap();
+         let saved = load_filters(&dir.path().join(FILTERS_FILE)).unwrap();
+         assert!(saved.filters.is_empty() && saved.malformed.is_empty());
+         assert_eq!(saved.get("favorites").unwrap_err(), "No saved filter named 'favorites'.");
+     }
+ 
+     #[test]
+     fn test_malformed_entries_are_reported_by_name() {
+         let dir = tempfile::tempdir().unwrap();
+         let path = dir.path().join(FILTERS_FILE);
+         fs::write(
+             &path,
+             "not_a_table = 3\n\n\
+              [good]\nlanguage = \"French\"\n\n\
+              [typo]\nlangauge = \"French\"\n\n\
+              [wrong_type]\nmin_rating = \"high\"\n\n\
+              [backwards]\nyears = { start = 2010, end = 2000 }\n",
+         )
+         .unwrap();
+ 
+         let saved = load_filters(&path).unwrap();
+         assert_eq!(saved.filters.keys().collect::<Vec<_>>(), ["good"]);
+         assert_eq!(saved.malformed.keys().collect::<Vec<_>>(), ["backwards", "not_a_table", "typo", "wrong_type"]);
+         assert!(saved.malformed["typo"].contains("langauge"));
+         assert_eq!(saved.malformed["backwards"], "Start year 2010 is after end year 2000.");
+         assert!(saved.get("typo").unwrap_err().starts_with("Saved filter 'typo' is malformed: "));
+ 
+         // Saving another filter keeps the malformed entries for the user to fix
+         save_filter(&path, "more", &MovieFilter::new().year(2001)).unwrap();
+         let saved = load_filters(&path).unwrap();
+         assert_eq!(saved.filters.len(), 2);
+         assert_eq!(saved.malformed.len(), 4);
+     }
+ 
+     #[test]
+     fn test_invalid_toml_fails_wholesale() {
+         let dir = tempfile::tempdir().unwrap();
+         let path = dir.path().join(FILTERS_FILE);
+         fs::write(&path, "[favorites\n").unwrap();
+         assert!(load_filters(&path).is_err());
+         assert!(save_filter(&path, "other", &MovieFilter::new()).is_err());
+     }
+ 
+     #[test]
+     fn test_validate_filter_name() {
+         assert!(validate_filter_name("favorites").is_ok());
+         assert!(validate_filter_name("best-of_2000s").is_ok());
+         assert!(validate_filter_name("").is_err());
+         assert!(validate_filter_name("two words").is_err());
+         assert!(validate_filter_name("a.b").is_err());
+         assert!(validate_filter_name(&"x".repeat(41)).is_err());
+     }
+ 
+     #[test]
+     fn test_show_saved_filters() {
+         let mut saved = SavedFilters::default();
+         saved.filters.insert("all".to_string(), MovieFilter::new());
+         saved.filters.insert(
+             "favorites".to_string(),
+             MovieFilter::new().years(2000..=2009).language("English").min_rating(8.0).rating_source(RatingSource::Max),
+         );
+         saved.malformed.insert("typo".to_string(), "unknown field".to_string());
+         let mut out = Vec::new();
+         show_saved_filters(&mut out, &saved).unwrap();
+         assert_eq!(
+             String::from_utf8(out).unwrap(),
+             "all        all movies\n\
+              favorites  years 2000-2009, language English, max rating >= 8.0\n\
+              Skipped malformed filter 'typo': unknown field\n\
+              -- 2 filters --\n"
+         );
+     }
+ }
@@ -0,0 +1,201 @@
+/*
+ * Fuzzy title search.
+ *
+ * Titles are ranked by normalized Levenshtein similarity to the query, so a typo
+ * such as "Shawshenk" still finds "The Shawshank Redemption". Comparisons ignore
+ * case and runs of whitespace. The normalized titles are computed once when the
+ * index is built, not on every query.
+ */
+
+ use std::collections::LinkedList;
+ use crate::text::fold_diacritics;
+ use crate::Movie;
+ 
+ /// The lowest similarity, from 0.0 to 1.0, a title needs to be reported.
+ pub const MIN_SIMILARITY: f64 = 0.6;
+ /// The most matches a search returns.
+ pub const MAX_MATCHES: usize = 10;
+ 
+ /// Lowercases `text` and collapses every run of whitespace into a single space,
+ /// dropping leading and trailing whitespace.
+ pub fn normalize(text: &str) -> String {
+     text.split_whitespace()
+         .map(str::to_lowercase)
+         .collect::<Vec<_>>()
+         .join(" ")
+ }
+ 
+ /// Counts the single-character insertions, deletions, and substitutions needed
+ /// to turn `a` into `b`.
+ pub fn levenshtein(a: &[char], b: &[char]) -> usize {
+     // Only the previous row of the distance table is needed
+     let mut previous: Vec<usize> = (0..=b.len()).collect();
+     let mut current = vec![0; b.len() + 1];
+     for (i, ca) in a.iter().enumerate() {
+         current[0] = i + 1;
+         for (j, cb) in b.iter().enumerate() {
+             let substitution = previous[j] + usize::from(ca != cb);
+             current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+         }
+         std::mem::swap(&mut previous, &mut current);
+     }
+     previous[b.len()]
+ }
+ 
+ /// Scores how alike two character sequences are, from 0.0 (nothing in common)
+ /// to 1.0 (identical), as one minus the edit distance over the longer length.
+ pub fn similarity(a: &[char], b: &[char]) -> f64 {
+     let longest = a.len().max(b.len());
+     if longest == 0 {
+         return 1.0;
+     }
+     1.0 - levenshtein(a, b) as f64 / longest as f64
+ }
+ 
+ /// Scores a normalized title against a normalized query.
+ ///
+ /// The query is compared with the whole title and with every run of consecutive
+ /// title words as long as the query, keeping the best score, so a query naming
+ /// part of a title is not penalized for the words it leaves out.
+ fn title_score(title: &[char], query: &[char]) -> f64 {
+     let mut best = similarity(title, query);
+ 
+     let query_words = query.split(|c| *c == ' ').count();
+     let words: Vec<&[char]> = title.split(|c| *c == ' ').collect();
+     if query_words < words.len() {
+         for window in words.windows(query_words) {
+             let phrase = window.join(&' ');
+             best = best.max(similarity(&phrase, query));
+         }
+     }
+     best
+ }
+ 
+ /// The normalized titles of a movie collection, in the collection's order.
+ pub struct TitleIndex {
+     titles: Vec<Vec<char>>,
+     fold: bool,
+ }
+ 
+ impl TitleIndex {
+     /// Normalizes every title of `movies` once so searches can reuse them. When
+     /// `fold` is true, diacritics are removed from titles and queries alike.
+     pub fn new(movies: &LinkedList<Movie>, fold: bool) -> Self {
+         let index = TitleIndex { titles: Vec::new(), fold };
+         let titles = movies.iter().map(|movie| index.key(&movie.title)).collect();
+         TitleIndex { titles, ..index }
+     }
+ 
+     /// Returns the normalized characters a title or query is compared by.
+     fn key(&self, text: &str) -> Vec<char> {
+         if self.fold {
+             normalize(&fold_diacritics(text)).chars().collect()
+         } else {
+             normalize(text).chars().collect()
+         }
+     }
+ 
+     /// Finds the titles most similar to `query`.
+     ///
+     /// # Arguments
+     ///
+     /// * `movies` - The linked list of movies the index was built from.
+     /// * `query` - The title to search for; case and extra whitespace are ignored.
+     ///
+     /// # Returns
+     ///
+     /// * `Vec<(&Movie, f64)>` - Up to `MAX_MATCHES` movies scoring at least
+     ///   `MIN_SIMILARITY`, best first, with ties in title order.
+     pub fn search<'a>(&self, movies: &'a LinkedList<Movie>, query: &str) -> Vec<(&'a Movie, f64)> {
+         debug_assert_eq!(self.titles.len(), movies.len(), "index built from another collection");
+ 
+         let query = self.key(query);
+         if query.is_empty() {
+             return Vec::new();
+         }
+ 
+         let mut matches: Vec<(&Movie, f64)> = self
+             .titles
+             .iter()
+             .zip(movies)
+             .filter_map(|(title, movie)| {
+                 let score = title_score(title, &query);
+                 (score >= MIN_SIMILARITY).then_some((movie, score))
+             })
+             .collect();
+         matches.sort_by(|(a, a_score), (b, b_score)| b_score.total_cmp(a_score).then_with(|| a.title.cmp(&b.title)));
+         matches.truncate(MAX_MATCHES);
+         matches
+     }
+ }
+ 
+ #[cfg(test)]
+ mod tests {
+     use super::*;
+ 
+     fn chars(text: &str) -> Vec<char> {
+         text.chars().collect()
+     }
+ 
+     #[test]
+     fn test_levenshtein_known_pairs() {
+         assert_eq!(levenshtein(&chars("kitten"), &chars("sitting")), 3);
+         assert_eq!(levenshtein(&chars("flaw"), &chars("lawn")), 2);
+         assert_eq!(levenshtein(&chars(""), &chars("abc")), 3);
+         assert_eq!(levenshtein(&chars("same"), &chars("same")), 0);
+     }
+ 
+     #[test]
+     fn test_similarity_range() {
+         assert_eq!(similarity(&chars("godfather"), &chars("godfather")), 1.0);
+         assert_eq!(similarity(&chars("abc"), &chars("xyz")), 0.0);
+         let score = similarity(&chars("shawshenk"), &chars("shawshank"));
+         assert!((score - 8.0 / 9.0).abs() < 1e-9);
+     }
+ 
+     #[test]
+     fn test_normalize_case_and_whitespace() {
+         assert_eq!(normalize("  The\tGodFather   Part  II "), "the godfather part ii");
+     }
+ 
+     #[test]
+     fn test_title_score_matches_part_of_title() {
+         let score = title_score(&chars("the shawshank redemption"), &chars("shawshenk"));
+         assert!(score > 0.8, "score was {}", score);
+     }
+ 
+     #[test]
+     fn test_search_ranks_and_filters() {
+         let mut movies = LinkedList::new();
+         for title in ["The Shawshank Redemption", "The Godfather", "Shaft", "Heat", "Les Misérables"] {
+             movies.push_back(Movie {
+                 title: title.to_string(),
+                 year: 2000,
+                 languages: Vec::new(),
+                 genres: Vec::new(),
+                 rating: Some(7.0),
+                 invalid_rating: None,
+                 runtime: None,
+                 critic_rating: None,
+                 audience_rating: None,
+             });
+         }
+         let index = TitleIndex::new(&movies, true);
+ 
+         let matches = index.search(&movies, "  SHAWSHENK ");
+         let titles: Vec<&str> = matches.iter().map(|(movie, _)| movie.title.as_str()).collect();
+         assert_eq!(titles, vec!["The Shawshank Redemption"]);
+ 
+         let matches = index.search(&movies, "the godfather");
+         assert_eq!(matches[0].0.title, "The Godfather");
+         assert_eq!(matches[0].1, 1.0);
+ 
+         assert!(index.search(&movies, "zzzz").is_empty());
+ 
+         let matches = index.search(&movies, "les miserables");
+         assert_eq!((matches[0].0.title.as_str(), matches[0].1), ("Les Misérables", 1.0));
+         let exact = TitleIndex::new(&movies, false);
+         assert!(exact.search(&movies, "les miserables")[0].1 < 1.0);
+         assert!(index.search(&movies, "   ").is_empty());
+     }
+ }
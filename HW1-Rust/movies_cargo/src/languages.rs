@@ -0,0 +1,195 @@
+/*
+ * Language name normalization.
+ *
+ * The same language is often written several ways in the data ("English",
+ * "english", " en ", "EN"). Every name read from the file is trimmed, case-folded,
+ * and looked up in an alias table that maps ISO 639 codes and spellings to one
+ * canonical name. The built-in table covers common languages and can be extended
+ * with a CSV file of `alias,canonical` pairs.
+ */
+
+ use std::collections::BTreeMap;
+ use std::error::Error;
+ use csv::ReaderBuilder;
+ use serde::{Deserialize, Serialize};
+ 
+ /// Canonical language names with their ISO 639-1 and 639-2 codes and other aliases.
+ const BUILTIN_ALIASES: &[(&str, &[&str])] = &[
+     ("Arabic", &["ar", "ara"]),
+     ("Chinese", &["zh", "zho", "chi", "mandarin"]),
+     ("Danish", &["da", "dan"]),
+     ("Dutch", &["nl", "nld", "dut"]),
+     ("English", &["en", "eng"]),
+     ("Finnish", &["fi", "fin"]),
+     ("French", &["fr", "fra", "fre"]),
+     ("German", &["de", "deu", "ger"]),
+     ("Greek", &["el", "ell", "gre"]),
+     ("Hebrew", &["he", "heb"]),
+     ("Hindi", &["hi", "hin"]),
+     ("Italian", &["it", "ita"]),
+     ("Japanese", &["ja", "jpn"]),
+     ("Korean", &["ko", "kor"]),
+     ("Norwegian", &["no", "nor"]),
+     ("Polish", &["pl", "pol"]),
+     ("Portuguese", &["pt", "por"]),
+     ("Russian", &["ru", "rus"]),
+     ("Spanish", &["es", "spa", "castilian"]),
+     ("Swedish", &["sv", "swe"]),
+     ("Turkish", &["tr", "tur"]),
+ ];
+ 
+ /// Case-folds a language name or alias for lookup.
+ fn fold(name: &str) -> String {
+     name.trim().to_lowercase()
+ }
+ 
+ /// Maps language aliases to canonical language names.
+ ///
+ /// Keys are case-folded, so lookups ignore case and surrounding whitespace. Every
+ /// canonical name is also registered as an alias of itself.
+ #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+ pub struct LanguageMap {
+     aliases: BTreeMap<String, String>,
+ }
+ 
+ impl Default for LanguageMap {
+     fn default() -> Self {
+         Self::builtin()
+     }
+ }
+ 
+ impl LanguageMap {
+     /// Creates a map holding only the built-in aliases.
+     pub fn builtin() -> Self {
+         let mut map = LanguageMap { aliases: BTreeMap::new() };
+         for (canonical, aliases) in BUILTIN_ALIASES {
+             for alias in *aliases {
+                 map.insert(alias, canonical).expect("built-in language aliases must not conflict");
+             }
+         }
+         map
+     }
+ 
+     /// Creates a map holding the built-in aliases extended with the `alias,canonical`
+     /// rows of the CSV file at `path`. A first row of `alias,canonical` is treated as
+     /// a header and skipped.
+     ///
+     /// # Errors
+     ///
+     /// Returns an error if the file cannot be read, a row does not have exactly two
+     /// non-empty fields, or an alias is mapped to two different canonical names.
+     pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+         let mut map = Self::builtin();
+         let mut rdr = ReaderBuilder::new().has_headers(false).flexible(true).from_path(path)?;
+ 
+         for (index, result) in rdr.records().enumerate() {
+             let record = result?;
+             let line = index + 1;
+             let (alias, canonical) = match (record.get(0), record.get(1), record.len()) {
+                 (Some(alias), Some(canonical), 2) if !alias.trim().is_empty() && !canonical.trim().is_empty() => {
+                     (alias.trim(), canonical.trim())
+                 }
+                 _ => return Err(format!("line {}: expected 'alias,canonical'", line).into()),
+             };
+             if line == 1 && fold(alias) == "alias" && fold(canonical) == "canonical" {
+                 continue;
+             }
+             map.insert(alias, canonical)
+                 .map_err(|message| format!("line {}: {}", line, message))?;
+         }
+         Ok(map)
+     }
+ 
+     /// Maps `alias` to `canonical`, resolving `canonical` itself through the map
+     /// first so that aliases of aliases end up at the same name.
+     ///
+     /// # Errors
+     ///
+     /// Returns a message if `alias` already maps to a different canonical name.
+     pub fn insert(&mut self, alias: &str, canonical: &str) -> Result<(), String> {
+         let canonical = self.canonical(canonical).to_string();
+         for key in [fold(alias), fold(&canonical)] {
+             match self.aliases.get(&key) {
+                 Some(existing) if *existing != canonical => {
+                     return Err(format!(
+                         "conflicting mappings for language '{}': '{}' and '{}'",
+                         key, existing, canonical
+                     ));
+                 }
+                 Some(_) => {}
+                 None => {
+                     self.aliases.insert(key, canonical.clone());
+                 }
+             }
+         }
+         Ok(())
+     }
+ 
+     /// Returns the canonical name for `name`, or `name` with surrounding whitespace
+     /// trimmed if it is not a known alias.
+     pub fn canonical<'a>(&'a self, name: &'a str) -> &'a str {
+         match self.aliases.get(&fold(name)) {
+             Some(canonical) => canonical,
+             None => name.trim(),
+         }
+     }
+ }
+ 
+ #[cfg(test)]
+ mod tests {
+     use super::*;
+     use std::fs;
+ 
+     /// Writes `contents` to a temporary language map file and loads it.
+     fn load(contents: &str) -> Result<LanguageMap, Box<dyn Error>> {
+         let dir = tempfile::tempdir().unwrap();
+         let path = dir.path().join("languages.csv");
+         fs::write(&path, contents).unwrap();
+         LanguageMap::from_file(path.to_str().unwrap())
+     }
+ 
+     #[test]
+     fn test_builtin_aliases_fold_case_and_whitespace() {
+         let map = LanguageMap::builtin();
+         for name in ["English", "english", " en ", "EN", "eng"] {
+             assert_eq!(map.canonical(name), "English");
+         }
+         assert_eq!(map.canonical("fre"), "French");
+     }
+ 
+     #[test]
+     fn test_unknown_alias_passes_through() {
+         let map = LanguageMap::builtin();
+         assert_eq!(map.canonical(" Klingon "), "Klingon");
+         assert_eq!(map.canonical("tlh"), "tlh");
+     }
+ 
+     #[test]
+     fn test_alias_file_extends_builtin_table() {
+         let map = load("alias,canonical\ntlh,Klingon\nklingonese,klingon\nBritish,en\n").unwrap();
+         assert_eq!(map.canonical("TLH"), "Klingon");
+         assert_eq!(map.canonical("klingon"), "Klingon");
+         assert_eq!(map.canonical("Klingonese"), "Klingon");
+         assert_eq!(map.canonical("british"), "English");
+         assert_eq!(map.canonical("fr"), "French");
+     }
+ 
+     #[test]
+     fn test_conflicting_mappings_are_rejected() {
+         let error = load("tlh,Klingon\nTLH,Vulcan\n").unwrap_err().to_string();
+         assert!(error.contains("line 2"), "{}", error);
+         assert!(error.contains("'Klingon' and 'Vulcan'"), "{}", error);
+ 
+         // Remapping a built-in alias is a conflict too
+         assert!(load("en,French\n").is_err());
+         // Repeating an existing mapping is not
+         assert!(load("en,English\neng,english\n").is_ok());
+     }
+ 
+     #[test]
+     fn test_malformed_alias_file_row() {
+         assert!(load("tlh\n").is_err());
+         assert!(load("tlh,Klingon,extra\n").is_err());
+         assert!(load(",Klingon\n").is_err());
+     }
+ }
@@ -0,0 +1,4036 @@
+/*
+ * Movie data library for the movies_cargo program.
+ *
+ * Parses a CSV file of movies into a linked list of Movie structs and provides the
+ * queries and reports behind the interactive menu and the command-line commands.
+ * The binary in main.rs handles the command line, user input, and the menu itself.
+ * Callers that query by year or language repeatedly can collect the parsed movies
+ * into a collection::MovieCollection, which indexes them.
+ */
+
+ use std::collections::{BTreeMap, BTreeSet, HashSet, LinkedList};
+ use std::error::Error;
+ use std::fs::{self, File};
+ use std::io::{self, BufRead, BufReader, Write};
+ use std::ops::RangeInclusive;
+ use std::path::Path;
+ use std::sync::Arc;
+ use std::time::{Duration, Instant};
+ use clap::ValueEnum;
+ use csv::{ReaderBuilder, StringRecord};
+ use rand::seq::SliceRandom;
+ use rand::Rng;
+ use regex::{Regex, RegexBuilder};
+ use serde::{Deserialize, Serialize};
+ use languages::LanguageMap;
+ 
+ pub mod cache;
+ pub mod collection;
+ pub mod expr;
+ pub mod filters;
+ pub mod fuzzy;
+ pub mod languages;
+ pub mod markdown;
+ pub mod ndjson;
+ pub mod text;
+ #[cfg(feature = "sqlite")]
+ pub mod sqlite;
+ 
+ /// The earliest release year accepted in the data and in year prompts.
+ pub const MIN_YEAR: i32 = 1900;
+ /// The latest release year accepted in the data and in year prompts.
+ pub const MAX_YEAR: i32 = 2021;
+ /// The number of decimal places ratings are shown with by default.
+ pub const RATING_PRECISION: usize = 1;
+ 
+ /// Represents a movie with its relevant details.
+ ///
+ /// Languages are interned through a [`LanguageTable`], so movies that share a
+ /// language share a single allocation of its name. Genres and runtime come from
+ /// optional CSV columns and are empty/`None` when the file does not have them.
+ /// A movie whose rating is missing or invalid is unrated: its `rating` is `None`
+ /// and the original text is kept in `invalid_rating`. Files with both a
+ /// CriticRating and an AudienceRating column also fill `critic_rating` and
+ /// `audience_rating`; see [`RatingSource`]. Serialized languages are written as
+ /// their names.
+ #[derive(Serialize, Deserialize)]
+ pub struct Movie {
+     pub title: String,
+     pub year: i32,
+     pub languages: Vec<Arc<str>>,
+     pub genres: Vec<String>,
+     pub rating: Option<f32>,
+     /// The rating text as it appeared in the file when it could not be parsed.
+     pub invalid_rating: Option<String>,
+     /// Runtime in minutes.
+     pub runtime: Option<u32>,
+     /// The critics' rating, from the CriticRating column.
+     pub critic_rating: Option<f32>,
+     /// The audience's rating, from the AudienceRating column.
+     pub audience_rating: Option<f32>,
+ }
+ 
+ impl Movie {
+     /// Returns true if the movie lists `language` (exact, case-sensitive match).
+     pub fn has_language(&self, language: &str) -> bool {
+         self.languages.iter().any(|lang| &**lang == language)
+     }
+ 
+     /// Returns true if the movie lists `genre` (exact, case-sensitive match).
+     pub fn has_genre(&self, genre: &str) -> bool {
+         self.genres.iter().any(|g| g == genre)
+     }
+ 
+     /// Returns the rating `source` selects.
+     pub fn rating_from(&self, source: RatingSource) -> Option<f32> {
+         match source {
+             RatingSource::Rating => self.rating,
+             RatingSource::Critic => self.critic_rating,
+             RatingSource::Audience => self.audience_rating,
+             RatingSource::Max => match (self.critic_rating, self.audience_rating) {
+                 (Some(critic), Some(audience)) => Some(critic.max(audience)),
+                 (critic, audience) => critic.or(audience),
+             },
+         }
+     }
+ 
+     /// Formats the rating with `precision` decimal places, or `unrated` when it is missing.
+     pub fn rating_label(&self, precision: usize) -> String {
+         self.rating_label_from(RatingSource::Rating, precision)
+     }
+ 
+     /// Formats the rating `source` selects like [`Movie::rating_label`].
+     pub fn rating_label_from(&self, source: RatingSource, precision: usize) -> String {
+         match self.rating_from(source) {
+             Some(rating) => format!("{:.*}", precision, rating),
+             None => "unrated".to_string(),
+         }
+     }
+ }
+ 
+ /// Which rating the highest-rated reports and minimum-rating filters compare.
+ #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+ #[serde(rename_all = "lowercase")]
+ pub enum RatingSource {
+     /// The Rating column, the fourth column of every file.
+     #[default]
+     Rating,
+     /// The CriticRating column.
+     Critic,
+     /// The AudienceRating column.
+     Audience,
+     /// The higher of the critic and audience ratings.
+     Max,
+ }
+ 
+ impl RatingSource {
+     /// Returns the name the source is selected by, such as `critic`.
+     pub fn name(self) -> &'static str {
+         match self {
+             RatingSource::Rating => "rating",
+             RatingSource::Critic => "critic",
+             RatingSource::Audience => "audience",
+             RatingSource::Max => "max",
+         }
+     }
+ 
+     /// Returns the label of a report's rating column, such as `Critic rating`.
+     pub fn heading(self) -> &'static str {
+         match self {
+             RatingSource::Rating => "Rating",
+             RatingSource::Critic => "Critic rating",
+             RatingSource::Audience => "Audience rating",
+             RatingSource::Max => "Max rating",
+         }
+     }
+ 
+     /// Returns the line text reports start with to say which ratings they use, or
+     /// `None` for the default source, which needs no note.
+     pub fn note(self) -> Option<String> {
+         match self {
+             RatingSource::Rating => None,
+             RatingSource::Max => Some("Using the higher of the critic and audience ratings".to_string()),
+             source => Some(format!("Using {} ratings", source.name())),
+         }
+     }
+ }
+ 
+ /// A combination of optional constraints on movies, built up one criterion at a
+ /// time:
+ ///
+ /// ```text
+ /// MovieFilter::new().year(1994).language("English").min_rating(8.0)
+ /// ```
+ ///
+ /// A criterion that is not set places no constraint, so an empty filter matches
+ /// every movie. Languages and genres must match exactly. Unrated movies never
+ /// satisfy a minimum rating, which is compared with the rating `rating_source`
+ /// selects.
+ ///
+ /// Filters are saved by name with serde (see [`filters`]); a criterion missing from
+ /// a saved filter is not set, and unknown keys are rejected.
+ #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+ #[serde(default, deny_unknown_fields)]
+ pub struct MovieFilter {
+     pub years: Option<RangeInclusive<i32>>,
+     pub language: Option<String>,
+     pub genre: Option<String>,
+     pub min_rating: Option<f32>,
+     pub rating_source: RatingSource,
+ }
+ 
+ impl MovieFilter {
+     /// Creates a filter that matches every movie.
+     pub fn new() -> Self {
+         Self::default()
+     }
+ 
+     /// Keeps only movies released in `year`.
+     pub fn year(self, year: i32) -> Self {
+         self.years(year..=year)
+     }
+ 
+     /// Keeps only movies released within the inclusive range `years`.
+     pub fn years(mut self, years: RangeInclusive<i32>) -> Self {
+         self.years = Some(years);
+         self
+     }
+ 
+     /// Keeps only movies listing `language`.
+     pub fn language(mut self, language: impl Into<String>) -> Self {
+         self.language = Some(language.into());
+         self
+     }
+ 
+     /// Keeps only movies listing `genre`.
+     pub fn genre(mut self, genre: impl Into<String>) -> Self {
+         self.genre = Some(genre.into());
+         self
+     }
+ 
+     /// Keeps only movies rated `min_rating` or higher.
+     pub fn min_rating(mut self, min_rating: f32) -> Self {
+         self.min_rating = Some(min_rating);
+         self
+     }
+ 
+     /// Compares the minimum rating with the rating `source` selects.
+     pub fn rating_source(mut self, source: RatingSource) -> Self {
+         self.rating_source = source;
+         self
+     }
+ 
+     /// Replaces the language with its canonical name from `language_map`.
+     pub fn normalize_language(&mut self, language_map: &LanguageMap) {
+         if let Some(language) = &mut self.language {
+             *language = language_map.canonical(language).to_string();
+         }
+     }
+ 
+     /// Returns true if no criterion is set.
+     pub fn is_empty(&self) -> bool {
+         self.years.is_none() && self.language.is_none() && self.genre.is_none() && self.min_rating.is_none()
+     }
+ 
+     /// Returns true if `movie` satisfies every criterion that is set.
+     pub fn matches(&self, movie: &Movie) -> bool {
+         self.years.as_ref().is_none_or(|years| years.contains(&movie.year))
+             && self.language.as_deref().is_none_or(|lang| movie.has_language(lang))
+             && self.genre.as_deref().is_none_or(|genre| movie.has_genre(genre))
+             && self.min_rating.is_none_or(|min| movie.rating_from(self.rating_source).is_some_and(|rating| rating >= min))
+     }
+ 
+     /// Returns the movies that match the filter, in file order.
+     pub fn apply<'a, 'm: 'a>(&'a self, movies: &'m LinkedList<Movie>) -> impl Iterator<Item = &'m Movie> + 'a {
+         movies.iter().filter(|movie| self.matches(movie))
+     }
+ }
+ 
+ /// Interns language names so each distinct language is allocated only once.
+ ///
+ /// A dataset of a million English-language movies would otherwise hold a
+ /// million separate copies of "English"; with the table every movie stores a
+ /// shared `Arc<str>` handle to the same name instead.
+ #[derive(Default)]
+ struct LanguageTable {
+     names: HashSet<Arc<str>>,
+ }
+ 
+ impl LanguageTable {
+     /// Creates an empty language table.
+     fn new() -> Self {
+         Self::default()
+     }
+ 
+     /// Returns the shared handle for `name`, allocating it on first use.
+     fn intern(&mut self, name: &str) -> Arc<str> {
+         if let Some(existing) = self.names.get(name) {
+             return Arc::clone(existing);
+         }
+         let interned: Arc<str> = Arc::from(name);
+         self.names.insert(Arc::clone(&interned));
+         interned
+     }
+ }
+ 
+ /// The entries of a bracketed list field.
+ #[derive(Debug, PartialEq)]
+ struct BracketedList<'a> {
+     entries: Vec<&'a str>,
+     /// True if the list contained both semicolons and commas, in which case it
+     /// was split on the semicolons.
+     mixed_separators: bool,
+ }
+ 
+ /// Parses a list field enclosed in `[]` with entries separated by semicolons,
+ /// such as `[English;French]`, or by commas, such as `[English, French]`.
+ ///
+ /// The accepted grammar, after the csv reader has removed the field's own quoting:
+ ///
+ /// ```text
+ /// list    = ws "[" [ entry { sep entry } ] "]" ws
+ /// sep     = ";" | ","
+ /// entry   = ws ( quoted | bare ) ws
+ /// quoted  = '"' { any character except '"' } '"'
+ /// bare    = { any character except ';', ',', '[', ']', '"' }
+ /// ```
+ ///
+ /// The list is split on semicolons if it has any outside quotes, and on commas
+ /// otherwise; a list with both is split on the semicolons and flagged as mixed.
+ /// Entries are trimmed and empty entries are dropped, so a trailing separator
+ /// (`[English;]`) is allowed and `[]` is an empty list. A quoted entry may contain
+ /// separators and brackets (`["Hindi; Urdu";English]`); its quotes are removed.
+ ///
+ /// # Returns
+ ///
+ /// * `Option<BracketedList>` - The entries, or `None` if the field is not bracketed,
+ ///   has an unquoted bracket inside the list, or has an unterminated quote.
+ fn parse_bracketed_list(field: &str) -> Option<BracketedList<'_>> {
+     let inner = field.trim().strip_prefix('[')?.strip_suffix(']')?;
+     let mut semicolons = Vec::new();
+     let mut commas = Vec::new();
+     let mut in_quotes = false;
+     for (i, c) in inner.char_indices() {
+         match c {
+             '"' => in_quotes = !in_quotes,
+             ';' if !in_quotes => semicolons.push(i),
+             ',' if !in_quotes => commas.push(i),
+             '[' | ']' if !in_quotes => return None,
+             _ => {}
+         }
+     }
+     if in_quotes {
+         return None;
+     }
+ 
+     let mixed_separators = !semicolons.is_empty() && !commas.is_empty();
+     let separators = if semicolons.is_empty() { commas } else { semicolons };
+     let mut entries = Vec::with_capacity(separators.len() + 1);
+     let mut start = 0;
+     for i in separators {
+         entries.push(&inner[start..i]);
+         start = i + 1;
+     }
+     entries.push(&inner[start..]);
+ 
+     let mut list = Vec::with_capacity(entries.len());
+     for entry in entries {
+         let entry = entry.trim();
+         let entry = match entry.strip_prefix('"') {
+             // A quoted entry must be quoted as a whole
+             Some(rest) => rest.strip_suffix('"').filter(|text| !text.contains('"'))?.trim(),
+             None if entry.contains('"') => return None,
+             None => entry,
+         };
+         if !entry.is_empty() {
+             list.push(entry);
+         }
+     }
+     Some(BracketedList { entries: list, mixed_separators })
+ }
+ 
+ /// What [`read_csv`] does with a record whose languages list is empty (`[]`).
+ #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+ pub enum EmptyLanguages {
+     /// Keep the movie with no languages.
+     #[default]
+     Keep,
+     /// Skip the record like any other invalid record.
+     Skip,
+ }
+ 
+ /// How [`read_csv`] reports the records it skips and the other problems it finds.
+ #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+ pub enum SkipReport {
+     /// Human-readable messages on standard output.
+     #[default]
+     Text,
+     /// One JSON [`SkipRecord`] per line on standard error, and no other messages.
+     Ndjson,
+ }
+ 
+ /// Why a record, or the rating of a record, was rejected.
+ ///
+ /// The serialized names are the stable reason codes of the NDJSON skip report.
+ #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+ #[serde(rename_all = "snake_case")]
+ pub enum SkipReason {
+     /// The title is empty.
+     MissingTitle,
+     /// The year is missing, not a number, or outside `MIN_YEAR..=MAX_YEAR`.
+     InvalidYear,
+     /// The languages list is malformed, or empty while empty lists are skipped.
+     InvalidLanguages,
+     /// The languages list has more than five entries.
+     TooManyLanguages,
+     /// A language name is longer than 20 characters.
+     LanguageTooLong,
+     /// The genres list is malformed.
+     InvalidGenres,
+     /// The rating is not a number on the rating scale. The movie is kept as unrated.
+     InvalidRating,
+ }
+ 
+ impl SkipReason {
+     /// Every reason, in declaration order.
+     pub const ALL: [SkipReason; 7] = [
+         SkipReason::MissingTitle,
+         SkipReason::InvalidYear,
+         SkipReason::InvalidLanguages,
+         SkipReason::TooManyLanguages,
+         SkipReason::LanguageTooLong,
+         SkipReason::InvalidGenres,
+         SkipReason::InvalidRating,
+     ];
+ 
+     /// Returns the reason code, the same name the NDJSON skip report uses.
+     pub fn code(self) -> &'static str {
+         match self {
+             SkipReason::MissingTitle => "missing_title",
+             SkipReason::InvalidYear => "invalid_year",
+             SkipReason::InvalidLanguages => "invalid_languages",
+             SkipReason::TooManyLanguages => "too_many_languages",
+             SkipReason::LanguageTooLong => "language_too_long",
+             SkipReason::InvalidGenres => "invalid_genres",
+             SkipReason::InvalidRating => "invalid_rating",
+         }
+     }
+ }
+ 
+ /// One entry of the skip report: the file line, the reason, and the offending text.
+ #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+ pub struct SkipRecord {
+     pub line: usize,
+     pub reason: SkipReason,
+     pub value: String,
+ }
+ 
+ impl SkipRecord {
+     /// Serializes the record as a single line of JSON.
+     pub fn to_json(&self) -> String {
+         serde_json::to_string(self).expect("skip records always serialize")
+     }
+ }
+ 
+ /// Settings that control how [`read_csv`] interprets the file.
+ ///
+ /// The options are stored with the binary cache, so changing any of them causes
+ /// the file to be parsed again.
+ #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+ pub struct ParseOptions {
+     /// The aliases used to normalize language names.
+     pub language_map: LanguageMap,
+     /// How records with an empty languages list are treated.
+     pub empty_languages: EmptyLanguages,
+     /// The range of valid ratings; ratings outside it are marked unrated.
+     pub rating_scale: RatingScale,
+     /// How skipped records and other problems are reported.
+     pub skip_report: SkipReport,
+ }
+ 
+ /// The inclusive range ratings are given on, such as 1 to 10 or 0 to 100.
+ #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+ pub struct RatingScale {
+     pub min: f32,
+     pub max: f32,
+ }
+ 
+ impl Default for RatingScale {
+     fn default() -> Self {
+         RatingScale { min: 1.0, max: 10.0 }
+     }
+ }
+ 
+ impl RatingScale {
+     /// Parses a scale written as `min,max`, such as `0,100`.
+     ///
+     /// # Errors
+     ///
+     /// Returns a message if either bound is not a finite number or `min` is not
+     /// less than `max`.
+     pub fn parse(text: &str) -> Result<Self, String> {
+         let (min, max) = text
+             .split_once(',')
+             .ok_or_else(|| format!("invalid rating scale '{}': expected MIN,MAX", text))?;
+         let bound = |value: &str| match value.trim().parse::<f32>() {
+             Ok(number) if number.is_finite() => Ok(number),
+             _ => Err(format!("invalid rating scale '{}': '{}' is not a number", text, value.trim())),
+         };
+         let (min, max) = (bound(min)?, bound(max)?);
+         if min >= max {
+             return Err(format!("invalid rating scale '{}': the minimum must be less than the maximum", text));
+         }
+         Ok(RatingScale { min, max })
+     }
+ 
+     /// Returns true if `rating` lies within the scale.
+     pub fn contains(&self, rating: f32) -> bool {
+         (self.min..=self.max).contains(&rating)
+     }
+ }
+ 
+ impl std::fmt::Display for RatingScale {
+     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+         write!(f, "{:.1}-{:.1}", self.min, self.max)
+     }
+ }
+ 
+ /// The longest runtime, in minutes, accepted as plausible.
+ const MAX_RUNTIME: u32 = 1000;
+ 
+ /// Returns the index of the header named `name` (case-insensitive), if any.
+ fn find_column(headers: &StringRecord, name: &str) -> Option<usize> {
+     headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name))
+ }
+ 
+ /// Reads and parses the CSV file to create a linked list of Movie structs.
+ ///
+ /// The first four columns are Title, Year, Languages, and Rating. The Genres and
+ /// Runtime columns are optional and located by header name, as are CriticRating and
+ /// AudienceRating, which are only read when the file has both. Without a Genres
+ /// header, a fifth column with an empty header is read as the genres, as in files
+ /// written before the lookup. Genres must use the same bracketed format as the
+ /// Languages column; a runtime that is not a whole number of minutes between 1 and
+ /// `MAX_RUNTIME` is reported and stored as `None`.
+ /// Language names are replaced by their canonical names from the language map in
+ /// `options`, and a language listed twice under different aliases is kept once.
+ /// See `parse_bracketed_list` for the accepted list syntax, and [`parse_record`]
+ /// for the parsing of a single record.
+ ///
+ /// # Arguments
+ ///
+ /// * `filename` - A string slice that holds the name of the CSV file.
+ /// * `options` - The language aliases and other parse settings.
+ ///
+ /// # Returns
+ ///
+ /// * `Result<LinkedList<Movie>, Box<dyn Error>>` - On success, returns a linked list of movies.
+ ///   On failure, returns an error.
+ ///
+ /// # Errors
+ ///
+ /// This function will return an error if the file cannot be opened or if there are issues
+ /// parsing the CSV records.
+ pub fn read_csv(filename: &str, options: &ParseOptions) -> Result<LinkedList<Movie>, Box<dyn Error>> {
+     let mut movies = LinkedList::new();
+     parse_csv(filename, options, print_skip(options), |movie| movies.push_back(movie))?;
+     Ok(movies)
+ }
+ 
+ /// Reads and parses the CSV file like [`read_csv`], but returns the problems found
+ /// instead of printing them, for a caller that prints them elsewhere with
+ /// [`print_problems`], such as a background thread that must not write to stdout.
+ ///
+ /// # Errors
+ ///
+ /// Returns an error if the file cannot be opened or is not well-formed CSV.
+ pub fn read_csv_quietly(
+     filename: &str,
+     options: &ParseOptions,
+ ) -> Result<(LinkedList<Movie>, Vec<RecordProblem>), Box<dyn Error>> {
+     let mut movies = LinkedList::new();
+     let mut problems = Vec::new();
+     let report = |skipped, message| problems.push(RecordProblem { skipped, message });
+     parse_csv(filename, options, report, |movie| movies.push_back(movie))?;
+     Ok((movies, problems))
+ }
+ 
+ /// Prints the problems returned by [`read_csv_quietly`] the way [`read_csv`] does.
+ pub fn print_problems(options: &ParseOptions, problems: Vec<RecordProblem>) {
+     let mut report = print_skip(options);
+     for problem in problems {
+         report(problem.skipped, problem.message);
+     }
+ }
+ 
+ /// Counts and measurements taken while parsing a file with [`read_csv_with_stats`].
+ #[derive(Debug, Clone, PartialEq)]
+ pub struct ParseStats {
+     /// The records read, excluding the header.
+     pub records: usize,
+     /// The movies kept.
+     pub movies: usize,
+     /// The records skipped as invalid.
+     pub skipped: usize,
+     /// The valid ratings that were written with a decimal comma.
+     pub comma_ratings: usize,
+     /// The wall time spent opening and parsing the file.
+     pub elapsed: Duration,
+     /// The estimated memory held by the parsed movies; see [`estimated_size`].
+     pub estimated_bytes: usize,
+ }
+ 
+ impl ParseStats {
+     /// Returns the records parsed per second, or 0 if no time was measured.
+     pub fn rows_per_second(&self) -> f64 {
+         let seconds = self.elapsed.as_secs_f64();
+         if seconds > 0.0 {
+             self.records as f64 / seconds
+         } else {
+             0.0
+         }
+     }
+ }
+ 
+ /// Reads and parses the CSV file like [`read_csv`], also returning how long it took
+ /// and how large the result is.
+ ///
+ /// # Errors
+ ///
+ /// Returns an error if the file cannot be opened or is not well-formed CSV.
+ pub fn read_csv_with_stats(
+     filename: &str,
+     options: &ParseOptions,
+ ) -> Result<(LinkedList<Movie>, ParseStats), Box<dyn Error>> {
+     let start = Instant::now();
+     let mut movies = LinkedList::new();
+     let counts = parse_csv(filename, options, print_skip(options), |movie| movies.push_back(movie))?;
+     let elapsed = start.elapsed();
+     let stats = ParseStats {
+         records: counts.records,
+         movies: movies.len(),
+         skipped: counts.records - movies.len(),
+         comma_ratings: counts.comma_ratings,
+         elapsed,
+         estimated_bytes: estimated_size(&movies),
+     };
+     Ok((movies, stats))
+ }
+ 
+ /// Estimates the bytes `movies` occupies from the size of each list node and the
+ /// capacity of every string and vector it owns. Each distinct interned language
+ /// name is counted once, however many movies share it. Allocator overhead is not
+ /// included.
+ pub fn estimated_size(movies: &LinkedList<Movie>) -> usize {
+     // A linked list node holds the movie and its two links
+     let node = std::mem::size_of::<Movie>() + 2 * std::mem::size_of::<usize>();
+     let mut languages = HashSet::new();
+     let mut bytes = 0;
+     for movie in movies {
+         bytes += node + movie.title.capacity();
+         bytes += movie.languages.capacity() * std::mem::size_of::<Arc<str>>();
+         bytes += movie.genres.capacity() * std::mem::size_of::<String>();
+         bytes += movie.genres.iter().map(String::capacity).sum::<usize>();
+         bytes += movie.invalid_rating.as_ref().map_or(0, String::capacity);
+         for language in &movie.languages {
+             if languages.insert(Arc::as_ptr(language) as *const u8) {
+                 // The shared allocation holds the strong and weak counts before the name
+                 bytes += 2 * std::mem::size_of::<usize>() + language.len();
+             }
+         }
+     }
+     bytes
+ }
+ 
+ /// Returns the skip reporter of [`read_csv`]: messages on stdout, or one JSON
+ /// object per skip record on stderr.
+ fn print_skip(options: &ParseOptions) -> impl FnMut(Option<SkipRecord>, String) + '_ {
+     move |skipped, message| match options.skip_report {
+         SkipReport::Text => println!("{}", message),
+         SkipReport::Ndjson => {
+             if let Some(skipped) = skipped {
+                 eprintln!("{}", skipped.to_json());
+             }
+         }
+     }
+ }
+ 
+ /// Reads a rating written with a comma as its decimal separator, such as `8,7`,
+ /// returning it with a dot instead.
+ ///
+ /// Only digits on both sides of a single comma are accepted. Three digits after the
+ /// comma, as in `1,234`, could be a thousands separator, so that text is rejected.
+ pub fn decimal_comma_rating(text: &str) -> Option<String> {
+     let (whole, fraction) = text.split_once(',')?;
+     let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+     if digits(whole) && digits(fraction) && fraction.len() != 3 {
+         Some(format!("{}.{}", whole, fraction))
+     } else {
+         None
+     }
+ }
+ 
+ /// Where [`parse_record`] finds the optional columns of a record.
+ #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+ pub struct RecordLayout {
+     /// The index of the Genres column, if the file has one.
+     pub genres: Option<usize>,
+     /// The index of the Runtime column, if the file has one.
+     pub runtime: Option<usize>,
+     /// The indexes of the CriticRating and AudienceRating columns, if the file has
+     /// both.
+     pub critic_and_audience: Option<(usize, usize)>,
+ }
+ 
+ impl RecordLayout {
+     /// Locates the optional columns by header name, so they can appear in any order.
+     /// The critic and audience ratings are only read when both columns are present.
+     /// Without a Genres header, a fifth column with an empty header holds the genres.
+     pub fn from_headers(headers: &StringRecord) -> Self {
+         RecordLayout {
+             genres: find_column(headers, "Genres")
+                 .or_else(|| headers.get(4).filter(|header| header.trim().is_empty()).map(|_| 4)),
+             runtime: find_column(headers, "Runtime"),
+             critic_and_audience: find_column(headers, "CriticRating").zip(find_column(headers, "AudienceRating")),
+         }
+     }
+ }
+ 
+ /// A problem found by [`parse_record`]: a message for the reader and, when the
+ /// problem rejects the record or its rating, the entry of the skip report.
+ #[derive(Debug, Clone, PartialEq)]
+ pub struct RecordProblem {
+     pub skipped: Option<SkipRecord>,
+     pub message: String,
+ }
+ 
+ impl RecordProblem {
+     /// Returns the reason of the skip report entry, if the problem has one.
+     pub fn reason(&self) -> Option<SkipReason> {
+         self.skipped.as_ref().map(|skipped| skipped.reason)
+     }
+ }
+ 
+ /// A record accepted by [`parse_record`].
+ pub struct ParsedRecord {
+     /// The movie, with canonical but not yet interned language names.
+     pub movie: Movie,
+     /// The problems that did not reject the record, such as an invalid rating.
+     pub problems: Vec<RecordProblem>,
+     /// True if the rating was valid and written with a decimal comma.
+     pub comma_rating: bool,
+ }
+ 
+ /// Parses one CSV record into a movie, applying every rule described for [`read_csv`].
+ ///
+ /// The function has no side effects, so records can be parsed in any order or on
+ /// any thread; problems are returned rather than printed.
+ ///
+ /// # Arguments
+ ///
+ /// * `record` - The fields of the record.
+ /// * `line` - The file line of the record, used in messages and skip records.
+ /// * `layout` - The positions of the optional columns.
+ /// * `options` - The language aliases and other parse settings.
+ ///
+ /// # Errors
+ ///
+ /// Returns the problem that rejected the record, whose skip record names the
+ /// [`SkipReason`].
+ pub fn parse_record(
+     record: &StringRecord,
+     line: usize,
+     layout: RecordLayout,
+     options: &ParseOptions,
+ ) -> Result<ParsedRecord, RecordProblem> {
+     let problem = |reason, value: &str, message: String| RecordProblem {
+         skipped: Some(SkipRecord { line, reason, value: value.to_string() }),
+         message,
+     };
+     let mut problems = Vec::new();
+ 
+     // Extract fields from the CSV record
+     let title = text::single_line(record.get(0).unwrap_or("")).trim().to_string();
+     let year_str = record.get(1).unwrap_or("").trim();
+     let languages_str = record.get(2).unwrap_or("").trim();
+     let rating_str = record.get(3).unwrap_or("").trim();
+     let genres_str = layout.genres.and_then(|i| record.get(i)).unwrap_or("").trim();
+     let runtime_str = layout.runtime.and_then(|i| record.get(i)).unwrap_or("").trim();
+ 
+     // Validate essential fields
+     if title.is_empty() || year_str.is_empty() {
+         let message = format!("Skipping record at line {} due to missing title or year.", line);
+         return Err(if title.is_empty() {
+             problem(SkipReason::MissingTitle, &title, message)
+         } else {
+             problem(SkipReason::InvalidYear, year_str, message)
+         });
+     }
+ 
+     // Parse year with error handling
+     let year = match year_str.parse::<i32>() {
+         Ok(y) if (MIN_YEAR..=MAX_YEAR).contains(&y) => y,
+         _ => {
+             let message = format!("Invalid year '{}' at line {}. Skipping record.", year_str, line);
+             return Err(problem(SkipReason::InvalidYear, year_str, message));
+         }
+     };
+ 
+     // Parse languages enclosed in [] and separated by semicolons or commas
+     let languages = match parse_bracketed_list(languages_str) {
+         Some(list) => {
+             if list.mixed_separators {
+                 let message = format!("Languages '{}' at line {} mix ';' and ','. Splitting on ';'.", languages_str, line);
+                 problems.push(RecordProblem { skipped: None, message });
+             }
+             list.entries
+         }
+         None => {
+             let message = format!("Invalid languages format '{}' at line {}. Skipping record.", languages_str, line);
+             return Err(problem(SkipReason::InvalidLanguages, languages_str, message));
+         }
+     };
+     if languages.is_empty() && options.empty_languages == EmptyLanguages::Skip {
+         let message = format!("No languages at line {}. Skipping record.", line);
+         return Err(problem(SkipReason::InvalidLanguages, languages_str, message));
+     }
+ 
+     // Enforce maximum number of languages and maximum length per language
+     if languages.len() > 5 {
+         let message = format!("Too many languages at line {}. Skipping record.", line);
+         return Err(problem(SkipReason::TooManyLanguages, languages_str, message));
+     }
+     if let Some(lang) = languages.iter().find(|lang| lang.len() > 20) {
+         let message = format!("Language name too long at line {}. Skipping record.", line);
+         return Err(problem(SkipReason::LanguageTooLong, lang, message));
+     }
+ 
+     // Parse the optional genres column using the same format as languages
+     let genres = if genres_str.is_empty() {
+         Vec::new()
+     } else {
+         match parse_bracketed_list(genres_str) {
+             Some(list) => list.entries.into_iter().map(String::from).collect(),
+             None => {
+                 let message = format!("Invalid genres format '{}' at line {}. Skipping record.", genres_str, line);
+                 return Err(problem(SkipReason::InvalidGenres, genres_str, message));
+             }
+         }
+     };
+ 
+     // Parse rating with error handling, keeping the bad text for the unrated listing.
+     // A decimal comma is read as a dot.
+     let scaled_rating = |text: &str| {
+         let dotted = decimal_comma_rating(text);
+         let rating = dotted.as_deref().unwrap_or(text).parse::<f32>().ok().filter(|&r| options.rating_scale.contains(r));
+         (rating, dotted.is_some())
+     };
+     let (rating, comma_rating) = scaled_rating(rating_str);
+     let (rating, invalid_rating) = match rating {
+         Some(r) => (Some(r), None),
+         None => {
+             let message = format!("Invalid rating '{}' at line {}. Marking as unrated.", rating_str, line);
+             problems.push(problem(SkipReason::InvalidRating, rating_str, message));
+             (None, Some(rating_str.to_string()))
+         }
+     };
+ 
+     // Parse the optional runtime, keeping the record when it is invalid
+     let runtime = if runtime_str.is_empty() {
+         None
+     } else {
+         match runtime_str.parse::<u32>() {
+             Ok(minutes) if (1..=MAX_RUNTIME).contains(&minutes) => Some(minutes),
+             _ => {
+                 let message = format!("Invalid runtime '{}' at line {}. Setting to none.", runtime_str, line);
+                 problems.push(RecordProblem { skipped: None, message });
+                 None
+             }
+         }
+     };
+ 
+     // Parse the critic and audience ratings when the file has both, leaving an
+     // empty or invalid one unset
+     let (mut critic_rating, mut audience_rating) = (None, None);
+     if let Some((critic, audience)) = layout.critic_and_audience {
+         for (column, label, slot) in [(critic, "critic", &mut critic_rating), (audience, "audience", &mut audience_rating)] {
+             let text = record.get(column).unwrap_or("").trim();
+             if text.is_empty() {
+                 continue;
+             }
+             *slot = scaled_rating(text).0;
+             if slot.is_none() {
+                 let message = format!("Invalid {} rating '{}' at line {}. Setting to none.", label, text, line);
+                 problems.push(RecordProblem { skipped: None, message });
+             }
+         }
+     }
+ 
+     // Normalize the validated languages, keeping a language listed under two aliases once
+     let mut canonical_languages: Vec<Arc<str>> = Vec::with_capacity(languages.len());
+     for lang in languages {
+         let canonical = options.language_map.canonical(lang);
+         if !canonical_languages.iter().any(|existing| &**existing == canonical) {
+             canonical_languages.push(Arc::from(canonical));
+         }
+     }
+ 
+     Ok(ParsedRecord {
+         movie: Movie {
+             title,
+             year,
+             languages: canonical_languages,
+             genres,
+             rating,
+             invalid_rating,
+             runtime,
+             critic_rating,
+             audience_rating,
+         },
+         problems,
+         comma_rating: rating.is_some() && comma_rating,
+     })
+ }
+ 
+ /// The UTF-8 byte order mark that Excel writes at the start of a CSV file.
+ const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+ 
+ /// Opens `filename` for reading, skipping a leading UTF-8 byte order mark so the
+ /// first header is read without it. Line endings need no care: the CSV reader
+ /// accepts `\r\n` as well as `\n`, and every field is trimmed.
+ fn open_csv(filename: &str) -> io::Result<BufReader<File>> {
+     let mut reader = BufReader::new(File::open(filename)?);
+     if reader.fill_buf()?.starts_with(UTF8_BOM) {
+         reader.consume(UTF8_BOM.len());
+     }
+     Ok(reader)
+ }
+ 
+ /// What [`parse_csv`] counted while reading a file.
+ struct ParseCounts {
+     /// The records read, including skipped ones.
+     records: usize,
+     /// The valid ratings that were written with a decimal comma.
+     comma_ratings: usize,
+ }
+ 
+ /// Parses the CSV file as described for [`read_csv`], passing every movie parsed
+ /// to `accept` and every problem found to `report` as a human-readable message,
+ /// together with a skip record when the problem rejects a record or its rating.
+ /// Ratings written with a decimal comma are reported once, with their count.
+ fn parse_csv(
+     filename: &str,
+     options: &ParseOptions,
+     mut report: impl FnMut(Option<SkipRecord>, String),
+     mut accept: impl FnMut(Movie),
+ ) -> Result<ParseCounts, Box<dyn Error>> {
+     let file = open_csv(filename)?;
+     let mut rdr = ReaderBuilder::new()
+         .has_headers(true) // Skip the header row
+         .from_reader(file);
+     let mut counts = ParseCounts { records: 0, comma_ratings: 0 };
+     let mut language_table = LanguageTable::new();
+     let layout = RecordLayout::from_headers(rdr.headers()?);
+ 
+     for (index, result) in rdr.records().enumerate() {
+         let record = result?;
+         counts.records += 1;
+         let parsed = match parse_record(&record, index + 2, layout, options) {
+             Ok(parsed) => parsed,
+             Err(problem) => {
+                 report(problem.skipped, problem.message);
+                 continue;
+             }
+         };
+         for problem in parsed.problems {
+             report(problem.skipped, problem.message);
+         }
+         if parsed.comma_rating {
+             counts.comma_ratings += 1;
+         }
+ 
+         // Intern the languages so repeated names share one allocation
+         let mut movie = parsed.movie;
+         movie.languages = movie.languages.iter().map(|language| language_table.intern(language)).collect();
+         accept(movie);
+     }
+ 
+     if counts.comma_ratings > 0 {
+         report(None, format!("Read {} ratings written with a decimal comma, such as '8,7'.", counts.comma_ratings));
+     }
+     Ok(counts)
+ }
+ 
+ /// The most example line numbers [`validate_csv`] keeps for each skip reason.
+ pub const VALIDATION_EXAMPLES: usize = 5;
+ 
+ /// The problems of one kind found by [`validate_csv`].
+ #[derive(Debug, Clone, Default, PartialEq)]
+ pub struct ReasonSummary {
+     /// How many times the problem occurred.
+     pub count: usize,
+     /// The first lines it occurred on, at most `VALIDATION_EXAMPLES` of them.
+     pub examples: Vec<usize>,
+ }
+ 
+ /// The outcome of checking a CSV file with [`validate_csv`].
+ #[derive(Debug, Clone, Default, PartialEq)]
+ pub struct ValidationReport {
+     /// The number of records in the file, excluding the header.
+     pub rows: usize,
+     /// The number of records without any problem.
+     pub valid: usize,
+     /// The problems found, by reason.
+     pub reasons: BTreeMap<SkipReason, ReasonSummary>,
+     /// The valid ratings that were written with a decimal comma, such as `8,7`.
+     pub comma_ratings: usize,
+ }
+ 
+ impl ValidationReport {
+     /// Returns true if every record is valid.
+     pub fn is_clean(&self) -> bool {
+         self.valid == self.rows
+     }
+ }
+ 
+ impl std::fmt::Display for ValidationReport {
+     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+         writeln!(f, "Rows: {}", self.rows)?;
+         writeln!(f, "Valid rows: {}", self.valid)?;
+         if self.comma_ratings > 0 {
+             writeln!(f, "Decimal comma ratings: {}", self.comma_ratings)?;
+         }
+         for (reason, summary) in &self.reasons {
+             let examples: Vec<String> = summary.examples.iter().map(usize::to_string).collect();
+             let more = if summary.count > summary.examples.len() { ", ..." } else { "" };
+             writeln!(f, "  {}: {} (lines {}{})", reason.code(), summary.count, examples.join(", "), more)?;
+         }
+         Ok(())
+     }
+ }
+ 
+ /// Checks every record of a CSV file against the rules [`read_csv`] applies,
+ /// without keeping the parsed movies or printing any messages.
+ ///
+ /// A record with an invalid rating is kept by `read_csv` as unrated, but counts as
+ /// a problem here.
+ ///
+ /// # Errors
+ ///
+ /// Returns an error if the file cannot be opened or is not well-formed CSV.
+ pub fn validate_csv(filename: &str, options: &ParseOptions) -> Result<ValidationReport, Box<dyn Error>> {
+     let mut report = ValidationReport::default();
+     let mut problem_lines = HashSet::new();
+     let record_problem = |skipped: Option<SkipRecord>, _| {
+         if let Some(skipped) = skipped {
+             problem_lines.insert(skipped.line);
+             let summary = report.reasons.entry(skipped.reason).or_default();
+             summary.count += 1;
+             if summary.examples.len() < VALIDATION_EXAMPLES {
+                 summary.examples.push(skipped.line);
+             }
+         }
+     };
+     let counts = parse_csv(filename, options, record_problem, |_| {})?;
+     report.rows = counts.records;
+     report.valid = counts.records - problem_lines.len();
+     report.comma_ratings = counts.comma_ratings;
+     Ok(report)
+ }
+ 
+ /// Writes the rows of a query result followed by a footer counting every row,
+ /// such as `-- 342 movies --`.
+ ///
+ /// All listing queries render through this type so they honor a row limit the
+ /// same way: rows past the limit are counted but not written, and a
+ /// `(+ 297 more)` note stands in for them. The footer always gives the full count.
+ pub struct Listing<'a, W: Write> {
+     out: &'a mut W,
+     noun: &'static str,
+     limit: Option<usize>,
+     rows: usize,
+     notes: Vec<String>,
+ }
+ 
+ impl<'a, W: Write> Listing<'a, W> {
+     /// Starts a listing of `noun`s (singular, such as "movie") written to `out`,
+     /// showing at most `limit` rows.
+     pub fn new(out: &'a mut W, noun: &'static str, limit: Option<usize>) -> Self {
+         Listing { out, noun, limit, rows: 0, notes: Vec::new() }
+     }
+ 
+     /// Returns true if rows written from now on would be left out.
+     pub fn is_full(&self) -> bool {
+         self.limit.is_some_and(|limit| self.rows >= limit)
+     }
+ 
+     /// Writes a line that is not counted as a row, such as a group heading,
+     /// unless the limit has been reached.
+     pub fn heading(&mut self, text: impl std::fmt::Display) -> io::Result<()> {
+         if self.is_full() {
+             return Ok(());
+         }
+         writeln!(self.out, "{}", text)
+     }
+ 
+     /// Counts one row, writing it with `write` unless the limit has been reached.
+     pub fn row(&mut self, write: impl FnOnce(&mut W) -> io::Result<()>) -> io::Result<()> {
+         let full = self.is_full();
+         self.rows += 1;
+         if full {
+             return Ok(());
+         }
+         write(self.out)
+     }
+ 
+     /// Adds a note written after the rows, just before the footer.
+     pub fn note(&mut self, text: String) {
+         self.notes.push(text);
+     }
+ 
+     /// Writes the note about left-out rows, any other notes, and the footer.
+     pub fn finish(self) -> io::Result<()> {
+         if let Some(limit) = self.limit.filter(|limit| self.rows > *limit) {
+             writeln!(self.out, "(+ {} more)", self.rows - limit)?;
+         }
+         for note in &self.notes {
+             writeln!(self.out, "{}", note)?;
+         }
+         writeln!(self.out, "{}", footer(self.rows, self.noun))
+     }
+ }
+ 
+ /// Returns the footer that ends a listing of `rows` `noun`s, such as "-- 12 movies --".
+ pub fn footer(rows: usize, noun: &str) -> String {
+     let plural = if rows == 1 { "" } else { "s" };
+     format!("-- {} {}{} --", rows, noun, plural)
+ }
+ 
+ /// Displays movies released in a specified year.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `year` - The year to filter movies by.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_movies_by_year(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     year: i32,
+     limit: Option<usize>,
+ ) -> io::Result<()> {
+     let filter = MovieFilter::new().year(year);
+     if filter.apply(movies).next().is_none() {
+         return writeln!(out, "No movies found in {}", year);
+     }
+     let mut listing = Listing::new(out, "movie", limit);
+     for movie in filter.apply(movies) {
+         listing.row(|out| writeln!(out, "{}", movie.title))?;
+     }
+     listing.finish()
+ }
+ 
+ /// The order in which per-year reports list their years.
+ #[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+ pub enum YearOrder {
+     /// Oldest year first.
+     #[default]
+     #[value(name = "asc")]
+     Ascending,
+     /// Newest year first.
+     #[value(name = "desc")]
+     Descending,
+ }
+ 
+ impl YearOrder {
+     /// Parses `asc` or `desc`, returning `None` for anything else.
+     pub fn parse(value: &str) -> Option<Self> {
+         match value {
+             "asc" => Some(YearOrder::Ascending),
+             "desc" => Some(YearOrder::Descending),
+             _ => None,
+         }
+     }
+ 
+     /// Flattens a map keyed by year into `(year, value)` pairs in this order.
+     pub fn arrange<T>(self, by_year: BTreeMap<i32, T>) -> Vec<(i32, T)> {
+         match self {
+             YearOrder::Ascending => by_year.into_iter().collect(),
+             YearOrder::Descending => by_year.into_iter().rev().collect(),
+         }
+     }
+ }
+ 
+ /// Groups the movies released within an inclusive range of years by year.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `start` - The first year of the range.
+ /// * `end` - The last year of the range.
+ /// * `order` - Whether the years are listed oldest or newest first.
+ ///
+ /// # Returns
+ ///
+ /// * `Vec<(i32, Vec<&Movie>)>` - Matching movies grouped by year in the requested
+ ///   order, each group in file order. Empty if nothing matches.
+ pub fn movies_in_year_range(movies: &LinkedList<Movie>, start: i32, end: i32, order: YearOrder) -> Vec<(i32, Vec<&Movie>)> {
+     let mut by_year: BTreeMap<i32, Vec<&Movie>> = BTreeMap::new();
+     for movie in MovieFilter::new().years(start..=end).apply(movies) {
+         by_year.entry(movie.year).or_default().push(movie);
+     }
+     order.arrange(by_year)
+ }
+ 
+ /// Displays movies released within a range of years, with a header before each year.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `years` - The inclusive range of years to show.
+ /// * `order` - Whether the years are listed oldest or newest first.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_movies_in_year_range(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     years: RangeInclusive<i32>,
+     order: YearOrder,
+     limit: Option<usize>,
+ ) -> io::Result<()> {
+     let by_year = movies_in_year_range(movies, *years.start(), *years.end(), order);
+     if by_year.is_empty() {
+         writeln!(out, "No movies found between {} and {}", years.start(), years.end())?;
+         return Ok(());
+     }
+     let mut listing = Listing::new(out, "movie", limit);
+     for (year, group) in by_year {
+         listing.heading(format_args!("{}:", year))?;
+         for movie in group {
+             listing.row(|out| writeln!(out, "  {}", movie.title))?;
+         }
+     }
+     listing.finish()
+ }
+ 
+ /// Finds the highest-rated movie for each year.
+ ///
+ /// Movies without a rating from `source` are excluded, so a year whose movies are
+ /// all unrated is absent. In case of ties, the first of the highest-rated movies in
+ /// file order is kept.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `order` - Whether the years are listed oldest or newest first.
+ /// * `source` - The rating that is compared.
+ ///
+ /// # Returns
+ ///
+ /// * `Vec<(i32, &Movie)>` - The highest-rated movie per year, in the requested
+ ///   year order.
+ pub fn highest_rated_per_year(movies: &LinkedList<Movie>, order: YearOrder, source: RatingSource) -> Vec<(i32, &Movie)> {
+     let mut highest_rated: BTreeMap<i32, &Movie> = BTreeMap::new();
+ 
+     for movie in movies {
+         if movie.rating_from(source).is_none() {
+             continue;
+         }
+         highest_rated.entry(movie.year)
+             .and_modify(|existing| {
+                 if movie.rating_from(source) > existing.rating_from(source) {
+                     // Update with the higher-rated movie
+                     *existing = movie;
+                 }
+             })
+             .or_insert(movie);
+     }
+ 
+     order.arrange(highest_rated)
+ }
+ 
+ /// Displays the highest-rated movie for each year.
+ ///
+ /// For each year, finds the movie with the highest rating and displays it.
+ /// In case of ties, the first of the highest-rated movies in file order is
+ /// displayed. A note after the report counts the unrated movies that were left
+ /// out. A report using any rating but the Rating column starts with a line naming
+ /// the source.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `order` - Whether the years are listed oldest or newest first.
+ /// * `source` - The rating that is compared and shown.
+ /// * `precision` - The number of decimal places ratings are shown with.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_highest_rated_movies(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     order: YearOrder,
+     source: RatingSource,
+     precision: usize,
+     limit: Option<usize>,
+ ) -> io::Result<()> {
+     if let Some(note) = source.note() {
+         writeln!(out, "{}", note)?;
+     }
+     let mut listing = Listing::new(out, "movie", limit);
+     for (year, movie) in highest_rated_per_year(movies, order, source) {
+         listing.row(|out| writeln!(out, "{} {} {}", year, movie.rating_label_from(source, precision), movie.title))?;
+     }
+     add_unrated_note(&mut listing, movies, source);
+     listing.finish()
+ }
+ 
+ /// Writes the highest-rated movie for each year as CSV with a `year,rating,title`
+ /// header. Titles containing commas or quotes are quoted by the CSV writer, and
+ /// unrated movies are excluded. For a source other than the Rating column the
+ /// rating column is named after it, such as `critic_rating`.
+ ///
+ /// # Arguments
+ ///
+ /// * `writer` - The destination for the CSV data.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `order` - Whether the years are listed oldest or newest first.
+ /// * `source` - The rating that is compared and written.
+ /// * `precision` - The number of decimal places ratings are written with.
+ pub fn write_highest_rated_csv(
+     writer: impl Write,
+     movies: &LinkedList<Movie>,
+     order: YearOrder,
+     source: RatingSource,
+     precision: usize,
+ ) -> csv::Result<()> {
+     let mut wtr = csv::Writer::from_writer(writer);
+     let rating_column = match source {
+         RatingSource::Rating => "rating".to_string(),
+         source => format!("{}_rating", source.name()),
+     };
+     wtr.write_record(["year", &rating_column, "title"])?;
+     for (year, movie) in highest_rated_per_year(movies, order, source) {
+         wtr.write_record([year.to_string(), movie.rating_label_from(source, precision), movie.title.clone()])?;
+     }
+     wtr.flush()?;
+     Ok(())
+ }
+ 
+ /// Writes the highest-rated CSV report to `destination`, which is either a file
+ /// path or `-` for `out`.
+ pub fn export_highest_rated_csv(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     destination: &str,
+     order: YearOrder,
+     source: RatingSource,
+     precision: usize,
+ ) -> csv::Result<()> {
+     if destination == "-" {
+         write_highest_rated_csv(out, movies, order, source, precision)
+     } else {
+         write_highest_rated_csv(File::create(destination)?, movies, order, source, precision)
+     }
+ }
+ 
+ /// What [`export_titles_by_year`] wrote.
+ #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+ pub struct YearExport {
+     /// The number of `YYYY.txt` files written.
+     pub files: usize,
+     /// The number of titles written across all files.
+     pub lines: usize,
+ }
+ 
+ /// Returns true if `dir` is an existing directory with at least one entry.
+ pub fn directory_has_entries(dir: &Path) -> io::Result<bool> {
+     match fs::read_dir(dir) {
+         Ok(mut entries) => Ok(entries.next().is_some()),
+         Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+         Err(e) => Err(e),
+     }
+ }
+ 
+ /// Writes the titles of each year's movies, one per line in file order, to a
+ /// `YYYY.txt` file in `dir`, the same layout the HW2 program produces.
+ ///
+ /// `dir` and any missing parents are created. Existing year files are replaced.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `dir` - The directory the year files are written to.
+ /// * `force` - Whether to write into a directory that already has entries.
+ ///
+ /// # Errors
+ ///
+ /// Returns an error if `dir` already has entries and `force` is false, or if a
+ /// directory or file cannot be created or written.
+ pub fn export_titles_by_year(movies: &LinkedList<Movie>, dir: &Path, force: bool) -> Result<YearExport, Box<dyn Error>> {
+     if !force && directory_has_entries(dir)? {
+         return Err(format!("Directory {} is not empty; use --force to write into it.", dir.display()).into());
+     }
+     fs::create_dir_all(dir)?;
+ 
+     let mut by_year: BTreeMap<i32, Vec<&str>> = BTreeMap::new();
+     for movie in movies {
+         by_year.entry(movie.year).or_default().push(&movie.title);
+     }
+ 
+     let mut export = YearExport { files: 0, lines: 0 };
+     for (year, titles) in by_year {
+         let mut file = io::BufWriter::new(File::create(dir.join(format!("{}.txt", year)))?);
+         for title in &titles {
+             writeln!(file, "{}", title)?;
+         }
+         file.flush()?;
+         export.files += 1;
+         export.lines += titles.len();
+     }
+     Ok(export)
+ }
+ 
+ /// Finds the lowest-rated movie for each year.
+ ///
+ /// Unrated movies are excluded, so a year that only contains unrated movies
+ /// maps to `None`. Ties are resolved alphabetically by title.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ ///
+ /// # Returns
+ ///
+ /// * `BTreeMap<i32, Option<&Movie>>` - The lowest-rated movie per year, keyed by year
+ ///   in ascending order.
+ pub fn lowest_rated_per_year(movies: &LinkedList<Movie>) -> BTreeMap<i32, Option<&Movie>> {
+     let mut lowest_rated: BTreeMap<i32, Option<&Movie>> = BTreeMap::new();
+ 
+     for movie in movies {
+         let entry = lowest_rated.entry(movie.year).or_insert(None);
+ 
+         // Unrated movies still register the year, but never win it
+         if movie.rating.is_none() {
+             continue;
+         }
+ 
+         let is_lower = match entry {
+             Some(existing) => {
+                 movie.rating < existing.rating
+                     || (movie.rating == existing.rating && movie.title < existing.title)
+             }
+             None => true,
+         };
+         if is_lower {
+             *entry = Some(movie);
+         }
+     }
+ 
+     lowest_rated
+ }
+ 
+ /// Displays the lowest-rated movie for each year.
+ ///
+ /// Uses the same `year rating title` format as the highest-rated report. Years
+ /// whose movies are all unrated are marked "(no rated movies)", and a note after
+ /// the report counts the unrated movies that were left out.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `precision` - The number of decimal places ratings are shown with.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_lowest_rated_movies(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     precision: usize,
+     limit: Option<usize>,
+ ) -> io::Result<()> {
+     let mut listing = Listing::new(out, "year", limit);
+     for (year, movie) in lowest_rated_per_year(movies) {
+         listing.row(|out| match movie {
+             Some(movie) => writeln!(out, "{} {} {}", year, movie.rating_label(precision), movie.title),
+             None => writeln!(out, "{} (no rated movies)", year),
+         })?;
+     }
+     add_unrated_note(&mut listing, movies, RatingSource::Rating);
+     listing.finish()
+ }
+ 
+ /// Finds the highest-rated movie for each language.
+ ///
+ /// A movie counts toward every language it lists. Unrated movies are excluded,
+ /// so a language whose movies are all unrated maps to `None`. Ties are resolved
+ /// alphabetically by title.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ ///
+ /// # Returns
+ ///
+ /// * `BTreeMap<&str, Option<&Movie>>` - The highest-rated movie per canonical
+ ///   language, keyed by language in alphabetical order.
+ pub fn highest_rated_per_language(movies: &LinkedList<Movie>) -> BTreeMap<&str, Option<&Movie>> {
+     let mut highest_rated: BTreeMap<&str, Option<&Movie>> = BTreeMap::new();
+ 
+     for movie in movies {
+         for language in &movie.languages {
+             let entry = highest_rated.entry(&**language).or_insert(None);
+ 
+             // Unrated movies still register the language, but never win it
+             if movie.rating.is_none() {
+                 continue;
+             }
+ 
+             let is_higher = match entry {
+                 Some(existing) => {
+                     movie.rating > existing.rating
+                         || (movie.rating == existing.rating && movie.title < existing.title)
+                 }
+                 None => true,
+             };
+             if is_higher {
+                 *entry = Some(movie);
+             }
+         }
+     }
+ 
+     highest_rated
+ }
+ 
+ /// Displays the highest-rated movie for each language, alphabetically by language.
+ ///
+ /// Each row gives the language followed by the movie in the `year rating title`
+ /// format of the per-year reports. Languages whose movies are all unrated are
+ /// marked "(no rated movies)", and a note after the report counts the unrated
+ /// movies that were left out.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `precision` - The number of decimal places ratings are shown with.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_highest_rated_per_language(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     precision: usize,
+     limit: Option<usize>,
+ ) -> io::Result<()> {
+     let mut listing = Listing::new(out, "language", limit);
+     for (language, movie) in highest_rated_per_language(movies) {
+         listing.row(|out| match movie {
+             Some(movie) => writeln!(out, "{}: {} {} {}", language, movie.year, movie.rating_label(precision), movie.title),
+             None => writeln!(out, "{}: (no rated movies)", language),
+         })?;
+     }
+     add_unrated_note(&mut listing, movies, RatingSource::Rating);
+     listing.finish()
+ }
+ 
+ /// Finds the language listed by the most movies of each year.
+ ///
+ /// A movie counts once toward each distinct language it lists. Movies without
+ /// languages are skipped, so a year whose movies all lack languages is left out.
+ /// Ties are resolved alphabetically by language.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ ///
+ /// # Returns
+ ///
+ /// * `BTreeMap<i32, (String, usize)>` - The most common language per year and the
+ ///   number of that year's movies listing it, keyed by year in ascending order.
+ pub fn dominant_language_by_year(movies: &LinkedList<Movie>) -> BTreeMap<i32, (String, usize)> {
+     let mut counts: BTreeMap<i32, BTreeMap<&str, usize>> = BTreeMap::new();
+     for movie in movies.iter().filter(|movie| !movie.languages.is_empty()) {
+         let languages: BTreeSet<&str> = movie.languages.iter().map(|language| &**language).collect();
+         let year = counts.entry(movie.year).or_default();
+         for language in languages {
+             *year.entry(language).or_insert(0) += 1;
+         }
+     }
+ 
+     counts
+         .into_iter()
+         .filter_map(|(year, languages)| {
+             // Iterating alphabetically and keeping only strictly larger counts lets
+             // the first language win a tie
+             let mut dominant: Option<(&str, usize)> = None;
+             for (language, count) in languages {
+                 if dominant.is_none_or(|(_, best)| count > best) {
+                     dominant = Some((language, count));
+                 }
+             }
+             dominant.map(|(language, count)| (year, (language.to_string(), count)))
+         })
+         .collect()
+ }
+ 
+ /// Displays the most common language of each year, oldest year first, as
+ /// `year: language (count movies)`.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_dominant_languages(out: &mut impl Write, movies: &LinkedList<Movie>, limit: Option<usize>) -> io::Result<()> {
+     let mut listing = Listing::new(out, "year", limit);
+     for (year, (language, count)) in dominant_language_by_year(movies) {
+         let noun = if count == 1 { "movie" } else { "movies" };
+         listing.row(|out| writeln!(out, "{}: {} ({} {})", year, language, count, noun))?;
+     }
+     listing.finish()
+ }
+ 
+ /// Finds the other movies that list at least one of the languages of `movie`.
+ ///
+ /// The results are ordered by the number of shared languages, then by rating, both
+ /// highest first, with unrated movies after rated ones and remaining ties ordered
+ /// by title. `movie` itself is left out; it is recognized by address, so it must
+ /// be an element of `movies`.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `movie` - The movie whose languages are looked for.
+ ///
+ /// # Returns
+ ///
+ /// * `Vec<(usize, &Movie)>` - Each matching movie with the number of distinct
+ ///   languages it shares with `movie`.
+ pub fn movies_sharing_languages<'a>(movies: &'a LinkedList<Movie>, movie: &Movie) -> Vec<(usize, &'a Movie)> {
+     let languages: BTreeSet<&str> = movie.languages.iter().map(|language| &**language).collect();
+     let mut sharing: Vec<(usize, &Movie)> = movies
+         .iter()
+         .filter(|other| !std::ptr::eq(*other, movie))
+         .filter_map(|other| {
+             let other_languages: BTreeSet<&str> = other.languages.iter().map(|language| &**language).collect();
+             let shared = languages.intersection(&other_languages).count();
+             (shared > 0).then_some((shared, other))
+         })
+         .collect();
+ 
+     let rating = |movie: &Movie| movie.rating.unwrap_or(f32::NEG_INFINITY);
+     sharing.sort_by(|(a_shared, a), (b_shared, b)| {
+         b_shared
+             .cmp(a_shared)
+             .then_with(|| rating(b).total_cmp(&rating(a)))
+             .then_with(|| a.title.cmp(&b.title))
+     });
+     sharing
+ }
+ 
+ /// Displays the movies sharing a language with `movie`, in the order of
+ /// [`movies_sharing_languages`], as `year rating title (N shared)`.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `movie` - The movie whose languages are looked for; an element of `movies`.
+ /// * `precision` - The number of decimal places ratings are shown with.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_movies_sharing_languages(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     movie: &Movie,
+     precision: usize,
+     limit: Option<usize>,
+ ) -> io::Result<()> {
+     let mut listing = Listing::new(out, "movie", limit);
+     for (shared, other) in movies_sharing_languages(movies, movie) {
+         listing.row(|out| writeln!(out, "{} {} {} ({} shared)", other.year, other.rating_label(precision), other.title, shared))?;
+     }
+     listing.finish()
+ }
+ 
+ /// Finds the `n` highest-rated movies of the whole collection.
+ ///
+ /// Unrated movies are excluded. Movies with equal ratings are ordered by title.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `n` - The most movies to return.
+ ///
+ /// # Returns
+ ///
+ /// * `Vec<&Movie>` - Up to `n` movies, highest rating first.
+ pub fn top_rated_movies(movies: &LinkedList<Movie>, n: usize) -> Vec<&Movie> {
+     let mut rated: Vec<(&Movie, f32)> = movies.iter().filter_map(|movie| Some((movie, movie.rating?))).collect();
+     rated.sort_by(|(a, a_rating), (b, b_rating)| b_rating.total_cmp(a_rating).then_with(|| a.title.cmp(&b.title)));
+     rated.into_iter().take(n).map(|(movie, _)| movie).collect()
+ }
+ 
+ /// Displays the `n` highest-rated movies of the whole collection in the same
+ /// `year rating title` format as the per-year reports, followed by a note counting
+ /// the unrated movies that were left out.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `n` - The most movies to show.
+ /// * `precision` - The number of decimal places ratings are shown with.
+ pub fn show_top_rated_movies(out: &mut impl Write, movies: &LinkedList<Movie>, n: usize, precision: usize) -> io::Result<()> {
+     let mut listing = Listing::new(out, "movie", None);
+     for movie in top_rated_movies(movies, n) {
+         listing.row(|out| writeln!(out, "{} {} {}", movie.year, movie.rating_label(precision), movie.title))?;
+     }
+     add_unrated_note(&mut listing, movies, RatingSource::Rating);
+     listing.finish()
+ }
+ 
+ /// The movies of one year, summarized for [`compare_years`].
+ pub struct YearSummary<'a> {
+     pub year: i32,
+     /// The number of movies released in the year, rated or not.
+     pub count: usize,
+     /// The mean rating of the rated movies, or `None` if none are rated.
+     pub average_rating: Option<f32>,
+     /// The highest-rated movie; the first in file order wins a tie.
+     pub top: Option<&'a Movie>,
+     /// The languages of the year's movies that no movie of the other year lists,
+     /// or every language of the year when it is not compared with another.
+     pub unique_languages: Vec<&'a str>,
+ }
+ 
+ /// Two years side by side, as returned by [`compare_years`].
+ pub struct YearComparison<'a> {
+     pub first: YearSummary<'a>,
+     /// The second year, or `None` when both years are the same.
+     pub second: Option<YearSummary<'a>>,
+ }
+ 
+ /// Compares the movies released in years `a` and `b`: how many there are, their
+ /// average rating, the highest-rated movie, and the languages only one year has.
+ ///
+ /// A year without movies gives a summary with a count of zero. When `a == b` the
+ /// comparison holds that year alone.
+ pub fn compare_years(movies: &LinkedList<Movie>, a: i32, b: i32) -> YearComparison<'_> {
+     if a == b {
+         return YearComparison { first: summarize_year(movies, a, &BTreeSet::new()), second: None };
+     }
+     YearComparison {
+         first: summarize_year(movies, a, &year_languages(movies, b)),
+         second: Some(summarize_year(movies, b, &year_languages(movies, a))),
+     }
+ }
+ 
+ /// Returns every language listed by a movie released in `year`.
+ fn year_languages(movies: &LinkedList<Movie>, year: i32) -> BTreeSet<&str> {
+     movies
+         .iter()
+         .filter(|movie| movie.year == year)
+         .flat_map(|movie| movie.languages.iter().map(|language| &**language))
+         .collect()
+ }
+ 
+ /// Summarizes the movies of `year`, leaving the languages in `others` out of its
+ /// unique languages.
+ fn summarize_year<'a>(movies: &'a LinkedList<Movie>, year: i32, others: &BTreeSet<&'a str>) -> YearSummary<'a> {
+     let in_year: Vec<&Movie> = movies.iter().filter(|movie| movie.year == year).collect();
+     let ratings: Vec<f32> = in_year.iter().filter_map(|movie| movie.rating).collect();
+     let average_rating = (!ratings.is_empty()).then(|| ratings.iter().sum::<f32>() / ratings.len() as f32);
+     let mut top: Option<&Movie> = None;
+     for movie in in_year.iter().filter(|movie| movie.rating.is_some()) {
+         if top.is_none_or(|best| movie.rating > best.rating) {
+             top = Some(movie);
+         }
+     }
+     YearSummary {
+         year,
+         count: in_year.len(),
+         average_rating,
+         top,
+         unique_languages: year_languages(movies, year).difference(others).copied().collect(),
+     }
+ }
+ 
+ /// Rating statistics for the rated movies of a single year.
+ #[derive(Debug, Clone, Copy, PartialEq)]
+ pub struct RatingStats {
+     pub min: f32,
+     pub median: f32,
+     pub p90: f32,
+     pub max: f32,
+ }
+ 
+ /// Computes the min, median, 90th percentile, and max rating for each year.
+ ///
+ /// Unrated movies are excluded; years without any rated movies are omitted. The median of an even count is the mean of the two
+ /// middle ratings, and the 90th percentile uses the nearest-rank method on the
+ /// sorted ratings of the year.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `order` - Whether the years are listed oldest or newest first.
+ ///
+ /// # Returns
+ ///
+ /// * `Vec<(i32, RatingStats)>` - Statistics per year, in the requested year order.
+ pub fn rating_stats_by_year(movies: &LinkedList<Movie>, order: YearOrder) -> Vec<(i32, RatingStats)> {
+     let mut ratings_by_year: BTreeMap<i32, Vec<f32>> = BTreeMap::new();
+     for movie in movies {
+         if let Some(rating) = movie.rating {
+             ratings_by_year.entry(movie.year).or_default().push(rating);
+         }
+     }
+ 
+     let stats_by_year = ratings_by_year
+         .into_iter()
+         .map(|(year, mut ratings)| {
+             ratings.sort_by(f32::total_cmp);
+             let count = ratings.len();
+             let median = if count % 2 == 0 {
+                 (ratings[count / 2 - 1] + ratings[count / 2]) / 2.0
+             } else {
+                 ratings[count / 2]
+             };
+             // Nearest rank: the smallest value with at least 90% of ratings at or below it
+             let p90_rank = (count * 9).div_ceil(10);
+             let stats = RatingStats {
+                 min: ratings[0],
+                 median,
+                 p90: ratings[p90_rank - 1],
+                 max: ratings[count - 1],
+             };
+             (year, stats)
+         })
+         .collect();
+     order.arrange(stats_by_year)
+ }
+ 
+ /// Displays rating statistics for each year in aligned columns, followed by a
+ /// note counting the unrated movies that were left out.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `order` - Whether the years are listed oldest or newest first.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_rating_stats(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     order: YearOrder,
+     limit: Option<usize>,
+ ) -> io::Result<()> {
+     writeln!(out, "{:<6} {:>6} {:>6} {:>6} {:>6}", "Year", "Min", "Median", "P90", "Max")?;
+     let mut listing = Listing::new(out, "year", limit);
+     for (year, stats) in rating_stats_by_year(movies, order) {
+         listing.row(|out| {
+             writeln!(out, 
+                 "{:<6} {:>6.1} {:>6.2} {:>6.1} {:>6.1}",
+                 year, stats.min, stats.median, stats.p90, stats.max
+             )
+         })?;
+     }
+     add_unrated_note(&mut listing, movies, RatingSource::Rating);
+     listing.finish()
+ }
+ 
+ /// Finds the movies whose rating was missing or invalid in the file.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ ///
+ /// # Returns
+ ///
+ /// * `Vec<&Movie>` - The unrated movies in file order.
+ pub fn unrated_movies(movies: &LinkedList<Movie>) -> Vec<&Movie> {
+     movies.iter().filter(|movie| movie.rating.is_none()).collect()
+ }
+ 
+ /// Adds a note counting the movies a rating-based report excluded because
+ /// `source` gives them no rating. Nothing is added when every movie is rated.
+ fn add_unrated_note(listing: &mut Listing<impl Write>, movies: &LinkedList<Movie>, source: RatingSource) {
+     let count = movies.iter().filter(|movie| movie.rating_from(source).is_none()).count();
+     if count > 0 {
+         listing.note(format!("({} unrated movies not included)", count));
+     }
+ }
+ 
+ /// Displays every unrated movie with the rating text that failed to parse.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_unrated_movies(out: &mut impl Write, movies: &LinkedList<Movie>, limit: Option<usize>) -> io::Result<()> {
+     let unrated = unrated_movies(movies);
+     if unrated.is_empty() {
+         writeln!(out, "All movies have a valid rating.")?;
+         return Ok(());
+     }
+     let mut listing = Listing::new(out, "movie", limit);
+     for movie in unrated {
+         listing.row(|out| match movie.invalid_rating.as_deref() {
+             Some(text) if !text.is_empty() => writeln!(out, "{} {} (rating '{}')", movie.year, movie.title, text),
+             _ => writeln!(out, "{} {} (rating missing)", movie.year, movie.title),
+         })?;
+     }
+     listing.finish()
+ }
+ 
+ /// The widest bar, in columns, drawn by the movies-per-year histogram.
+ const HISTOGRAM_WIDTH: usize = 60;
+ 
+ /// Counts how many movies were released in each year.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ ///
+ /// # Returns
+ ///
+ /// * `BTreeMap<i32, usize>` - Movie counts keyed by year in ascending order. Years
+ ///   without movies are absent.
+ pub fn count_by_year(movies: &LinkedList<Movie>) -> BTreeMap<i32, usize> {
+     let mut counts = BTreeMap::new();
+     for movie in movies {
+         *counts.entry(movie.year).or_insert(0) += 1;
+     }
+     counts
+ }
+ 
+ /// Scales `count` to a bar width so that `max_count` fills `HISTOGRAM_WIDTH`
+ /// columns. Any non-zero count gets at least one column so it stays visible.
+ fn histogram_bar_width(count: usize, max_count: usize) -> usize {
+     if count == 0 || max_count == 0 {
+         return 0;
+     }
+     (count * HISTOGRAM_WIDTH / max_count).max(1)
+ }
+ 
+ /// Displays an ASCII histogram of the number of movies released each year.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `order` - Whether the years are listed oldest or newest first.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_year_histogram(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     order: YearOrder,
+     limit: Option<usize>,
+ ) -> io::Result<()> {
+     let counts = count_by_year(movies);
+     let max_count = counts.values().copied().max().unwrap_or(0);
+     let mut listing = Listing::new(out, "year", limit);
+     for (year, count) in order.arrange(counts) {
+         let bar = "#".repeat(histogram_bar_width(count, max_count));
+         listing.row(|out| writeln!(out, "{} {:>5} {}", year, count, bar))?;
+     }
+     listing.finish()
+ }
+ 
+ /// The fewest rated movies a year needs to be considered by
+ /// [`year_with_highest_average`].
+ pub const SUPERLATIVE_MIN_RATED: usize = 3;
+ 
+ /// Finds the year with the most movies, the earliest one on a tie.
+ ///
+ /// # Returns
+ ///
+ /// * `Option<(i32, usize)>` - The year and its number of movies, or `None` if there
+ ///   are no movies.
+ pub fn year_with_most_movies(movies: &LinkedList<Movie>) -> Option<(i32, usize)> {
+     // Years come oldest first, and only a strictly larger count replaces the best
+     count_by_year(movies)
+         .into_iter()
+         .fold(None, |best, (year, count)| match best {
+             Some((_, best_count)) if best_count >= count => best,
+             _ => Some((year, count)),
+         })
+ }
+ 
+ /// Finds the year whose rated movies have the highest average rating, the earliest
+ /// one on a tie. Years with fewer than `min_rated` rated movies are not considered.
+ ///
+ /// # Returns
+ ///
+ /// * `Option<(i32, f32)>` - The year and its average rating, or `None` if no year
+ ///   has enough rated movies.
+ pub fn year_with_highest_average(movies: &LinkedList<Movie>, min_rated: usize) -> Option<(i32, f32)> {
+     let mut ratings: BTreeMap<i32, Vec<f32>> = BTreeMap::new();
+     for movie in movies {
+         if let Some(rating) = movie.rating {
+             ratings.entry(movie.year).or_default().push(rating);
+         }
+     }
+     ratings
+         .into_iter()
+         .filter(|(_, ratings)| ratings.len() >= min_rated)
+         .map(|(year, ratings)| (year, ratings.iter().sum::<f32>() / ratings.len() as f32))
+         .fold(None, |best, (year, average)| match best {
+             Some((_, best_average)) if best_average >= average => best,
+             _ => Some((year, average)),
+         })
+ }
+ 
+ /// Returns the movie with the largest `key`, with ties going to the alphabetically
+ /// first title.
+ fn best_movie_by<K: Ord>(movies: &LinkedList<Movie>, key: impl Fn(&Movie) -> K) -> Option<&Movie> {
+     movies.iter().min_by(|a, b| key(b).cmp(&key(a)).then_with(|| a.title.cmp(&b.title)))
+ }
+ 
+ /// Finds the movie listing the most languages, the alphabetically first title on
+ /// a tie.
+ ///
+ /// # Returns
+ ///
+ /// * `Option<&Movie>` - The movie, or `None` if there are no movies.
+ pub fn most_multilingual_movie(movies: &LinkedList<Movie>) -> Option<&Movie> {
+     best_movie_by(movies, |movie| movie.languages.len())
+ }
+ 
+ /// Finds the movie with the longest title in characters, the alphabetically first
+ /// title on a tie.
+ ///
+ /// # Returns
+ ///
+ /// * `Option<&Movie>` - The movie, or `None` if there are no movies.
+ pub fn longest_title(movies: &LinkedList<Movie>) -> Option<&Movie> {
+     best_movie_by(movies, |movie| movie.title.chars().count())
+ }
+ 
+ /// Displays the superlatives of the collection, one per line: the year with the
+ /// most movies, the year with the highest average rating among years with at
+ /// least `SUPERLATIVE_MIN_RATED` rated movies, the movie with the most languages,
+ /// and the longest title. A superlative with no answer is shown as `none`.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `precision` - The number of decimal places ratings are shown with.
+ pub fn show_superlatives(out: &mut impl Write, movies: &LinkedList<Movie>, precision: usize) -> io::Result<()> {
+     let plural = |count: usize, noun: &str| format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" });
+ 
+     match year_with_most_movies(movies) {
+         Some((year, count)) => writeln!(out, "Most releases: {} ({})", year, plural(count, "movie"))?,
+         None => writeln!(out, "Most releases: none")?,
+     }
+     match year_with_highest_average(movies, SUPERLATIVE_MIN_RATED) {
+         Some((year, average)) => writeln!(out, "Highest average rating: {} ({:.*})", year, precision, average)?,
+         None => writeln!(
+             out,
+             "Highest average rating: none (no year has {} rated movies)",
+             SUPERLATIVE_MIN_RATED
+         )?,
+     }
+     match most_multilingual_movie(movies) {
+         Some(movie) => {
+             let languages = plural(movie.languages.len(), "language");
+             writeln!(out, "Most languages: {} ({}), {}", movie.title, movie.year, languages)?
+         }
+         None => writeln!(out, "Most languages: none")?,
+     }
+     match longest_title(movies) {
+         Some(movie) => {
+             let length = plural(movie.title.chars().count(), "character");
+             writeln!(out, "Longest title: {} ({}), {}", movie.title, movie.year, length)?
+         }
+         None => writeln!(out, "Longest title: none")?,
+     }
+     Ok(())
+ }
+ 
+ /// Sorts movies by year and then by title, both ascending, so a listing does not
+ /// depend on the order of the rows in the file. Movies with the same year and title
+ /// keep their file order.
+ fn sort_by_year_and_title(movies: &mut [&Movie]) {
+     movies.sort_by(|a, b| a.year.cmp(&b.year).then_with(|| a.title.cmp(&b.title)));
+ }
+ 
+ /// Finds the movies listing `language`, sorted by year and then by title.
+ ///
+ /// Only exact case-sensitive matches are considered, so `language` should be a
+ /// canonical name from the [`LanguageMap`] the movies were parsed with.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `language` - The language to filter movies by.
+ ///
+ /// # Returns
+ ///
+ /// * `Vec<&Movie>` - The matching movies, oldest first.
+ pub fn movies_by_language<'a>(movies: &'a LinkedList<Movie>, language: &str) -> Vec<&'a Movie> {
+     let mut matches: Vec<&Movie> = MovieFilter::new().language(language).apply(movies).collect();
+     sort_by_year_and_title(&mut matches);
+     matches
+ }
+ 
+ /// Displays movies and their release years for a specified language, in the
+ /// order of [`movies_by_language`].
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `language` - The language to filter movies by.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_movies_by_language(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     language: &str,
+     limit: Option<usize>,
+ ) -> io::Result<()> {
+     let matches = movies_by_language(movies, language);
+     if matches.is_empty() {
+         return writeln!(out, "No movies found in {}", language);
+     }
+     let mut listing = Listing::new(out, "movie", limit);
+     for movie in matches {
+         listing.row(|out| writeln!(out, "{} {}", movie.year, movie.title))?;
+     }
+     listing.finish()
+ }
+ 
+ /// Displays movies and their release years for a specified genre.
+ ///
+ /// Only exact case-sensitive matches are considered.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `genre` - The genre to filter movies by.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_movies_by_genre(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     genre: &str,
+     limit: Option<usize>,
+ ) -> io::Result<()> {
+     let filter = MovieFilter::new().genre(genre);
+     if filter.apply(movies).next().is_none() {
+         return writeln!(out, "No movies found in {}", genre);
+     }
+     let mut listing = Listing::new(out, "movie", limit);
+     for movie in filter.apply(movies) {
+         listing.row(|out| writeln!(out, "{} {}", movie.year, movie.title))?;
+     }
+     listing.finish()
+ }
+ 
+ /// Displays the release year, rating, and title of every movie matching `filter`.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `filter` - The criteria a movie must satisfy; an empty filter lists every movie.
+ /// * `precision` - The number of decimal places ratings are shown with.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_filtered_movies(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     filter: &MovieFilter,
+     precision: usize,
+     limit: Option<usize>,
+ ) -> io::Result<()> {
+     if filter.apply(movies).next().is_none() {
+         return writeln!(out, "No movies match the filter");
+     }
+     let mut listing = Listing::new(out, "movie", limit);
+     for movie in filter.apply(movies) {
+         let rating = movie.rating_label_from(filter.rating_source, precision);
+         listing.row(|out| writeln!(out, "{} {} {}", movie.year, rating, movie.title))?;
+     }
+     listing.finish()
+ }
+ 
+ /// The most memory, in bytes, a compiled title pattern may use. Patterns such as
+ /// `(a{1000}){1000}` that would grow past it are rejected instead of compiled.
+ pub const TITLE_PATTERN_SIZE_LIMIT: usize = 1 << 20;
+ 
+ /// Compiles a regular expression for searching titles.
+ ///
+ /// Matching ignores case unless the pattern turns that off with `(?-i)`.
+ ///
+ /// # Errors
+ ///
+ /// Returns the regex error if the pattern is invalid or its compiled form would
+ /// exceed `TITLE_PATTERN_SIZE_LIMIT`.
+ pub fn compile_title_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+     RegexBuilder::new(pattern)
+         .case_insensitive(true)
+         .size_limit(TITLE_PATTERN_SIZE_LIMIT)
+         .build()
+ }
+ 
+ /// Finds the movies whose title matches `pattern` anywhere, sorted by year and
+ /// then by title.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `pattern` - A pattern from `compile_title_pattern`.
+ ///
+ /// # Returns
+ ///
+ /// * `Vec<&Movie>` - The matching movies, oldest first.
+ pub fn title_matches<'a>(movies: &'a LinkedList<Movie>, pattern: &Regex) -> Vec<&'a Movie> {
+     let mut matches: Vec<&Movie> = movies.iter().filter(|movie| pattern.is_match(&movie.title)).collect();
+     sort_by_year_and_title(&mut matches);
+     matches
+ }
+ 
+ /// Displays the release year, rating, and title of every movie whose title
+ /// matches `pattern` anywhere, in the order of [`title_matches`]; anchor the
+ /// pattern with `^` and `$` to match whole titles.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `pattern` - A pattern from `compile_title_pattern`.
+ /// * `precision` - The number of decimal places ratings are shown with.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_title_matches(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     pattern: &Regex,
+     precision: usize,
+     limit: Option<usize>,
+ ) -> io::Result<()> {
+     let matches = title_matches(movies, pattern);
+     if matches.is_empty() {
+         return writeln!(out, "No titles match '{}'", pattern);
+     }
+     let mut listing = Listing::new(out, "movie", limit);
+     for movie in matches {
+         listing.row(|out| writeln!(out, "{} {} {}", movie.year, movie.rating_label(precision), movie.title))?;
+     }
+     listing.finish()
+ }
+ 
+ /// Counts the movies listing each distinct genre.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ ///
+ /// # Returns
+ ///
+ /// * `BTreeMap<&str, usize>` - Movie counts keyed by genre in alphabetical order.
+ pub fn distinct_genres(movies: &LinkedList<Movie>) -> BTreeMap<&str, usize> {
+     let mut genres = BTreeMap::new();
+     for movie in movies {
+         for genre in &movie.genres {
+             *genres.entry(genre.as_str()).or_insert(0) += 1;
+         }
+     }
+     genres
+ }
+ 
+ /// Displays every distinct genre with the number of movies listing it.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_distinct_genres(out: &mut impl Write, movies: &LinkedList<Movie>, limit: Option<usize>) -> io::Result<()> {
+     let genres = distinct_genres(movies);
+     if genres.is_empty() {
+         writeln!(out, "No genres found in the data")?;
+         return Ok(());
+     }
+     let mut listing = Listing::new(out, "genre", limit);
+     for (genre, count) in genres {
+         listing.row(|out| writeln!(out, "{} {}", genre, count))?;
+     }
+     listing.finish()
+ }
+ 
+ /// Counts the movies listing each distinct language.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ ///
+ /// # Returns
+ ///
+ /// * `BTreeMap<&str, usize>` - Movie counts keyed by canonical language name in
+ ///   alphabetical order.
+ pub fn distinct_languages(movies: &LinkedList<Movie>) -> BTreeMap<&str, usize> {
+     let mut languages = BTreeMap::new();
+     for movie in movies {
+         for language in &movie.languages {
+             *languages.entry(&**language).or_insert(0) += 1;
+         }
+     }
+     languages
+ }
+ 
+ /// Displays every distinct language with the number of movies listing it.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_distinct_languages(out: &mut impl Write, movies: &LinkedList<Movie>, limit: Option<usize>) -> io::Result<()> {
+     let languages = distinct_languages(movies);
+     if languages.is_empty() {
+         writeln!(out, "No languages found in the data")?;
+         return Ok(());
+     }
+     let mut listing = Listing::new(out, "language", limit);
+     for (language, count) in languages {
+         listing.row(|out| writeln!(out, "{} {}", language, count))?;
+     }
+     listing.finish()
+ }
+ 
+ /// Finds the `n` longest and `n` shortest movies among those with a known runtime.
+ ///
+ /// Ties are resolved alphabetically by title.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `n` - The number of movies to return in each list.
+ ///
+ /// # Returns
+ ///
+ /// * `(Vec<&Movie>, Vec<&Movie>)` - The longest movies (longest first) and the
+ ///   shortest movies (shortest first).
+ pub fn longest_and_shortest(movies: &LinkedList<Movie>, n: usize) -> (Vec<&Movie>, Vec<&Movie>) {
+     let mut timed: Vec<(u32, &Movie)> = movies
+         .iter()
+         .filter_map(|movie| movie.runtime.map(|minutes| (minutes, movie)))
+         .collect();
+ 
+     timed.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.title.cmp(&b.1.title)));
+     let shortest = timed.iter().take(n).map(|(_, movie)| *movie).collect();
+ 
+     timed.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.title.cmp(&b.1.title)));
+     let longest = timed.iter().take(n).map(|(_, movie)| *movie).collect();
+ 
+     (longest, shortest)
+ }
+ 
+ /// Computes the average runtime of each year's movies with a known runtime.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ ///
+ /// # Returns
+ ///
+ /// * `BTreeMap<i32, f64>` - Average minutes keyed by year in ascending order.
+ ///   Years without any known runtime are omitted.
+ pub fn average_runtime_by_year(movies: &LinkedList<Movie>) -> BTreeMap<i32, f64> {
+     let mut totals: BTreeMap<i32, (u64, u64)> = BTreeMap::new();
+     for movie in movies {
+         if let Some(minutes) = movie.runtime {
+             let (sum, count) = totals.entry(movie.year).or_insert((0, 0));
+             *sum += u64::from(minutes);
+             *count += 1;
+         }
+     }
+     totals
+         .into_iter()
+         .map(|(year, (sum, count))| (year, sum as f64 / count as f64))
+         .collect()
+ }
+ 
+ /// Displays the `n` longest and shortest movies and the average runtime per year.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `n` - The number of movies to list as longest and as shortest.
+ pub fn show_runtime_report(out: &mut impl Write, movies: &LinkedList<Movie>, n: usize) -> io::Result<()> {
+     let (longest, shortest) = longest_and_shortest(movies, n);
+     if longest.is_empty() {
+         writeln!(out, "No movies with a known runtime")?;
+         return Ok(());
+     }
+ 
+     writeln!(out, "Longest movies:")?;
+     for movie in longest {
+         writeln!(out, "{:>5} min {} {}", movie.runtime.unwrap_or(0), movie.year, movie.title)?;
+     }
+     writeln!(out, "Shortest movies:")?;
+     for movie in shortest {
+         writeln!(out, "{:>5} min {} {}", movie.runtime.unwrap_or(0), movie.year, movie.title)?;
+     }
+     writeln!(out, "Average runtime per year:")?;
+     for (year, average) in average_runtime_by_year(movies) {
+         writeln!(out, "{} {:.1} min", year, average)?;
+     }
+     Ok(())
+ }
+ 
+ /// Picks one movie uniformly at random from those matching `filter`.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `filter` - The constraints a candidate movie must satisfy.
+ /// * `rng` - The random number generator used for the selection.
+ ///
+ /// # Returns
+ ///
+ /// * `Option<&Movie>` - The chosen movie, or `None` if no movie matches the filters.
+ pub fn pick_random_movie<'a, R: Rng>(
+     movies: &'a LinkedList<Movie>,
+     filter: &MovieFilter,
+     rng: &mut R,
+ ) -> Option<&'a Movie> {
+     let candidates: Vec<&Movie> = filter.apply(movies).collect();
+     candidates.choose(rng).copied()
+ }
+ 
+ /// Displays every known detail of a movie, one field per line.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movie` - The movie to display.
+ /// * `precision` - The number of decimal places the rating is shown with.
+ pub fn print_movie_details(out: &mut impl Write, movie: &Movie, precision: usize) -> io::Result<()> {
+     let languages: Vec<&str> = movie.languages.iter().map(|lang| &**lang).collect();
+     writeln!(out, "Title: {}", movie.title)?;
+     writeln!(out, "Year: {}", movie.year)?;
+     match (movie.rating, movie.invalid_rating.as_deref()) {
+         (Some(rating), _) => writeln!(out, "Rating: {:.*}", precision, rating)?,
+         (None, Some(text)) if !text.is_empty() => writeln!(out, "Rating: unrated (invalid value '{}')", text)?,
+         (None, _) => writeln!(out, "Rating: unrated (missing)")?,
+     }
+     for (label, rating) in [("Critic rating", movie.critic_rating), ("Audience rating", movie.audience_rating)] {
+         if let Some(rating) = rating {
+             writeln!(out, "{}: {:.*}", label, precision, rating)?;
+         }
+     }
+     writeln!(out, "Languages: {}", languages.join(", "))?;
+     Ok(())
+ }
+ 
+ /// Finds every movie whose title matches `title` exactly, ignoring case.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `title` - The title to look up.
+ /// * `fold` - Whether diacritics are ignored too, so "Amelie" finds "Amélie".
+ ///
+ /// # Returns
+ ///
+ /// * `Vec<&Movie>` - The matching movies sorted by year in ascending order.
+ pub fn movies_by_title<'a>(movies: &'a LinkedList<Movie>, title: &str, fold: bool) -> Vec<&'a Movie> {
+     let title = text::search_key(title, fold);
+     let mut matches: Vec<&Movie> = movies
+         .iter()
+         .filter(|movie| text::search_key(&movie.title, fold) == title)
+         .collect();
+     matches.sort_by_key(|movie| movie.year);
+     matches
+ }
+ 
+ /// Suggests up to `limit` titles sharing the longest case-insensitive prefix with `title`.
+ ///
+ /// Titles that share no prefix at all are never suggested. Ties are resolved
+ /// alphabetically and duplicate titles are only suggested once.
+ pub fn suggest_titles<'a>(movies: &'a LinkedList<Movie>, title: &str, limit: usize) -> Vec<&'a str> {
+     let query: Vec<char> = title.to_lowercase().chars().collect();
+     let mut scored: Vec<(usize, &str)> = movies
+         .iter()
+         .map(|movie| {
+             let shared = movie
+                 .title
+                 .to_lowercase()
+                 .chars()
+                 .zip(&query)
+                 .take_while(|(a, b)| a == *b)
+                 .count();
+             (shared, movie.title.as_str())
+         })
+         .filter(|(shared, _)| *shared > 0)
+         .collect();
+     scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+     scored.dedup_by(|a, b| a.1 == b.1);
+     scored.into_iter().take(limit).map(|(_, title)| title).collect()
+ }
+ 
+ /// Displays the detail card of every movie with the given title, or suggests
+ /// close titles when nothing matches.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `title` - The title to look up (exact, case-insensitive).
+ /// * `fold` - Whether diacritics are ignored when comparing titles.
+ /// * `precision` - The number of decimal places ratings are shown with.
+ pub fn show_movie_details(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     title: &str,
+     fold: bool,
+     precision: usize,
+ ) -> io::Result<()> {
+     let matches = movies_by_title(movies, title, fold);
+     if matches.is_empty() {
+         writeln!(out, "No movie found with title {}", title)?;
+         let suggestions = suggest_titles(movies, title, 3);
+         if !suggestions.is_empty() {
+             writeln!(out, "Did you mean: {}?", suggestions.join(", "))?;
+         }
+         return Ok(());
+     }
+ 
+     if matches.len() > 1 {
+         writeln!(out, "Found {} movies titled {}:", matches.len(), title)?;
+     }
+     for (i, movie) in matches.iter().enumerate() {
+         if i > 0 {
+             writeln!(out)?;
+         }
+         print_movie_details(out, movie, precision)?;
+     }
+     Ok(())
+ }
+ 
+ /// Displays the titles most similar to `query` with their similarity scores.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the output is written to.
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `titles` - The title index built from `movies`.
+ /// * `query` - The approximate title to search for.
+ /// * `limit` - The most rows to show, or `None` to show every row.
+ pub fn show_fuzzy_matches(
+     out: &mut impl Write,
+     movies: &LinkedList<Movie>,
+     titles: &fuzzy::TitleIndex,
+     query: &str,
+     limit: Option<usize>,
+ ) -> io::Result<()> {
+     let matches = titles.search(movies, query);
+     if matches.is_empty() {
+         writeln!(out, "No titles similar to {}", query)?;
+         return Ok(());
+     }
+     let mut listing = Listing::new(out, "title", limit);
+     for (movie, score) in matches {
+         listing.row(|out| writeln!(out, "{:.2} {} ({})", score, movie.title, movie.year))?;
+     }
+     listing.finish()
+ }
+ 
+ /// Parses a single year (`1994`) or an inclusive year range (`1990-1999`).
+ ///
+ /// Both ends must lie between `MIN_YEAR` and `MAX_YEAR` and the start must not
+ /// exceed the end.
+ pub fn parse_year_range(input: &str) -> Option<RangeInclusive<i32>> {
+     let (start, end) = match input.split_once('-') {
+         Some((start, end)) => (start.trim().parse().ok()?, end.trim().parse().ok()?),
+         None => {
+             let year = input.trim().parse().ok()?;
+             (year, year)
+         }
+     };
+     validate_year_range(start, end).ok()
+ }
+ 
+ /// Checks that both years lie within `MIN_YEAR..=MAX_YEAR` and that `start <= end`.
+ ///
+ /// # Returns
+ ///
+ /// * `Result<RangeInclusive<i32>, String>` - The validated range, or a message
+ ///   describing why it was rejected.
+ pub fn validate_year_range(start: i32, end: i32) -> Result<RangeInclusive<i32>, String> {
+     let bounds = MIN_YEAR..=MAX_YEAR;
+     if !bounds.contains(&start) || !bounds.contains(&end) {
+         return Err(format!("Years must be between {} and {}.", MIN_YEAR, MAX_YEAR));
+     }
+     if start > end {
+         return Err(format!("Start year {} is after end year {}.", start, end));
+     }
+     Ok(start..=end)
+ }
+ 
+ 
+ #[cfg(test)]
+ mod tests {
+     use super::*;
+     use rand::rngs::StdRng;
+     use rand::SeedableRng;
+ 
+     /// Runs a display function against an in-memory sink and returns what it wrote.
+     fn render(display: impl FnOnce(&mut Vec<u8>) -> io::Result<()>) -> String {
+         let mut out = Vec::new();
+         display(&mut out).unwrap();
+         String::from_utf8(out).unwrap()
+     }
+ 
+     /// Helper function to create a sample linked list of movies for testing.
+     fn sample_movies() -> LinkedList<Movie> {
+         let mut movies = LinkedList::new();
+         movies.push_back(Movie {
+             title: "The Shawshank Redemption".to_string(),
+             year: 1994,
+             languages: vec!["English".into()],
+             genres: Vec::new(),
+             runtime: None,
+             rating: Some(9.3),
+             invalid_rating: None,
+             critic_rating: None,
+             audience_rating: None,
+         });
+         movies.push_back(Movie {
+             title: "The Godfather".to_string(),
+             year: 1972,
+             languages: vec!["English".into(), "Italian".into()],
+             genres: Vec::new(),
+             runtime: None,
+             rating: Some(9.2),
+             invalid_rating: None,
+             critic_rating: None,
+             audience_rating: None,
+         });
+         movies.push_back(Movie {
+             title: "The Dark Knight".to_string(),
+             year: 2008,
+             languages: vec!["English".into(), "Mandarin".into()],
+             genres: Vec::new(),
+             runtime: None,
+             rating: Some(9.0),
+             invalid_rating: None,
+             critic_rating: None,
+             audience_rating: None,
+         });
+         movies.push_back(Movie {
+             title: "12 Angry Men".to_string(),
+             year: 1957,
+             languages: vec!["English".into()],
+             genres: Vec::new(),
+             runtime: None,
+             rating: Some(8.9),
+             invalid_rating: None,
+             critic_rating: None,
+             audience_rating: None,
+         });
+         movies.push_back(Movie {
+             title: "Schindler's List".to_string(),
+             year: 1993,
+             languages: vec!["English".into(), "German".into(), "Polish".into()],
+             genres: Vec::new(),
+             runtime: None,
+             rating: Some(8.9),
+             invalid_rating: None,
+             critic_rating: None,
+             audience_rating: None,
+         });
+         movies
+     }
+ 
+     #[test]
+     fn test_read_csv_valid_file() {
+         // Assuming "movies_sample_1.csv" exists and is properly formatted
+         let result = read_csv("movies_sample_1.csv", &ParseOptions::default());
+         assert!(result.is_ok());
+         let movies = result.unwrap();
+         // The sample CSV contains 24 valid movie records
+         assert_eq!(movies.len(), 24);
+         // It has no Genres column, so every movie has an empty genre list
+         assert!(movies.iter().all(|m| m.genres.is_empty()));
+     }
+ 
+     #[test]
+     fn test_show_movies_by_year_found() {
+         let movies = sample_movies();
+         let output = render(|out| show_movies_by_year(out, &movies, 1994, None));
+         assert_eq!(output, "The Shawshank Redemption\n-- 1 movie --\n");
+     }
+ 
+     #[test]
+     fn test_show_movies_by_year_not_found() {
+         let movies = sample_movies();
+         let output = render(|out| show_movies_by_year(out, &movies, 2020, None));
+         assert_eq!(output, "No movies found in 2020\n");
+     }
+ 
+     /// Renders the numbers `1..=rows` through a listing with `limit`.
+     fn render_listing(rows: usize, limit: Option<usize>) -> String {
+         render(|out| {
+             let mut listing = Listing::new(out, "movie", limit);
+             for row in 1..=rows {
+                 listing.row(|out| writeln!(out, "{}", row))?;
+             }
+             listing.finish()
+         })
+     }
+ 
+     #[test]
+     fn test_listing_unlimited() {
+         assert_eq!(render_listing(3, None), "1\n2\n3\n-- 3 movies --\n");
+         assert_eq!(render_listing(1, None), "1\n-- 1 movie --\n");
+         assert_eq!(render_listing(0, None), "-- 0 movies --\n");
+         // A limit the rows do not reach changes nothing
+         assert_eq!(render_listing(3, Some(3)), render_listing(3, None));
+     }
+ 
+     #[test]
+     fn test_listing_limited() {
+         assert_eq!(render_listing(5, Some(2)), "1\n2\n(+ 3 more)\n-- 5 movies --\n");
+ 
+         // Headings stop with the rows, and notes come after the truncation note
+         let output = render(|out| {
+             let mut listing = Listing::new(out, "movie", Some(1));
+             for group in ["A", "B"] {
+                 listing.heading(format_args!("{}:", group))?;
+                 listing.row(|out| writeln!(out, "  {}1", group))?;
+             }
+             listing.note("(note)".to_string());
+             listing.finish()
+         });
+         assert_eq!(output, "A:\n  A1\n(+ 1 more)\n(note)\n-- 2 movies --\n");
+     }
+ 
+     #[test]
+     fn test_show_highest_rated_movies() {
+         let movies = sample_movies();
+         let output = render(|out| show_highest_rated_movies(out, &movies, YearOrder::Ascending, RatingSource::Rating, RATING_PRECISION, None));
+         assert_eq!(
+             output,
+             "1957 8.9 12 Angry Men\n\
+              1972 9.2 The Godfather\n\
+              1993 8.9 Schindler's List\n\
+              1994 9.3 The Shawshank Redemption\n\
+              2008 9.0 The Dark Knight\n\
+              -- 5 movies --\n"
+         );
+     }
+ 
+     #[test]
+     fn test_show_highest_rated_movies_descending() {
+         let movies = sample_movies();
+         let output = render(|out| show_highest_rated_movies(out, &movies, YearOrder::Descending, RatingSource::Rating, RATING_PRECISION, None));
+         assert_eq!(
+             output,
+             "2008 9.0 The Dark Knight\n\
+              1994 9.3 The Shawshank Redemption\n\
+              1993 8.9 Schindler's List\n\
+              1972 9.2 The Godfather\n\
+              1957 8.9 12 Angry Men\n\
+              -- 5 movies --\n"
+         );
+     }
+ 
+     #[test]
+     fn test_show_movies_by_language_found() {
+         let movies = sample_movies();
+         let output = render(|out| show_movies_by_language(out, &movies, "German", None));
+         assert_eq!(output, "1993 Schindler's List\n-- 1 movie --\n");
+     }
+ 
+     #[test]
+     fn test_movies_by_language_sorted_by_year_then_title() {
+         let mut movies = LinkedList::new();
+         for (title, year) in [("Zodiac", 2007), ("Heat", 1995), ("Atonement", 2007), ("Casino", 1995), ("Ran", 1985)] {
+             movies.push_back(movie(title, year, 8.0));
+         }
+         let titles = |movies: &LinkedList<Movie>| -> Vec<String> {
+             movies_by_language(movies, "English").iter().map(|movie| movie.title.clone()).collect()
+         };
+         let sorted = titles(&movies);
+         assert_eq!(sorted, ["Ran", "Casino", "Heat", "Atonement", "Zodiac"]);
+ 
+         // The order is the same however the file is sorted
+         let reversed: LinkedList<Movie> = movies.into_iter().rev().collect();
+         assert_eq!(titles(&reversed), sorted);
+         let output = render(|out| show_movies_by_language(out, &reversed, "English", Some(2)));
+         assert_eq!(output, "1985 Ran\n1995 Casino\n(+ 3 more)\n-- 5 movies --\n");
+ 
+         let pattern = compile_title_pattern("[aeiou]n").unwrap();
+         let matching: Vec<&str> = title_matches(&reversed, &pattern).iter().map(|movie| movie.title.as_str()).collect();
+         assert_eq!(matching, ["Ran", "Casino", "Atonement"]);
+     }
+ 
+     #[test]
+     fn test_show_movies_by_language_not_found() {
+         let movies = sample_movies();
+         let output = render(|out| show_movies_by_language(out, &movies, "Japanese", None));
+         assert_eq!(output, "No movies found in Japanese\n");
+     }
+ 
+     #[test]
+     fn test_show_movies_in_year_range() {
+         let movies = sample_movies();
+         let output = render(|out| show_movies_in_year_range(out, &movies, 1990..=1999, YearOrder::Ascending, None));
+         assert_eq!(output, "1993:\n  Schindler's List\n1994:\n  The Shawshank Redemption\n-- 2 movies --\n");
+         let output = render(|out| show_movies_in_year_range(out, &movies, 1990..=1999, YearOrder::Descending, None));
+         assert_eq!(output, "1994:\n  The Shawshank Redemption\n1993:\n  Schindler's List\n-- 2 movies --\n");
+         let output = render(|out| show_movies_in_year_range(out, &movies, 2010..=2020, YearOrder::Ascending, None));
+         assert_eq!(output, "No movies found between 2010 and 2020\n");
+     }
+ 
+     #[test]
+     fn test_show_lowest_rated_movies() {
+         let mut movies = LinkedList::new();
+         movies.push_back(unrated_movie("Phantom", 2001, "N/A"));
+         movies.push_back(movie("Memento", 2000, 8.4));
+         let output = render(|out| show_lowest_rated_movies(out, &movies, RATING_PRECISION, None));
+         assert_eq!(output, "2000 8.4 Memento\n2001 (no rated movies)\n(1 unrated movies not included)\n-- 2 years --\n");
+     }
+ 
+     #[test]
+     fn test_show_movie_details() {
+         let movies = sample_movies();
+         let output = render(|out| show_movie_details(out, &movies, "the godfather", true, RATING_PRECISION));
+         assert_eq!(
+             output,
+             "Title: The Godfather\nYear: 1972\nRating: 9.2\nLanguages: English, Italian\n"
+         );
+         let output = render(|out| show_movie_details(out, &movies, "12 Angry Mem", true, RATING_PRECISION));
+         assert_eq!(output, "No movie found with title 12 Angry Mem\nDid you mean: 12 Angry Men?\n");
+     }
+ 
+     #[test]
+     fn test_show_movies_by_genre_not_found() {
+         let movies = sample_movies();
+         let output = render(|out| show_movies_by_genre(out, &movies, "Drama", None));
+         assert_eq!(output, "No movies found in Drama\n");
+         let output = render(|out| show_distinct_genres(out, &movies, None));
+         assert_eq!(output, "No genres found in the data\n");
+     }
+ 
+     #[test]
+     fn test_show_year_histogram() {
+         let mut movies = sample_movies();
+         movies.push_back(movie("Forrest Gump", 1994, 8.8));
+         let output = render(|out| show_year_histogram(out, &movies, YearOrder::Ascending, None));
+         let bar = "#".repeat(30);
+         let full = "#".repeat(HISTOGRAM_WIDTH);
+         let expected = format!(
+             "1957     1 {bar}\n1972     1 {bar}\n1993     1 {bar}\n1994     2 {full}\n2008     1 {bar}\n-- 5 years --\n"
+         );
+         assert_eq!(output, expected);
+     }
+ 
+     #[test]
+     fn test_language_table_interns_duplicates() {
+         let mut table = LanguageTable::new();
+         let first = table.intern("English");
+         let second = table.intern("English");
+         let other = table.intern("French");
+         assert!(Arc::ptr_eq(&first, &second));
+         assert!(!Arc::ptr_eq(&first, &other));
+         assert_eq!(&*second, "English");
+     }
+ 
+     /// Loads a generated 100,000-row file, checks that every row naming a language
+     /// holds a handle to one shared allocation for it, and compares the bytes the
+     /// languages take against the one-`String`-per-occurrence layout used before
+     /// interning.
+     ///
+     /// With four distinct languages spread over 200,000 occurrences, owned strings
+     /// need about 6.1 MB (a 24-byte `String` plus the name for every occurrence),
+     /// while interned handles need about 3.2 MB (a 16-byte `Arc<str>` per occurrence
+     /// plus one allocation per distinct name).
+     #[test]
+     fn test_read_csv_interns_languages_in_large_file() {
+         const ROWS: usize = 100_000;
+         let languages = ["English", "French", "German", "Spanish"];
+         let dir = tempfile::tempdir().unwrap();
+         let path = dir.path().join("movies_large.csv");
+         let mut file = File::create(&path).unwrap();
+         writeln!(file, "Title,Year,Languages,Rating Value").unwrap();
+         for i in 0..ROWS {
+             let first = languages[i % languages.len()];
+             let second = languages[(i + 1) % languages.len()];
+             writeln!(file, "Movie {},{},[{};{}],7.5", i, 1900 + i % 122, first, second).unwrap();
+         }
+         drop(file);
+ 
+         let movies = read_csv(path.to_str().unwrap(), &ParseOptions::default()).unwrap();
+         assert_eq!(movies.len(), ROWS);
+ 
+         // The first and last rows both list English, and must share its allocation
+         let english = |movie: &Movie| {
+             Arc::clone(movie.languages.iter().find(|lang| &***lang == "English").unwrap())
+         };
+         assert!(Arc::ptr_eq(&english(movies.front().unwrap()), &english(movies.back().unwrap())));
+ 
+         // Every occurrence of each language must point at the same allocation
+         for &language in &languages {
+             let handles: Vec<&Arc<str>> = movies
+                 .iter()
+                 .flat_map(|m| m.languages.iter())
+                 .filter(|lang| &***lang == language)
+                 .collect();
+             assert_eq!(handles.len(), ROWS / 2);
+             assert!(handles.windows(2).all(|pair| Arc::ptr_eq(pair[0], pair[1])));
+         }
+ 
+         // Count each shared allocation once, with the strong and weak counts before the name
+         let mut allocations = HashSet::new();
+         let mut occurrences = 0;
+         let mut name_bytes = 0;
+         let mut distinct_bytes = 0;
+         for language in movies.iter().flat_map(|m| m.languages.iter()) {
+             occurrences += 1;
+             name_bytes += language.len();
+             if allocations.insert(Arc::as_ptr(language) as *const u8) {
+                 distinct_bytes += 2 * std::mem::size_of::<usize>() + language.len();
+             }
+         }
+         assert_eq!(allocations.len(), languages.len());
+         let owned_bytes = occurrences * std::mem::size_of::<String>() + name_bytes;
+         let interned_bytes = occurrences * std::mem::size_of::<Arc<str>>() + distinct_bytes;
+         assert!(interned_bytes < owned_bytes);
+         assert!(interned_bytes * 10 <= owned_bytes * 6, "{} bytes interned vs {} bytes owned", interned_bytes, owned_bytes);
+     }
+ 
+     /// Creates a rated movie in the given languages.
+     fn movie_in(title: &str, rating: Option<f32>, languages: &[&str]) -> Movie {
+         Movie {
+             languages: languages.iter().map(|&lang| Arc::from(lang)).collect(),
+             rating,
+             ..movie(title, 2000, 0.0)
+         }
+     }
+ 
+     #[test]
+     fn test_highest_rated_per_language_counts_every_language() {
+         let mut movies = LinkedList::new();
+         movies.push_back(movie_in("Babel", Some(7.5), &["English", "Spanish", "Japanese", "Arabic", "French"]));
+         movies.push_back(movie_in("Amelie", Some(8.3), &["French"]));
+         movies.push_back(movie_in("Biutiful", Some(7.5), &["Spanish"]));
+         movies.push_back(movie_in("Alatriste", Some(7.5), &["Spanish"]));
+         movies.push_back(movie_in("Klingon Opera", None, &["Klingon", "English"]));
+ 
+         let highest = highest_rated_per_language(&movies);
+         let titles: Vec<(&str, Option<&str>)> =
+             highest.iter().map(|(lang, movie)| (*lang, movie.map(|m| m.title.as_str()))).collect();
+         assert_eq!(
+             titles,
+             vec![
+                 ("Arabic", Some("Babel")),
+                 ("English", Some("Babel")),
+                 ("French", Some("Amelie")),
+                 ("Japanese", Some("Babel")),
+                 ("Klingon", None),
+                 // Ties resolve alphabetically by title
+                 ("Spanish", Some("Alatriste")),
+             ]
+         );
+ 
+         let output = render(|out| show_highest_rated_per_language(out, &movies, 1, Some(2)));
+         assert_eq!(
+             output,
+             "Arabic: 2000 7.5 Babel\nEnglish: 2000 7.5 Babel\n(+ 4 more)\n(1 unrated movies not included)\n-- 6 languages --\n"
+         );
+         let output = render(|out| show_highest_rated_per_language(out, &movies, 1, None));
+         assert!(output.contains("\nKlingon: (no rated movies)\n"), "{}", output);
+     }
+ 
+     #[test]
+     fn test_dominant_language_by_year() {
+         let in_year = |title: &str, year: i32, languages: &[&str]| Movie { year, ..movie_in(title, Some(7.0), languages) };
+         let mut movies = LinkedList::new();
+         movies.push_back(in_year("Babel", 2006, &["Spanish", "English", "Spanish"]));
+         movies.push_back(in_year("Volver", 2006, &["Spanish"]));
+         movies.push_back(in_year("Paris", 2006, &["French", "English"]));
+         // A tie between French and German goes to French
+         movies.push_back(in_year("Amelie", 2001, &["German", "French"]));
+         movies.push_back(in_year("Lola", 2001, &["German"]));
+         movies.push_back(in_year("Cache", 2001, &["French"]));
+         // A year whose only movies lack languages is left out
+         movies.push_back(in_year("Silent", 1927, &[]));
+         movies.push_back(in_year("Quiet", 1927, &[]));
+         movies.push_back(in_year("Untitled", 2006, &[]));
+ 
+         let dominant = dominant_language_by_year(&movies);
+         let expected = BTreeMap::from([(2001, ("French".to_string(), 2)), (2006, ("English".to_string(), 2))]);
+         assert_eq!(dominant, expected);
+ 
+         movies.push_back(in_year("Solo", 1999, &["Hindi"]));
+         let output = render(|out| show_dominant_languages(out, &movies, None));
+         assert_eq!(output, "1999: Hindi (1 movie)\n2001: French (2 movies)\n2006: English (2 movies)\n-- 3 years --\n");
+     }
+ 
+     #[test]
+     fn test_superlatives() {
+         let in_year = |title: &str, year: i32, rating: Option<f32>, languages: &[&str]| Movie {
+             year,
+             ..movie_in(title, rating, languages)
+         };
+         let mut movies = LinkedList::new();
+         assert_eq!(year_with_most_movies(&movies), None);
+         assert_eq!(year_with_highest_average(&movies, SUPERLATIVE_MIN_RATED), None);
+         assert!(most_multilingual_movie(&movies).is_none());
+         assert!(longest_title(&movies).is_none());
+         let output = render(|out| show_superlatives(out, &movies, 1));
+         assert_eq!(
+             output,
+             "Most releases: none\nHighest average rating: none (no year has 3 rated movies)\n\
+              Most languages: none\nLongest title: none\n"
+         );
+ 
+         // 1999 and 2004 both have three movies and a 7.0 average; the earlier year wins
+         movies.push_back(in_year("Ronin", 2004, Some(6.0), &["English", "French"]));
+         movies.push_back(in_year("Brazil", 2004, Some(7.0), &["English"]));
+         movies.push_back(in_year("Heat", 2004, Some(8.0), &["English", "Spanish"]));
+         movies.push_back(in_year("Magnolia", 1999, Some(7.5), &["English"]));
+         movies.push_back(in_year("Tarzan", 1999, Some(6.5), &["English", "French"]));
+         movies.push_back(in_year("Go", 1999, Some(7.0), &["English"]));
+         // Two highly rated movies are too few to count
+         movies.push_back(in_year("Pulp Fiction", 1994, Some(8.9), &["English", "Spanish"]));
+         movies.push_back(in_year("Amelie", 1994, Some(8.3), &["French"]));
+         movies.push_back(in_year("Babel Again", 1994, None, &["English", "Spanish"]));
+         movies.push_back(in_year("Jackie Brown", 1997, None, &["English"]));
+ 
+         // 1994, 1999, and 2004 all have three movies
+         assert_eq!(year_with_most_movies(&movies), Some((1994, 3)));
+         assert_eq!(year_with_highest_average(&movies, SUPERLATIVE_MIN_RATED), Some((1999, 7.0)));
+         assert_eq!(year_with_highest_average(&movies, 2), Some((1994, 8.6)));
+         // Four two-language movies tie; the first title alphabetically wins
+         assert_eq!(most_multilingual_movie(&movies).unwrap().title, "Babel Again");
+         // "Jackie Brown" and "Pulp Fiction" both have 12 characters
+         assert_eq!(longest_title(&movies).unwrap().title, "Jackie Brown");
+ 
+         let output = render(|out| show_superlatives(out, &movies, 2));
+         assert_eq!(
+             output,
+             "Most releases: 1994 (3 movies)\nHighest average rating: 1999 (7.00)\n\
+              Most languages: Babel Again (1994), 2 languages\nLongest title: Jackie Brown (1997), 12 characters\n"
+         );
+     }
+ 
+     #[test]
+     fn test_movies_sharing_languages() {
+         let mut movies = LinkedList::new();
+         movies.push_back(movie_in("Babel", Some(7.5), &["English", "Spanish", "Japanese"]));
+         movies.push_back(movie_in("Biutiful", Some(7.5), &["Spanish"]));
+         movies.push_back(movie_in("Amelie", Some(8.3), &["French"]));
+         movies.push_back(movie_in("Lost in Translation", Some(7.7), &["English", "Japanese"]));
+         // Sharing every language, listed twice, still counts each once
+         movies.push_back(movie_in("Babel Again", None, &["Japanese", "English", "Spanish", "English"]));
+         movies.push_back(movie_in("Heat", Some(8.3), &["English", "Spanish"]));
+         movies.push_back(movie_in("Alatriste", Some(7.5), &["Spanish"]));
+ 
+         let babel = movies.front().unwrap();
+         let sharing: Vec<(usize, &str)> =
+             movies_sharing_languages(&movies, babel).into_iter().map(|(shared, movie)| (shared, movie.title.as_str())).collect();
+         assert_eq!(
+             sharing,
+             [(3, "Babel Again"), (2, "Heat"), (2, "Lost in Translation"), (1, "Alatriste"), (1, "Biutiful")]
+         );
+ 
+         // No other movie speaks French
+         let amelie = movies.iter().find(|movie| movie.title == "Amelie").unwrap();
+         assert!(movies_sharing_languages(&movies, amelie).is_empty());
+         let output = render(|out| show_movies_sharing_languages(out, &movies, amelie, 1, None));
+         assert_eq!(output, "-- 0 movies --\n");
+ 
+         let output = render(|out| show_movies_sharing_languages(out, &movies, babel, 1, Some(2)));
+         assert_eq!(output, "2000 unrated Babel Again (3 shared)\n2000 8.3 Heat (2 shared)\n(+ 3 more)\n-- 5 movies --\n");
+     }
+ 
+     #[test]
+     fn test_show_title_matches() {
+         let movies = sample_movies();
+         let pattern = compile_title_pattern("^the .*(father|knight)$").unwrap();
+         let output = render(|out| show_title_matches(out, &movies, &pattern, 1, None));
+         assert_eq!(output, "1972 9.2 The Godfather\n2008 9.0 The Dark Knight\n-- 2 movies --\n");
+ 
+         let pattern = compile_title_pattern("^Zorro").unwrap();
+         let output = render(|out| show_title_matches(out, &movies, &pattern, 1, None));
+         assert_eq!(output, "No titles match '^Zorro'\n");
+     }
+ 
+     #[test]
+     fn test_compile_title_pattern() {
+         let pattern = compile_title_pattern("^the .* redemption$").unwrap();
+         assert!(pattern.is_match("The Shawshank Redemption"));
+         // (?-i) turns case-insensitive matching back off
+         let pattern = compile_title_pattern("(?-i)^the").unwrap();
+         assert!(!pattern.is_match("The Shawshank Redemption"));
+ 
+         assert!(compile_title_pattern("(unclosed").is_err());
+         let error = compile_title_pattern("(a{1000}){1000}").unwrap_err();
+         assert!(matches!(error, regex::Error::CompiledTooBig(_)), "{}", error);
+     }
+ 
+     #[test]
+     fn test_bom_and_crlf_are_ignored() {
+         let movies = read_csv("movies_sample_bom_crlf.csv", &ParseOptions::default()).unwrap();
+         let titles: Vec<&str> = movies.iter().map(|movie| movie.title.as_str()).collect();
+         assert_eq!(titles, ["The Dark Knight", "Iron Man", "Up"]);
+         // The Genres header is found, and the last field of each line has no carriage return
+         assert_eq!(movies.front().unwrap().genres, ["Action", "Crime"]);
+         assert_eq!(movies.back().unwrap().genres, ["Animation"]);
+ 
+         let dir = tempfile::tempdir().unwrap();
+         export_titles_by_year(&movies, dir.path(), false).unwrap();
+         let mut names: Vec<String> = fs::read_dir(dir.path())
+             .unwrap()
+             .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+             .collect();
+         names.sort();
+         assert_eq!(names, ["2008.txt", "2009.txt"]);
+     }
+ 
+     #[test]
+     fn test_multiline_title_is_read_on_one_line() {
+         let movies = read_csv("movies_sample_multiline.csv", &ParseOptions::default()).unwrap();
+         assert_eq!(movies.front().unwrap().title, "The Lord of the Rings: The Return of the King");
+ 
+         let dir = tempfile::tempdir().unwrap();
+         let export = export_titles_by_year(&movies, dir.path(), false).unwrap();
+         assert_eq!(export, YearExport { files: 1, lines: 2 });
+         let text = fs::read_to_string(dir.path().join("2003.txt")).unwrap();
+         assert_eq!(text.lines().collect::<Vec<_>>(), ["The Lord of the Rings: The Return of the King", "Finding Nemo"]);
+     }
+ 
+     #[test]
+     fn test_export_titles_by_year() {
+         let dir = tempfile::tempdir().unwrap();
+         let target = dir.path().join("by_year");
+         let movies = read_csv("movies_sample_quoted.csv", &ParseOptions::default()).unwrap();
+ 
+         let export = export_titles_by_year(&movies, &target, false).unwrap();
+         assert_eq!(export, YearExport { files: 6, lines: 6 });
+         let mut names: Vec<String> = fs::read_dir(&target)
+             .unwrap()
+             .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+             .collect();
+         names.sort();
+         assert_eq!(names, ["1989.txt", "2000.txt", "2001.txt", "2007.txt", "2010.txt", "2011.txt"]);
+         assert_eq!(fs::read_to_string(target.join("1989.txt")).unwrap(), "The \"Burbs\"\n");
+         assert_eq!(fs::read_to_string(target.join("2000.txt")).unwrap(), "Crouching Tiger, Hidden Dragon\n");
+ 
+         // A directory with entries is only written to when forced
+         let error = export_titles_by_year(&movies, &target, false).unwrap_err();
+         assert!(error.to_string().contains("is not empty"), "{}", error);
+         assert_eq!(export_titles_by_year(&movies, &target, true).unwrap(), export);
+     }
+ 
+     #[test]
+     fn test_top_rated_movies() {
+         let mut movies = LinkedList::new();
+         movies.push_back(movie("Heat", 1995, 8.3));
+         movies.push_back(unrated_movie("Gigli", 2003, "N/A"));
+         movies.push_back(movie("Amelie", 2001, 8.3));
+         movies.push_back(movie("Memento", 2000, 8.4));
+         let titles: Vec<&str> = top_rated_movies(&movies, 2).iter().map(|m| m.title.as_str()).collect();
+         assert_eq!(titles, vec!["Memento", "Amelie"]);
+         assert_eq!(top_rated_movies(&movies, 10).len(), 3);
+ 
+         let output = render(|out| show_top_rated_movies(out, &movies, 2, 1));
+         assert_eq!(output, "2000 8.4 Memento\n2001 8.3 Amelie\n(1 unrated movies not included)\n-- 2 movies --\n");
+     }
+ 
+     #[test]
+     fn test_lowest_rated_per_year_excludes_unrated_movies() {
+         let mut movies = sample_movies();
+         movies.push_back(Movie {
+             title: "Unrated 1994".to_string(),
+             year: 1994,
+             languages: vec!["English".into()],
+             genres: Vec::new(),
+             runtime: None,
+             rating: None,
+             invalid_rating: Some("N/A".to_string()),
+             critic_rating: None,
+             audience_rating: None,
+         });
+         movies.push_back(Movie {
+             title: "Forrest Gump".to_string(),
+             year: 1994,
+             languages: vec!["English".into()],
+             genres: Vec::new(),
+             runtime: None,
+             rating: Some(8.8),
+             invalid_rating: None,
+             critic_rating: None,
+             audience_rating: None,
+         });
+ 
+         let lowest = lowest_rated_per_year(&movies);
+         let movie = lowest[&1994].expect("1994 has rated movies");
+         assert_eq!(movie.title, "Forrest Gump");
+         assert_eq!(lowest[&1972].unwrap().title, "The Godfather");
+     }
+ 
+     #[test]
+     fn test_lowest_rated_per_year_only_unrated_movies() {
+         let mut movies = sample_movies();
+         movies.push_back(Movie {
+             title: "Phantom".to_string(),
+             year: 2001,
+             languages: vec!["English".into()],
+             genres: Vec::new(),
+             runtime: None,
+             rating: None,
+             invalid_rating: Some("N/A".to_string()),
+             critic_rating: None,
+             audience_rating: None,
+         });
+ 
+         let lowest = lowest_rated_per_year(&movies);
+         assert!(lowest.contains_key(&2001));
+         assert!(lowest[&2001].is_none());
+     }
+ 
+     #[test]
+     fn test_lowest_rated_per_year_ties_resolve_alphabetically() {
+         let mut movies = LinkedList::new();
+         for title in ["Zodiac", "Alien", "Memento"] {
+             movies.push_back(Movie {
+                 title: title.to_string(),
+                 year: 2000,
+                 languages: vec!["English".into()],
+                 genres: Vec::new(),
+                 runtime: None,
+                 rating: Some(6.0),
+                 invalid_rating: None,
+                 critic_rating: None,
+                 audience_rating: None,
+             });
+         }
+ 
+         let lowest = lowest_rated_per_year(&movies);
+         assert_eq!(lowest[&2000].unwrap().title, "Alien");
+         let years: Vec<i32> = lowest_rated_per_year(&sample_movies()).into_keys().collect();
+         assert_eq!(years, vec![1957, 1972, 1993, 1994, 2008]);
+     }
+ 
+     #[test]
+     fn test_highest_rated_per_year_ties_keep_the_first_in_file_order() {
+         let movies: LinkedList<Movie> =
+             ["Zodiac", "Alien", "Memento"].into_iter().map(|title| movie(title, 2000, 8.0)).collect();
+ 
+         let highest = highest_rated_per_year(&movies, YearOrder::Ascending, RatingSource::Rating);
+         assert_eq!(highest.len(), 1);
+         assert_eq!(highest[0].1.title, "Zodiac");
+     }
+ 
+     /// Helper function to create a single-language movie for testing.
+     fn movie(title: &str, year: i32, rating: f32) -> Movie {
+         Movie {
+             title: title.to_string(),
+             year,
+             languages: vec!["English".into()],
+             genres: Vec::new(),
+             runtime: None,
+             rating: Some(rating),
+             invalid_rating: None,
+             critic_rating: None,
+             audience_rating: None,
+         }
+     }
+ 
+     /// Helper function to create a movie whose rating text `invalid_rating` did not parse.
+     fn unrated_movie(title: &str, year: i32, invalid_rating: &str) -> Movie {
+         Movie {
+             rating: None,
+             invalid_rating: Some(invalid_rating.to_string()),
+             ..movie(title, year, 1.0)
+         }
+     }
+ 
+     #[test]
+     fn test_rating_stats_by_year_odd_count() {
+         let mut movies = LinkedList::new();
+         for (title, rating) in [("A", 5.0), ("B", 9.0), ("C", 7.0)] {
+             movies.push_back(movie(title, 2000, rating));
+         }
+         movies.push_back(unrated_movie("D", 2000, ""));
+ 
+         let (year, stats) = rating_stats_by_year(&movies, YearOrder::Ascending)[0];
+         assert_eq!(year, 2000);
+         assert_eq!(stats, RatingStats { min: 5.0, median: 7.0, p90: 9.0, max: 9.0 });
+     }
+ 
+     #[test]
+     fn test_rating_stats_by_year_even_count() {
+         let mut movies = LinkedList::new();
+         let ratings = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0];
+         for (i, rating) in ratings.iter().rev().enumerate() {
+             movies.push_back(movie(&format!("Movie {}", i), 2010, *rating));
+         }
+ 
+         let (_, stats) = rating_stats_by_year(&movies, YearOrder::Ascending)[0];
+         assert_eq!(stats, RatingStats { min: 1.0, median: 5.5, p90: 9.0, max: 10.0 });
+     }
+ 
+     #[test]
+     fn test_rating_stats_by_year_single_movie() {
+         let mut movies = LinkedList::new();
+         movies.push_back(movie("Solo", 1999, 6.4));
+         movies.push_back(unrated_movie("Unrated", 2001, "abc"));
+ 
+         let stats = rating_stats_by_year(&movies, YearOrder::Ascending);
+         assert_eq!(stats, vec![(1999, RatingStats { min: 6.4, median: 6.4, p90: 6.4, max: 6.4 })]);
+     }
+ 
+     #[test]
+     fn test_rating_stats_by_year_order() {
+         let mut movies = LinkedList::new();
+         for (title, year) in [("A", 2001), ("B", 1985), ("C", 2010)] {
+             movies.push_back(movie(title, year, 7.0));
+         }
+ 
+         let years = |order| -> Vec<i32> { rating_stats_by_year(&movies, order).iter().map(|(year, _)| *year).collect() };
+         assert_eq!(years(YearOrder::Ascending), vec![1985, 2001, 2010]);
+         assert_eq!(years(YearOrder::Descending), vec![2010, 2001, 1985]);
+     }
+ 
+     #[test]
+     fn test_count_by_year() {
+         let mut movies = sample_movies();
+         movies.push_back(movie("Forrest Gump", 1994, 8.8));
+ 
+         let counts = count_by_year(&movies);
+         assert_eq!(counts[&1994], 2);
+         assert_eq!(counts[&1957], 1);
+         assert!(!counts.contains_key(&2000));
+         assert_eq!(counts.values().sum::<usize>(), movies.len());
+     }
+ 
+     #[test]
+     fn test_histogram_bar_width_scales_to_sixty_columns() {
+         // A max count of 240 compresses four movies into each column
+         assert_eq!(histogram_bar_width(240, 240), HISTOGRAM_WIDTH);
+         assert_eq!(histogram_bar_width(120, 240), 30);
+         assert_eq!(histogram_bar_width(7, 240), 1);
+         assert_eq!(histogram_bar_width(1, 240), 1);
+         assert_eq!(histogram_bar_width(0, 240), 0);
+         // Counts below the width are stretched so the longest bar still fills it
+         assert_eq!(histogram_bar_width(3, 3), HISTOGRAM_WIDTH);
+     }
+ 
+     #[test]
+     fn test_pick_random_movie_is_deterministic_with_seed() {
+         let movies = sample_movies();
+         let filter = MovieFilter::new();
+         let first = pick_random_movie(&movies, &filter, &mut StdRng::seed_from_u64(42)).unwrap();
+         let second = pick_random_movie(&movies, &filter, &mut StdRng::seed_from_u64(42)).unwrap();
+         assert_eq!(first.title, second.title);
+     }
+ 
+     #[test]
+     fn test_pick_random_movie_applies_filters() {
+         let movies = sample_movies();
+         let filter = MovieFilter::new().years(1990..=1999).language("German").min_rating(8.5);
+         let mut rng = StdRng::seed_from_u64(7);
+         for _ in 0..10 {
+             let movie = pick_random_movie(&movies, &filter, &mut rng).unwrap();
+             assert_eq!(movie.title, "Schindler's List");
+         }
+     }
+ 
+     #[test]
+     fn test_movie_filter_matches_every_criterion() {
+         let heat = Movie {
+             languages: vec!["English".into(), "Spanish".into()],
+             genres: vec!["Crime".to_string()],
+             ..movie("Heat", 1995, 8.3)
+         };
+ 
+         assert!(MovieFilter::new().is_empty());
+         assert!(MovieFilter::new().matches(&heat));
+         assert!(MovieFilter::new().year(1995).matches(&heat));
+         assert!(!MovieFilter::new().year(1996).matches(&heat));
+         assert!(MovieFilter::new().years(1990..=1995).matches(&heat));
+         assert!(MovieFilter::new().language("Spanish").matches(&heat));
+         assert!(!MovieFilter::new().language("spanish").matches(&heat));
+         assert!(MovieFilter::new().genre("Crime").matches(&heat));
+         assert!(MovieFilter::new().min_rating(8.3).matches(&heat));
+         assert!(!MovieFilter::new().min_rating(8.4).matches(&heat));
+ 
+         // Every criterion that is set must hold
+         let filter = MovieFilter::new().year(1995).language("English").min_rating(8.0);
+         assert!(!filter.is_empty());
+         assert!(filter.matches(&heat));
+         assert!(!filter.clone().year(1994).matches(&heat));
+         assert!(!filter.language("French").matches(&heat));
+         assert!(!MovieFilter::new().min_rating(1.0).matches(&unrated_movie("Blank", 1995, "")));
+     }
+ 
+     #[test]
+     fn test_show_filtered_movies() {
+         let movies = sample_movies();
+         let filter = MovieFilter::new().years(1990..=1999).min_rating(9.0);
+         let output = render(|out| show_filtered_movies(out, &movies, &filter, RATING_PRECISION, None));
+         assert_eq!(output, "1994 9.3 The Shawshank Redemption\n-- 1 movie --\n");
+ 
+         let output = render(|out| show_filtered_movies(out, &movies, &MovieFilter::new(), RATING_PRECISION, Some(1)));
+         assert!(output.ends_with("(+ 4 more)\n-- 5 movies --\n"), "{}", output);
+ 
+         let filter = MovieFilter::new().language("Klingon");
+         let output = render(|out| show_filtered_movies(out, &movies, &filter, RATING_PRECISION, None));
+         assert_eq!(output, "No movies match the filter\n");
+     }
+ 
+     #[test]
+     fn test_pick_random_movie_empty_filter_set() {
+         let movies = sample_movies();
+         let filter = MovieFilter::new().min_rating(9.9);
+         assert!(pick_random_movie(&movies, &filter, &mut StdRng::seed_from_u64(1)).is_none());
+     }
+ 
+     #[test]
+     fn test_parse_year_range() {
+         assert_eq!(parse_year_range("1994"), Some(1994..=1994));
+         assert_eq!(parse_year_range("1990-1999"), Some(1990..=1999));
+         assert_eq!(parse_year_range("1999-1990"), None);
+         assert_eq!(parse_year_range("1890-1999"), None);
+         assert_eq!(parse_year_range("abc"), None);
+     }
+ 
+     #[test]
+     fn test_movies_by_title_multiple_years() {
+         let mut movies = sample_movies();
+         movies.push_back(movie("Dune", 2021, 8.0));
+         movies.push_back(movie("Dune", 1984, 6.3));
+ 
+         let matches = movies_by_title(&movies, "dUNE", true);
+         let years: Vec<i32> = matches.iter().map(|m| m.year).collect();
+         assert_eq!(years, vec![1984, 2021]);
+         assert!(movies_by_title(&movies, "Dun", true).is_empty());
+     }
+ 
+     #[test]
+     fn test_suggest_titles_by_prefix() {
+         let movies = sample_movies();
+         let suggestions = suggest_titles(&movies, "The Godfathr", 3);
+         assert_eq!(suggestions, vec!["The Godfather", "The Dark Knight", "The Shawshank Redemption"]);
+         assert!(suggest_titles(&movies, "Xyz", 3).is_empty());
+     }
+ 
+     #[test]
+     fn test_movies_in_year_range_groups_by_year() {
+         let mut movies = sample_movies();
+         movies.push_back(movie("Forrest Gump", 1994, 8.8));
+ 
+         let by_year = movies_in_year_range(&movies, 1990, 1999, YearOrder::Ascending);
+         let years: Vec<i32> = by_year.iter().map(|(year, _)| *year).collect();
+         assert_eq!(years, vec![1993, 1994]);
+         let titles: Vec<&str> = by_year[1].1.iter().map(|m| m.title.as_str()).collect();
+         assert_eq!(titles, vec!["The Shawshank Redemption", "Forrest Gump"]);
+     }
+ 
+     #[test]
+     fn test_movies_in_year_range_empty_result() {
+         let movies = sample_movies();
+         assert!(movies_in_year_range(&movies, 2010, 2020, YearOrder::Descending).is_empty());
+     }
+ 
+     #[test]
+     fn test_validate_year_range() {
+         assert_eq!(validate_year_range(1990, 1999), Ok(1990..=1999));
+         assert!(validate_year_range(1999, 1990).is_err());
+         assert!(validate_year_range(1899, 1990).is_err());
+         assert!(validate_year_range(1990, 2022).is_err());
+     }
+ 
+     #[test]
+     fn test_read_csv_with_genres_column() {
+         let movies = read_csv("movies_sample_genres.csv", &ParseOptions::default()).unwrap();
+         // One record has an unbracketed genres field and is skipped
+         assert_eq!(movies.len(), 5);
+ 
+         let godfather = movies.iter().find(|m| m.title == "The Godfather").unwrap();
+         assert_eq!(godfather.genres, vec!["Crime", "Drama"]);
+         let untagged = movies.iter().find(|m| m.title == "Untagged").unwrap();
+         assert!(untagged.genres.is_empty());
+         assert!(movies.iter().any(|m| m.has_genre("Action")));
+     }
+ 
+     #[test]
+     fn test_read_csv_quietly_returns_the_problems() {
+         let (movies, problems) = read_csv_quietly("movies_sample_messy.csv", &ParseOptions::default()).unwrap();
+         assert_eq!(movies.len(), read_csv("movies_sample_messy.csv", &ParseOptions::default()).unwrap().len());
+         assert_eq!(problems[0].message, "Invalid year '19x4' at line 3. Skipping record.");
+         assert_eq!(problems[0].reason(), Some(SkipReason::InvalidYear));
+     }
+ 
+     #[test]
+     fn test_distinct_genres() {
+         let movies = read_csv("movies_sample_genres.csv", &ParseOptions::default()).unwrap();
+         let genres = distinct_genres(&movies);
+         let expected: Vec<(&str, usize)> = vec![("Action", 1), ("Biography", 1), ("Crime", 2), ("Drama", 4), ("History", 1)];
+         assert_eq!(genres.into_iter().collect::<Vec<_>>(), expected);
+         assert!(distinct_genres(&sample_movies()).is_empty());
+     }
+ 
+     #[test]
+     fn test_read_csv_runtime_column() {
+         let movies = read_csv("movies_sample_runtime.csv", &ParseOptions::default()).unwrap();
+         // Rows with missing or invalid runtimes are kept with no runtime
+         assert_eq!(movies.len(), 6);
+ 
+         let runtime = |title: &str| movies.iter().find(|m| m.title == title).unwrap().runtime;
+         assert_eq!(runtime("The Godfather"), Some(175));
+         assert_eq!(runtime("Untimed"), None);
+         assert_eq!(runtime("Zero Minutes"), None);
+         assert_eq!(runtime("Endless"), None);
+         assert_eq!(runtime("Garbled"), None);
+     }
+ 
+     #[test]
+     fn test_read_csv_critic_and_audience_columns() {
+         let movies = read_csv("movies_sample_critics.csv", &ParseOptions::default()).unwrap();
+         assert_eq!(movies.len(), 7);
+         let ratings = |title: &str| {
+             let movie = movies.iter().find(|m| m.title == title).unwrap();
+             (movie.rating, movie.critic_rating, movie.audience_rating)
+         };
+         assert_eq!(ratings("Heat"), (Some(8.3), Some(8.6), Some(9.1)));
+         // An empty or invalid critic rating leaves only that rating unset
+         assert_eq!(ratings("Chocolat"), (Some(7.2), None, Some(7.9)));
+         assert_eq!(ratings("Snatch"), (Some(8.3), None, Some(8.9)));
+ 
+         let highest = |source| -> Vec<&str> {
+             highest_rated_per_year(&movies, YearOrder::Ascending, source).iter().map(|(_, m)| m.title.as_str()).collect()
+         };
+         assert_eq!(highest(RatingSource::Rating), ["Se7en", "Gladiator"]);
+         assert_eq!(highest(RatingSource::Critic), ["Casino", "Memento"]);
+         assert_eq!(highest(RatingSource::Audience), ["Se7en", "Gladiator"]);
+         assert_eq!(highest(RatingSource::Max), ["Casino", "Gladiator"]);
+ 
+         let output = render(|out| show_highest_rated_movies(out, &movies, YearOrder::Ascending, RatingSource::Critic, 1, None));
+         assert_eq!(
+             output,
+             "Using critic ratings\n1995 9.7 Casino\n2000 9.2 Memento\n(2 unrated movies not included)\n-- 2 movies --\n"
+         );
+         let filter = MovieFilter::new().min_rating(9.0).rating_source(RatingSource::Audience);
+         let titles: Vec<&str> = filter.apply(&movies).map(|m| m.title.as_str()).collect();
+         assert_eq!(titles, ["Heat", "Se7en", "Gladiator"]);
+         assert!(!filter.is_empty());
+         assert!(MovieFilter::new().rating_source(RatingSource::Max).is_empty());
+     }
+ 
+     #[test]
+     fn test_critic_and_audience_columns_need_both_headers() {
+         let headers = |names: &[&str]| RecordLayout::from_headers(&StringRecord::from(names.to_vec()));
+         let layout = headers(&["Title", "Year", "Languages", "Rating", "AudienceRating", "criticrating"]);
+         assert_eq!(layout.critic_and_audience, Some((5, 4)));
+         assert_eq!(headers(&["Title", "Year", "Languages", "Rating", "CriticRating"]).critic_and_audience, None);
+ 
+         // The single-rating files keep the Rating column only
+         let movies = read_csv("movies_sample_1.csv", &ParseOptions::default()).unwrap();
+         assert!(movies.iter().all(|m| m.critic_rating.is_none() && m.audience_rating.is_none()));
+         assert!(highest_rated_per_year(&movies, YearOrder::Ascending, RatingSource::Critic).is_empty());
+         assert_eq!(
+             highest_rated_per_year(&movies, YearOrder::Ascending, RatingSource::Rating).len(),
+             count_by_year(&movies).len()
+         );
+     }
+ 
+     #[test]
+     fn test_genres_fall_back_to_the_fifth_column() {
+         let headers = |names: &[&str]| RecordLayout::from_headers(&StringRecord::from(names.to_vec()));
+         assert_eq!(headers(&["Title", "Year", "Languages", "Rating", " "]).genres, Some(4));
+         assert_eq!(headers(&["Title", "Year", "Languages", "Rating", "Runtime", "Genres"]).genres, Some(5));
+         assert_eq!(headers(&["Title", "Year", "Languages", "Rating", "runtime"]).genres, None);
+         assert_eq!(headers(&["Title", "Year", "Languages", "Rating", "Director"]).genres, None);
+         assert_eq!(headers(&["Title", "Year", "Languages", "Rating", "CriticRating", "AudienceRating"]).genres, None);
+         assert_eq!(headers(&["Title", "Year", "Languages", "Rating"]).genres, None);
+ 
+         let dir = tempfile::tempdir().unwrap();
+         let path = dir.path().join("movies_unnamed_genres.csv");
+         std::fs::write(&path, "Title,Year,Languages,Rating Value,\nHeat,1995,[English],8.3,[Crime;Drama]\n").unwrap();
+         let movies = read_csv(path.to_str().unwrap(), &ParseOptions::default()).unwrap();
+         assert_eq!(movies.front().unwrap().genres, ["Crime", "Drama"]);
+ 
+         // A named fifth column that is not Genres is ignored rather than rejecting every row
+         let path = dir.path().join("movies_director.csv");
+         std::fs::write(&path, "Title,Year,Languages,Rating Value,Director\nHeat,1995,[English],8.3,Michael Mann\n").unwrap();
+         let movies = read_csv(path.to_str().unwrap(), &ParseOptions::default()).unwrap();
+         assert_eq!(movies.len(), 1);
+         assert!(movies.front().unwrap().genres.is_empty());
+     }
+ 
+     #[test]
+     fn test_rating_scale_parse() {
+         assert_eq!(RatingScale::parse("0,100"), Ok(RatingScale { min: 0.0, max: 100.0 }));
+         assert_eq!(RatingScale::parse(" 1 , 5.5 "), Ok(RatingScale { min: 1.0, max: 5.5 }));
+         assert!(RatingScale::parse("100").is_err());
+         assert!(RatingScale::parse("0,ten").is_err());
+         assert!(RatingScale::parse("10,1").is_err());
+         assert!(RatingScale::parse("5,5").is_err());
+         assert!(RatingScale::parse("0,inf").is_err());
+         assert_eq!(RatingScale::default().to_string(), "1.0-10.0");
+     }
+ 
+     #[test]
+     fn test_read_csv_rating_scale() {
+         // Every rating in the file is outside the default 1-10 scale
+         let movies = read_csv("movies_sample_scale100.csv", &ParseOptions::default()).unwrap();
+         assert_eq!(movies.len(), 6);
+         assert!(movies.iter().all(|m| m.rating.is_none()));
+ 
+         let options = ParseOptions { rating_scale: RatingScale::parse("0,100").unwrap(), ..ParseOptions::default() };
+         let movies = read_csv("movies_sample_scale100.csv", &options).unwrap();
+         let ratings: Vec<(&str, Option<f32>)> = movies.iter().map(|m| (m.title.as_str(), m.rating)).collect();
+         assert_eq!(
+             ratings,
+             vec![
+                 ("Amelie", Some(83.5)),
+                 ("Memento", Some(84.25)),
+                 ("Spirited Away", Some(86.0)),
+                 ("Gigli", Some(0.0)),
+                 ("Overrated", None),
+                 ("Underrated", None),
+             ]
+         );
+     }
+ 
+     #[test]
+     fn test_rating_precision() {
+         let options = ParseOptions { rating_scale: RatingScale::parse("0,100").unwrap(), ..ParseOptions::default() };
+         let movies = read_csv("movies_sample_scale100.csv", &options).unwrap();
+ 
+         let output = render(|out| show_highest_rated_movies(out, &movies, YearOrder::Ascending, RatingSource::Rating, 2, None));
+         assert!(output.starts_with("2000 84.25 Memento\n2001 86.00 Spirited Away\n2003 0.00 Gigli\n"), "{}", output);
+         let output = render(|out| show_movie_details(out, &movies, "amelie", true, 2));
+         assert!(output.contains("Rating: 83.50\n"), "{}", output);
+         let output = render(|out| show_movie_details(out, &movies, "amelie", true, 0));
+         assert!(output.contains("Rating: 84\n"), "{}", output);
+     }
+ 
+     #[test]
+     fn test_read_csv_keeps_invalid_rating_text() {
+         let movies = read_csv("movies_sample_unrated.csv", &ParseOptions::default()).unwrap();
+         assert_eq!(movies.len(), 5);
+ 
+         let ratings: Vec<(Option<f32>, Option<&str>)> =
+             movies.iter().map(|m| (m.rating, m.invalid_rating.as_deref())).collect();
+         assert_eq!(
+             ratings,
+             vec![(Some(9.2), None), (None, Some("N/A")), (None, Some("")), (None, Some("11.5")), (Some(8.3), None)]
+         );
+     }
+ 
+     #[test]
+     fn test_show_unrated_movies() {
+         let movies = read_csv("movies_sample_unrated.csv", &ParseOptions::default()).unwrap();
+         let output = render(|out| show_unrated_movies(out, &movies, None));
+         assert_eq!(
+             output,
+             "1972 Pending Review (rating 'N/A')\n1985 Blank Slate (rating missing)\n1985 Off The Charts (rating '11.5')\n-- 3 movies --\n"
+         );
+         let output = render(|out| show_unrated_movies(out, &sample_movies(), None));
+         assert_eq!(output, "All movies have a valid rating.\n");
+     }
+ 
+     #[test]
+     fn test_rating_reports_exclude_unrated_movies() {
+         let movies = read_csv("movies_sample_unrated.csv", &ParseOptions::default()).unwrap();
+         let output = render(|out| show_highest_rated_movies(out, &movies, YearOrder::Ascending, RatingSource::Rating, RATING_PRECISION, None));
+         assert_eq!(output, "1972 9.2 The Godfather\n1995 8.3 Heat\n(3 unrated movies not included)\n-- 2 movies --\n");
+         let output = render(|out| show_rating_stats(out, &movies, YearOrder::Ascending, None));
+         assert!(output.ends_with("(3 unrated movies not included)\n-- 2 years --\n"));
+         assert!(!output.contains("1985"));
+ 
+         // Queries that do not depend on the rating still include unrated movies
+         let output = render(|out| show_movies_by_year(out, &movies, 1985, None));
+         assert_eq!(output, "Blank Slate\nOff The Charts\n-- 2 movies --\n");
+         let output = render(|out| show_movie_details(out, &movies, "Pending Review", true, RATING_PRECISION));
+         assert!(output.contains("Rating: unrated (invalid value 'N/A')\n"));
+     }
+ 
+     #[test]
+     fn test_pick_random_movie_min_rating_excludes_unrated() {
+         let movies = read_csv("movies_sample_unrated.csv", &ParseOptions::default()).unwrap();
+         let filter = MovieFilter::new().min_rating(1.0);
+         let mut rng = StdRng::seed_from_u64(3);
+         for _ in 0..10 {
+             assert!(pick_random_movie(&movies, &filter, &mut rng).unwrap().rating.is_some());
+         }
+     }
+ 
+     #[test]
+     fn test_show_fuzzy_matches() {
+         let movies = sample_movies();
+         let titles = fuzzy::TitleIndex::new(&movies, true);
+         let output = render(|out| show_fuzzy_matches(out, &movies, &titles, "Shawshenk", None));
+         assert_eq!(output, "0.89 The Shawshank Redemption (1994)\n-- 1 title --\n");
+         let output = render(|out| show_fuzzy_matches(out, &movies, &titles, "Casablanca", None));
+         assert_eq!(output, "No titles similar to Casablanca\n");
+     }
+ 
+     /// Returns the entries `parse_bracketed_list` finds in `field`.
+     fn entries(field: &str) -> Option<Vec<&str>> {
+         parse_bracketed_list(field).map(|list| list.entries)
+     }
+ 
+     #[test]
+     fn test_parse_bracketed_list_grammar() {
+         assert_eq!(entries("[English;French]"), Some(vec!["English", "French"]));
+         // Surrounding whitespace and whitespace around entries
+         assert_eq!(entries("  [ English ; French ]  "), Some(vec!["English", "French"]));
+         // A trailing semicolon and empty entries are dropped
+         assert_eq!(entries("[English;]"), Some(vec!["English"]));
+         assert_eq!(entries("[English;;French]"), Some(vec!["English", "French"]));
+         // An empty list
+         assert_eq!(entries("[]"), Some(vec![]));
+         assert_eq!(entries("[ ; ]"), Some(vec![]));
+         // Quoted entries keep semicolons and brackets
+         assert_eq!(entries(r#"["Hindi; Urdu";English]"#), Some(vec!["Hindi; Urdu", "English"]));
+         assert_eq!(entries(r#"[ "Klingon [tlh]" ]"#), Some(vec!["Klingon [tlh]"]));
+     }
+ 
+     #[test]
+     fn test_parse_bracketed_list_commas() {
+         assert_eq!(entries("[English, French]"), Some(vec!["English", "French"]));
+         assert_eq!(entries("[English,French,]"), Some(vec!["English", "French"]));
+         assert_eq!(entries(r#"["Hindi, Urdu", English]"#), Some(vec!["Hindi, Urdu", "English"]));
+         assert!(!parse_bracketed_list("[English, French]").unwrap().mixed_separators);
+ 
+         // Semicolons win when both separators appear
+         let list = parse_bracketed_list("[Arabic; English, French]").unwrap();
+         assert_eq!(list.entries, vec!["Arabic", "English, French"]);
+         assert!(list.mixed_separators);
+         // Separators inside quotes do not count
+         assert!(!parse_bracketed_list(r#"["Hindi; Urdu", English]"#).unwrap().mixed_separators);
+     }
+ 
+     #[test]
+     fn test_read_csv_comma_separated_languages() {
+         let movies = read_csv("movies_sample_commas.csv", &ParseOptions::default()).unwrap();
+         let parsed: Vec<(&str, Vec<&str>)> = movies
+             .iter()
+             .map(|m| (m.title.as_str(), m.languages.iter().map(|l| &**l).collect()))
+             .collect();
+         // The six-language row fails the existing limit after splitting on commas
+         assert_eq!(
+             parsed,
+             vec![
+                 ("Amelie", vec!["French", "English"]),
+                 ("Heat", vec!["English", "Spanish"]),
+                 ("Babel", vec!["Arabic", "English, French"]),
+                 ("Mediterraneo", vec!["Italian", "Greek"]),
+             ]
+         );
+     }
+ 
+     #[test]
+     fn test_parse_bracketed_list_rejects_malformed_fields() {
+         assert_eq!(entries("English;French"), None);
+         assert_eq!(entries("[English"), None);
+         assert_eq!(entries("English]"), None);
+         assert_eq!(entries(""), None);
+         // Unquoted brackets inside the list
+         assert_eq!(entries("[[English]]"), None);
+         assert_eq!(entries("[English];[French]"), None);
+         // Unterminated or partial quotes
+         assert_eq!(entries(r#"["English]"#), None);
+         assert_eq!(entries(r#"[Eng"lish"]"#), None);
+         assert_eq!(entries(r#"["Eng"lish]"#), None);
+     }
+ 
+     #[test]
+     fn test_read_csv_quoted_titles_and_languages() {
+         let movies = read_csv("movies_sample_quoted.csv", &ParseOptions::default()).unwrap();
+         let parsed: Vec<(&str, Vec<&str>)> = movies
+             .iter()
+             .map(|m| (m.title.as_str(), m.languages.iter().map(|l| &**l).collect()))
+             .collect();
+         assert_eq!(
+             parsed,
+             vec![
+                 ("Crouching Tiger, Hidden Dragon", vec!["Chinese"]),
+                 ("The \"Burbs\"", vec!["English"]),
+                 ("Monsoon Wedding", vec!["Hindi; Urdu", "English"]),
+                 ("[Rec]", vec!["Spanish"]),
+                 ("The Artist", vec![]),
+                 ("Hello, \"World\"; Again", vec!["English", "French"]),
+             ]
+         );
+     }
+ 
+     #[test]
+     fn test_read_csv_empty_languages_option() {
+         let options = ParseOptions { empty_languages: EmptyLanguages::Skip, ..ParseOptions::default() };
+         let movies = read_csv("movies_sample_quoted.csv", &options).unwrap();
+         assert_eq!(movies.len(), 5);
+         assert!(movies.iter().all(|m| m.title != "The Artist"));
+     }
+ 
+     #[test]
+     fn test_read_csv_normalizes_languages() {
+         let movies = read_csv("movies_sample_languages.csv", &ParseOptions::default()).unwrap();
+         let languages: Vec<Vec<&str>> =
+             movies.iter().map(|m| m.languages.iter().map(|lang| &**lang).collect()).collect();
+         assert_eq!(
+             languages,
+             vec![
+                 vec!["English", "Italian"],
+                 vec!["French"],
+                 vec!["English"],
+                 vec!["English"],
+                 vec!["Japanese", "English"],
+                 vec!["Klingon", "English"],
+             ]
+         );
+     }
+ 
+     #[test]
+     fn test_show_distinct_languages_merges_aliases() {
+         let movies = read_csv("movies_sample_languages.csv", &ParseOptions::default()).unwrap();
+         let output = render(|out| show_distinct_languages(out, &movies, None));
+         assert_eq!(output, "English 5\nFrench 1\nItalian 1\nJapanese 1\nKlingon 1\n-- 5 languages --\n");
+         let output = render(|out| show_distinct_languages(out, &LinkedList::new(), None));
+         assert_eq!(output, "No languages found in the data\n");
+     }
+ 
+     #[test]
+     fn test_longest_and_shortest() {
+         let movies = read_csv("movies_sample_runtime.csv", &ParseOptions::default()).unwrap();
+         let (longest, shortest) = longest_and_shortest(&movies, 1);
+         assert_eq!(longest[0].title, "The Godfather");
+         assert_eq!(shortest[0].title, "Toy Story");
+ 
+         // Asking for more movies than are timed returns every timed movie
+         let (longest, shortest) = longest_and_shortest(&movies, 10);
+         assert_eq!(longest.len(), 2);
+         assert_eq!(shortest.len(), 2);
+         assert!(longest_and_shortest(&sample_movies(), 3).0.is_empty());
+     }
+ 
+     #[test]
+     fn test_average_runtime_by_year() {
+         let mut movies = LinkedList::new();
+         for (title, minutes) in [("A", Some(100)), ("B", Some(121)), ("C", None)] {
+             movies.push_back(Movie { runtime: minutes, ..movie(title, 1995, 7.0) });
+         }
+         movies.push_back(movie("D", 1996, 7.0));
+ 
+         let averages = average_runtime_by_year(&movies);
+         assert_eq!(averages[&1995], 110.5);
+         assert!(!averages.contains_key(&1996));
+     }
+ 
+     #[test]
+     fn test_highest_rated_csv_round_trip() {
+         let mut movies = sample_movies();
+         movies.push_back(movie("Crouching Tiger, \"Hidden\" Dragon", 2000, 7.9));
+ 
+         let dir = tempfile::tempdir().unwrap();
+         let path = dir.path().join("highest.csv");
+         let destination = path.to_str().unwrap();
+         export_highest_rated_csv(&mut Vec::new(), &movies, destination, YearOrder::Ascending, RatingSource::Rating, RATING_PRECISION)
+             .unwrap();
+ 
+         let mut rdr = csv::Reader::from_path(&path).unwrap();
+         assert_eq!(rdr.headers().unwrap(), vec!["year", "rating", "title"]);
+         let rows: Vec<(i32, Option<f32>, String)> = rdr.deserialize().map(|row| row.unwrap()).collect();
+         let expected: Vec<(i32, Option<f32>, String)> = highest_rated_per_year(&movies, YearOrder::Ascending, RatingSource::Rating)
+             .into_iter()
+             .map(|(year, movie)| (year, movie.rating, movie.title.clone()))
+             .collect();
+         assert_eq!(rows, expected);
+     }
+ 
+     /// Parses a file holding the header and `row`, returning the skip records reported.
+     fn skip_records(row: &str, options: &ParseOptions) -> Vec<SkipRecord> {
+         let dir = tempfile::tempdir().unwrap();
+         let path = dir.path().join("movies_skipped.csv");
+         std::fs::write(&path, format!("Title,Year,Languages,Rating Value,Genres\nFine,2001,[English],7,\n{}\n", row)).unwrap();
+         let mut skipped = Vec::new();
+         parse_csv(path.to_str().unwrap(), options, |record, _| skipped.extend(record), |_| {}).unwrap();
+         skipped
+     }
+ 
+     /// Asserts that `row` produces exactly one skip record on line 3.
+     fn assert_skipped(row: &str, reason: SkipReason, value: &str) {
+         let expected = SkipRecord { line: 3, reason, value: value.to_string() };
+         assert_eq!(skip_records(row, &ParseOptions::default()), vec![expected]);
+     }
+ 
+     #[test]
+     fn test_skip_reason_missing_title() {
+         assert_skipped(" ,2001,[English],7,", SkipReason::MissingTitle, "");
+     }
+ 
+     #[test]
+     fn test_skip_reason_invalid_year() {
+         assert_skipped("Thor,19x4,[English],7,", SkipReason::InvalidYear, "19x4");
+         assert_skipped("Thor,,[English],7,", SkipReason::InvalidYear, "");
+     }
+ 
+     #[test]
+     fn test_skip_reason_invalid_languages() {
+         assert_skipped("Thor,2011,English,7,", SkipReason::InvalidLanguages, "English");
+         let options = ParseOptions { empty_languages: EmptyLanguages::Skip, ..ParseOptions::default() };
+         let skipped = skip_records("Thor,2011,[],7,", &options);
+         assert_eq!(skipped.iter().map(|record| record.reason).collect::<Vec<_>>(), vec![SkipReason::InvalidLanguages]);
+     }
+ 
+     #[test]
+     fn test_skip_reason_too_many_languages() {
+         assert_skipped("Thor,2011,[a;b;c;d;e;f],7,", SkipReason::TooManyLanguages, "[a;b;c;d;e;f]");
+     }
+ 
+     #[test]
+     fn test_skip_reason_language_too_long() {
+         assert_skipped("Thor,2011,[English;Extremely Long Language],7,", SkipReason::LanguageTooLong, "Extremely Long Language");
+     }
+ 
+     #[test]
+     fn test_skip_reason_invalid_genres() {
+         assert_skipped("Thor,2011,[English],7,Action", SkipReason::InvalidGenres, "Action");
+     }
+ 
+     #[test]
+     fn test_skip_reason_invalid_rating() {
+         assert_skipped("Thor,2011,[English],eleven,", SkipReason::InvalidRating, "eleven");
+     }
+ 
+     #[test]
+     fn test_read_csv_with_stats_counts() {
+         let (movies, stats) = read_csv_with_stats("movies_sample_genres.csv", &ParseOptions::default()).unwrap();
+         assert_eq!((stats.records, stats.movies, stats.skipped), (6, 5, 1));
+         assert_eq!(stats.movies, movies.len());
+         assert_eq!(stats.comma_ratings, 0);
+         assert_eq!(stats.estimated_bytes, estimated_size(&movies));
+ 
+         let (movies, stats) = read_csv_with_stats("movies_sample_1.csv", &ParseOptions::default()).unwrap();
+         assert_eq!((stats.records, stats.movies, stats.skipped), (24, 24, 0));
+         let titles: usize = movies.iter().map(|movie| movie.title.len()).sum();
+         assert!(stats.estimated_bytes > 24 * std::mem::size_of::<Movie>() + titles);
+     }
+ 
+     #[test]
+     fn test_estimated_size_counts_shared_languages_once() {
+         let mut movies = LinkedList::new();
+         movies.push_back(movie_in("A", None, &["English"]));
+         let one = estimated_size(&movies);
+         let mut shared = movie_in("A", None, &[]);
+         shared.languages = movies.front().unwrap().languages.clone();
+         movies.push_back(shared);
+         // The second movie adds its node, title, and language handle but not the name
+         let name = 2 * std::mem::size_of::<usize>() + "English".len();
+         assert_eq!(estimated_size(&movies), 2 * one - name);
+     }
+ 
+     #[test]
+     fn test_compare_years() {
+         let mut movies = LinkedList::new();
+         movies.push_back(Movie { year: 1994, ..movie_in("Chungking Express", Some(8.0), &["Cantonese", "English"]) });
+         movies.push_back(Movie { year: 1994, ..movie_in("Three Colors: Red", Some(8.1), &["French"]) });
+         movies.push_back(Movie { year: 1994, ..movie_in("Unrated", None, &["German"]) });
+         movies.push_back(Movie { year: 2004, ..movie_in("Oldboy", Some(8.1), &["Korean", "English"]) });
+         movies.push_back(Movie { year: 2004, ..movie_in("The Sea Inside", Some(8.1), &["Spanish"]) });
+ 
+         let comparison = compare_years(&movies, 1994, 2004);
+         let first = &comparison.first;
+         assert_eq!((first.year, first.count), (1994, 3));
+         assert!((first.average_rating.unwrap() - 8.05).abs() < 1e-6);
+         assert_eq!(first.top.unwrap().title, "Three Colors: Red");
+         assert_eq!(first.unique_languages, ["Cantonese", "French", "German"]);
+         let second = comparison.second.as_ref().unwrap();
+         assert_eq!(second.top.unwrap().title, "Oldboy");
+         assert_eq!(second.unique_languages, ["Korean", "Spanish"]);
+ 
+         let comparison = compare_years(&movies, 2004, 1950);
+         let empty = comparison.second.unwrap();
+         assert_eq!((empty.count, empty.average_rating), (0, None));
+         assert!(empty.top.is_none());
+         assert!(empty.unique_languages.is_empty());
+         assert_eq!(comparison.first.unique_languages, ["English", "Korean", "Spanish"]);
+ 
+         let comparison = compare_years(&movies, 1994, 1994);
+         assert!(comparison.second.is_none());
+         assert_eq!(comparison.first.unique_languages, ["Cantonese", "English", "French", "German"]);
+     }
+ 
+     #[test]
+     fn test_decimal_comma_rating() {
+         assert_eq!(decimal_comma_rating("8,7").as_deref(), Some("8.7"));
+         assert_eq!(decimal_comma_rating("10,25").as_deref(), Some("10.25"));
+         assert_eq!(decimal_comma_rating("1,234"), None);
+         assert_eq!(decimal_comma_rating("8.7"), None);
+         assert_eq!(decimal_comma_rating("8,"), None);
+         assert_eq!(decimal_comma_rating(",7"), None);
+         assert_eq!(decimal_comma_rating("8,7,1"), None);
+         assert_eq!(decimal_comma_rating("-8,7"), None);
+     }
+ 
+     #[test]
+     fn test_read_csv_decimal_comma_ratings() {
+         let dir = tempfile::tempdir().unwrap();
+         let path = dir.path().join("movies_comma.csv");
+         let rows = "Amélie,2001,[French],\"8,7\"\nHeat,1995,[English],\"1,234\"\nMemento,2000,[English],8.7\n";
+         std::fs::write(&path, format!("Title,Year,Languages,Rating Value\n{}", rows)).unwrap();
+         let (mut movies, mut messages) = (Vec::new(), Vec::new());
+         let report = |_, message| messages.push(message);
+         parse_csv(path.to_str().unwrap(), &ParseOptions::default(), report, |movie| movies.push(movie)).unwrap();
+ 
+         let ratings: Vec<(Option<f32>, Option<&str>)> =
+             movies.iter().map(|m| (m.rating, m.invalid_rating.as_deref())).collect();
+         assert_eq!(ratings, vec![(Some(8.7), None), (None, Some("1,234")), (Some(8.7), None)]);
+         assert_eq!(messages.last().unwrap(), "Read 1 ratings written with a decimal comma, such as '8,7'.");
+     }
+ 
+     #[test]
+     fn test_skip_reason_codes_match_json() {
+         for reason in SkipReason::ALL {
+             let record = SkipRecord { line: 1, reason, value: String::new() };
+             assert!(record.to_json().contains(&format!("\"reason\":\"{}\"", reason.code())), "{:?}", reason);
+         }
+     }
+ 
+     #[test]
+     fn test_validate_csv_messy_file() {
+         let report = validate_csv("movies_sample_messy.csv", &ParseOptions::default()).unwrap();
+         assert_eq!(report.rows, 13);
+         assert_eq!(report.valid, 4);
+         assert_eq!(report.comma_ratings, 1);
+         assert!(!report.is_clean());
+         let summary = |reason| report.reasons.get(&reason).cloned().unwrap_or_default();
+         assert_eq!(summary(SkipReason::InvalidRating), ReasonSummary { count: 5, examples: vec![4, 7, 9, 10, 14] });
+         assert_eq!(summary(SkipReason::InvalidYear), ReasonSummary { count: 2, examples: vec![3, 12] });
+         assert_eq!(summary(SkipReason::MissingTitle).count, 1);
+         assert_eq!(summary(SkipReason::TooManyLanguages).count, 1);
+         assert!(!report.reasons.contains_key(&SkipReason::InvalidGenres));
+ 
+         assert_eq!(
+             report.to_string(),
+             "Rows: 13\n\
+              Valid rows: 4\n\
+              Decimal comma ratings: 1\n  \
+              missing_title: 1 (lines 5)\n  \
+              invalid_year: 2 (lines 3, 12)\n  \
+              too_many_languages: 1 (lines 6)\n  \
+              invalid_rating: 5 (lines 4, 7, 9, 10, 14)\n"
+         );
+ 
+         let report = validate_csv("movies_sample_genres.csv", &ParseOptions::default()).unwrap();
+         assert_eq!(report.reasons.keys().copied().collect::<Vec<_>>(), vec![SkipReason::InvalidGenres]);
+     }
+ 
+     #[test]
+     fn test_skip_record_json() {
+         let record = SkipRecord { line: 17, reason: SkipReason::InvalidYear, value: "19x4".to_string() };
+         assert_eq!(record.to_json(), r#"{"line":17,"reason":"invalid_year","value":"19x4"}"#);
+         let record = SkipRecord { line: 4, reason: SkipReason::TooManyLanguages, value: "say \"hi\"".to_string() };
+         assert_eq!(record.to_json(), r#"{"line":4,"reason":"too_many_languages","value":"say \"hi\""}"#);
+     }
+ }
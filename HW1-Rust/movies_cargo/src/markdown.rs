@@ -0,0 +1,132 @@
+/*
+ * Markdown table output.
+ *
+ * Query results are rendered as GitHub-flavored Markdown tables so they can be
+ * pasted into issues and documents. Columns are not padded to a common width;
+ * Markdown renderers align them anyway. Characters that Markdown would treat as
+ * table separators or emphasis are escaped with a backslash.
+ */
+
+ use std::io::{self, Write};
+ use crate::{Movie, RatingSource, RatingStats};
+ 
+ /// Escapes the characters in `text` that would end a table cell or be rendered
+ /// as emphasis or code.
+ pub fn escape_cell(text: &str) -> String {
+     let mut escaped = String::with_capacity(text.len());
+     for c in text.chars() {
+         if matches!(c, '\\' | '|' | '*' | '_' | '`') {
+             escaped.push('\\');
+         }
+         escaped.push(c);
+     }
+     escaped
+ }
+ 
+ /// Writes one table row from already-escaped cells.
+ fn write_row(out: &mut impl Write, cells: &[String]) -> io::Result<()> {
+     writeln!(out, "| {} |", cells.join(" | "))
+ }
+ 
+ /// Writes the header row and separator line of a table.
+ fn write_header(out: &mut impl Write, columns: &[&str]) -> io::Result<()> {
+     writeln!(out, "| {} |", columns.join(" | "))?;
+     writeln!(out, "|{}", " --- |".repeat(columns.len()))
+ }
+ 
+ /// Writes movies as a table with Title, Year, Rating, and Languages columns. The
+ /// Rating column holds the rating `source` selects and is headed accordingly,
+ /// such as "Critic rating".
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the table is written to.
+ /// * `movies` - The movies to list, one row each, in the order given.
+ /// * `source` - The rating shown.
+ /// * `precision` - The number of decimal places ratings are shown with.
+ pub fn write_movie_table<'a>(
+     out: &mut impl Write,
+     movies: impl IntoIterator<Item = &'a Movie>,
+     source: RatingSource,
+     precision: usize,
+ ) -> io::Result<()> {
+     write_header(out, &["Title", "Year", source.heading(), "Languages"])?;
+     for movie in movies {
+         let languages: Vec<&str> = movie.languages.iter().map(|language| &**language).collect();
+         write_row(out, &[
+             escape_cell(&movie.title),
+             movie.year.to_string(),
+             movie.rating_label_from(source, precision),
+             escape_cell(&languages.join(", ")),
+         ])?;
+     }
+     Ok(())
+ }
+ 
+ /// Writes rating statistics as a table with Year, Min, Median, P90, and Max columns.
+ ///
+ /// # Arguments
+ ///
+ /// * `out` - The writer the table is written to.
+ /// * `stats` - The statistics of each year, in the order given.
+ pub fn write_rating_stats_table(out: &mut impl Write, stats: &[(i32, RatingStats)]) -> io::Result<()> {
+     write_header(out, &["Year", "Min", "Median", "P90", "Max"])?;
+     for (year, stats) in stats {
+         write_row(out, &[
+             year.to_string(),
+             format!("{:.1}", stats.min),
+             format!("{:.2}", stats.median),
+             format!("{:.1}", stats.p90),
+             format!("{:.1}", stats.max),
+         ])?;
+     }
+     Ok(())
+ }
+ 
+ #[cfg(test)]
+ mod tests {
+     use super::*;
+     use std::sync::Arc;
+ 
+     #[test]
+     fn test_escape_cell() {
+         assert_eq!(escape_cell("Pipe | Dreams"), "Pipe \\| Dreams");
+         assert_eq!(escape_cell("*batteries not included"), "\\*batteries not included");
+         assert_eq!(escape_cell("a\\b_c`d"), "a\\\\b\\_c\\`d");
+         assert_eq!(escape_cell("Amélie"), "Amélie");
+     }
+ 
+     #[test]
+     fn test_write_movie_table() {
+         let movie = Movie {
+             title: "Pipe | Dreams".to_string(),
+             year: 1976,
+             languages: vec![Arc::from("English"), Arc::from("French")],
+             genres: Vec::new(),
+             rating: None,
+             invalid_rating: None,
+             runtime: None,
+             critic_rating: None,
+             audience_rating: None,
+         };
+         let mut out = Vec::new();
+         write_movie_table(&mut out, [&movie], RatingSource::Rating, 1).unwrap();
+         assert_eq!(
+             String::from_utf8(out).unwrap(),
+             "| Title | Year | Rating | Languages |\n\
+              | --- | --- | --- | --- |\n\
+              | Pipe \\| Dreams | 1976 | unrated | English, French |\n"
+         );
+     }
+ 
+     #[test]
+     fn test_write_rating_stats_table() {
+         let stats = RatingStats { min: 6.5, median: 7.25, p90: 8.0, max: 8.1 };
+         let mut out = Vec::new();
+         write_rating_stats_table(&mut out, &[(2012, stats)]).unwrap();
+         assert_eq!(
+             String::from_utf8(out).unwrap(),
+             "| Year | Min | Median | P90 | Max |\n| --- | --- | --- | --- | --- |\n| 2012 | 6.5 | 7.25 | 8.0 | 8.1 |\n"
+         );
+     }
+ }
@@ -0,0 +1,93 @@
+/*
+ * Newline-delimited JSON output.
+ *
+ * Query results are written as one JSON object per line, with no enclosing array,
+ * so tools such as jq can process them a line at a time. A movie is written with
+ * the same fields it is serialized with everywhere else; its languages are names.
+ * Each line is flushed as soon as it is written so a reader sees it immediately.
+ */
+
+ use std::io::{self, Write};
+ use serde::Serialize;
+ use crate::{Movie, RatingStats};
+ 
+ /// Writes `value` as one line of JSON and flushes it.
+ fn write_line(out: &mut impl Write, value: &impl Serialize) -> io::Result<()> {
+     serde_json::to_writer(&mut *out, value)?;
+     writeln!(out)?;
+     out.flush()
+ }
+ 
+ /// Writes each movie as a JSON object on its own line.
+ ///
+ /// # Returns
+ ///
+ /// * `io::Result<usize>` - The number of movies written.
+ pub fn write_movies<'a>(out: &mut impl Write, movies: impl IntoIterator<Item = &'a Movie>) -> io::Result<usize> {
+     let mut count = 0;
+     for movie in movies {
+         write_line(out, movie)?;
+         count += 1;
+     }
+     Ok(count)
+ }
+ 
+ /// Writes the rating statistics of each year as a JSON object with `year`, `min`,
+ /// `median`, `p90`, and `max` fields on its own line.
+ pub fn write_rating_stats(out: &mut impl Write, stats: &[(i32, RatingStats)]) -> io::Result<()> {
+     /// One line of the statistics, keeping the ratings as `f32` so they are
+     /// written as short as they were read.
+     #[derive(Serialize)]
+     struct YearStats {
+         year: i32,
+         min: f32,
+         median: f32,
+         p90: f32,
+         max: f32,
+     }
+ 
+     for &(year, RatingStats { min, median, p90, max }) in stats {
+         write_line(out, &YearStats { year, min, median, p90, max })?;
+     }
+     Ok(())
+ }
+ 
+ #[cfg(test)]
+ mod tests {
+     use super::*;
+     use std::sync::Arc;
+     use serde_json::{json, Value};
+ 
+     #[test]
+     fn test_write_movies_one_object_per_line() {
+         let movie = Movie {
+             title: "Heat, \"the\" movie".to_string(),
+             year: 1995,
+             languages: vec![Arc::from("English"), Arc::from("Spanish")],
+             genres: vec!["Crime".to_string()],
+             rating: Some(8.3),
+             invalid_rating: None,
+             runtime: Some(170),
+             critic_rating: None,
+             audience_rating: None,
+         };
+         let mut out = Vec::new();
+         assert_eq!(write_movies(&mut out, [&movie, &movie]).unwrap(), 2);
+         let output = String::from_utf8(out).unwrap();
+         assert_eq!(output.lines().count(), 2);
+         for line in output.lines() {
+             let value: Value = serde_json::from_str(line).unwrap();
+             assert_eq!(value["title"], "Heat, \"the\" movie");
+             assert_eq!(value["languages"], json!(["English", "Spanish"]));
+             assert_eq!(value["runtime"], 170);
+         }
+     }
+ 
+     #[test]
+     fn test_write_rating_stats() {
+         let stats = RatingStats { min: 6.5, median: 7.25, p90: 8.0, max: 8.1 };
+         let mut out = Vec::new();
+         write_rating_stats(&mut out, &[(2012, stats)]).unwrap();
+         assert_eq!(String::from_utf8(out).unwrap(), "{\"year\":2012,\"min\":6.5,\"median\":7.25,\"p90\":8.0,\"max\":8.1}\n");
+     }
+ }
@@ -0,0 +1,238 @@
+/*
+ * Typed command prompt.
+ *
+ * --repl replaces the numbered menu with a `movies>` prompt that accepts the same
+ * commands as --script, parsed by the same tokenizer, plus `help` and `quit`. Built
+ * with the `readline` cargo feature, the prompt also has line editing, history, and
+ * tab completion of command names.
+ */
+
+ use std::collections::LinkedList;
+ use std::io::{self, BufRead, ErrorKind, Write};
+ use movies_cargo::languages::LanguageMap;
+ use movies_cargo::Movie;
+ use crate::commands::{self, Command, ReportOptions};
+ 
+ /// The prompt shown before each command.
+ pub const PROMPT: &str = "movies> ";
+ 
+ /// A source of command lines for the prompt.
+ pub trait LineReader {
+     /// Shows `prompt` and reads one line.
+     ///
+     /// # Errors
+     ///
+     /// Fails with `ErrorKind::Interrupted` when the user cancels the line and with
+     /// `ErrorKind::UnexpectedEof` at the end of input.
+     fn read_line(&mut self, prompt: &str) -> io::Result<String>;
+ }
+ 
+ /// Reads lines from a buffered reader, writing the prompt to stdout.
+ pub struct PlainReader<R: BufRead> {
+     input: R,
+ }
+ 
+ impl<R: BufRead> PlainReader<R> {
+     pub fn new(input: R) -> Self {
+         PlainReader { input }
+     }
+ }
+ 
+ impl<R: BufRead> LineReader for PlainReader<R> {
+     fn read_line(&mut self, prompt: &str) -> io::Result<String> {
+         print!("{}", prompt);
+         io::stdout().flush()?;
+         let mut line = String::new();
+         if self.input.read_line(&mut line)? == 0 {
+             return Err(ErrorKind::UnexpectedEof.into());
+         }
+         Ok(line)
+     }
+ }
+ 
+ #[cfg(feature = "readline")]
+ pub use readline::Editor;
+ 
+ #[cfg(feature = "readline")]
+ mod readline {
+     use std::io::{self, ErrorKind};
+     use rustyline::completion::Completer;
+     use rustyline::error::ReadlineError;
+     use rustyline::highlight::Highlighter;
+     use rustyline::hint::Hinter;
+     use rustyline::history::DefaultHistory;
+     use rustyline::validate::Validator;
+     use rustyline::{Context, Helper};
+     use super::LineReader;
+     use crate::commands::COMMANDS;
+ 
+     /// Completes the command name at the start of the line.
+     pub struct CommandCompleter;
+ 
+     impl Completer for CommandCompleter {
+         type Candidate = String;
+ 
+         fn complete(&self, line: &str, pos: usize, _: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+             let typed = &line[..pos];
+             if typed.contains(char::is_whitespace) {
+                 return Ok((pos, Vec::new()));
+             }
+             let candidates = COMMANDS
+                 .iter()
+                 .map(|spec| spec.name)
+                 .filter(|name| name.starts_with(typed))
+                 .map(String::from)
+                 .collect();
+             Ok((0, candidates))
+         }
+     }
+ 
+     impl Hinter for CommandCompleter {
+         type Hint = String;
+     }
+ 
+     impl Highlighter for CommandCompleter {}
+ 
+     impl Validator for CommandCompleter {}
+ 
+     impl Helper for CommandCompleter {}
+ 
+     /// A line editor with history and command name completion.
+     pub struct Editor(rustyline::Editor<CommandCompleter, DefaultHistory>);
+ 
+     impl Editor {
+         pub fn new() -> rustyline::Result<Self> {
+             let mut editor = rustyline::Editor::new()?;
+             editor.set_helper(Some(CommandCompleter));
+             Ok(Editor(editor))
+         }
+     }
+ 
+     impl LineReader for Editor {
+         fn read_line(&mut self, prompt: &str) -> io::Result<String> {
+             match self.0.readline(prompt) {
+                 Ok(line) => {
+                     // History is a convenience; failing to record a line is not an error
+                     let _ = self.0.add_history_entry(line.as_str());
+                     Ok(line)
+                 }
+                 Err(ReadlineError::Interrupted) => Err(ErrorKind::Interrupted.into()),
+                 Err(ReadlineError::Eof) => Err(ErrorKind::UnexpectedEof.into()),
+                 Err(ReadlineError::Io(e)) => Err(e),
+                 Err(e) => Err(io::Error::other(e)),
+             }
+         }
+     }
+ }
+ 
+ /// Reads and runs commands until `quit` or the end of input.
+ ///
+ /// Command output goes to `out`; a line that fails to parse or run is reported to
+ /// `err` and the prompt continues. A cancelled line is discarded.
+ ///
+ /// # Errors
+ ///
+ /// Returns an error if reading a line or writing output fails.
+ pub fn run_repl(
+     out: &mut impl Write,
+     err: &mut impl Write,
+     reader: &mut impl LineReader,
+     movies: &LinkedList<Movie>,
+     report: ReportOptions,
+     language_map: &LanguageMap,
+ ) -> io::Result<()> {
+     loop {
+         let line = match reader.read_line(PROMPT) {
+             Ok(line) => line,
+             Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+             Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+             Err(e) => return Err(e),
+         };
+         let words = match commands::tokenize(&line) {
+             Ok(words) if words.is_empty() => continue,
+             Ok(words) => words,
+             Err(message) => {
+                 writeln!(err, "Error: {}", message)?;
+                 continue;
+             }
+         };
+         let words: Vec<&str> = words.iter().map(String::as_str).collect();
+         match Command::parse(&words) {
+             Ok(Command::Quit) => return Ok(()),
+             Ok(command) => {
+                 if let Err(e) = commands::run_command(out, movies, &command, report, language_map) {
+                     writeln!(err, "Error: {}", e)?;
+                 }
+             }
+             Err(message) => writeln!(err, "Error: {}", message)?,
+         }
+     }
+ }
+ 
+ #[cfg(test)]
+ mod tests {
+     use super::*;
+     use movies_cargo::{read_csv, ParseOptions, RatingSource, YearOrder, RATING_PRECISION};
+     use crate::commands::ReportFormat;
+ 
+     /// Replays fixed lines, then reports the end of input.
+     struct Scripted(Vec<io::Result<String>>);
+ 
+     impl LineReader for Scripted {
+         fn read_line(&mut self, _: &str) -> io::Result<String> {
+             if self.0.is_empty() {
+                 return Err(ErrorKind::UnexpectedEof.into());
+             }
+             self.0.remove(0)
+         }
+     }
+ 
+     /// Runs the prompt over `lines`, returning what it wrote to stdout and stderr.
+     fn run(lines: Vec<io::Result<String>>) -> (String, String) {
+         let movies = read_csv("movies_sample_1.csv", &ParseOptions::default()).unwrap();
+         let report = ReportOptions {
+             output: ReportFormat::Text,
+             order: YearOrder::Ascending,
+             precision: RATING_PRECISION,
+             limit: None,
+             force: false,
+             fold_diacritics: true,
+             rating_source: RatingSource::Rating,
+         };
+         let (mut out, mut err) = (Vec::new(), Vec::new());
+         let mut reader = Scripted(lines);
+         run_repl(&mut out, &mut err, &mut reader, &movies, report, &LanguageMap::builtin()).unwrap();
+         (String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap())
+     }
+ 
+     #[test]
+     fn test_repl_runs_commands_until_quit() {
+         let lines = ["year 2009", "", "top 2", "quit", "stats"].map(|line| Ok(line.to_string()));
+         let (out, err) = run(lines.into());
+         assert_eq!(
+             out,
+             "Sherlock Holmes\n-- 1 movie --\n\
+              2018 8.5 Avengers: Infinity War\n2012 8.1 Anna Karenina\n-- 2 movies --\n"
+         );
+         assert_eq!(err, "");
+     }
+ 
+     #[test]
+     fn test_repl_reports_errors_and_continues() {
+         let lines = vec![
+             Ok("yeer 2009".to_string()),
+             Err(ErrorKind::Interrupted.into()),
+             Ok("year 20x9".to_string()),
+             Ok("lang \"Old English".to_string()),
+             Ok("help".to_string()),
+         ];
+         let (out, err) = run(lines);
+         assert_eq!(
+             err,
+             "Error: Unknown command 'yeer'.\n\
+              Error: Invalid year '20x9'.\n\
+              Error: Unterminated quote in '\"Old English'.\n"
+         );
+         assert!(out.starts_with("  year YEAR "), "{}", out);
+     }
+ }
@@ -0,0 +1,106 @@
+/*
+ * SQLite export for the parsed movie collection.
+ *
+ * Only compiled with the `sqlite` cargo feature so default builds do not pull in
+ * rusqlite and the bundled SQLite library.
+ */
+
+ use std::collections::LinkedList;
+ use rusqlite::{params, Connection};
+ use crate::Movie;
+ 
+ /// Creates the export tables if they do not exist yet.
+ const SCHEMA: &str = "
+     CREATE TABLE IF NOT EXISTS movies (
+         id INTEGER PRIMARY KEY,
+         title TEXT NOT NULL,
+         year INTEGER NOT NULL,
+         rating REAL
+     );
+     CREATE TABLE IF NOT EXISTS movie_languages (
+         movie_id INTEGER NOT NULL REFERENCES movies(id),
+         language TEXT NOT NULL
+     );
+ ";
+ 
+ /// Writes the movies into the SQLite database at `db_path`.
+ ///
+ /// The schema is created if it is missing and any rows from a previous export
+ /// are replaced, so running the export twice leaves the same contents. All
+ /// inserts happen in a single transaction. Unrated movies are exported with a
+ /// NULL rating.
+ ///
+ /// # Arguments
+ ///
+ /// * `movies` - A reference to the linked list of movies.
+ /// * `db_path` - The path of the database file, created if it does not exist.
+ ///
+ /// # Returns
+ ///
+ /// * `rusqlite::Result<usize>` - The number of movies written.
+ pub fn export_sqlite(movies: &LinkedList<Movie>, db_path: &str) -> rusqlite::Result<usize> {
+     let mut conn = Connection::open(db_path)?;
+     conn.execute_batch(SCHEMA)?;
+ 
+     let tx = conn.transaction()?;
+     tx.execute("DELETE FROM movie_languages", [])?;
+     tx.execute("DELETE FROM movies", [])?;
+     {
+         let mut insert_movie =
+             tx.prepare("INSERT INTO movies (id, title, year, rating) VALUES (?1, ?2, ?3, ?4)")?;
+         let mut insert_language =
+             tx.prepare("INSERT INTO movie_languages (movie_id, language) VALUES (?1, ?2)")?;
+ 
+         for (id, movie) in (1_i64..).zip(movies) {
+             insert_movie.execute(params![id, movie.title, movie.year, movie.rating])?;
+             for language in &movie.languages {
+                 insert_language.execute(params![id, &**language])?;
+             }
+         }
+     }
+     tx.commit()?;
+ 
+     Ok(movies.len())
+ }
+ 
+ #[cfg(test)]
+ mod tests {
+     use super::*;
+     use crate::{read_csv, ParseOptions};
+ 
+     #[test]
+     fn test_export_sqlite_round_trip() {
+         let movies = read_csv("movies_sample_1.csv", &ParseOptions::default()).unwrap();
+         let dir = tempfile::tempdir().unwrap();
+         let db_path = dir.path().join("movies.db");
+         let db_path = db_path.to_str().unwrap();
+ 
+         // Exporting twice must not duplicate rows
+         export_sqlite(&movies, db_path).unwrap();
+         assert_eq!(export_sqlite(&movies, db_path).unwrap(), 24);
+ 
+         let conn = Connection::open(db_path).unwrap();
+         let count: i64 = conn.query_row("SELECT COUNT(*) FROM movies", [], |row| row.get(0)).unwrap();
+         assert_eq!(count, 24);
+         let languages: i64 = conn
+             .query_row("SELECT COUNT(*) FROM movie_languages", [], |row| row.get(0))
+             .unwrap();
+         let expected: usize = movies.iter().map(|m| m.languages.len()).sum();
+         assert_eq!(languages as usize, expected);
+ 
+         let (year, rating): (i32, f64) = conn
+             .query_row("SELECT year, rating FROM movies WHERE title = 'Iron Man'", [], |row| {
+                 Ok((row.get(0)?, row.get(1)?))
+             })
+             .unwrap();
+         assert_eq!((year, rating as f32), (2008, 7.9));
+         let mut stmt = conn
+             .prepare(
+                 "SELECT language FROM movie_languages JOIN movies ON movies.id = movie_id \
+                  WHERE title = 'Iron Man' ORDER BY movie_languages.rowid",
+             )
+             .unwrap();
+         let iron_man: Vec<String> = stmt.query_map([], |row| row.get(0)).unwrap().map(|r| r.unwrap()).collect();
+         assert_eq!(iron_man, vec!["English", "Persian", "Urdu", "Arabic", "Hungarian"]);
+     }
+ }
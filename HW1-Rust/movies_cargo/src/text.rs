@@ -0,0 +1,118 @@
+/*
+ * Text folding for title searches.
+ *
+ * Searches compare titles by a search key: the title lowercased and, unless the
+ * user turned it off, with its diacritics removed, so "Les Miserables" finds
+ * "Les Misérables". Titles are only folded for comparison; they are always shown
+ * as they appear in the file, except that line breaks and other control characters
+ * are replaced when the title is read, so every listing shows it on one line.
+ */
+
+ use std::borrow::Cow;
+ use unicode_normalization::char::is_combining_mark;
+ use unicode_normalization::UnicodeNormalization;
+ 
+ /// Removes diacritics from `text`.
+ ///
+ /// The text is decomposed with NFKD and its combining marks are dropped, which
+ /// turns "é" into "e" and compatibility forms such as "ﬁ" into "fi". Letters that
+ /// do not decompose are replaced by their usual ASCII spelling instead: "ß" becomes
+ /// "ss", "Ł" "L", "Ø" "O", "Đ" "D", "Æ" "AE", "Œ" "OE", "Þ" "TH", and the dotless
+ /// "ı" "i". Any other character is kept as it is.
+ pub fn fold_diacritics(text: &str) -> String {
+     let mut folded = String::with_capacity(text.len());
+     for c in text.nfkd().filter(|&c| !is_combining_mark(c)) {
+         match c {
+             'ß' => folded.push_str("ss"),
+             'ẞ' => folded.push_str("SS"),
+             'Ł' => folded.push('L'),
+             'ł' => folded.push('l'),
+             'Ø' => folded.push('O'),
+             'ø' => folded.push('o'),
+             'Đ' => folded.push('D'),
+             'đ' => folded.push('d'),
+             'Æ' => folded.push_str("AE"),
+             'æ' => folded.push_str("ae"),
+             'Œ' => folded.push_str("OE"),
+             'œ' => folded.push_str("oe"),
+             'Þ' => folded.push_str("TH"),
+             'þ' => folded.push_str("th"),
+             'ı' => folded.push('i'),
+             c => folded.push(c),
+         }
+     }
+     folded
+ }
+ 
+ /// Returns the key titles are compared by: `title` lowercased, with its diacritics
+ /// removed when `fold` is true.
+ pub fn search_key(title: &str, fold: bool) -> String {
+     if fold {
+         fold_diacritics(title).to_lowercase()
+     } else {
+         title.to_lowercase()
+     }
+ }
+ 
+ /// Replaces each run of control characters in `text`, such as the line break of a
+ /// multi-line quoted title, with a single space. Text without any is borrowed as it is.
+ pub fn single_line(text: &str) -> Cow<'_, str> {
+     if !text.chars().any(char::is_control) {
+         return Cow::Borrowed(text);
+     }
+     let mut line = String::with_capacity(text.len());
+     let mut in_run = false;
+     for c in text.chars() {
+         if !c.is_control() {
+             line.push(c);
+         } else if !in_run {
+             line.push(' ');
+         }
+         in_run = c.is_control();
+     }
+     Cow::Owned(line)
+ }
+ 
+ #[cfg(test)]
+ mod tests {
+     use super::*;
+ 
+     #[test]
+     fn test_single_line() {
+         assert!(matches!(single_line("Heat"), Cow::Borrowed("Heat")));
+         assert_eq!(single_line("The Lord of the Rings:\r\nThe Return of the King"), "The Lord of the Rings: The Return of the King");
+         assert_eq!(single_line("Bell\u{7}\tand\u{1b}escape"), "Bell and escape");
+     }
+ 
+     #[test]
+     fn test_fold_diacritics_pairs() {
+         let pairs = [
+             ("Les Misérables", "Les Miserables"),
+             ("Amélie", "Amelie"),
+             ("Señor Ñandú", "Senor Nandu"),
+             ("Crème Brûlée", "Creme Brulee"),
+             ("Ça ira", "Ca ira"),
+             ("Das Boot für Straße", "Das Boot fur Strasse"),
+             ("Łódź", "Lodz"),
+             ("Øresund", "Oresund"),
+             ("Đorđe", "Dorde"),
+             ("Æon Flux", "AEon Flux"),
+             ("Œuvre", "OEuvre"),
+             ("Þór", "THor"),
+             ("Kırmızı", "Kirmizi"),
+             ("ﬁlm", "film"),
+             ("Crouching Tiger", "Crouching Tiger"),
+             ("千と千尋の神隠し", "千と千尋の神隠し"),
+         ];
+         for (accented, plain) in pairs {
+             assert_eq!(fold_diacritics(accented), plain, "{}", accented);
+         }
+     }
+ 
+     #[test]
+     fn test_search_key() {
+         assert_eq!(search_key("Les Misérables", true), "les miserables");
+         assert_eq!(search_key("Les Misérables", false), "les misérables");
+         assert_eq!(search_key("STRASSE", true), search_key("Straße", true));
+     }
+ }
@@ -0,0 +1,646 @@
+/*
+ * Command-line tests for the movies_cargo binary.
+ *
+ * Each test runs the compiled program against the sample CSV files in the crate
+ * root. The parsed-data cache is bypassed with --no-cache so tests running in
+ * parallel never share a cache file.
+ */
+
+ use assert_cmd::Command;
+ use predicates::prelude::*;
+ 
+ /// Creates a command for the movies_cargo binary.
+ fn movies_cargo() -> Command {
+     Command::cargo_bin("movies_cargo").unwrap()
+ }
+ 
+ #[test]
+ fn test_help_and_version() {
+     movies_cargo()
+         .arg("--help")
+         .assert()
+         .success()
+         .stdout(predicate::str::contains("Usage: movies_cargo [OPTIONS] <CSV_FILE> [COMMAND]..."))
+         .stdout(predicate::str::contains("completions").not());
+     movies_cargo()
+         .arg("--version")
+         .assert()
+         .success()
+         .stdout(format!("movies_cargo {}\n", env!("CARGO_PKG_VERSION")));
+ }
+ 
+ #[test]
+ fn test_missing_file_argument_is_a_usage_error() {
+     movies_cargo()
+         .env_remove("MOVIES_CSV")
+         .assert()
+         .code(2)
+         .stderr(predicate::str::contains("the following required arguments were not provided"))
+         .stderr(predicate::str::contains("<CSV_FILE>"));
+ }
+ 
+ #[test]
+ fn test_invalid_option_value_is_a_usage_error() {
+     movies_cargo()
+         .args(["--order", "newest", "movies_sample_1.csv"])
+         .assert()
+         .code(2)
+         .stderr(predicate::str::contains("invalid value 'newest'"));
+ }
+ 
+ #[test]
+ fn test_completions_for_each_shell() {
+     for (shell, marker) in [("bash", "_movies_cargo()"), ("zsh", "#compdef movies_cargo"), ("fish", "complete -c movies_cargo")] {
+         movies_cargo()
+             .args(["completions", shell])
+             .assert()
+             .success()
+             .stdout(predicate::str::contains(marker));
+     }
+ }
+ 
+ #[test]
+ fn test_interactive_menu_with_options() {
+     movies_cargo()
+         .args(["--seed", "42", "--no-cache", "--order", "desc", "movies_sample_1.csv"])
+         .write_stdin("2\n\n\n")
+         .assert()
+         .success()
+         .stdout(predicate::str::contains("Processed file movies_sample_1.csv (from the command line) and parsed data for 24 movies"))
+         .stdout(predicate::str::contains("2018 8.5 Avengers: Infinity War\n2017 7.9 Thor: Ragnarok\n"));
+ }
+ 
+ #[test]
+ fn test_movies_csv_environment_variable() {
+     movies_cargo()
+         .env("MOVIES_CSV", "movies_sample_1.csv")
+         .args(["--no-cache"])
+         .write_stdin("27\n")
+         .assert()
+         .success()
+         .stdout(predicate::str::contains("Processed file movies_sample_1.csv (from MOVIES_CSV) and parsed data for 24 movies"));
+ 
+     // A file named on the command line takes precedence over the environment
+     movies_cargo()
+         .env("MOVIES_CSV", "movies_sample_genres.csv")
+         .args(["--no-cache", "movies_sample_1.csv"])
+         .write_stdin("27\n")
+         .assert()
+         .success()
+         .stdout(predicate::str::contains("Processed file movies_sample_1.csv (from the command line) and parsed data for 24 movies"));
+ 
+     // A missing file gets the same error from either source
+     movies_cargo()
+         .env("MOVIES_CSV", "no_such_movies.csv")
+         .arg("--no-cache")
+         .assert()
+         .code(1)
+         .stderr(predicate::str::starts_with("Error: Could not read movie file no_such_movies.csv (from MOVIES_CSV): "));
+     movies_cargo()
+         .env_remove("MOVIES_CSV")
+         .args(["--no-cache", "no_such_movies.csv"])
+         .assert()
+         .code(1)
+         .stderr(predicate::str::starts_with("Error: Could not read movie file no_such_movies.csv (from the command line): "));
+ }
+ 
+ #[test]
+ fn test_year_range_command() {
+     movies_cargo()
+         .args(["--no-cache", "movies_sample_1.csv", "year-range", "2008", "2009"])
+         .assert()
+         .success()
+         .stdout("2008:\n  The Incredible Hulk\n  Iron Man\n2009:\n  Sherlock Holmes\n-- 3 movies --\n");
+ }
+ 
+ #[test]
+ fn test_custom_filter_menu_option() {
+     movies_cargo()
+         .args(["--no-cache", "movies_sample_1.csv"])
+         .write_stdin("16\n2008-2012\nen\n7.5\n\n")
+         .assert()
+         .success()
+         .stdout(predicate::str::contains(
+             "2009 7.6 Sherlock Holmes\n2008 7.9 Iron Man\n2012 8.1 The Avengers\n2012 8.1 Anna Karenina\n-- 4 movies --\n",
+         ));
+ 
+     // A filter without criteria asks before listing everything
+     movies_cargo()
+         .args(["--no-cache", "movies_sample_1.csv"])
+         .write_stdin("16\n\n\n\nn\n")
+         .assert()
+         .success()
+         .stdout(predicate::str::contains("No criteria given; this lists all 24 movies. Continue? (y/N)"))
+         .stdout(predicate::str::contains("-- 24 movies --").not());
+ }
+ 
+ #[test]
+ fn test_limit_option() {
+     movies_cargo()
+         .args(["--no-cache", "--limit", "1", "movies_sample_1.csv", "year-range", "2008", "2009"])
+         .assert()
+         .success()
+         .stdout("2008:\n  The Incredible Hulk\n(+ 2 more)\n-- 3 movies --\n");
+     movies_cargo()
+         .args(["--no-cache", "--limit", "2", "movies_sample_1.csv"])
+         .write_stdin("2\n\n\n2\n\nall\n")
+         .assert()
+         .success()
+         .stdout(predicate::str::contains("How many rows should be shown? (press Enter for 2)"))
+         .stdout(predicate::str::contains("(+ 10 more)\n-- 12 movies --\n"))
+         .stdout(predicate::str::contains("2018 8.5 Avengers: Infinity War\n-- 12 movies --\n"));
+ }
+ 
+ #[test]
+ fn test_highest_command_as_csv() {
+     movies_cargo()
+         .args(["--output", "csv", "--no-cache", "movies_sample_1.csv", "highest"])
+         .assert()
+         .success()
+         .stdout(predicate::str::starts_with("year,rating,title\n2003,6.6,Right on Track\n"));
+ }
+ 
+ #[test]
+ fn test_regex_search_menu_option() {
+     // An invalid pattern shows the regex error and asks again
+     movies_cargo()
+         .args(["--no-cache", "movies_sample_1.csv"])
+         .write_stdin("18\ncaptain (america\n^captain america: the\n\n")
+         .assert()
+         .success()
+         .stdout(predicate::str::contains("Invalid pattern: regex parse error:"))
+         .stdout(predicate::str::contains(
+             "2011 6.9 Captain America: The First Avenger\n2014 7.8 Captain America: The Winter Soldier\n-- 2 movies --\n",
+         ));
+ }
+ 
+ #[test]
+ fn test_skip_report_ndjson() {
+     let dir = tempfile::tempdir().unwrap();
+     let csv = dir.path().join("movies.csv");
+     std::fs::write(&csv, "Title,Year,Languages,Rating Value\nThor,19x4,[English],7\nHeat,1995,\"[en,es;fr]\",8.3\nMemento,2000,[English],none\n").unwrap();
+     movies_cargo()
+         .args(["--skip-report", "ndjson", csv.to_str().unwrap(), "year", "1995"])
+         .assert()
+         .success()
+         .stdout("Heat\n-- 1 movie --\n")
+         .stderr(
+             "{\"line\":2,\"reason\":\"invalid_year\",\"value\":\"19x4\"}\n\
+              {\"line\":4,\"reason\":\"invalid_rating\",\"value\":\"none\"}\n",
+         );
+ }
+ 
+ #[test]
+ fn test_menu_movies_sharing_a_language_asks_which_title() {
+     let dir = tempfile::tempdir().unwrap();
+     let csv = dir.path().join("movies.csv");
+     std::fs::write(
+         &csv,
+         "Title,Year,Languages,Rating Value\nSolaris,1972,[Russian],8.0\nSolaris,2002,[English],6.2\n\
+          Stalker,1979,[Russian],8.1\nHeat,1995,[English;Spanish],8.3\n",
+     )
+     .unwrap();
+     movies_cargo()
+         .args(["--no-cache", csv.to_str().unwrap()])
+         .write_stdin("22\nsolaris\n1980\n1972\n\n27\n")
+         .assert()
+         .success()
+         .stdout(predicate::str::contains("Found 2 movies titled solaris; enter the year of one (1972, 2002):"))
+         .stdout(predicate::str::contains("Please enter one of the listed years."))
+         .stdout(predicate::str::contains("1979 8.1 Stalker (1 shared)\n-- 1 movie --\n"));
+ }
+ 
+ #[test]
+ fn test_menu_sub_prompts_go_back() {
+     // b at the year prompt, then b at the order prompt where Enter would keep the default
+     movies_cargo()
+         .args(["--no-cache", "movies_sample_1.csv"])
+         .write_stdin("1\nb\n2\nb\n3\n\n27\n")
+         .assert()
+         .success()
+         .stdout(predicate::str::contains("Cancelled.").count(3))
+         .stdout(predicate::str::contains("Invalid").not())
+         .stdout(predicate::str::contains("Exiting the program."));
+ }
+ 
+ #[cfg(unix)]
+ #[test]
+ fn test_second_ctrl_c_exits_while_a_prompt_waits() {
+     use std::io::Write;
+     use std::process::{Command as Process, Stdio};
+     use std::thread;
+     use std::time::{Duration, Instant};
+ 
+     let mut child = Process::new(assert_cmd::cargo::cargo_bin("movies_cargo"))
+         .args(["--no-cache", "movies_sample_1.csv"])
+         .stdin(Stdio::piped())
+         .stdout(Stdio::null())
+         .stderr(Stdio::piped())
+         .spawn()
+         .unwrap();
+     // Option 1 waits at its year prompt, with the menu loop holding the stdout lock
+     let mut stdin = child.stdin.take().unwrap();
+     stdin.write_all(b"1\n").unwrap();
+     thread::sleep(Duration::from_millis(500));
+ 
+     let interrupt = || {
+         let status = Process::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap();
+         assert!(status.success());
+     };
+     interrupt();
+     thread::sleep(Duration::from_millis(100));
+     interrupt();
+ 
+     let deadline = Instant::now() + Duration::from_secs(10);
+     let status = loop {
+         if let Some(status) = child.try_wait().unwrap() {
+             break status;
+         }
+         if Instant::now() > deadline {
+             child.kill().unwrap();
+             panic!("the program did not exit after the second Ctrl-C");
+         }
+         thread::sleep(Duration::from_millis(20));
+     };
+     assert_eq!(status.code(), Some(130));
+     let output = child.wait_with_output().unwrap();
+     assert!(String::from_utf8_lossy(&output.stderr).contains("Exiting the program."));
+ }
+ 
+ #[test]
+ fn test_watch_reloads_a_file_with_a_skipped_row() {
+     use std::io::Write;
+     use std::process::{Command as Process, Stdio};
+     use std::thread;
+     use std::time::{Duration, SystemTime};
+ 
+     let dir = tempfile::tempdir().unwrap();
+     let csv = dir.path().join("movies_watched.csv");
+     std::fs::write(&csv, "Title,Year,Languages,Rating Value\nThor,2011,[English],7\n").unwrap();
+     let mut child = Process::new(assert_cmd::cargo::cargo_bin("movies_cargo"))
+         .args(["--no-cache", "--watch", csv.to_str().unwrap()])
+         .stdin(Stdio::piped())
+         .stdout(Stdio::piped())
+         .spawn()
+         .unwrap();
+     thread::sleep(Duration::from_millis(300));
+ 
+     // The menu waits for a choice while the change is picked up
+     std::fs::write(&csv, "Title,Year,Languages,Rating Value\nThor,2011,[English],7\nOld,1800,[English],7\nHeat,1995,[English],8.3\n")
+         .unwrap();
+     let file = std::fs::File::options().write(true).open(&csv).unwrap();
+     file.set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+     thread::sleep(Duration::from_millis(2500));
+ 
+     // Any line wakes the menu loop, which swaps in the reload before showing the menu again
+     let mut stdin = child.stdin.take().unwrap();
+     stdin.write_all(b"x\n").unwrap();
+     drop(stdin);
+     let output = child.wait_with_output().unwrap();
+     let stdout = String::from_utf8_lossy(&output.stdout);
+     assert!(output.status.success());
+     assert!(stdout.contains("Invalid year '1800' at line 3. Skipping record.\nReloaded: 2 movies (was 1)"), "{}", stdout);
+ }
+ 
+ #[test]
+ fn test_menu_superlatives() {
+     movies_cargo()
+         .args(["--no-cache", "movies_sample_1.csv"])
+         .write_stdin("23\n27\n")
+         .assert()
+         .success()
+         .stdout(predicate::str::contains(
+             "Most releases: 2016 (4 movies)\n\
+              Highest average rating: 2012 (7.8)\n\
+              Most languages: Captain America: Civil War (2016), 5 languages\n\
+              Longest title: Captain America: The Winter Soldier (2014), 35 characters\n",
+         ));
+ }
+ 
+ #[test]
+ fn test_saved_filters_round_trip() {
+     let config = tempfile::tempdir().unwrap();
+     let matches = "2009 7.6 Sherlock Holmes\n2008 7.9 Iron Man\n2012 8.1 The Avengers\n2012 8.1 Anna Karenina\n-- 4 movies --\n";
+ 
+     // Enter a filter with option 16, save it with option 24, then list and run it
+     movies_cargo()
+         .env("MOVIES_CARGO_CONFIG_DIR", config.path())
+         .args(["--no-cache", "movies_sample_1.csv"])
+         .write_stdin("16\n2008-2012\nen\n7.5\n\n24\nfavorites\n25\n26\nfavorites\n\n27\n")
+         .assert()
+         .success()
+         .stdout(predicate::str::contains(
+             "Enter a name for the filter (years 2008-2012, language English, rating >= 7.5), or b to go back:",
+         ))
+         .stdout(predicate::str::contains("Saved filter favorites to "))
+         .stdout(predicate::str::contains("favorites  years 2008-2012, language English, rating >= 7.5\n-- 1 filter --\n"))
+         .stdout(predicate::str::contains(matches).count(2));
+ 
+     // The saved filter outlives the session, and a broken entry does not hide it
+     let path = config.path().join("filters.toml");
+     let mut text = std::fs::read_to_string(&path).unwrap();
+     text.push_str("\n[broken]\nyears = \"sometime\"\n");
+     std::fs::write(&path, text).unwrap();
+     movies_cargo()
+         .env("MOVIES_CARGO_CONFIG_DIR", config.path())
+         .args(["--no-cache", "movies_sample_1.csv", "run-filter", "favorites"])
+         .assert()
+         .success()
+         .stdout(matches);
+     movies_cargo()
+         .env("MOVIES_CARGO_CONFIG_DIR", config.path())
+         .args(["--no-cache", "movies_sample_1.csv", "filters"])
+         .assert()
+         .success()
+         .stdout(predicate::str::contains("Skipped malformed filter 'broken': "));
+     movies_cargo()
+         .env("MOVIES_CARGO_CONFIG_DIR", config.path())
+         .args(["--no-cache", "movies_sample_1.csv", "run-filter", "broken"])
+         .assert()
+         .failure()
+         .stderr(predicate::str::contains("Saved filter 'broken' is malformed: "));
+ 
+     // The save-filter command saves the same filter without the menu
+     movies_cargo()
+         .env("MOVIES_CARGO_CONFIG_DIR", config.path())
+         .args(["--no-cache", "movies_sample_1.csv", "save-filter", "again", "year=2008-2012", "lang=en", "rating=7.5"])
+         .assert()
+         .success()
+         .stdout(format!("Saved filter again to {}\n", path.display()));
+     movies_cargo()
+         .env("MOVIES_CARGO_CONFIG_DIR", config.path())
+         .args(["--no-cache", "movies_sample_1.csv", "run-filter", "again"])
+         .assert()
+         .success()
+         .stdout(matches);
+     movies_cargo()
+         .env("MOVIES_CARGO_CONFIG_DIR", config.path())
+         .args(["--no-cache", "movies_sample_1.csv", "filters"])
+         .assert()
+         .success()
+         .stdout(predicate::str::contains("again      years 2008-2012, language English, rating >= 7.5\n"));
+ }
+ 
+ #[test]
+ fn test_validate_subcommand() {
+     let dir = tempfile::tempdir().unwrap();
+     let csv = dir.path().join("movies.csv");
+     std::fs::write(&csv, "Title,Year,Languages,Rating Value\nHeat,1995,[English],8.3\nMemento,2000,[English],8.4\n").unwrap();
+     movies_cargo()
+         .args(["validate", csv.to_str().unwrap()])
+         .assert()
+         .success()
+         .stdout(format!("Checked {}\nRows: 2\nValid rows: 2\n", csv.display()));
+ 
+     movies_cargo()
+         .args(["validate", "movies_sample_messy.csv"])
+         .assert()
+         .code(1)
+         .stdout(
+             "Checked movies_sample_messy.csv\n\
+              Rows: 13\n\
+              Valid rows: 4\n\
+              Decimal comma ratings: 1\n  \
+              missing_title: 1 (lines 5)\n  \
+              invalid_year: 2 (lines 3, 12)\n  \
+              too_many_languages: 1 (lines 6)\n  \
+              invalid_rating: 5 (lines 4, 7, 9, 10, 14)\n",
+         );
+ 
+     movies_cargo()
+         .args(["validate", "--rating-scale=-5,20", "movies_sample_messy.csv"])
+         .assert()
+         .code(1)
+         .stdout(predicate::str::contains("invalid_rating: 3 (lines 4, 9, 14)\n"));
+ }
+ 
+ #[test]
+ fn test_ndjson_format() {
+     let dir = tempfile::tempdir().unwrap();
+     let csv = dir.path().join("movies.csv");
+     std::fs::write(
+         &csv,
+         "Title,Year,Languages,Rating Value\nHeat,1995,\"[English,Spanish]\",8.3\nSe7en,1995,[English],none\nBad,19x5,[English],5\n",
+     )
+     .unwrap();
+     let assert = movies_cargo()
+         .args(["--format", "ndjson", csv.to_str().unwrap(), "year", "1995"])
+         .assert()
+         .success()
+         .stderr(
+             "{\"line\":3,\"reason\":\"invalid_rating\",\"value\":\"none\"}\n\
+              {\"line\":4,\"reason\":\"invalid_year\",\"value\":\"19x5\"}\n\
+              -- 2 movies --\n",
+         );
+     let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+     let movies: Vec<serde_json::Value> = stdout.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+     assert_eq!(movies.len(), 2);
+     assert_eq!(movies[0]["title"], "Heat");
+     assert_eq!(movies[0]["languages"], serde_json::json!(["English", "Spanish"]));
+     assert_eq!(movies[0]["rating"], 8.3);
+     assert_eq!(movies[1]["rating"], serde_json::Value::Null);
+     assert_eq!(movies[1]["invalid_rating"], "none");
+ }
+ 
+ #[test]
+ fn test_markdown_format_matches_golden_file() {
+     movies_cargo()
+         .args(["--no-cache", "--format", "markdown", "movies_sample_markdown.csv", "year-range", "1970", "2005"])
+         .assert()
+         .success()
+         .stdout(include_str!("golden/markdown_year_range.md"));
+ }
+ 
+ #[test]
+ fn test_rating_scale_and_precision_options() {
+     movies_cargo()
+         .args(["--no-cache", "--rating-scale", "0,100", "--rating-precision", "2"])
+         .args(["movies_sample_scale100.csv", "highest"])
+         .assert()
+         .success()
+         .stdout(predicate::str::contains("Invalid rating '105' at line 6. Marking as unrated."))
+         .stdout(predicate::str::contains("2000 84.25 Memento\n2001 86.00 Spirited Away\n2003 0.00 Gigli\n"));
+ }
+ 
+ #[test]
+ fn test_language_map_option() {
+     let dir = tempfile::tempdir().unwrap();
+     let map = dir.path().join("aliases.csv");
+     std::fs::write(&map, "en,French\n").unwrap();
+     movies_cargo()
+         .args(["--no-cache", "--language-map", map.to_str().unwrap(), "movies_sample_1.csv", "highest"])
+         .assert()
+         .code(1)
+         .stderr(predicate::str::contains("conflicting mappings for language 'en'"));
+ }
+ 
+ #[test]
+ fn test_expr_command() {
+     movies_cargo()
+         .args(["--no-cache", "movies_sample_1.csv", "expr", "year=2009 or (year>=2018 and title='queen')"])
+         .assert()
+         .success()
+         .stdout(predicate::str::ends_with("2009 7.6 Sherlock Holmes\n2018 6.9 Mary Queen of Scots\n-- 2 movies --\n"));
+ 
+     movies_cargo()
+         .args(["--no-cache", "movies_sample_1.csv", "expr", "year>=2000 and rating>>8.5"])
+         .assert()
+         .code(1)
+         .stderr("Error: Invalid expression: unexpected token '>' at column 23.\n");
+ }
+ 
+ #[test]
+ fn test_fold_diacritics_option() {
+     let dir = tempfile::tempdir().unwrap();
+     let csv = dir.path().join("movies.csv");
+     std::fs::write(&csv, "Title,Year,Languages,Rating Value\nLes Misérables,2012,[English],7.6\n").unwrap();
+     movies_cargo()
+         .args(["--no-cache", csv.to_str().unwrap(), "expr", "title='les miserables'"])
+         .assert()
+         .success()
+         .stdout("2012 7.6 Les Misérables\n-- 1 movie --\n");
+     movies_cargo()
+         .args(["--no-cache", "--fold-diacritics", "false", csv.to_str().unwrap(), "expr", "title='les miserables'"])
+         .assert()
+         .success()
+         .stdout("No movies match the expression\n");
+ }
+ 
+ #[test]
+ fn test_timing_option() {
+     movies_cargo()
+         .args(["--timing", "movies_sample_1.csv", "year", "2009"])
+         .assert()
+         .success()
+         .stdout("Sherlock Holmes\n-- 1 movie --\n")
+         .stderr(predicate::str::starts_with("Parsed 24 rows (24 movies, 0 skipped) in "))
+         .stderr(predicate::str::contains(" rows/s, about "));
+ }
+ 
+ #[test]
+ fn test_rating_source_option() {
+     movies_cargo()
+         .args(["--no-cache", "--rating-source", "max", "movies_sample_critics.csv", "highest"])
+         .assert()
+         .success()
+         .stdout(
+             "Invalid critic rating 'seven' at line 8. Setting to none.\n\
+              Using the higher of the critic and audience ratings\n\
+              1995 9.7 Casino\n\
+              2000 9.5 Gladiator\n\
+              -- 2 movies --\n",
+         );
+     movies_cargo()
+         .args(["--no-cache", "--rating-source", "critic", "--output", "csv", "movies_sample_critics.csv", "highest"])
+         .assert()
+         .success()
+         .stdout(predicate::str::ends_with("year,critic_rating,title\n1995,9.7,Casino\n2000,9.2,Memento\n"));
+     movies_cargo()
+         .args(["--no-cache", "movies_sample_critics.csv", "highest"])
+         .assert()
+         .success()
+         .stdout(predicate::str::ends_with("1995 8.6 Se7en\n2000 8.5 Gladiator\n-- 2 movies --\n"));
+ 
+     // The single-rating file has nothing for the critic source to compare
+     movies_cargo()
+         .args(["--no-cache", "--rating-source", "critic", "movies_sample_1.csv", "highest"])
+         .assert()
+         .code(1)
+         .stderr(
+             "Error: --rating-source critic found no ratings in movies_sample_1.csv; \
+              it needs CriticRating and AudienceRating columns.\n",
+         );
+ }
+ 
+ #[test]
+ fn test_unknown_command() {
+     movies_cargo()
+         .args(["--no-cache", "movies_sample_1.csv", "lowest-ever"])
+         .assert()
+         .code(1)
+         .stderr("Error: Unknown command 'lowest-ever'.\n");
+ }
+ 
+ #[test]
+ fn test_strict_filename() {
+     // Without the flag, a file name with spaces goes straight to the loader
+     movies_cargo()
+         .args(["--no-cache", "no such file with spaces.csv", "highest"])
+         .assert()
+         .failure()
+         .stderr(predicate::str::contains("contains spaces").not());
+     movies_cargo()
+         .args(["--strict-filename", "no such file with spaces.csv"])
+         .assert()
+         .code(1)
+         .stderr("Error: File name 'no such file with spaces.csv' contains spaces.\n");
+ }
+ 
+ #[test]
+ fn test_script_option() {
+     let dir = tempfile::tempdir().unwrap();
+     let script = dir.path().join("report.txt");
+     std::fs::write(&script, "year 2009\nyearz 2010\nyear-range 2008 2008\n").unwrap();
+     movies_cargo()
+         .args(["--no-cache", "--script", script.to_str().unwrap(), "movies_sample_1.csv"])
+         .assert()
+         .code(1)
+         .stdout(
+             "== year 2009 ==\nSherlock Holmes\n-- 1 movie --\n\
+              == yearz 2010 ==\n\
+              == year-range 2008 2008 ==\n2008:\n  The Incredible Hulk\n  Iron Man\n-- 2 movies --\n",
+         )
+         .stderr("Error: line 2: Unknown command 'yearz'.\n");
+     movies_cargo()
+         .args(["--no-cache", "--script", script.to_str().unwrap(), "--script-strict", "movies_sample_1.csv"])
+         .assert()
+         .code(1)
+         .stdout("== year 2009 ==\nSherlock Holmes\n-- 1 movie --\n== yearz 2010 ==\n")
+         .stderr("Error: line 2: Unknown command 'yearz'.\n");
+ }
+ 
+ #[test]
+ fn test_repl_option() {
+     movies_cargo()
+         .args(["--no-cache", "--repl", "movies_sample_1.csv"])
+         .write_stdin("year 2009\nlang \"Old English\"\nquit\nyear 2012\n")
+         .assert()
+         .success()
+         .stdout(predicate::str::contains("movies> Sherlock Holmes\n-- 1 movie --\nmovies> No movies found in Old English\nmovies> "))
+         .stdout(predicate::str::contains("The Avengers").not());
+     movies_cargo()
+         .args(["--no-cache", "--repl", "movies_sample_1.csv", "highest"])
+         .assert()
+         .code(2);
+ }
+ 
+ #[test]
+ fn test_export_by_year_command() {
+     let dir = tempfile::tempdir().unwrap();
+     let target = dir.path().join("by_year");
+     let target = target.to_str().unwrap();
+     movies_cargo()
+         .args(["--no-cache", "movies_sample_genres.csv", "export-by-year", target])
+         .assert()
+         .success()
+         .stdout(predicate::str::ends_with(format!("Wrote 5 titles to 5 files in {}\n", target)));
+     let mut names: Vec<String> = std::fs::read_dir(target)
+         .unwrap()
+         .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+         .collect();
+     names.sort();
+     assert_eq!(names, ["1972.txt", "1993.txt", "1994.txt", "2001.txt", "2008.txt"]);
+     let titles = std::fs::read_to_string(std::path::Path::new(target).join("1994.txt")).unwrap();
+     assert_eq!(titles, "The Shawshank Redemption\n");
+ 
+     // The directory now has entries, so writing again needs --force
+     movies_cargo()
+         .args(["--no-cache", "movies_sample_genres.csv", "export-by-year", target])
+         .assert()
+         .code(1)
+         .stderr(predicate::str::contains("is not empty; use --force"));
+     movies_cargo()
+         .args(["--no-cache", "--force", "movies_sample_genres.csv", "export-by-year", target])
+         .assert()
+         .success();
+ }
@@ -0,0 +1,109 @@
+/*
+ * Property tests for parse_record.
+ *
+ * Records are generated from the rules read_csv documents: valid records must
+ * always parse, each kind of invalid record must always be skipped for its own
+ * reason, and no field contents at all may make the parser panic.
+ */
+
+ use csv::StringRecord;
+ use movies_cargo::{parse_record, ParseOptions, RecordLayout, SkipReason, MAX_YEAR, MIN_YEAR};
+ use proptest::prelude::*;
+ 
+ /// Builds a record from its fields, in file order.
+ fn record(fields: &[&str]) -> StringRecord {
+     StringRecord::from(fields.to_vec())
+ }
+ 
+ /// Parses `fields` with the default options and no optional columns, returning
+ /// the skip reason, or `None` if the record was accepted.
+ fn skip_reason(fields: &[&str]) -> Option<SkipReason> {
+     match parse_record(&record(fields), 2, RecordLayout::default(), &ParseOptions::default()) {
+         Ok(_) => None,
+         Err(problem) => problem.reason(),
+     }
+ }
+ 
+ /// A language name short enough to be valid.
+ fn language() -> impl Strategy<Value = String> {
+     "[A-Za-z]{1,20}"
+ }
+ 
+ /// A bracketed languages field with `count` valid names.
+ fn languages(count: impl Into<proptest::sample::SizeRange>) -> impl Strategy<Value = String> {
+     prop::collection::vec(language(), count).prop_map(|names| format!("[{}]", names.join(";")))
+ }
+ 
+ /// A year outside `MIN_YEAR..=MAX_YEAR`, or text that is not a year at all.
+ fn invalid_year() -> impl Strategy<Value = String> {
+     prop_oneof![
+         (i32::MIN..MIN_YEAR).prop_map(|year| year.to_string()),
+         (MAX_YEAR + 1..=i32::MAX).prop_map(|year| year.to_string()),
+         "[A-Za-z]{1,6}",
+     ]
+ }
+ 
+ /// A languages field that is not a well-formed bracketed list.
+ fn malformed_languages() -> impl Strategy<Value = String> {
+     let inner = "[A-Za-z;]{0,30}";
+     prop_oneof![
+         inner.prop_map(|inner| format!("{}]", inner)),
+         inner.prop_map(|inner| format!("[{}", inner)),
+         inner.prop_map(|inner| format!("[\"{}]", inner)),
+         (inner, inner).prop_map(|(a, b)| format!("[{}[{}]", a, b)),
+     ]
+ }
+ 
+ proptest! {
+     #[test]
+     fn test_valid_records_always_parse(
+         title in "[A-Za-z0-9][A-Za-z0-9 :']{0,40}",
+         year in MIN_YEAR..=MAX_YEAR,
+         languages in languages(0..=5),
+         rating in 10..=100u32,
+     ) {
+         let rating = format!("{}.{}", rating / 10, rating % 10);
+         let fields = [title.as_str(), &year.to_string(), &languages, &rating];
+         let parsed = match parse_record(&record(&fields), 2, RecordLayout::default(), &ParseOptions::default()) {
+             Ok(parsed) => parsed,
+             Err(problem) => return Err(TestCaseError::fail(problem.message)),
+         };
+         prop_assert_eq!(&parsed.movie.title, title.trim());
+         prop_assert_eq!(parsed.movie.year, year);
+         prop_assert_eq!(parsed.movie.rating, Some(rating.parse::<f32>().unwrap()));
+         prop_assert!(parsed.movie.languages.len() <= 5);
+         prop_assert!(parsed.problems.is_empty());
+         prop_assert!(!parsed.comma_rating);
+     }
+ 
+     #[test]
+     fn test_out_of_range_years_are_skipped(year in invalid_year(), languages in languages(1..=5)) {
+         prop_assert_eq!(skip_reason(&["Heat", &year, &languages, "8.3"]), Some(SkipReason::InvalidYear));
+     }
+ 
+     #[test]
+     fn test_malformed_brackets_are_skipped(languages in malformed_languages()) {
+         prop_assert_eq!(skip_reason(&["Heat", "1995", &languages, "8.3"]), Some(SkipReason::InvalidLanguages));
+     }
+ 
+     #[test]
+     fn test_oversized_language_lists_are_skipped(languages in languages(6..=12)) {
+         prop_assert_eq!(skip_reason(&["Heat", "1995", &languages, "8.3"]), Some(SkipReason::TooManyLanguages));
+     }
+ 
+     #[test]
+     fn test_overlong_language_names_are_skipped(short in language(), long in "[A-Za-z]{21,40}") {
+         let languages = format!("[{};{}]", short, long);
+         prop_assert_eq!(skip_reason(&["Heat", "1995", &languages, "8.3"]), Some(SkipReason::LanguageTooLong));
+     }
+ 
+     #[test]
+     fn test_arbitrary_fields_never_panic(fields in prop::collection::vec(any::<String>(), 0..10)) {
+         let layout = RecordLayout { genres: Some(4), runtime: Some(5), critic_and_audience: Some((6, 7)) };
+         let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+         // Every rejected record carries a skip record for the report
+         if let Err(problem) = parse_record(&record(&fields), 2, layout, &ParseOptions::default()) {
+             prop_assert!(problem.reason().is_some(), "{}", problem.message);
+         }
+     }
+ }
@@ -0,0 +1,43 @@
+/*
+ * Shared support code for integration tests and benchmarks.
+ *
+ * Integration tests include it with `mod support;` and the benchmarks with a
+ * `#[path]` attribute, so none of it is compiled into the movies_cargo binary.
+ */
+
+ use std::fs;
+ use std::path::{Path, PathBuf};
+ use rand::rngs::StdRng;
+ use rand::seq::SliceRandom;
+ use rand::{Rng, SeedableRng};
+ use movies_cargo::{MAX_YEAR, MIN_YEAR};
+ 
+ /// The languages synthetic movies are drawn from.
+ pub const LANGUAGES: &[&str] = &[
+     "English", "French", "German", "Spanish", "Italian", "Japanese", "Korean", "Hindi",
+ ];
+ 
+ /// Generates a movies CSV with a header row and `rows` records.
+ ///
+ /// The same `seed` always produces the same file. Every record is valid: years
+ /// lie between `MIN_YEAR` and `MAX_YEAR`, each movie has one to three distinct
+ /// languages, and ratings run from 1.0 to 10.0 with one decimal place.
+ pub fn synthetic_csv(rows: usize, seed: u64) -> String {
+     let mut rng = StdRng::seed_from_u64(seed);
+     let mut csv = String::from("Title,Year,Languages,Rating Value\n");
+     for i in 0..rows {
+         let year = rng.gen_range(MIN_YEAR..=MAX_YEAR);
+         let count = rng.gen_range(1..=3);
+         let languages: Vec<&str> = LANGUAGES.choose_multiple(&mut rng, count).copied().collect();
+         let rating = f32::from(rng.gen_range(10_u8..=100)) / 10.0;
+         csv.push_str(&format!("Movie {},{},[{}],{:.1}\n", i, year, languages.join(";"), rating));
+     }
+     csv
+ }
+ 
+ /// Writes `synthetic_csv(rows, seed)` to a file in `dir` and returns its path.
+ pub fn write_synthetic_csv(dir: &Path, rows: usize, seed: u64) -> PathBuf {
+     let path = dir.join(format!("movies_{}.csv", rows));
+     fs::write(&path, synthetic_csv(rows, seed)).unwrap();
+     path
+ }
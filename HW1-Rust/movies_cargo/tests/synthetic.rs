@@ -0,0 +1,26 @@
+/*
+ * Parsing and query tests over generated movie data.
+ */
+
+ mod support;
+ 
+ use movies_cargo::{highest_rated_per_year, read_csv, ParseOptions, RatingSource, YearOrder};
+ 
+ #[test]
+ fn test_synthetic_csv_is_deterministic() {
+     assert_eq!(support::synthetic_csv(100, 7), support::synthetic_csv(100, 7));
+     assert_ne!(support::synthetic_csv(100, 7), support::synthetic_csv(100, 8));
+ }
+ 
+ #[test]
+ fn test_every_synthetic_row_parses() {
+     let dir = tempfile::tempdir().unwrap();
+     let path = support::write_synthetic_csv(dir.path(), 10_000, 42);
+     let movies = read_csv(path.to_str().unwrap(), &ParseOptions::default()).unwrap();
+     assert_eq!(movies.len(), 10_000);
+     assert!(movies.iter().all(|movie| movie.rating.is_some() && !movie.languages.is_empty()));
+ 
+     // 10k movies spread over 122 years leave no year empty
+     let highest = highest_rated_per_year(&movies, YearOrder::Ascending, RatingSource::Rating);
+     assert_eq!(highest.len(), 122);
+ }
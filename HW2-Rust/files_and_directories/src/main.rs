@@ -1,22 +1,38 @@
-/**
+/*
  * movies_processor.rs
  *
  * Description:
  * ------------
  * This Rust program processes CSV files containing movie data within the current directory.
  * It provides a user-friendly interface to select a CSV file based on specific criteria
- * (largest, smallest, or user-specified) and organizes the movies by their release year.
+ * (largest, smallest, most or least recently modified, or user-specified) and organizes the movies by their release year.
  * For each year, the program creates a text file listing all movie titles released that year.
  *
  * Features:
  * ---------
  * - **Directory Operations**: Reads directory entries to identify relevant CSV files.
- * - **File Selection**: Allows users to select the largest or smallest CSV file with
- *   the prefix `movies_` or specify a file by name.
+ * - **File Selection**: Allows users to select the largest, smallest, most recently modified,
+ *   or least recently modified CSV file with the prefix `movies_`, or the one with the most or
+ *   fewest data rows, choose one by number from
+ *   a table of the matching files sorted by name, or specify a file by name. When several
+ *   files tie, the one whose name sorts first is picked.
+ *   Files whose modification time cannot be read are skipped with a warning when picking by
+ *   modification time. `--prefix` and `--suffix` change
+ *   the pattern the largest and smallest files are picked from, e.g.
+ *   `--prefix films- --suffix .csv` for `films-2021.csv`. With `--recursive`, subdirectories
+ *   are searched too, up to `--max-depth` levels (5 by default), and the chosen file is shown
+ *   relative to the current directory. Symbolic links to directories are not followed.
+ * - **Symbolic Links**: Links to CSV files are followed and compared by their target's size and
+ *   modification time; broken links are skipped with a warning. With `--no-follow-symlinks`,
+ *   links are ignored entirely.
+ * - **Compressed Files**: With `--include-compressed`, gzip-compressed files such as `movies_2019.csv.gz`
+ *   match too and are decompressed while they are read. They are compared by their compressed size on disk
+ *   when picking the largest or smallest file.
  * - **CSV Parsing**: Utilizes the `csv` crate to parse CSV files and extract movie information.
  * - **Data Processing**: Organizes movies by their release year and creates corresponding text files.
  * - **Directory and File Creation**: Creates new directories and files with specific naming conventions
- *   and sets appropriate permissions using Unix-style permissions.
+ *   and sets appropriate permissions using Unix-style permissions. On other platforms, such as
+ *   Windows, the files are created with the platform's default permissions.
  * - **Error Handling**: Implements robust error handling to manage file access, parsing, and permission issues.
  *
  * Compilation:
@@ -33,16 +49,214 @@
  *
  *     cargo run
  *
+ * The generated directories are named after your ONID. Pass it with `--onid`:
+ *
+ *     cargo run -- --onid yourname
+ *
+ * or set the `ONID` environment variable; the flag wins when both are given. The ONID is
+ * trimmed, lowercased, and stripped of whitespace, so `My Name` becomes `myname`; the result must
+ * be 3 to 30 lowercase letters or digits. An empty ONID, or one with `/`, `\`, or a leading dot,
+ * is rejected with the reason before the menu is shown.
+ *
  * The program will present a menu-driven interface with the following options:
  *
- * 1. **Select file to process**: Choose a file based on size or specify a file name.
+ * 1. **Select file to process**: Choose a file based on size or modification time, or specify a file name.
  * 2. **Exit the program**: Terminate the program.
  *
  * After selecting a file to process, the program will:
  * - Create a new directory named `<your_onid>.movies.<random_number>` with permissions `rwxr-x---`.
  * - Parse the selected CSV file to extract movie titles and their release years.
  * - For each release year, create a text file named `YYYY.txt` containing the titles of movies released that year,
- *   with permissions `rw-r-----`.
+ *   with permissions `rw-r-----`. Years are written in ascending order and titles are sorted by their bytes,
+ *   so processing the same file twice produces identical year files. A title repeated within a year,
+ *   ignoring surrounding whitespace, is written once and the number of dropped repeats is printed;
+ *   `--keep-duplicates` writes every occurrence unchanged.
+ *
+ * `--output-format json` writes `YYYY.json` files instead, each holding an object such as
+ * `{"year": 1994, "count": 3, "titles": ["...", ...]}` with the same titles and permissions as the
+ * text files. It cannot be combined with `--stream`.
+ *
+ * `--output-format csv` writes `YYYY.csv` files with a `title` header row, or the names given to
+ * `--columns`, and one row per movie. Titles holding commas or quotes are quoted as CSV requires.
+ * It also cannot be combined with `--stream`.
+ *
+ * `--select largest|smallest|newest|oldest|most-rows|fewest-rows` skips the menu: the program
+ * picks that file, processes it, and exits; see Exit status below.
+ *
+ * `most-rows` and `fewest-rows`, or options 8 and 9 of the file selection menu, compare the
+ * files by their number of data rows instead of their size in bytes, so a file of a few movies
+ * with long quoted descriptions does not count as the largest. Each file is read one record at
+ * a time, with a progress line on the standard error; a file that cannot be read is skipped
+ * with a warning.
+ *
+ * `--file PATH` processes PATH without searching the directory and exits, like `--select`. With
+ * `--file -`, the CSV data is read from the standard input, so the menus, which also read the
+ * standard input, are never shown; this mode only works with the non-interactive flags:
+ *
+ *     other_program | cargo run -- --file - --onid yourname
+ *
+ * `--all`, or option 7 of the file selection menu, processes every matching file in name order,
+ * each into its own new directory, printing a status line per file and a summary of successes
+ * and failures. A failed file does not stop the batch unless `--fail-fast` is given. With `--all`,
+ * the exit status is that of the first failed file, or 2 if no file matches. `--jobs N` processes up to N files
+ * at the same time; each file's messages are printed together, every line prefixed with
+ * `[file name]`, and each file always gets a directory name no other file is using.
+ *
+ * `--glob PATTERN` picks the files matching a glob pattern relative to the current directory
+ * instead of the prefix and suffix, as in `--glob 'movies_2024_*.csv'`; `*` does not match a
+ * `/`, so subdirectories are only searched when named, as in `--glob 'old/movies_*.csv'`. A single match is processed
+ * right away, several are shown as a numbered list to choose from, and with `--all` each is
+ * processed. No match exits with status 2. A pattern that is absolute or contains `..` is
+ * rejected, and a match that leads outside the current directory through a symbolic link is
+ * skipped with a warning.
+ *
+ * `--max-size BYTES` leaves files larger than BYTES out of the picks by size and by rows,
+ * printing a notice for each on stderr; sizes take the binary units K, M, G, and T, as in `500M` or `2G`.
+ * A file over the limit that is named with `--file` or `--glob`, or chosen otherwise, is only
+ * processed after the user confirms it, or right away with `--force`; refusing it exits with
+ * status 5. There is no limit by default.
+ *
+ * A UTF-8 byte order mark at the start of the file is ignored, and so are `\r\n` line endings,
+ * as in CSV files exported from Excel on Windows.
+ *
+ * `--encoding latin1` reads a Latin-1 (ISO-8859-1) CSV file, and `--encoding auto` reads UTF-8
+ * until the first byte that is not valid UTF-8, then warns and reads the rest as Latin-1. With
+ * the default `--encoding utf8`, such a byte stops processing with exit status 3, unless
+ * `--lossy` is given, which reads each invalid byte sequence as the replacement character U+FFFD.
+ *
+ * The column delimiter is detected from the header row: of `,`, `;`, tab, and `|`, the one that
+ * splits it into the most columns is used, and a message names it unless it is the comma, which
+ * also wins ties.
+ * `--delimiter CHAR` sets it instead, as in `--delimiter ';'` or `--delimiter tab`.
+ *
+ * `--no-header` reads the first row as data, for files without a header row; the title is then
+ * the first column and the year the second, and `--columns` can only name the title. Without
+ * it, a first row whose second field is a plausible year prints a warning suggesting the flag.
+ *
+ * `--title-col 3 --year-col 1` reads the title and year from those zero-based columns instead,
+ * taking precedence over the header names and the first two columns; either flag may be given
+ * alone. The two must differ. Rows may then have any number of columns, and a row too short to
+ * hold either column is skipped and counted as a column out of range. Pair them with
+ * `--no-header` for files without a header row.
+ *
+ * Once a file is processed, a line such as `1994: 12 titles -> onid.movies.4711/1994.txt`
+ * is printed for each year file in ascending year order; `--quiet` leaves these lines out. The
+ * JSON report of `--format json` lists the same counts and paths under `year_files`.
+ *
+ * `--name-scheme timestamp` makes `{rand}` stand for the local time as `YYYYMMDD-HHMMSS`
+ * instead of a random number, naming the directory `onid.movies.20240309-140501`. A name that is
+ * taken, such as by a run within the same second, gets `-1`, `-2`, and so on appended to the
+ * time. The default is `--name-scheme random`.
+ *
+ * `--dir-template TEMPLATE` names the new directory after TEMPLATE instead of
+ * `{onid}.movies.{rand}`. The placeholders are `{onid}`, `{rand}` (a random number from 0 to
+ * 99999), `{source}` (the CSV file name without its extensions), and `{date}` (today as YYYYMMDD,
+ * in UTC), as in `--dir-template '{source}-{date}'`. A template that expands to an empty name or
+ * a path separator is rejected. Without `{rand}`, a name that is taken gets a numeric suffix,
+ * such as `movies_2-20240309.1`. `--clean` only finds directories with the default names.
+ *
+ * A subdirectory or file that cannot be inspected while searching is skipped with a warning
+ * naming the OS error. If the current directory itself cannot be read, the non-interactive
+ * flags exit with status 4 and the menu reports the error.
+ *
+ * `--output-dir PATH` creates the `<your_onid>.movies.<random_number>` directory under PATH
+ * instead of the current directory, creating PATH first if it does not exist.
+ *
+ * The Title and Year columns are found by their header names, in any order and ignoring case.
+ * If the header row does not name both, the first two columns are used with a warning; a file with
+ * fewer than two columns is an error.
+ *
+ * `--columns title,rating` writes the listed columns of each movie, tab-separated and in that order,
+ * instead of only the title. A name matches a header ignoring case, or the first word of one, so
+ * `rating` finds `Rating Value`. If a listed column is missing, the file fails before any row is
+ * read and the available columns are listed.
+ *
+ * Line breaks, tabs, and other control characters inside a title or another written value, such
+ * as a multi-line quoted title, are replaced with a single space, so each movie stays on one line.
+ * The summary counts the titles that were cleaned this way.
+ *
+ * Only years that are whole numbers from 1800 to 2100 are written; rows with other years, such as
+ * `19x7` or `../etc`, are skipped and counted. `--min-year` and `--max-year` change the range.
+ *
+ * `--from-year 2000` and `--to-year 2009`, alone or together, write only the movies released in
+ * that range. The other rows are counted as filtered in the summary and the manifest, apart from
+ * the rows skipped for invalid data, and do not fail `--strict`. A year is checked against
+ * `--min-year` and `--max-year` first, so a bogus year is still skipped even inside the range.
+ *
+ * `--exclude-years 2013,2015` never writes the listed years, counting their rows as excluded in
+ * the summary and the manifest. An excluded year is excluded even inside `--from-year` and
+ * `--to-year`. An entry that is not a whole number is rejected before anything is processed.
+ *
+ * After parsing, the program prints how many rows were read, written, and skipped, with the
+ * reasons rows were skipped (missing title, missing year, invalid year, or column out of range) and up to five
+ * example line numbers each. `--skip-report` also writes every skipped line number to
+ * `skipped.txt` in the new directory. With `--strict`, any skipped row fails the run before
+ * anything is written, so `--select` exits with status 3.
+ *
+ * After processing, the menu prints a summary: the number of years and titles written, the year
+ * files with the fewest and most titles, and the full path of the new directory. With `--select`,
+ * the summary is printed on one line, or as a JSON object with `--format json`. The JSON object is
+ * then the only output on stdout; the progress messages and prompts go to stderr.
+ *
+ * `--stream` writes each title to its year file as soon as its row is read, so memory use does not
+ * grow with the size of the CSV file. Titles then keep their CSV order and repeats are kept, and
+ * with `--strict` the output is discarded if a row was skipped. At most 64 year files
+ * are open at once.
+ *
+ * The year files are written and synced in a hidden `.tmp.<your_onid>.movies.<random_number>`
+ * directory, which is renamed to its final name only once everything is written. If processing
+ * fails part way, the hidden directory is removed and the cleanup is reported, so a
+ * `<your_onid>.movies.<random_number>` directory is always complete.
+ *
+ * `--clean` lists the `<your_onid>.movies.<random_number>` directories in the output directory,
+ * including the `<your_onid>.movies.YYYYMMDD-HHMMSS` ones of `--name-scheme timestamp`, with
+ * their creation time and size, asks for confirmation, removes them, and reports how many
+ * directories and bytes were removed. `--yes` skips the confirmation. Directories of another ONID,
+ * or whose names merely contain the pattern, are never touched.
+ *
+ * `--by-decade` writes each year file in a subdirectory for its decade, such as `1990s/1994.txt`,
+ * created with the same permissions as the new directory. The manifest lists the nested paths.
+ *
+ * Each new directory also holds `manifest.json`, written last, recording the full path, size, and
+ * modification time of the CSV file, when it was processed, how many rows were read, written,
+ * and skipped, and each file written with its number of lines.
+ *
+ * Each run also leaves `process.log` in its directory, with the permissions of the year files. It
+ * records the full path of the CSV file, when the run started and finished, every skipped row
+ * with its line number and reason, the summary, and each file written. It is started before the
+ * CSV file is read and written a line at a time, each skipped row as soon as it is read, so an
+ * interrupted run still leaves what happened so far. When a run fails, the log ends with the
+ * error and is kept beside the output as `<your_onid>.movies.<random_number>.process.log`
+ * before the hidden directory is removed. Since it is finished after the manifest it is not
+ * listed there or in `SHA256SUMS`. `--no-log` leaves it out.
+ *
+ * `--checksums` also writes `SHA256SUMS` before the manifest, holding the SHA-256 hash of each
+ * year file and `skipped.txt` in the `hash  name` format of `sha256sum`, so the directory can
+ * be checked with `sha256sum -c SHA256SUMS`. It gets the permissions of the year files, and the
+ * manifest records that checksums were written.
+ *
+ * `--dry-run` parses the chosen file and prints the directory and year files it would create,
+ * with the number of titles in each, without writing anything. Combined with `--select`,
+ * the exit status is 3 if the file cannot be parsed. The summary then says what would be written,
+ * as in `Would write 9 titles in 8 year files to ...`, and the JSON report has `"dry_run": true`.
+ *
+ * A CSV file without a single valid movie record, such as an empty file, one with only a header
+ * row, or one whose rows are all skipped, creates no directory at all: the program prints
+ * "No valid movie records found in <file>; nothing to do" and exits with status 6.
+ *
+ * Exit status:
+ * - 0: success.
+ * - 2: no CSV file matches the search.
+ * - 3: the CSV file cannot be parsed, or `--strict` found a skipped row.
+ * - 4: a file or directory cannot be read, created, or written.
+ * - 5: the command-line arguments or the ONID are invalid.
+ * - 6: the CSV file has no valid movie records.
+ *
+ * The non-interactive flags exit with these codes. From the menu, exiting right after a failed
+ * operation returns that operation's code, and 0 otherwise.
+ *
+ * `--dir-mode` and `--file-mode` replace those permissions with octal modes such as
+ * `--dir-mode 700 --file-mode 600`. They are accepted but ignored on non-Unix platforms.
  *
  * Example Interaction:
  * --------------------
@@ -56,8 +270,15 @@
  * Enter 1 to pick the largest file
  * Enter 2 to pick the smallest file
  * Enter 3 to specify the name of a file
+ * Enter 4 to pick the most recently modified file
+ * Enter 5 to pick the least recently modified file
+ * Enter 6 to choose from a list of the matching files
+ * Enter 7 to process every matching file
+ * Enter 8 to pick the file with the most data rows
+ * Enter 9 to pick the file with the fewest data rows
  *
- * Enter a choice from 1 to 3: 1
+ * Enter a choice from 1 to 9: 1
+ * Picked the largest file matching movies_*.csv
  * Now processing the chosen file named movies_1.csv
  * Created directory with name your_onid.movies.83465
  *
@@ -75,23 +296,910 @@
  * 12/4/2024
  */
 
-use csv::ReaderBuilder; // For reading and parsing CSV files
-use rand::Rng; // For generating random numbers
-use std::collections::HashMap; // For storing movies organized by year
+use chrono::{Local, NaiveDateTime}; // For timestamps in directory names
+use clap::{Parser, ValueEnum}; // For parsing command-line options
+use csv::{ReaderBuilder, StringRecord}; // For reading and parsing CSV files
+use encoding_rs::WINDOWS_1252; // For reading Latin-1 CSV files
+use flate2::read::GzDecoder; // For reading gzip-compressed CSV files
+use rand::{Rng, RngCore}; // For generating random numbers
+use serde::{Deserialize, Serialize}; // For the JSON report and the manifest
+use sha2::{Digest, Sha256}; // For the checksums of the output files
+use std::borrow::Cow; // For cleaning titles without copying the clean ones
+use std::cmp::Ordering; // For choosing between the largest and smallest file
+use std::collections::{BTreeMap, HashMap, VecDeque}; // For storing movies organized by year, in ascending year order
 use std::env; // For accessing environment variables and current directory
+use std::fmt; // For displaying the file name pattern
 use std::fs::{self, File, OpenOptions}; // For file and directory operations
-use std::io::{self, Write}; // For input/output operations
-use std::path::Path; // For handling filesystem paths
-use std::process; // For exiting the program
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write}; // For input/output operations
+use std::num::NonZeroUsize; // For the number of worker threads
+use std::ops::RangeInclusive; // For the range of accepted release years
+#[cfg(unix)]
 use std::os::unix::fs::PermissionsExt; // For setting file and directory permissions
+use std::path::{Component, Path, PathBuf}; // For handling filesystem paths
+use std::process; // For exiting the program
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering}; // For sharing batch progress between workers
+use std::sync::Mutex; // For collecting the results of the workers
+use std::thread; // For processing a batch on several threads
+use std::time::SystemTime; // For comparing file modification times
 
-// Define a constant for the user's ONID (replace "clinicke" with your actual ONID)
+// The ONID used when neither --onid nor the ONID environment variable is given
 const ONID: &str = "clinicke";
 
+// The permissions of a new output directory (rwxr-x---) unless --dir-mode is given
+const DEFAULT_DIR_MODE: u32 = 0o750;
+
+// The permissions of each year file (rw-r-----) unless --file-mode is given
+const DEFAULT_FILE_MODE: u32 = 0o640;
+
+// The earliest and latest release years accepted unless --min-year or --max-year is given
+const DEFAULT_MIN_YEAR: u32 = 1800;
+const DEFAULT_MAX_YEAR: u32 = 2100;
+
+/// Command-line options of the program.
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Organizes the movies of a CSV file into one text file per release year"
+)]
+struct Options {
+    /// The ONID the generated directories are named after; defaults to $ONID, then clinicke
+    #[arg(long)]
+    onid: Option<String>,
+
+    /// The prefix a CSV file name must start with to be picked as the largest or smallest file
+    #[arg(long, default_value = "movies_")]
+    prefix: String,
+
+    /// The suffix a CSV file name must end with to be picked as the largest or smallest file
+    #[arg(long, default_value = ".csv")]
+    suffix: String,
+
+    /// Also pick gzip-compressed files, whose names end with the suffix followed by .gz
+    #[arg(long)]
+    include_compressed: bool,
+
+    /// The permissions of the output directory in octal, such as 700 [default: 750]
+    #[arg(long, value_name = "MODE", value_parser = parse_mode)]
+    dir_mode: Option<u32>,
+
+    /// The permissions of each year file in octal, such as 600 [default: 640]
+    #[arg(long, value_name = "MODE", value_parser = parse_mode)]
+    file_mode: Option<u32>,
+
+    /// How --select prints the processing report
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+
+    /// Process every matching file, one output directory each, and exit instead of showing the menu
+    #[arg(long, conflicts_with = "select")]
+    all: bool,
+
+    /// Process this file, or the standard input for `-`, and exit instead of showing the menu
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["select", "all"])]
+    file: Option<String>,
+
+    /// Remove the output directories of this ONID from the output directory and exit
+    #[arg(long, conflicts_with_all = ["select", "all", "file"])]
+    clean: bool,
+
+    /// Remove the directories found by --clean without asking for confirmation
+    #[arg(long, requires = "clean")]
+    yes: bool,
+
+    /// Stop a batch at the first file that fails
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// How many files a batch processes at the same time
+    #[arg(long, value_name = "N", default_value = "1")]
+    jobs: NonZeroUsize,
+
+    /// Pick a file this way, process it, and exit instead of showing the menu
+    #[arg(long, value_enum, value_name = "HOW")]
+    select: Option<Selection>,
+
+    /// Also search subdirectories for the largest or smallest file
+    #[arg(long)]
+    recursive: bool,
+
+    /// Process the files matching this glob pattern, relative to the current directory, such as 'movies_2024_*.csv'
+    #[arg(long, value_name = "PATTERN", conflicts_with_all = ["select", "file", "clean"])]
+    glob: Option<String>,
+
+    /// Leave files larger than this out of the picks by size and by rows, such as 500M or 2G [default: no limit]
+    #[arg(long, value_name = "BYTES", value_parser = parse_size)]
+    max_size: Option<u64>,
+
+    /// Process a named file over --max-size without asking for confirmation
+    #[arg(long)]
+    force: bool,
+
+    /// Ignore symbolic links to CSV files instead of following them
+    #[arg(long)]
+    no_follow_symlinks: bool,
+
+    /// How many levels of subdirectories --recursive searches
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    max_depth: usize,
+
+    /// The directory the output directory is created in, created if missing [default: current directory]
+    #[arg(long, value_name = "PATH")]
+    output_dir: Option<PathBuf>,
+
+    /// The earliest release year written; rows with earlier years are skipped
+    #[arg(long, value_name = "YEAR", default_value_t = DEFAULT_MIN_YEAR)]
+    min_year: u32,
+
+    /// The latest release year written; rows with later years are skipped
+    #[arg(long, value_name = "YEAR", default_value_t = DEFAULT_MAX_YEAR)]
+    max_year: u32,
+
+    /// Only write movies released in or after this year; the others are counted as filtered
+    #[arg(long, value_name = "YEAR")]
+    from_year: Option<u32>,
+
+    /// Only write movies released in or before this year; the others are counted as filtered
+    #[arg(long, value_name = "YEAR")]
+    to_year: Option<u32>,
+
+    /// Never write movies released in these years, such as 2013,2015
+    #[arg(long, value_name = "YEARS", value_delimiter = ',', value_parser = parse_excluded_year)]
+    exclude_years: Vec<u32>,
+
+    /// The character separating the columns, such as ';' or 'tab' [default: detected from the header row]
+    #[arg(long, value_name = "CHAR", value_parser = parse_delimiter)]
+    delimiter: Option<u8>,
+
+    /// Read the first row as data instead of headers; the title is the first column and the year the second
+    #[arg(long)]
+    no_header: bool,
+
+    /// The zero-based column the titles are read from, overriding the headers
+    #[arg(long, value_name = "N")]
+    title_col: Option<usize>,
+
+    /// The zero-based column the release years are read from, overriding the headers
+    #[arg(long, value_name = "N")]
+    year_col: Option<usize>,
+
+    /// The character encoding of the CSV file; auto reads UTF-8 and falls back to Latin-1
+    #[arg(long, value_enum, value_name = "ENCODING", default_value_t = InputEncoding::Utf8)]
+    encoding: InputEncoding,
+
+    /// Read bytes that are not valid UTF-8 as U+FFFD instead of failing
+    #[arg(long)]
+    lossy: bool,
+
+    /// The columns written for each movie, tab-separated, in this order
+    #[arg(
+        long,
+        value_name = "NAMES",
+        value_delimiter = ',',
+        default_value = "title"
+    )]
+    columns: Vec<String>,
+
+    /// Write every occurrence of a title instead of dropping repeats within a year
+    #[arg(long)]
+    keep_duplicates: bool,
+
+    /// Also write every skipped row's line number to skipped.txt in the new directory
+    #[arg(long)]
+    skip_report: bool,
+
+    /// Fail without writing anything if any row is skipped
+    #[arg(long)]
+    strict: bool,
+
+    /// Write each title as soon as its row is read instead of holding every title in memory;
+    /// titles keep their CSV order and repeats are kept
+    #[arg(long)]
+    stream: bool,
+
+    /// The format of each year file
+    #[arg(long, value_enum, default_value_t = OutputFormat::Txt, conflicts_with = "stream")]
+    output_format: OutputFormat,
+
+    /// Write each year file in a subdirectory for its decade, such as 1990s/1994.txt
+    #[arg(long)]
+    by_decade: bool,
+
+    /// The name of the output directory, with the placeholders {onid}, {rand}, {source}, and {date}
+    #[arg(long, value_name = "TEMPLATE", default_value = DEFAULT_DIR_TEMPLATE)]
+    dir_template: String,
+
+    /// What {rand} in the directory name stands for
+    #[arg(long, value_enum, value_name = "SCHEME", default_value_t = NameScheme::Random)]
+    name_scheme: NameScheme,
+
+    /// Print the directory and year files that would be created without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Do not print how many titles each year file holds
+    #[arg(long)]
+    quiet: bool,
+
+    /// Also write the SHA-256 hash of each output file to SHA256SUMS in the new directory
+    #[arg(long)]
+    checksums: bool,
+
+    /// Do not write process.log in the new directory
+    #[arg(long)]
+    no_log: bool,
+}
+
+/// How a CSV file is picked from the files matching the search.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Selection {
+    /// The largest file.
+    Largest,
+    /// The smallest file.
+    Smallest,
+    /// The most recently modified file.
+    Newest,
+    /// The least recently modified file.
+    Oldest,
+    /// The file with the most data rows.
+    MostRows,
+    /// The file with the fewest data rows.
+    FewestRows,
+}
+
+impl Selection {
+    /// Describes the file the selection picks, such as `most recently modified file`.
+    fn describe(self) -> &'static str {
+        match self {
+            Selection::Largest => "largest file",
+            Selection::Smallest => "smallest file",
+            Selection::Newest => "most recently modified file",
+            Selection::Oldest => "least recently modified file",
+            Selection::MostRows => "file with the most data rows",
+            Selection::FewestRows => "file with the fewest data rows",
+        }
+    }
+}
+
+/// How the processing report is printed after `--select`.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum ReportFormat {
+    /// A single line of text.
+    Text,
+    /// A single JSON object.
+    Json,
+}
+
+/// What makes each new directory name unique, standing for `{rand}` in the name template.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum NameScheme {
+    /// A random number from 0 to 99999.
+    Random,
+    /// The local time as `YYYYMMDD-HHMMSS`.
+    Timestamp,
+}
+
+/// The character encoding of a CSV file.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum InputEncoding {
+    /// UTF-8, the default.
+    Utf8,
+    /// ISO-8859-1, read as its superset Windows-1252.
+    Latin1,
+    /// UTF-8, switching to Latin-1 at the first byte that is not valid UTF-8.
+    Auto,
+}
+
+/// The format of the year files `process_file` writes.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    /// `YYYY.txt` with one title per line.
+    Txt,
+    /// `YYYY.json` with the year, the number of titles, and the titles.
+    Json,
+    /// `YYYY.csv` with a header row of the written columns, then one row per title.
+    Csv,
+}
+
+impl OutputFormat {
+    /// The extension of the year files, without the dot.
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Txt => "txt",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+        }
+    }
+}
+
+/// The contents of a `YYYY.json` year file.
+#[derive(Debug, PartialEq, Serialize)]
+struct YearFile<'a> {
+    /// The release year.
+    year: u32,
+    /// How many titles follow.
+    count: usize,
+    /// The titles released that year.
+    titles: &'a [String],
+}
+
+/// Settings that shape the output of `process_file`.
+#[derive(Debug, Clone)]
+struct ProcessOptions {
+    /// The ONID the new directory is named after.
+    onid: String,
+    /// The permission bits of the new directory.
+    dir_mode: u32,
+    /// The permission bits of each year file.
+    file_mode: u32,
+    /// The directory the output directory is created in; empty for the current directory.
+    output_dir: PathBuf,
+    /// Whether to only print what would be created instead of writing it.
+    dry_run: bool,
+    /// Whether to keep repeated titles within a year instead of dropping them.
+    keep_duplicates: bool,
+    /// How the CSV file is read.
+    read: ReadOptions,
+    /// Whether to write the line numbers of all skipped rows to `skipped.txt`.
+    skip_report: bool,
+    /// Whether a skipped row fails the run.
+    strict: bool,
+    /// Whether to write titles as they are read, unsorted and with repeats, instead of buffering them.
+    stream: bool,
+    /// The format of each year file.
+    output_format: OutputFormat,
+    /// Whether to write each year file in a subdirectory for its decade.
+    by_decade: bool,
+    /// The template the name of the new directory is expanded from.
+    dir_template: String,
+    /// What `{rand}` in the template stands for.
+    name_scheme: NameScheme,
+    /// Whether to leave out the line for each year file once processing finishes.
+    quiet: bool,
+    /// Whether to write `SHA256SUMS` with the hash of each file written.
+    checksums: bool,
+    /// Whether to write `process.log` as the run goes.
+    log: bool,
+}
+
+/// Settings that shape how the CSV file is read.
+#[derive(Debug, Clone, PartialEq)]
+struct ReadOptions {
+    /// The release years that are valid; rows with other years are skipped.
+    years: RangeInclusive<u32>,
+    /// The earliest valid release year written; earlier rows are filtered.
+    from_year: Option<u32>,
+    /// The latest valid release year written; later rows are filtered.
+    to_year: Option<u32>,
+    /// The valid release years that are never written, even within `from_year` and `to_year`.
+    excluded_years: Vec<u32>,
+    /// The header names of the columns written for each movie, in order.
+    columns: Vec<String>,
+    /// The byte separating the columns, or `None` to detect it from the header row.
+    delimiter: Option<u8>,
+    /// Whether the first row holds the column names; without it, every row is data.
+    headers: bool,
+    /// The column of the titles, or `None` to find it from the headers.
+    title_col: Option<usize>,
+    /// The column of the release years, or `None` to find it from the headers.
+    year_col: Option<usize>,
+    /// The character encoding of the CSV file.
+    encoding: InputEncoding,
+    /// Whether bytes that are not valid UTF-8 are read as U+FFFD instead of failing.
+    lossy: bool,
+}
+
+impl Default for ReadOptions {
+    fn default() -> ReadOptions {
+        ReadOptions {
+            years: DEFAULT_MIN_YEAR..=DEFAULT_MAX_YEAR,
+            columns: vec!["title".to_string()],
+            from_year: None,
+            to_year: None,
+            excluded_years: Vec::new(),
+            delimiter: None,
+            headers: true,
+            title_col: None,
+            year_col: None,
+            encoding: InputEncoding::Utf8,
+            lossy: false,
+        }
+    }
+}
+
+impl ReadOptions {
+    /// Whether the valid release year `year` is within `--from-year` and `--to-year`.
+    fn wanted_year(&self, year: u32) -> bool {
+        self.from_year.is_none_or(|from| year >= from) && self.to_year.is_none_or(|to| year <= to)
+    }
+}
+
+/// Parses a column delimiter: a single ASCII character such as `;`, or `tab` or `\t` for a tab.
+///
+/// # Returns
+///
+/// A `Result` holding the delimiter byte, or a message if the text is not one ASCII character.
+fn parse_delimiter(text: &str) -> Result<u8, String> {
+    match text {
+        "tab" | "\\t" | "\t" => Ok(b'\t'),
+        _ if text.len() == 1 && text.is_ascii() && text != "\"" && text != "\n" => {
+            Ok(text.as_bytes()[0])
+        }
+        _ => Err(format!(
+            "'{}' is not a single character such as ; or tab",
+            text
+        )),
+    }
+}
+
+/// Parses a permission mode written in octal, such as `750`, `0750`, or `0o750`.
+///
+/// # Returns
+///
+/// A `Result` holding the mode, or a message if the text is not octal or exceeds `0o777`.
+fn parse_mode(text: &str) -> Result<u32, String> {
+    let digits = text.strip_prefix("0o").unwrap_or(text);
+    let mode = u32::from_str_radix(digits, 8)
+        .map_err(|_| format!("'{}' is not an octal mode such as 750", text))?;
+    if mode > 0o777 {
+        return Err(format!("mode {} is larger than 777", text));
+    }
+    Ok(mode)
+}
+
+/// Parses one entry of the `--exclude-years` list, a whole number such as `2013`.
+///
+/// # Returns
+///
+/// A `Result` holding the year, or a message if the entry is not a whole number.
+fn parse_excluded_year(text: &str) -> Result<u32, String> {
+    text.trim().parse().map_err(|_| {
+        format!(
+            "'{}' is not a year; list whole numbers such as 2013,2015",
+            text
+        )
+    })
+}
+
+/// Parses a size in bytes with an optional binary unit, such as `1024`, `500M`, `2G`, or
+/// `1.5GiB`. The units K, M, G, and T are powers of 1024 and may be followed by `B` or `iB`.
+///
+/// # Returns
+///
+/// A `Result` holding the size in bytes, or a message if the text is not a size.
+fn parse_size(text: &str) -> Result<u64, String> {
+    let invalid = || format!("'{}' is not a size such as 500M or 2G", text);
+    let trimmed = text.trim();
+    let unit_start = trimmed
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(unit_start);
+    let unit = unit.trim_start().to_ascii_uppercase();
+    let unit = match unit.strip_suffix("IB") {
+        Some("") => return Err(invalid()), // "iB" needs a unit before it
+        Some(unit) => unit,
+        None => unit.strip_suffix('B').unwrap_or(&unit),
+    };
+    let power = match unit {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return Err(invalid()),
+    };
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let bytes = number * 1024f64.powi(power);
+    if !bytes.is_finite() || bytes >= u64::MAX as f64 {
+        return Err(format!("'{}' is too large", text));
+    }
+    Ok(bytes.round() as u64)
+}
+
+/// Chooses the ONID from the `--onid` flag, then the `ONID` environment variable,
+/// then the `ONID` constant, and checks that it is valid.
+///
+/// # Arguments
+///
+/// * `flag` - The value of `--onid`, if it was given.
+/// * `env` - The value of the `ONID` environment variable, if it is set.
+///
+/// # Returns
+///
+/// A `Result` holding the ONID, or a message naming the invalid value and where it came from.
+fn resolve_onid(flag: Option<&str>, env: Option<&str>) -> Result<String, String> {
+    let (onid, source) = match (flag, env) {
+        (Some(onid), _) => (onid, "--onid"),
+        (None, Some(onid)) => (onid, "the ONID environment variable"),
+        (None, None) => (ONID, "the built-in default"),
+    };
+    validate_onid(onid)
+        .map_err(|reason| format!("Invalid ONID '{}' from {}: {}", onid, source, reason))
+}
+
+/// Why an ONID cannot name the output directories.
+#[derive(Debug, Clone, PartialEq)]
+enum OnidError {
+    /// Nothing is left once whitespace is removed.
+    Empty,
+    /// It contains a path separator, `/` or `\`.
+    PathSeparator(char),
+    /// It starts with a dot, which would hide the directories.
+    LeadingDot,
+    /// It is not 3 to 30 characters long; holds the length.
+    Length(usize),
+    /// It contains a character other than a lowercase letter or digit.
+    InvalidCharacter(char),
+}
+
+impl fmt::Display for OnidError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OnidError::Empty => write!(f, "it is empty"),
+            OnidError::PathSeparator(c) => {
+                write!(f, "it may not contain the path separator '{}'", c)
+            }
+            OnidError::LeadingDot => write!(f, "it may not start with a dot"),
+            OnidError::Length(length) => {
+                write!(f, "it must be 3 to 30 characters long, not {}", length)
+            }
+            OnidError::InvalidCharacter(c) => write!(
+                f,
+                "it may only contain lowercase letters and digits, not '{}'",
+                c
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OnidError {}
+
+/// Normalizes an ONID by trimming it, lowercasing it, and removing any whitespace, then
+/// checks that the result is 3 to 30 lowercase ASCII letters or digits.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the normalized ONID, or the `OnidError` saying what is wrong.
+fn validate_onid(onid: &str) -> Result<String, OnidError> {
+    let normalized: String = onid
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    if normalized.is_empty() {
+        return Err(OnidError::Empty);
+    }
+    if let Some(separator) = normalized.chars().find(|&c| c == '/' || c == '\\') {
+        return Err(OnidError::PathSeparator(separator));
+    }
+    if normalized.starts_with('.') {
+        return Err(OnidError::LeadingDot);
+    }
+    let length = normalized.chars().count();
+    if !(3..=30).contains(&length) {
+        return Err(OnidError::Length(length));
+    }
+    if let Some(c) = normalized
+        .chars()
+        .find(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit()))
+    {
+        return Err(OnidError::InvalidCharacter(c));
+    }
+    Ok(normalized)
+}
+
+/// Why the program failed, which decides its exit status.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Failure {
+    /// No CSV file matched the search.
+    NoMatchingCsv,
+    /// The CSV file could not be parsed, or a row was skipped under `--strict`.
+    Parse,
+    /// A file or directory could not be read, created, or written.
+    FileSystem,
+    /// The command-line arguments or the ONID were invalid.
+    InvalidArguments,
+    /// The CSV file had no valid movie records, so nothing was written.
+    NoMovieRecords,
+}
+
+impl Failure {
+    /// Classifies an error returned by `process_file`. File system errors arrive as
+    /// `io::Error`, or as a `csv::Error` wrapping one; everything else is about the data.
+    fn of(error: &(dyn std::error::Error + 'static)) -> Failure {
+        if error.is::<NoMovieRecords>() {
+            return Failure::NoMovieRecords;
+        }
+        if error.is::<io::Error>() {
+            return Failure::FileSystem;
+        }
+        match error.downcast_ref::<csv::Error>().map(csv::Error::kind) {
+            Some(csv::ErrorKind::Io(_)) => Failure::FileSystem,
+            _ => Failure::Parse,
+        }
+    }
+
+    /// The exit status of the program for this failure.
+    fn exit_code(self) -> i32 {
+        match self {
+            Failure::NoMatchingCsv => 2,
+            Failure::Parse => 3,
+            Failure::FileSystem => 4,
+            Failure::InvalidArguments => 5,
+            Failure::NoMovieRecords => 6,
+        }
+    }
+}
+
 /// The main function serves as the entry point of the program.
 /// It presents a menu to the user to either select a file to process or exit the program.
 /// The program continues to loop until the user chooses to exit.
 fn main() {
+    // Report invalid arguments with their own exit status; help and version still exit with 0
+    let options = Options::try_parse().unwrap_or_else(|e| {
+        if !e.use_stderr() {
+            e.exit();
+        }
+        let _ = e.print();
+        process::exit(Failure::InvalidArguments.exit_code());
+    });
+
+    // Resolve the ONID once up front so an invalid value is reported before the menu
+    let env_onid = env::var("ONID").ok();
+    let onid = match resolve_onid(options.onid.as_deref(), env_onid.as_deref()) {
+        Ok(onid) => onid,
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            process::exit(Failure::InvalidArguments.exit_code());
+        }
+    };
+
+    if options.min_year > options.max_year {
+        eprintln!(
+            "Error: --min-year {} is after --max-year {}",
+            options.min_year, options.max_year
+        );
+        process::exit(Failure::InvalidArguments.exit_code());
+    }
+    if let (Some(title), Some(year)) = (options.title_col, options.year_col) {
+        if title == year {
+            eprintln!(
+                "Error: --title-col and --year-col are both column {}",
+                title
+            );
+            process::exit(Failure::InvalidArguments.exit_code());
+        }
+    }
+    if let (Some(from), Some(to)) = (options.from_year, options.to_year) {
+        if from > to {
+            eprintln!("Error: --from-year {} is after --to-year {}", from, to);
+            process::exit(Failure::InvalidArguments.exit_code());
+        }
+    }
+
+    // Check the glob pattern before anything is searched
+    if let Some(Err(message)) = options.glob.as_deref().map(check_glob_pattern) {
+        eprintln!("Error: invalid --glob: {}", message);
+        process::exit(Failure::InvalidArguments.exit_code());
+    }
+
+    // Check the directory name template before any file is processed
+    let sample = TemplateValues {
+        onid: &onid,
+        rand: "0",
+        source: "movies",
+        date: "20240101",
+    };
+    if let Err(message) = expand_dir_template(&options.dir_template, &sample) {
+        eprintln!("Error: invalid --dir-template: {}", message);
+        process::exit(Failure::InvalidArguments.exit_code());
+    }
+
+    // Permission bits only exist on Unix; elsewhere the files keep their default permissions
+    if cfg!(not(unix)) && (options.dir_mode.is_some() || options.file_mode.is_some()) {
+        eprintln!("Warning: --dir-mode and --file-mode are ignored on this platform.");
+    }
+
+    let current_dir = match env::current_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("Error: cannot access the current directory: {}", e);
+            process::exit(Failure::FileSystem.exit_code());
+        }
+    };
+    let search = CsvSearch {
+        root: current_dir, // Search the current directory
+        pattern: FilePattern {
+            prefix: options.prefix,
+            suffix: options.suffix,
+            compressed: options.include_compressed,
+        },
+        max_depth: if options.recursive {
+            options.max_depth
+        } else {
+            0
+        },
+        follow_symlinks: !options.no_follow_symlinks,
+        glob: options.glob,
+        max_size: options.max_size,
+    };
+    // Asks before processing a single file over --max-size, unless --force was given
+    let size_allowed = |file_name: &str| {
+        check_file_size(
+            Path::new(file_name),
+            options.max_size,
+            options.force,
+            |prompt| {
+                // Keep the standard output for the JSON object alone
+                if options.format == ReportFormat::Json {
+                    eprint!("{}", prompt);
+                } else {
+                    print!("{}", prompt);
+                    let _ = io::stdout().flush();
+                }
+                matches!(read_user_input().to_lowercase().as_str(), "y" | "yes")
+            },
+        )
+    };
+    let refuse_and_exit = |file_name: &str| -> ! {
+        eprintln!(
+            "Not processing {}; pass --force to process files over --max-size.",
+            file_name
+        );
+        process::exit(Failure::InvalidArguments.exit_code());
+    };
+    let process_options = ProcessOptions {
+        onid,
+        dir_mode: options.dir_mode.unwrap_or(DEFAULT_DIR_MODE),
+        file_mode: options.file_mode.unwrap_or(DEFAULT_FILE_MODE),
+        output_dir: options.output_dir.unwrap_or_default(),
+        dry_run: options.dry_run,
+        keep_duplicates: options.keep_duplicates,
+        read: ReadOptions {
+            years: options.min_year..=options.max_year,
+            columns: options.columns,
+            from_year: options.from_year,
+            to_year: options.to_year,
+            excluded_years: options.exclude_years,
+            delimiter: options.delimiter,
+            headers: !options.no_header,
+            title_col: options.title_col,
+            year_col: options.year_col,
+            encoding: options.encoding,
+            lossy: options.lossy,
+        },
+        skip_report: options.skip_report,
+        strict: options.strict,
+        stream: options.stream,
+        output_format: options.output_format,
+        by_decade: options.by_decade,
+        dir_template: options.dir_template,
+        name_scheme: options.name_scheme,
+        quiet: options.quiet,
+        checksums: options.checksums,
+        log: !options.no_log,
+    };
+
+    // With --clean, remove earlier output directories instead of processing anything
+    if options.clean {
+        let dir = if process_options.output_dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            process_options.output_dir.as_path()
+        };
+        let confirm = |count: usize| {
+            print!(
+                "Remove {} {}? [y/N] ",
+                count,
+                if count == 1 {
+                    "directory"
+                } else {
+                    "directories"
+                }
+            );
+            let _ = io::stdout().flush();
+            matches!(read_user_input().to_lowercase().as_str(), "y" | "yes")
+        };
+        match clean_output_dirs(
+            dir,
+            &process_options.onid,
+            options.yes,
+            confirm,
+            &mut io::stdout(),
+        ) {
+            Ok(_) => return,
+            Err(e) => {
+                eprintln!("Error cleaning {}: {}", dir.display(), e);
+                process::exit(Failure::FileSystem.exit_code());
+            }
+        }
+    }
+
+    // Messages about the chosen file; with --format json, stdout holds only the JSON object
+    let note = |message: &str| match options.format {
+        ReportFormat::Text => println!("{}", message),
+        ReportFormat::Json => eprintln!("{}", message),
+    };
+
+    // With --file, process that file without searching the directory or showing the menu
+    if let Some(file_name) = &options.file {
+        if !size_allowed(file_name) {
+            refuse_and_exit(file_name);
+        }
+        process_and_exit(file_name, &process_options, options.format);
+    }
+
+    // With --all, process every matching file without showing the menu
+    if options.all {
+        let batch = process_batch(
+            &search,
+            &process_options,
+            options.fail_fast,
+            options.jobs.get(),
+        );
+        process::exit(batch.failure().map_or(0, Failure::exit_code));
+    }
+
+    // With --glob, process the one matching file, or let the user choose among several
+    if search.glob.is_some() {
+        let files = match list_matching_csvs(&search) {
+            Ok(files) => files,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(Failure::FileSystem.exit_code());
+            }
+        };
+        let file_name = match files.as_slice() {
+            [] => {
+                eprintln!("No files matching {} were found.", search.describe());
+                process::exit(Failure::NoMatchingCsv.exit_code());
+            }
+            [file] => file.name.clone(),
+            _ => match choose_from_list(&search) {
+                Ok(Some(file_name)) => file_name,
+                Ok(None) => process::exit(0),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    process::exit(Failure::FileSystem.exit_code());
+                }
+            },
+        };
+        if !size_allowed(&file_name) {
+            refuse_and_exit(&file_name);
+        }
+        note(&format!(
+            "Now processing the chosen file named {}",
+            file_name
+        ));
+        process_and_exit(&file_name, &process_options, options.format);
+    }
+
+    // With --select, process one file without showing the menu
+    if let Some(selection) = options.select {
+        let file_name = match pick_file(&search, selection) {
+            Ok(Some(file_name)) => file_name,
+            Ok(None) => {
+                eprintln!("No files matching {} were found.", search.describe());
+                process::exit(Failure::NoMatchingCsv.exit_code());
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(Failure::FileSystem.exit_code());
+            }
+        };
+        note(&picked_message(selection, &search.pattern));
+        if !size_allowed(&file_name) {
+            refuse_and_exit(&file_name);
+        }
+        note(&format!(
+            "Now processing the chosen file named {}",
+            file_name
+        ));
+        process_and_exit(&file_name, &process_options, options.format);
+    }
+
+    // The failure of the last operation, which sets the exit status if the user exits right after it
+    let mut last_failure = None;
     loop {
         // Display the main menu options
         println!("1. Select file to process");
@@ -108,18 +1216,44 @@ fn main() {
         match choice.as_str() {
             "1" => {
                 // If the user chooses to select a file, attempt to select and process it
-                if let Some(file_name) = select_file() {
-                    println!("Now processing the chosen file named {}", file_name);
-                    // Attempt to process the selected file and handle any errors
-                    if let Err(e) = process_file(&file_name) {
-                        eprintln!("Error processing file: {}", e);
+                last_failure = None;
+                match select_file(&search) {
+                    Ok(Some(FileChoice::One(file_name))) if !size_allowed(&file_name) => {
+                        println!("Not processing {}.", file_name);
+                    }
+                    Ok(Some(FileChoice::One(file_name))) => {
+                        println!("Now processing the chosen file named {}", file_name);
+                        // Attempt to process the selected file and handle any errors
+                        match process_file(&file_name, &process_options) {
+                            Ok(report) => {
+                                report.lines().iter().for_each(|line| println!("{}", line))
+                            }
+                            Err(e) => {
+                                eprintln!("Error processing file: {}", e);
+                                last_failure = Some(Failure::of(&*e));
+                            }
+                        }
+                    }
+                    Ok(Some(FileChoice::All)) => {
+                        last_failure = process_batch(
+                            &search,
+                            &process_options,
+                            options.fail_fast,
+                            options.jobs.get(),
+                        )
+                        .failure();
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        last_failure = Some(Failure::FileSystem);
                     }
                 }
             }
             "2" => {
                 // If the user chooses to exit, print a message and terminate the program
                 println!("Exiting the program.");
-                process::exit(0);
+                process::exit(last_failure.map_or(0, Failure::exit_code));
             }
             _ => {
                 // If the user enters an invalid choice, display an error message
@@ -129,11 +1263,32 @@ fn main() {
     }
 }
 
+/// Processes `file_name` without the menu, prints the report in `format`, and exits with
+/// status 0, or with the status of the failure if processing failed.
+fn process_and_exit(file_name: &str, options: &ProcessOptions, format: ReportFormat) -> ! {
+    // The JSON object is the only output on stdout, so the progress messages go to stderr
+    let result = match format {
+        ReportFormat::Text => process_file(file_name, options),
+        ReportFormat::Json => process_file_to(file_name, options, &mut io::stderr()),
+    };
+    match result {
+        Ok(report) => match format {
+            ReportFormat::Text => println!("{}", report.one_line()),
+            ReportFormat::Json => println!("{}", report.to_json()),
+        },
+        Err(e) => {
+            eprintln!("Error processing file: {}", e);
+            process::exit(Failure::of(&*e).exit_code());
+        }
+    }
+    process::exit(0);
+}
+
 /// Reads a line of input from the standard input (stdin),
 /// trims any leading/trailing whitespace, and returns it as a String.
-/// 
+///
 /// # Returns
-/// 
+///
 /// A `String` containing the user's input.
 fn read_user_input() -> String {
     let mut input = String::new(); // Initialize a mutable String to store user input
@@ -143,48 +1298,95 @@ fn read_user_input() -> String {
     input.trim().to_string() // Trim whitespace and convert to String
 }
 
+/// Shows the files matching `search` as a numbered table and asks the user to pick one
+/// by number. Invalid numbers ask again; `0` goes back to the previous menu.
+///
+/// # Returns
+///
+/// A `Result` holding an `Option<String>` containing the path of the chosen file, relative
+/// to the search root, or `None` if the user went back or no files match, or the error from
+/// reading the search root.
+fn choose_from_list(search: &CsvSearch) -> io::Result<Option<String>> {
+    let candidates = list_matching_csvs(search)?;
+    if candidates.is_empty() {
+        println!("No files matching {} were found.\n", search.describe());
+        return Ok(None);
+    }
+
+    // Display the table of matching files
+    println!("\n{:>3}  {:<40} {:>10}  Modified", "#", "Name", "Size");
+    for (index, candidate) in candidates.iter().enumerate() {
+        println!("{}", format_candidate_row(index + 1, candidate));
+    }
+
+    loop {
+        print!(
+            "\nEnter a file number from 1 to {}, or 0 to go back: ",
+            candidates.len()
+        );
+        io::stdout().flush().unwrap(); // Ensure the prompt is displayed immediately
+
+        match read_user_input().parse::<usize>() {
+            Ok(0) => return Ok(None),
+            Ok(number) if number <= candidates.len() => {
+                return Ok(Some(candidates[number - 1].name.clone()))
+            }
+            _ => println!(
+                "Invalid choice. Please enter a number from 0 to {}.",
+                candidates.len()
+            ),
+        }
+    }
+}
+
+/// What the user chose to process in the file selection submenu.
+#[derive(Debug, Clone, PartialEq)]
+enum FileChoice {
+    /// The file with this name.
+    One(String),
+    /// Every file matching the search.
+    All,
+}
+
 /// Presents a submenu to the user for selecting a file to process.
-/// The user can choose to pick the largest CSV file with the prefix `movies_`,
-/// the smallest such file, or specify a file by name.
-/// 
+/// The user can choose to pick the largest file in the current directory whose name
+/// matches `search`, the smallest such file, the most or least recently modified such
+/// file, the file with the most or fewest data rows, choose one from a numbered list of
+/// the matching files, specify a file by name, or process every matching file.
+///
 /// # Returns
-/// 
-/// An `Option<String>` containing the name of the selected file if successful.
-fn select_file() -> Option<String> {
+///
+/// A `Result` holding an `Option<FileChoice>` containing the name of the selected file, or
+/// `FileChoice::All`, if successful, or the error from reading the search root.
+fn select_file(search: &CsvSearch) -> io::Result<Option<FileChoice>> {
     loop {
         // Display the file selection menu options
         println!("\nWhich file you want to process?");
         println!("Enter 1 to pick the largest file");
         println!("Enter 2 to pick the smallest file");
-        println!("Enter 3 to specify the name of a file\n");
+        println!("Enter 3 to specify the name of a file");
+        println!("Enter 4 to pick the most recently modified file");
+        println!("Enter 5 to pick the least recently modified file");
+        println!("Enter 6 to choose from a list of the matching files");
+        println!("Enter 7 to process every matching file");
+        println!("Enter 8 to pick the file with the most data rows");
+        println!("Enter 9 to pick the file with the fewest data rows\n");
 
         // Prompt the user to enter their choice
-        print!("Enter a choice from 1 to 3: ");
+        print!("Enter a choice from 1 to 9: ");
         io::stdout().flush().unwrap(); // Ensure the prompt is displayed immediately
 
         // Read the user's input
         let choice = read_user_input();
 
         // Handle the user's choice using a match statement
-        match choice.as_str() {
-            "1" => {
-                // If the user chooses to pick the largest file
-                if let Some(file) = find_largest_csv() {
-                    return Some(file); // Return the largest file's name
-                } else {
-                    // If no matching files are found, display an error message
-                    println!("No files matching the criteria were found.\n");
-                }
-            }
-            "2" => {
-                // If the user chooses to pick the smallest file
-                if let Some(file) = find_smallest_csv() {
-                    return Some(file); // Return the smallest file's name
-                } else {
-                    // If no matching files are found, display an error message
-                    println!("No files matching the criteria were found.\n");
-                }
-            }
+        let selection = match choice.as_str() {
+            "1" => Selection::Largest,
+            "2" => Selection::Smallest,
+            "4" => Selection::Newest,
+            "5" => Selection::Oldest,
+            "8" => Selection::MostRows,
+            "9" => Selection::FewestRows,
             "3" => {
                 // If the user chooses to specify a file by name
                 print!("Enter the complete file name: ");
@@ -193,194 +1395,6151 @@ fn select_file() -> Option<String> {
 
                 // Check if the specified file exists in the current directory
                 if Path::new(&file_name).exists() {
-                    return Some(file_name); // Return the specified file's name
-                } else {
-                    // If the file does not exist, display an error message and loop again
-                    println!("The file {} was not found. Try again\n", file_name);
+                    return Ok(Some(FileChoice::One(file_name))); // Return the specified file's name
                 }
+                // If the file does not exist, display an error message and loop again
+                println!("The file {} was not found. Try again\n", file_name);
+                continue;
             }
+            "6" => match choose_from_list(search)? {
+                Some(file) => return Ok(Some(FileChoice::One(file))), // Return the chosen file's name
+                None => continue, // Go back to the file selection menu
+            },
+            "7" => return Ok(Some(FileChoice::All)),
             _ => {
                 // If the user enters an invalid choice, display an error message
-                println!("Invalid choice. Please enter a number from 1 to 3.\n");
-            }
-        }
-    }
-}
-
-/// Finds the largest CSV file in the current directory that starts with the prefix `movies_`.
-/// In case of a tie (multiple files with the same largest size), any one of them is returned.
-/// 
-/// # Returns
-/// 
-/// An `Option<String>` containing the name of the largest matching file if found.
-fn find_largest_csv() -> Option<String> {
-    let current_dir = env::current_dir().expect("Cannot access current directory"); // Get the current directory
-    let mut largest_file: Option<(String, u64)> = None; // Initialize a variable to keep track of the largest file
-
-    // Iterate over each entry in the current directory
-    for entry in fs::read_dir(current_dir).expect("Cannot read directory") {
-        if let Ok(entry) = entry {
-            let path = entry.path(); // Get the path of the directory entry
-            if path.is_file() {
-                // Check if the entry is a file
-                if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-                    // Convert the file name to a string slice
-                    if file_name.starts_with("movies_") && file_name.ends_with(".csv") {
-                        // Check if the file name matches the required prefix and extension
-                        if let Ok(metadata) = fs::metadata(&path) {
-                            let size = metadata.len(); // Get the file size in bytes
-                            match &largest_file {
-                                Some((_, current_max)) => {
-                                    // If a largest file is already tracked, compare sizes
-                                    if size > *current_max {
-                                        largest_file = Some((file_name.to_string(), size)); // Update if current file is larger
-                                    }
-                                }
-                                None => {
-                                    // If no largest file is tracked yet, set the current file as largest
-                                    largest_file = Some((file_name.to_string(), size));
-                                }
-                            }
-                        }
-                    }
-                }
+                println!("Invalid choice. Please enter a number from 1 to 9.\n");
+                continue;
             }
+        };
+
+        // Pick the file the user chose among the matching files
+        if let Some(file) = pick_file(search, selection)? {
+            println!("{}", picked_message(selection, &search.pattern));
+            return Ok(Some(FileChoice::One(file))); // Return the picked file's name
         }
+        // If no matching files are found, display an error message
+        println!("No files matching {} were found.\n", search.pattern);
     }
+}
 
-    // If a largest file is found, print a message and return its name
-    largest_file.map(|(name, _)| {
-        println!("Now processing the chosen file named {}", name);
-        name
-    })
+/// The pattern a CSV file name must match to be picked as the largest or smallest file:
+/// it must start with `prefix` and end with `suffix`, or, if `compressed` is set, with
+/// `suffix` followed by `.gz`.
+#[derive(Debug, Clone)]
+struct FilePattern {
+    prefix: String,
+    suffix: String,
+    compressed: bool,
 }
 
-/// Finds the smallest CSV file in the current directory that starts with the prefix `movies_`.
-/// In case of a tie (multiple files with the same smallest size), any one of them is returned.
-/// 
-/// # Returns
-/// 
-/// An `Option<String>` containing the name of the smallest matching file if found.
-fn find_smallest_csv() -> Option<String> {
-    let current_dir = env::current_dir().expect("Cannot access current directory"); // Get the current directory
-    let mut smallest_file: Option<(String, u64)> = None; // Initialize a variable to keep track of the smallest file
-
-    // Iterate over each entry in the current directory
-    for entry in fs::read_dir(current_dir).expect("Cannot read directory") {
-        if let Ok(entry) = entry {
-            let path = entry.path(); // Get the path of the directory entry
-            if path.is_file() {
-                // Check if the entry is a file
-                if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-                    // Convert the file name to a string slice
-                    if file_name.starts_with("movies_") && file_name.ends_with(".csv") {
-                        // Check if the file name matches the required prefix and extension
-                        if let Ok(metadata) = fs::metadata(&path) {
-                            let size = metadata.len(); // Get the file size in bytes
-                            match &smallest_file {
-                                Some((_, current_min)) => {
-                                    // If a smallest file is already tracked, compare sizes
-                                    if size < *current_min {
-                                        smallest_file = Some((file_name.to_string(), size)); // Update if current file is smaller
-                                    }
-                                }
-                                None => {
-                                    // If no smallest file is tracked yet, set the current file as smallest
-                                    smallest_file = Some((file_name.to_string(), size));
-                                }
-                            }
-                        }
-                    }
-                }
+impl FilePattern {
+    /// Returns true if `file_name` starts with the prefix and ends with the suffix, or with
+    /// the suffix and `.gz` when compressed files match too.
+    fn matches(&self, file_name: &str) -> bool {
+        let uncompressed_name = match file_name.strip_suffix(GZIP_SUFFIX) {
+            Some(name) if self.compressed => name,
+            _ => file_name,
+        };
+        file_name.starts_with(&self.prefix) && uncompressed_name.ends_with(&self.suffix)
+    }
+}
+
+impl fmt::Display for FilePattern {
+    /// Shows the pattern as a glob, such as `movies_*.csv`, or `movies_*.csv[.gz]` when
+    /// compressed files match too.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}*{}", self.prefix, self.suffix)?;
+        if self.compressed {
+            write!(f, "[{}]", GZIP_SUFFIX)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where to look for CSV files to pick from and which names qualify.
+#[derive(Debug, Clone)]
+struct CsvSearch {
+    /// The directory the search starts in; picked files are named relative to it.
+    root: PathBuf,
+    /// The pattern a file name must match.
+    pattern: FilePattern,
+    /// How many levels of subdirectories are searched; 0 searches only `root`.
+    max_depth: usize,
+    /// Whether symbolic links to files are followed; links to directories never are.
+    follow_symlinks: bool,
+    /// A glob pattern relative to `root` that picks the files instead of `pattern` and
+    /// `max_depth`, from `--glob`.
+    glob: Option<String>,
+    /// Files larger than this many bytes are left out of the picks by size and by rows.
+    max_size: Option<u64>,
+}
+
+impl CsvSearch {
+    /// Describes which file names the search matches: the glob pattern if there is one,
+    /// otherwise the prefix and suffix pattern.
+    fn describe(&self) -> String {
+        self.glob
+            .clone()
+            .unwrap_or_else(|| self.pattern.to_string())
+    }
+}
+
+/// A file that matched a CSV search.
+#[derive(Debug, Clone, PartialEq)]
+struct CsvCandidate {
+    /// The path of the file relative to the search root.
+    name: String,
+    /// The size of the file in bytes.
+    size: u64,
+    /// When the file was last modified, or `None` if that could not be read.
+    modified: Option<SystemTime>,
+}
+
+/// Lists the files under `search.root` whose names match the search pattern, with their
+/// sizes and modification times, in the order the directories return them. Subdirectories are searched
+/// up to `search.max_depth` levels deep; symbolic links to directories are not followed,
+/// so a link cycle cannot make the search loop. A subdirectory or file that cannot be
+/// inspected is skipped with a warning naming the OS error.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with a `Vec` of the matching files, or the error from reading
+/// `search.root` itself.
+fn matching_csv_files(search: &CsvSearch) -> io::Result<Vec<CsvCandidate>> {
+    if let Some(pattern) = &search.glob {
+        return glob_matching_files(search, pattern);
+    }
+    let mut files = Vec::new();
+    collect_matching_files(search, Path::new(""), 0, &mut files)?;
+    Ok(files)
+}
+
+/// Checks that `pattern` is a valid glob pattern that stays inside the search root: it
+/// may not be absolute or contain a `..` component.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` if the pattern can be used, or an error message explaining why not.
+fn check_glob_pattern(pattern: &str) -> Result<(), String> {
+    glob::Pattern::new(pattern)
+        .map_err(|e| format!("'{}' is not a valid pattern: {}", pattern, e))?;
+    let path = Path::new(pattern);
+    if path.has_root()
+        || path
+            .components()
+            .any(|part| !matches!(part, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(format!(
+            "'{}' must name files inside the search directory",
+            pattern
+        ));
+    }
+    Ok(())
+}
+
+/// Lists the files matching the glob `pattern` relative to `search.root`, in name order, with
+/// their sizes and modification times. Matches that are not files, or that resolve to a
+/// path outside the search root through a symbolic link, are skipped; symbolic links to files
+/// follow `search.follow_symlinks`. A path that cannot be inspected is skipped with a warning.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with a `Vec` of the matching files, or the error from reading
+/// `search.root` itself.
+fn glob_matching_files(search: &CsvSearch, pattern: &str) -> io::Result<Vec<CsvCandidate>> {
+    let root = fs::canonicalize(&search.root).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Cannot read directory {}: {}", search.root.display(), e),
+        )
+    })?;
+    check_glob_pattern(pattern)
+        .map_err(|message| io::Error::new(io::ErrorKind::InvalidInput, message))?;
+
+    // Escape the root so only the pattern itself is expanded
+    let full_pattern = format!(
+        "{}/{}",
+        glob::Pattern::escape(&root.to_string_lossy()),
+        pattern
+    );
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..glob::MatchOptions::new()
+    };
+    let paths = glob::glob_with(&full_pattern, options)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+
+    let mut files = Vec::new();
+    for path in paths {
+        let path = match path {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!(
+                    "Warning: cannot inspect {}: {}; skipping it.",
+                    e.path().display(),
+                    e.error()
+                );
+                continue;
+            }
+        };
+        let is_link =
+            fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_symlink());
+        if is_link && !search.follow_symlinks {
+            continue;
+        }
+        match fs::canonicalize(&path) {
+            Ok(target) if !target.starts_with(&root) => {
+                eprintln!(
+                    "Warning: {} leads outside {}; skipping it.",
+                    path.display(),
+                    search.root.display()
+                );
+                continue;
+            }
+            Ok(_) => {}
+            Err(e) if is_link && e.kind() == io::ErrorKind::NotFound => {
+                eprintln!(
+                    "Warning: {} is a broken symbolic link; skipping it.",
+                    path.display()
+                );
+                continue;
             }
+            Err(e) => {
+                eprintln!(
+                    "Warning: cannot inspect {}: {}; skipping it.",
+                    path.display(),
+                    e
+                );
+                continue;
+            }
+        }
+        let Some(name) = path.strip_prefix(&root).ok().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => files.push(CsvCandidate {
+                name: name.to_string(),
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            }),
+            Ok(_) => {} // Skip directories and other non-files
+            Err(e) => eprintln!(
+                "Warning: cannot inspect {}: {}; skipping it.",
+                path.display(),
+                e
+            ),
         }
     }
+    Ok(files)
+}
 
-    // If a smallest file is found, print a message and return its name
-    smallest_file.map(|(name, _)| {
-        println!("Now processing the chosen file named {}", name);
-        name
-    })
+/// Adds the matching files in the directory `relative` (relative to `search.root`) to
+/// `files`, then searches its subdirectories while `depth` is below `search.max_depth`.
+/// Only an error reading the search root is returned; the rest are warnings.
+fn collect_matching_files(
+    search: &CsvSearch,
+    relative: &Path,
+    depth: usize,
+    files: &mut Vec<CsvCandidate>,
+) -> io::Result<()> {
+    let dir = search.root.join(relative);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if depth > 0 => {
+            // Skip subdirectories that cannot be read
+            eprintln!(
+                "Warning: cannot read directory {}: {}; skipping it.",
+                dir.display(),
+                e
+            );
+            return Ok(());
+        }
+        Err(e) => {
+            return Err(io::Error::new(
+                e.kind(),
+                format!("Cannot read directory {}: {}", search.root.display(), e),
+            ))
+        }
+    };
+
+    // Iterate over each entry in the directory
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!(
+                    "Warning: cannot read an entry of {}: {}; skipping it.",
+                    dir.display(),
+                    e
+                );
+                continue;
+            }
+        };
+        let path = entry.path(); // Get the path of the directory entry
+        let relative_path = relative.join(entry.file_name()); // The path reported to the user
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(e) => {
+                eprintln!(
+                    "Warning: cannot inspect {}: {}; skipping it.",
+                    path.display(),
+                    e
+                );
+                continue;
+            }
+        };
+        if file_type.is_dir() {
+            // Descend into real directories only; the file type of a symbolic link is not a directory
+            if depth < search.max_depth {
+                collect_matching_files(search, &relative_path, depth + 1, files)?;
+            }
+            continue;
+        }
+        if file_type.is_symlink() && !search.follow_symlinks {
+            continue; // Ignore links entirely when asked to
+        }
+        let (Some(file_name), Some(relative_name)) = (
+            path.file_name().and_then(|n| n.to_str()),
+            relative_path.to_str(),
+        ) else {
+            continue;
+        };
+        // Check if the file name matches the required prefix and suffix
+        if !search.pattern.matches(file_name) {
+            continue;
+        }
+        // The metadata of a link is that of its target, so a linked file is compared by its own size
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => files.push(CsvCandidate {
+                name: relative_name.to_string(),
+                size: metadata.len(), // Record the file size in bytes
+                modified: metadata.modified().ok(),
+            }),
+            Ok(_) => {} // Skip other non-files, such as links to directories
+            Err(e) if file_type.is_symlink() && e.kind() == io::ErrorKind::NotFound => {
+                eprintln!(
+                    "Warning: {} is a broken symbolic link; skipping it.",
+                    path.display()
+                );
+            }
+            Err(e) => eprintln!(
+                "Warning: cannot inspect {}: {}; skipping it.",
+                path.display(),
+                e
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Finds the largest file matching `search` when `wanted` is `Ordering::Greater`, or the
+/// smallest when it is `Ordering::Less`.
+/// Ties between files of the same size go to the lexicographically smallest path, so the
+/// result does not depend on the order the file system lists the directory in.
+///
+/// # Returns
+///
+/// A `Result` holding an `Option<String>` containing the path of the chosen file, relative
+/// to the search root, if found, or the error from reading the search root.
+fn find_extreme_csv(search: &CsvSearch, wanted: Ordering) -> io::Result<Option<String>> {
+    let files = within_size_limit(search, matching_csv_files(search)?);
+    Ok(pick_extreme(
+        files.into_iter().map(|file| (file.name, file.size)),
+        wanted,
+    ))
+}
+
+/// Leaves the files larger than `search.max_size` out of `files`, printing a notice for each
+/// on stderr, as the other messages of the search are.
+fn within_size_limit(search: &CsvSearch, files: Vec<CsvCandidate>) -> Vec<CsvCandidate> {
+    files
+        .into_iter()
+        .filter(|file| match search.max_size {
+            Some(limit) if file.size > limit => {
+                eprintln!(
+                    "Leaving out {}: its size of {} is over --max-size {}.",
+                    file.name,
+                    format_size(file.size),
+                    format_size(limit)
+                );
+                false
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+/// Finds the file matching `search` with the most data rows when `wanted` is
+/// `Ordering::Greater`, or the fewest when it is `Ordering::Less`, counting the rows of each
+/// file in turn with a progress line on the standard error. A file whose rows cannot be
+/// counted is skipped with a warning. Ties go to the lexicographically smallest path, as in
+/// `find_extreme_csv`.
+///
+/// # Returns
+///
+/// A `Result` holding an `Option<String>` containing the path of the chosen file, relative
+/// to the search root, if found, or the error from reading the search root.
+fn find_extreme_rows_csv(search: &CsvSearch, wanted: Ordering) -> io::Result<Option<String>> {
+    let files = within_size_limit(search, matching_csv_files(search)?);
+    let total = files.len();
+    let counted = files.into_iter().enumerate().filter_map(|(index, file)| {
+        eprintln!(
+            "Counting the rows of {} ({} of {})",
+            file.name,
+            index + 1,
+            total
+        );
+        match count_csv_rows(&search.root.join(&file.name)) {
+            Ok(rows) => Some((file.name, rows)),
+            Err(e) => {
+                eprintln!(
+                    "Warning: cannot count the rows of {}: {}; skipping it.",
+                    file.name, e
+                );
+                None
+            }
+        }
+    });
+    Ok(pick_extreme(counted, wanted))
+}
+
+/// Counts the data rows of the CSV file at `path`, not counting the header row, reading one
+/// record at a time so the file is never held in memory. Gzip-compressed files are counted
+/// after decompressing them.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the number of data rows, or the error from reading the file.
+fn count_csv_rows(path: &Path) -> Result<usize, csv::Error> {
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .from_reader(open_input(&path.to_string_lossy())?);
+    let mut record = csv::ByteRecord::new();
+    let mut rows = 0;
+    while rdr.read_byte_record(&mut record)? {
+        rows += 1;
+    }
+    Ok(rows)
+}
+
+/// Checks whether the file at `path` may be processed under the `max_size` limit. A file
+/// over the limit is processed only if `force` is set or `confirm` returns true when called
+/// with a question to ask the user. A file whose size cannot be read is allowed, so that
+/// processing it reports the error.
+fn check_file_size(
+    path: &Path,
+    max_size: Option<u64>,
+    force: bool,
+    confirm: impl FnOnce(&str) -> bool,
+) -> bool {
+    let (Some(limit), false) = (max_size, force) else {
+        return true;
+    };
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.len() > limit => confirm(&format!(
+            "{} is {}, over --max-size {}. Process it anyway? [y/N] ",
+            path.display(),
+            format_size(metadata.len()),
+            format_size(limit)
+        )),
+        _ => true,
+    }
+}
+
+/// Picks the name whose key compares as `wanted` against every other key, breaking ties
+/// by choosing the lexicographically smallest name.
+fn pick_extreme<K: Ord>(
+    files: impl IntoIterator<Item = (String, K)>,
+    wanted: Ordering,
+) -> Option<String> {
+    files
+        .into_iter()
+        .reduce(|best, file| match file.1.cmp(&best.1) {
+            order if order == wanted => file,
+            Ordering::Equal if file.0 < best.0 => file, // Prefer the smaller name on a tie
+            _ => best,
+        })
+        .map(|(name, _)| name)
+}
+
+/// Lists the files matching `search` sorted by name, for the numbered selection table.
+///
+/// # Returns
+///
+/// A `Result` holding a `Vec` of the matching files, sorted by their paths relative to the
+/// search root, or the error from reading the search root.
+fn list_matching_csvs(search: &CsvSearch) -> io::Result<Vec<CsvCandidate>> {
+    let mut files = matching_csv_files(search)?;
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(files)
+}
+
+/// Formats one row of the numbered selection table: the number, name, size, and
+/// modification date of `candidate`.
+fn format_candidate_row(number: usize, candidate: &CsvCandidate) -> String {
+    let modified = candidate
+        .modified
+        .map_or_else(|| "unknown".to_string(), format_date);
+    format!(
+        "{:>3}  {:<40} {:>10}  {}",
+        number,
+        candidate.name,
+        format_size(candidate.size),
+        modified
+    )
+}
+
+/// Formats a size in bytes with binary units, such as `512 B` or `1.5 KiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Formats a time as a UTC date and time, such as `2024-03-09 14:05`.
+/// Times before the Unix epoch are shown as the epoch.
+fn format_date(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, seconds_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Convert days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60
+    )
+}
+
+/// Lists the files matching `search` together with their modification times.
+/// Files whose modification time cannot be read are skipped with a warning.
+fn modified_csv_files(search: &CsvSearch) -> io::Result<Vec<(String, SystemTime)>> {
+    Ok(matching_csv_files(search)?
+        .into_iter()
+        .filter_map(|file| match file.modified {
+            Some(modified) => Some((file.name, modified)),
+            None => {
+                eprintln!(
+                    "Warning: cannot read the modification time of {}; skipping it.",
+                    file.name
+                );
+                None
+            }
+        })
+        .collect())
+}
+
+/// Finds the most recently modified file matching `search` when `wanted` is
+/// `Ordering::Greater`, or the least recently modified when it is `Ordering::Less`.
+/// Ties go to the lexicographically smallest path, as in `find_extreme_csv`.
+///
+/// # Returns
+///
+/// A `Result` holding an `Option<String>` containing the path of the chosen file, relative
+/// to the search root, if found, or the error from reading the search root.
+fn find_extreme_modified_csv(search: &CsvSearch, wanted: Ordering) -> io::Result<Option<String>> {
+    Ok(pick_extreme(modified_csv_files(search)?, wanted))
+}
+
+/// Describes the file `selection` picked among the files matching `pattern`, noting that
+/// compressed files are compared by their size on disk.
+fn picked_message(selection: Selection, pattern: &FilePattern) -> String {
+    let mut message = format!("Picked the {} matching {}", selection.describe(), pattern);
+    if pattern.compressed && matches!(selection, Selection::Largest | Selection::Smallest) {
+        message.push_str(" (compressed files are compared by their compressed size on disk)");
+    }
+    message
+}
+
+/// Picks the file matching `search` that `selection` asks for.
+///
+/// # Returns
+///
+/// A `Result` holding an `Option<String>` containing the path of the picked file, relative
+/// to the search root, or `None` if no file qualifies, or the error from reading the search root.
+fn pick_file(search: &CsvSearch, selection: Selection) -> io::Result<Option<String>> {
+    match selection {
+        Selection::Largest => find_extreme_csv(search, Ordering::Greater),
+        Selection::Smallest => find_extreme_csv(search, Ordering::Less),
+        Selection::Newest => find_extreme_modified_csv(search, Ordering::Greater),
+        Selection::Oldest => find_extreme_modified_csv(search, Ordering::Less),
+        Selection::MostRows => find_extreme_rows_csv(search, Ordering::Greater),
+        Selection::FewestRows => find_extreme_rows_csv(search, Ordering::Less),
+    }
+}
+
+/// Sets the Unix permission bits of `path` to `mode`, such as `0o750` for `rwxr-x---`.
+///
+/// Platforms without Unix permissions, such as Windows, have no equivalent of the
+/// group and other bits, so there the file keeps its default permissions.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok(())` if the permissions were set, or the I/O error otherwise.
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    let mut perms = fs::metadata(path)?.permissions(); // Get current permissions
+    perms.set_mode(mode); // Set the desired permissions using octal notation
+    fs::set_permissions(path, perms) // Apply the new permissions
+}
+
+/// Sets the Unix permission bits of `path` to `mode`, such as `0o750` for `rwxr-x---`.
+///
+/// Platforms without Unix permissions, such as Windows, have no equivalent of the
+/// group and other bits, so there the file keeps its default permissions.
+///
+/// # Returns
+///
+/// A `Result` which is always `Ok(())` on this platform.
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Returns the path of the text file listing the titles of `year` inside `dir`,
+/// such as `dir/1994.txt`, joined with the platform's path separator.
+fn year_file_path(dir: &Path, year: &str) -> PathBuf {
+    dir.join(format!("{}.txt", year))
+}
+
+/// Returns the name of the decade subdirectory of `year`, such as `1990s` for 1994. The
+/// decade is computed from the number, so any valid year has one.
+fn decade_name(year: &str) -> String {
+    let year: u32 = year.parse().expect("Years are validated whole numbers");
+    format!("{}s", year - year % 10)
+}
+
+/// Returns the directory the year file of `year` goes in: `dir` itself, or its decade
+/// subdirectory with `by_decade`.
+fn year_dir(dir: &Path, year: &str, by_decade: bool) -> PathBuf {
+    if by_decade {
+        dir.join(decade_name(year))
+    } else {
+        dir.to_path_buf()
+    }
+}
+
+/// Creates the decade subdirectory `path` with `mode` unless an earlier year already created it.
+fn create_decade_dir(path: &Path, mode: u32) -> io::Result<()> {
+    match fs::create_dir(path) {
+        Ok(()) => set_mode(path, mode),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the name the manifest lists `path` under: its path relative to `dir_name`,
+/// such as `1990s/1994.txt`.
+fn manifest_name(dir_name: &Path, path: &Path) -> String {
+    path.strip_prefix(dir_name)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Returns the path of the year file of `year` inside `dir` in `format`, such as `dir/1994.json`.
+fn year_output_path(dir: &Path, year: &str, format: OutputFormat) -> PathBuf {
+    year_file_path(dir, year).with_extension(format.extension())
+}
+
+/// Formats the contents of the year file of `year`, holding `titles`, in `format`. Each title
+/// holds the tab-separated values of `columns`, which name the header row of a CSV file.
+fn year_file_contents(
+    year: &str,
+    titles: &[String],
+    columns: &[String],
+    format: OutputFormat,
+) -> String {
+    match format {
+        OutputFormat::Txt => titles.iter().map(|title| format!("{}\n", title)).collect(),
+        OutputFormat::Json => {
+            let year = year.parse().expect("Years are validated whole numbers");
+            let file = YearFile {
+                year,
+                count: titles.len(),
+                titles,
+            };
+            serde_json::to_string_pretty(&file).expect("A year file always serializes to JSON")
+                + "\n"
+        }
+        OutputFormat::Csv => {
+            // Values never hold tabs, since `output_line` replaced them, so the columns split cleanly
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer
+                .write_record(columns.iter().map(|name| name.trim()))
+                .expect("Writing CSV to memory cannot fail");
+            for title in titles {
+                writer
+                    .write_record(title.split('\t'))
+                    .expect("Writing CSV to memory cannot fail");
+            }
+            let bytes = writer
+                .into_inner()
+                .expect("Writing CSV to memory cannot fail");
+            String::from_utf8(bytes).expect("The titles are valid UTF-8")
+        }
+    }
 }
 
 /// Processes the specified CSV file by performing the following operations:
-/// 
-/// 1. Creates a new directory named `your_onid.movies.random` with permissions `rwxr-x---`.
-/// 2. Parses the CSV file to organize movies by their release year.
-/// 3. Creates a `.txt` file for each year containing the titles of movies released that year,
-///    with permissions `rw-r-----`.
-/// 
+///
+/// 1. Parses the CSV file to organize movies by their release year.
+/// 2. Creates a new directory named `your_onid.movies.random` with permissions `rwxr-x---`,
+///    or `options.dir_mode`, inside `options.output_dir`, which is created if it is missing.
+/// 3. Creates a `.txt` file for each year, in ascending year order, containing the titles of
+///    movies released that year in alphabetical (byte) order, without repeats unless
+///    `options.keep_duplicates` is set, with permissions `rw-r-----`, or `options.file_mode`.
+///    With `options.output_format` set to JSON, a `.json` file holding the year, the number
+///    of titles, and the titles is written instead, and with CSV, a `.csv` file with a header.
+///
+/// Steps 2 and 3 happen in a hidden staging directory that is renamed to the final name only
+/// once every file is written and synced; on an error it is removed instead.
+///
+/// With `options.dry_run`, only the first step runs and the plan is printed instead. With
+/// `options.stream`, the titles are written as they are read instead; see `stream_file`.
 /// After processing, the program returns to the main menu.
-/// 
+///
 /// # Arguments
-/// 
+///
 /// * `file_name` - A string slice that holds the name of the file to process.
-/// 
+/// * `options` - The ONID the new directory is named after, the permissions to set, and
+///   whether this is a dry run.
+///
 /// # Returns
-/// 
+///
 /// A `Result` which is:
-/// 
-/// - `Ok(())` if the file was processed successfully.
+///
+/// - `Ok(ProcessReport)` with the path of the new directory and the year files written to it
+///   if the file was processed successfully.
 /// - An error of type `Box<dyn std::error::Error>` if an error occurred during processing.
-fn process_file(file_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-    // Generate a random number between 0 and 99999 inclusive for the directory name
-    let random_number = rand::thread_rng().gen_range(0..=99999);
-    // Format the directory name using the user's ONID and the random number
-    let dir_name = format!("{}.movies.{}", ONID, random_number);
-    fs::create_dir(&dir_name)?; // Create the new directory
-
-    // Set permissions to rwxr-x--- (owner: read, write, execute; group: read, execute; others: none)
-    let mut perms = fs::metadata(&dir_name)?.permissions(); // Get current permissions
-    perms.set_mode(0o750); // Set the desired permissions using octal notation
-    fs::set_permissions(&dir_name, perms)?; // Apply the new permissions to the directory
-
-    println!("Created directory with name {}\n", dir_name); // Inform the user about the created directory
-
-    // Open the specified CSV file for reading
-    let file = File::open(file_name)?;
-    // Initialize a CSV reader with headers
-    let mut rdr = ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(file);
+fn process_file(
+    file_name: &str,
+    options: &ProcessOptions,
+) -> Result<ProcessReport, Box<dyn std::error::Error>> {
+    process_file_to(file_name, options, &mut io::stdout())
+}
+
+/// Processes `file_name` like `process_file`, but writes the progress messages to `out`
+/// instead of the standard output.
+fn process_file_to(
+    file_name: &str,
+    options: &ProcessOptions,
+    out: &mut dyn Write,
+) -> Result<ProcessReport, Box<dyn std::error::Error>> {
+    process_file_with(
+        file_name,
+        options,
+        &mut NameSource::new(&mut rand::thread_rng()),
+        out,
+    )
+}
 
-    // Initialize a HashMap to store movie titles organized by their release year
-    let mut movies_by_year: HashMap<String, Vec<String>> = HashMap::new();
+/// Processes `file_name` like `process_file_to`, drawing the random numbers and timestamps of
+/// the directory name from `names`, so tests can predict the names that will be tried.
+fn process_file_with(
+    file_name: &str,
+    options: &ProcessOptions,
+    names: &mut NameSource,
+    out: &mut dyn Write,
+) -> Result<ProcessReport, Box<dyn std::error::Error>> {
+    // A dry run only prints the plan, so it always buffers
+    if options.stream && !options.dry_run {
+        return stream_file(file_name, options, names, out);
+    }
 
-    // Iterate over each record (row) in the CSV file
-    for result in rdr.records() {
-        let record = result?; // Unwrap the result or return an error
+    // In a dry run, print the plan instead of creating anything
+    if options.dry_run {
+        let mut parsed = read_movies_by_year(file_name, &options.read)?;
+        let movies_by_year = sort_parsed_titles(
+            file_name,
+            &mut parsed,
+            options,
+            &mut RunLog { file: None },
+            out,
+        )?;
+        let counts = movies_by_year
+            .iter()
+            .map(|(year, titles)| (year.clone(), titles.len()))
+            .collect();
+        let dir_name = dir_name_for(options, file_name, names, 0)?;
+        for line in dry_run_plan(
+            &dir_name,
+            &movies_by_year,
+            options.output_format,
+            options.by_decade,
+        ) {
+            writeln!(out, "{}", line)?;
+        }
+        return Ok(ProcessReport {
+            dry_run: true,
+            ..ProcessReport::new(dir_name, &counts)
+        }
+        .with_year_files(options));
+    }
+
+    // Write everything into a hidden staging directory, which only gets its final name once
+    // complete; the log is started first, so it records the skipped rows as they are read
+    let staging = StagingDir::create(options, file_name, names)?;
+    let written = write_sorted_titles(staging.path(), file_name, options, out);
+    let (dir_name, (counts, manifest)) = staging.finish(written, out)?;
+    if options.skip_report {
+        writeln!(
+            out,
+            "Wrote the skipped rows to {}",
+            dir_name.join(SKIP_REPORT_NAME).display()
+        )?;
+    }
 
-        // Extract the 'Title' and 'Year' fields from the record
-        let title = record.get(0).unwrap_or("").to_string(); // Get the first column (Title)
-        let year = record.get(1).unwrap_or("").to_string(); // Get the second column (Year)
+    let report = ProcessReport {
+        manifest: Some(manifest),
+        ..ProcessReport::new(dir_name, &counts)
+    }
+    .with_year_files(options);
+    report.print_year_files(options, out)?;
+    Ok(report) // Indicate that the file was processed successfully
+}
 
-        // If both title and year are present, add the title to the corresponding year's list
-        if !title.is_empty() && !year.is_empty() {
-            movies_by_year.entry(year).or_insert_with(Vec::new).push(title);
+/// Prints the summary of `parsed`, read from `file_name`, and records it in `log`, then takes
+/// out each year's titles, sorted, with the repeated ones dropped unless
+/// `options.keep_duplicates` is set.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the sorted titles of each year, or an error when
+/// `options.strict` is set and a row was skipped, or when no row has a valid movie.
+fn sort_parsed_titles(
+    file_name: &str,
+    parsed: &mut ParsedMovies,
+    options: &ProcessOptions,
+    log: &mut RunLog,
+    out: &mut dyn Write,
+) -> Result<BTreeMap<String, Vec<String>>, Box<dyn std::error::Error>> {
+    for line in parsed.delimiter_note().into_iter().chain(parsed.summary()) {
+        writeln!(out, "{}", line)?;
+    }
+    log.summary(parsed)?;
+    let skipped = parsed.skipped_count();
+    if options.strict && skipped > 0 {
+        return Err(format!(
+            "{} skipped {} row(s) and --strict is set; nothing was written",
+            input_name(file_name),
+            skipped
+        )
+        .into());
+    }
+    if parsed.by_year.is_empty() {
+        return Err(NoMovieRecords {
+            file: input_name(file_name).to_string(),
         }
+        .into());
+    }
+    let mut movies_by_year = std::mem::take(&mut parsed.by_year);
+
+    // Sort each year's titles so repeated runs write identical files, dropping repeats unless asked not to
+    if options.keep_duplicates {
+        movies_by_year.values_mut().for_each(|titles| titles.sort());
+    } else {
+        let dropped: usize = movies_by_year.values_mut().map(dedupe_titles).sum();
+        writeln!(
+            out,
+            "Dropped {} duplicate {}",
+            dropped,
+            if dropped == 1 { "title" } else { "titles" }
+        )?;
+    }
+    Ok(movies_by_year)
+}
+
+/// Reads `file_name` and writes its year files, the optional skip report and checksums, and
+/// the manifest into `dir_name`, with `process.log` written from the start, so each skipped
+/// row is logged as soon as it is read.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the number of titles written for each year and the manifest
+/// written last, or the error that stopped processing.
+fn write_sorted_titles(
+    dir_name: &Path,
+    file_name: &str,
+    options: &ProcessOptions,
+    out: &mut dyn Write,
+) -> Result<(BTreeMap<String, usize>, Manifest), Box<dyn std::error::Error>> {
+    let mut log = RunLog::create(dir_name, options)?;
+    log.start(file_name, Local::now().naive_local())?;
+    let mut parsed = read_movies_by_year_with(file_name, &options.read, |line, reason| {
+        log.skipped(line, reason)
+    })?;
+    let movies_by_year = sort_parsed_titles(file_name, &mut parsed, options, &mut log, out)?;
+    let counts = movies_by_year
+        .iter()
+        .map(|(year, titles)| (year.clone(), titles.len()))
+        .collect();
+
+    let mut files = write_year_files(dir_name, movies_by_year, options)?;
+    if options.skip_report {
+        let skip_report = parsed.skip_report();
+        write_skip_report(dir_name, &skip_report, options)?;
+        files.push(ManifestFile::new(SKIP_REPORT_NAME, &skip_report));
     }
+    if options.checksums {
+        files.push(write_checksums(dir_name, &files, options)?);
+    }
+    // The manifest comes last, so its presence shows that the run completed
+    log.files(&files)?;
+    let manifest = Manifest {
+        checksums: options.checksums,
+        ..Manifest::new(file_name, &parsed, files)?
+    };
+    write_manifest(dir_name, &manifest, options)?;
+    log.finish()?;
+    Ok((counts, manifest))
+}
 
-    // Iterate over each year and its corresponding list of movie titles
+/// Writes each year's titles to its year file in `dir_name`, syncing every file to disk.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the name and line count of each file written, or the error
+/// from writing a file.
+fn write_year_files(
+    dir_name: &Path,
+    movies_by_year: BTreeMap<String, Vec<String>>,
+    options: &ProcessOptions,
+) -> io::Result<Vec<ManifestFile>> {
+    let mut files = Vec::new();
+    // Iterate over each year, in ascending order, and its corresponding list of movie titles
     for (year, titles) in movies_by_year {
-        // Define the path for the year's text file within the new directory
-        let year_file_path = format!("{}/{}.txt", dir_name, year);
-        // Open the year's text file for writing, creating it if it doesn't exist
+        // Define the path for the year's file within the new directory
+        let year_dir = year_dir(dir_name, &year, options.by_decade);
+        if options.by_decade {
+            create_decade_dir(&year_dir, options.dir_mode)?;
+        }
+        let year_file_path = year_output_path(&year_dir, &year, options.output_format);
+        // Open the year's file for writing, creating it if it doesn't exist
         let mut file = OpenOptions::new()
             .write(true) // Enable writing
             .create(true) // Create the file if it doesn't exist
             .truncate(true) // Truncate the file to zero length if it exists
             .open(&year_file_path)?; // Open the file
 
-        // Write each movie title to the year's text file, one per line
-        for title in titles {
-            writeln!(file, "{}", title)?; // Write the title followed by a newline
+        // Write the titles, one per line in a text file or as a list in a JSON file
+        let contents =
+            year_file_contents(&year, &titles, &options.read.columns, options.output_format);
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?; // Make sure the titles are on disk before the directory is renamed
+
+        // Set permissions, by default rw-r----- (owner: read, write; group: read; others: none)
+        set_mode(&year_file_path, options.file_mode)?;
+        files.push(ManifestFile::new(
+            &manifest_name(dir_name, &year_file_path),
+            &contents,
+        ));
+    }
+    Ok(files)
+}
+
+/// Processes `file_name` into a new directory like `process_file`, but writes each title to its
+/// year file as soon as its row is read, so memory does not grow with the size of the CSV
+/// file. Titles keep their CSV order and repeats are kept. With `options.strict`, the new
+/// output is discarded if any row was skipped. Like `process_file`, the files are written in a
+/// hidden staging directory that is renamed once complete, so an error never leaves a
+/// partial directory behind.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok(ProcessReport)` with the year files written, or the error that
+/// stopped processing.
+fn stream_file(
+    file_name: &str,
+    options: &ProcessOptions,
+    names: &mut NameSource,
+    out: &mut dyn Write,
+) -> Result<ProcessReport, Box<dyn std::error::Error>> {
+    let staging = StagingDir::create(options, file_name, names)?;
+    let streamed = stream_into(staging.path(), file_name, options, out);
+    let (dir_name, (counts, manifest)) = staging.finish(streamed, out)?;
+    if options.skip_report {
+        writeln!(
+            out,
+            "Wrote the skipped rows to {}",
+            dir_name.join(SKIP_REPORT_NAME).display()
+        )?;
+    }
+
+    let report = ProcessReport {
+        manifest: Some(manifest),
+        ..ProcessReport::new(dir_name, &counts)
+    }
+    .with_year_files(options);
+    report.print_year_files(options, out)?;
+    Ok(report)
+}
+
+/// Appends each title of `file_name` to its year file in `dir_name` while the rows are read.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the number of titles written for each year and the manifest
+/// written last, or the error that stopped processing.
+fn stream_into(
+    dir_name: &Path,
+    file_name: &str,
+    options: &ProcessOptions,
+    out: &mut dyn Write,
+) -> Result<(BTreeMap<String, usize>, Manifest), Box<dyn std::error::Error>> {
+    let mut log = RunLog::create(dir_name, options)?;
+    log.start(file_name, Local::now().naive_local())?;
+    let mut writers = YearWriters::new(dir_name, options.file_mode, MAX_OPEN_YEAR_FILES);
+    if options.by_decade {
+        writers = writers.by_decade(options.dir_mode);
+    }
+    let parsed = read_movies(file_name, &options.read, |row| match row {
+        Row::Title { year, title } => writers.write(&year, &title),
+        Row::Skipped { line, reason } => log.skipped(line, reason),
+    })?;
+    let counts = writers.finish()?;
+
+    for line in parsed.delimiter_note().into_iter().chain(parsed.summary()) {
+        writeln!(out, "{}", line)?;
+    }
+    log.summary(&parsed)?;
+    let skipped = parsed.skipped_count();
+    if options.strict && skipped > 0 {
+        return Err(format!(
+            "{} skipped {} row(s) and --strict is set; the output was removed",
+            input_name(file_name),
+            skipped
+        )
+        .into());
+    }
+    if counts.is_empty() {
+        return Err(NoMovieRecords {
+            file: input_name(file_name).to_string(),
+        }
+        .into());
+    }
+    let mut files: Vec<_> = counts
+        .iter()
+        .map(|(year, &titles)| {
+            let path = year_file_path(&year_dir(dir_name, year, options.by_decade), year);
+            ManifestFile {
+                name: manifest_name(dir_name, &path),
+                lines: titles,
+            }
+        })
+        .collect();
+    if options.skip_report {
+        let skip_report = parsed.skip_report();
+        write_skip_report(dir_name, &skip_report, options)?;
+        files.push(ManifestFile::new(SKIP_REPORT_NAME, &skip_report));
+    }
+    if options.checksums {
+        files.push(write_checksums(dir_name, &files, options)?);
+    }
+    // The manifest comes last, so its presence shows that the run completed
+    log.files(&files)?;
+    let manifest = Manifest {
+        checksums: options.checksums,
+        ..Manifest::new(file_name, &parsed, files)?
+    };
+    write_manifest(dir_name, &manifest, options)?;
+    log.finish()?;
+    Ok((counts, manifest))
+}
+
+/// How many directory names `StagingDir::create` tries before giving up.
+const DIR_NAME_ATTEMPTS: usize = 100;
+
+/// The directory name template used unless `--dir-template` is given.
+const DEFAULT_DIR_TEMPLATE: &str = "{onid}.movies.{rand}";
+
+/// The values the placeholders of a directory name template stand for.
+#[derive(Debug, Clone, Copy)]
+struct TemplateValues<'a> {
+    /// `{onid}`: the user's ONID.
+    onid: &'a str,
+    /// `{rand}`: a random number from 0 to 99999, or a timestamp.
+    rand: &'a str,
+    /// `{source}`: the name of the CSV file without its extensions.
+    source: &'a str,
+    /// `{date}`: today's date as `YYYYMMDD`.
+    date: &'a str,
+}
+
+/// Expands the placeholders `{onid}`, `{rand}`, `{source}`, and `{date}` of `template` with
+/// `values`.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the directory name, or a message if the template has an
+/// unknown or unclosed placeholder, or expands to an empty name, `.`, `..`, or a name
+/// containing a path separator.
+fn expand_dir_template(template: &str, values: &TemplateValues) -> Result<String, String> {
+    let mut name = String::new();
+    let mut rest = template;
+    loop {
+        let start = rest.find('{').unwrap_or(rest.len());
+        if rest[..start].contains('}') {
+            return Err(format!("'{}' has a '}}' without a matching '{{'", template));
+        }
+        name.push_str(&rest[..start]);
+        if start == rest.len() {
+            break;
+        }
+        let placeholder = &rest[start + 1..];
+        let end = placeholder
+            .find('}')
+            .ok_or_else(|| format!("'{}' has a '{{' without a matching '}}'", template))?;
+        match &placeholder[..end] {
+            "onid" => name.push_str(values.onid),
+            "rand" => name.push_str(values.rand),
+            "source" => name.push_str(values.source),
+            "date" => name.push_str(values.date),
+            other => return Err(format!("'{}' has an unknown placeholder {{{}}}; use {{onid}}, {{rand}}, {{source}}, or {{date}}", template, other)),
+        }
+        rest = &placeholder[end + 1..];
+    }
+
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(format!(
+            "'{}' expands to '{}', which cannot name a directory",
+            template, name
+        ));
+    }
+    if name.contains(['/', '\\']) {
+        return Err(format!(
+            "'{}' expands to '{}', which contains a path separator",
+            template, name
+        ));
+    }
+    Ok(name)
+}
+
+/// Returns the `{source}` value for `file_name`: its file name without the `.gz` and CSV
+/// extensions, or `stdin` for the standard input.
+fn source_stem(file_name: &str) -> String {
+    if file_name == STDIN_NAME {
+        return "stdin".to_string();
+    }
+    let name = Path::new(file_name.strip_suffix(GZIP_SUFFIX).unwrap_or(file_name));
+    name.file_stem().map_or_else(
+        || "stdin".to_string(),
+        |stem| stem.to_string_lossy().into_owned(),
+    )
+}
+
+/// Where the changing parts of new directory names come from, so tests can predict the
+/// names that will be tried.
+struct NameSource<'a> {
+    /// Draws the random numbers of `NameScheme::Random`.
+    rng: &'a mut dyn RngCore,
+    /// Reads the local time for `NameScheme::Timestamp`.
+    clock: fn() -> NaiveDateTime,
+}
+
+impl<'a> NameSource<'a> {
+    /// Draws random numbers from `rng` and reads the time from the system clock.
+    fn new(rng: &'a mut dyn RngCore) -> NameSource<'a> {
+        NameSource {
+            rng,
+            clock: || Local::now().naive_local(),
+        }
+    }
+}
+
+/// Picks a new directory name inside `options.output_dir` by expanding `options.dir_template`
+/// for `file_name`, with `{rand}` standing for what `options.name_scheme` draws from `names`.
+/// Every `attempt` after the first is made unique: a timestamp gets a suffix such as
+/// `-1`, a random number is drawn again, and without `{rand}` in the template, the name
+/// gets a numeric suffix such as `name.2`.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the path of the directory, or a message if the template
+/// cannot be expanded.
+fn dir_name_for(
+    options: &ProcessOptions,
+    file_name: &str,
+    names: &mut NameSource,
+    attempt: usize,
+) -> Result<PathBuf, String> {
+    let unique = match options.name_scheme {
+        // Generate a random number between 0 and 99999 inclusive for the directory name
+        NameScheme::Random => names.rng.gen_range(0..=99999).to_string(),
+        NameScheme::Timestamp => {
+            let timestamp = (names.clock)().format("%Y%m%d-%H%M%S").to_string();
+            if attempt > 0 {
+                format!("{}-{}", timestamp, attempt)
+            } else {
+                timestamp
+            }
+        }
+    };
+    let date = format_date(SystemTime::now())[..10].replace('-', "");
+    let source = source_stem(file_name);
+    let values = TemplateValues {
+        onid: &options.onid,
+        rand: &unique,
+        source: &source,
+        date: &date,
+    };
+    let mut name = expand_dir_template(&options.dir_template, &values)?;
+    if attempt > 0 && !options.dir_template.contains("{rand}") {
+        name = format!("{}.{}", name, attempt);
+    }
+    Ok(options.output_dir.join(name))
+}
+
+/// Returns the hidden staging name `.tmp.your_onid.movies.random` of the output directory `dir_name`.
+fn staging_name(dir_name: &Path) -> PathBuf {
+    let name = dir_name.file_name().unwrap_or_default().to_string_lossy();
+    dir_name.with_file_name(format!(".tmp.{}", name))
+}
+
+/// A hidden directory the output is written in before it gets its final name, so the final
+/// directory only ever appears complete.
+struct StagingDir {
+    /// The hidden directory being written.
+    staging: PathBuf,
+    /// The name the directory gets once everything is written.
+    final_name: PathBuf,
+}
+
+impl StagingDir {
+    /// Creates a new staging directory for processing `file_name` with `options.dir_mode`,
+    /// first creating `options.output_dir` if it is missing. Another name is tried if either
+    /// the staging name or the final name is taken, for example by another file of the same
+    /// batch, so concurrent runs never share a directory. The names are drawn from `names`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok(StagingDir)` with the new directory, or the error otherwise.
+    fn create(
+        options: &ProcessOptions,
+        file_name: &str,
+        names: &mut NameSource,
+    ) -> Result<StagingDir, Box<dyn std::error::Error>> {
+        // Create the base directory if needed; it must not be an existing file
+        if options.output_dir.is_file() {
+            return Err(io::Error::other(format!(
+                "Output directory {} is a file",
+                options.output_dir.display()
+            ))
+            .into());
+        }
+        if !options.output_dir.as_os_str().is_empty() {
+            fs::create_dir_all(&options.output_dir)?;
+        }
+
+        // Creating a directory fails if it exists, so a created staging name is never shared
+        for attempt in 0..=DIR_NAME_ATTEMPTS {
+            let final_name = dir_name_for(options, file_name, names, attempt)?;
+            if final_name.exists() {
+                continue;
+            }
+            let staging = staging_name(&final_name);
+            match fs::create_dir(&staging) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+            // Another run may have finished the same name in the meantime
+            if final_name.exists() {
+                fs::remove_dir(&staging)?;
+                continue;
+            }
+
+            // Set permissions, by default rwxr-x--- (owner: read, write, execute; group: read, execute; others: none)
+            set_mode(&staging, options.dir_mode)?;
+            return Ok(StagingDir {
+                staging,
+                final_name,
+            });
+        }
+        let message = format!(
+            "Cannot find an unused directory name in {} after {} attempts",
+            options.output_dir.display(),
+            DIR_NAME_ATTEMPTS
+        );
+        Err(io::Error::new(io::ErrorKind::AlreadyExists, message).into())
+    }
+
+    /// The path of the staging directory, where the files are written.
+    fn path(&self) -> &Path {
+        &self.staging
+    }
+
+    /// Finishes the output written by `result`: if it succeeded, the staging directory is
+    /// renamed to its final name; otherwise its `process.log` is kept next to it, and it is
+    /// removed and the cleanup is reported.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok` with the final directory and the value of `result`, or the
+    /// error of `result` or of the rename.
+    fn finish<T>(
+        self,
+        result: Result<T, Box<dyn std::error::Error>>,
+        out: &mut dyn Write,
+    ) -> Result<(PathBuf, T), Box<dyn std::error::Error>> {
+        let value = match result.and_then(|value| self.rename().map(|()| value)) {
+            Ok(value) => value,
+            Err(e) => {
+                match self.keep_log(&*e) {
+                    Ok(Some(kept)) => {
+                        writeln!(out, "Kept the log of the failed run in {}", kept.display())?
+                    }
+                    Ok(None) => {}
+                    Err(log_error) => {
+                        writeln!(out, "Cannot keep the log of the failed run: {}", log_error)?
+                    }
+                }
+                match fs::remove_dir_all(&self.staging) {
+                    Ok(()) => writeln!(
+                        out,
+                        "Removed the partial output in {}",
+                        self.staging.display()
+                    )?,
+                    Err(remove_error) => writeln!(
+                        out,
+                        "Cannot remove the partial output in {}: {}",
+                        self.staging.display(),
+                        remove_error
+                    )?,
+                }
+                return Err(e);
+            }
+        };
+        writeln!(
+            out,
+            "Created directory with name {}\n",
+            self.final_name.display()
+        )?; // Inform the user about the created directory
+        Ok((self.final_name, value))
+    }
+
+    /// Ends the `process.log` of a failed run with `error` and moves it out of the staging
+    /// directory, to `<final name>.process.log` beside it, so the run still leaves its log.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok` with the path the log was moved to, or `None` if the run has
+    /// no log, or the error from writing or moving it.
+    fn keep_log(&self, error: &dyn std::error::Error) -> io::Result<Option<PathBuf>> {
+        let log_path = self.staging.join(LOG_NAME);
+        if !log_path.is_file() {
+            return Ok(None);
+        }
+        let mut log = OpenOptions::new().append(true).open(&log_path)?;
+        writeln!(log, "Failed: {}", error)?;
+        log.sync_all()?;
+        let name = self
+            .final_name
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let kept = self
+            .final_name
+            .with_file_name(format!("{}.{}", name, LOG_NAME));
+        fs::rename(&log_path, &kept)?;
+        Ok(Some(kept))
+    }
+
+    /// Renames the staging directory to its final name, refusing to replace a directory
+    /// that appeared under that name in the meantime.
+    fn rename(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.final_name.exists() {
+            let message = format!(
+                "Cannot rename the output to {}: it already exists",
+                self.final_name.display()
+            );
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, message).into());
         }
+        fs::rename(&self.staging, &self.final_name)?;
+        Ok(())
+    }
+}
+
+/// The name of the file describing the run, written last in the new directory.
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// A file written in the new directory, as listed in the manifest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ManifestFile {
+    /// The file name, such as `1994.txt`.
+    name: String,
+    /// How many lines the file holds.
+    lines: usize,
+}
+
+impl ManifestFile {
+    /// Describes the file `name` holding `contents`.
+    fn new(name: &str, contents: &str) -> ManifestFile {
+        ManifestFile {
+            name: name.to_string(),
+            lines: contents.lines().count(),
+        }
+    }
+}
+
+/// What `manifest.json` records about a run, so it can be audited without the CSV file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Manifest {
+    /// The full path of the CSV file, or `-` for the standard input.
+    source: String,
+    /// The size of the CSV file in bytes, unknown for the standard input.
+    source_size: Option<u64>,
+    /// When the CSV file was last modified, in seconds since the Unix epoch.
+    source_modified: Option<u64>,
+    /// When the file was processed, in seconds since the Unix epoch.
+    processed_at: u64,
+    /// How many data rows were read.
+    rows_read: usize,
+    /// How many rows were written to a year file.
+    rows_written: usize,
+    /// How many rows were skipped.
+    rows_skipped: usize,
+    /// How many rows were left out by `--from-year` and `--to-year`.
+    #[serde(default)]
+    rows_filtered: usize,
+    /// How many rows were left out by `--exclude-years`.
+    #[serde(default)]
+    rows_excluded: usize,
+    /// The files written before the manifest, in the order they were written.
+    files: Vec<ManifestFile>,
+    /// Whether `SHA256SUMS` holds the hash of each file written before it.
+    #[serde(default)]
+    checksums: bool,
+}
+
+impl Manifest {
+    /// Describes processing `file_name`, whose rows were counted in `parsed`, into `files`,
+    /// looking up the size and modification time of the CSV file.
+    fn new(
+        file_name: &str,
+        parsed: &ParsedMovies,
+        files: Vec<ManifestFile>,
+    ) -> io::Result<Manifest> {
+        let unix_seconds = |time: SystemTime| {
+            time.duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        };
+        let metadata = if file_name == STDIN_NAME {
+            None
+        } else {
+            Some(fs::metadata(file_name)?)
+        };
+        Ok(Manifest {
+            source: source_path(file_name),
+            source_size: metadata.as_ref().map(|metadata| metadata.len()),
+            source_modified: metadata
+                .and_then(|metadata| metadata.modified().ok())
+                .map(unix_seconds),
+            processed_at: unix_seconds(SystemTime::now()),
+            rows_read: parsed.rows_read,
+            rows_written: parsed.written_count(),
+            rows_skipped: parsed.skipped_count(),
+            rows_filtered: parsed.filtered,
+            rows_excluded: parsed.excluded,
+            files,
+            checksums: false,
+        })
+    }
+}
+
+/// Returns the full path of the CSV file `file_name`, or `-` for the standard input.
+fn source_path(file_name: &str) -> String {
+    if file_name == STDIN_NAME {
+        return file_name.to_string();
+    }
+    std::path::absolute(file_name)
+        .unwrap_or_else(|_| PathBuf::from(file_name))
+        .display()
+        .to_string()
+}
+
+/// Writes `manifest` as JSON to `manifest.json` in `dir_name`, with the permissions of the year files.
+fn write_manifest(
+    dir_name: &Path,
+    manifest: &Manifest,
+    options: &ProcessOptions,
+) -> io::Result<()> {
+    let manifest_path = dir_name.join(MANIFEST_NAME);
+    let mut file = File::create(&manifest_path)?;
+    serde_json::to_writer_pretty(&mut file, manifest)?;
+    file.write_all(b"\n")?;
+    file.sync_all()?;
+    set_mode(&manifest_path, options.file_mode)
+}
+
+/// The name of the file `--checksums` writes in the new directory.
+const CHECKSUMS_NAME: &str = "SHA256SUMS";
 
-        // Set permissions to rw-r----- (owner: read, write; group: read; others: none)
-        let mut perms = fs::metadata(&year_file_path)?.permissions(); // Get current permissions
-        perms.set_mode(0o640); // Set the desired permissions using octal notation
-        fs::set_permissions(&year_file_path, perms)?; // Apply the new permissions to the file
+/// Computes the SHA-256 hash of the file at `path` as lowercase hex, reading it in pieces
+/// rather than all at once.
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Writes `SHA256SUMS` in `dir_name` with the SHA-256 hash of each of `files`, one
+/// `hash  name` line each as `sha256sum` writes them, so `sha256sum -c SHA256SUMS` checks them.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the description of `SHA256SUMS` for the manifest, or the
+/// error from reading a file or writing `SHA256SUMS`.
+fn write_checksums(
+    dir_name: &Path,
+    files: &[ManifestFile],
+    options: &ProcessOptions,
+) -> io::Result<ManifestFile> {
+    let mut sums = String::new();
+    for file in files {
+        sums.push_str(&format!(
+            "{}  {}\n",
+            sha256_file(&dir_name.join(&file.name))?,
+            file.name
+        ));
     }
+    let sums_path = dir_name.join(CHECKSUMS_NAME);
+    let mut sums_file = File::create(&sums_path)?;
+    sums_file.write_all(sums.as_bytes())?;
+    sums_file.sync_all()?;
+    set_mode(&sums_path, options.file_mode)?;
+    Ok(ManifestFile::new(CHECKSUMS_NAME, &sums))
+}
 
-    Ok(()) // Indicate that the file was processed successfully
+/// Writes `report`, the line numbers of every skipped row, to `skipped.txt` in `dir_name`.
+fn write_skip_report(dir_name: &Path, report: &str, options: &ProcessOptions) -> io::Result<()> {
+    let report_path = dir_name.join(SKIP_REPORT_NAME);
+    let mut file = File::create(&report_path)?;
+    file.write_all(report.as_bytes())?;
+    file.sync_all()?;
+    set_mode(&report_path, options.file_mode)
+}
+
+/// The name of the log of the run, written in the new directory as the run goes.
+const LOG_NAME: &str = "process.log";
+
+/// The `process.log` of one run. Each line is written straight to the file, without
+/// buffering, so an interrupted run still leaves the lines written so far.
+struct RunLog {
+    /// The log file, or `None` if `--no-log` turned the log off.
+    file: Option<File>,
+}
+
+impl RunLog {
+    /// Creates `process.log` in `dir_name` with the permissions of the year files, or a log
+    /// that writes nothing if `options.log` is not set.
+    fn create(dir_name: &Path, options: &ProcessOptions) -> io::Result<RunLog> {
+        if !options.log {
+            return Ok(RunLog { file: None });
+        }
+        let log_path = dir_name.join(LOG_NAME);
+        let file = File::create(&log_path)?;
+        set_mode(&log_path, options.file_mode)?;
+        Ok(RunLog { file: Some(file) })
+    }
+
+    /// Appends `line` to the log.
+    fn line(&mut self, line: &str) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.write_all(format!("{}\n", line).as_bytes()),
+            None => Ok(()),
+        }
+    }
+
+    /// Records the CSV file `file_name` and when the run started.
+    fn start(&mut self, file_name: &str, started: NaiveDateTime) -> io::Result<()> {
+        self.line(&format!("Started at {}", started.format(LOG_TIME_FORMAT)))?;
+        self.line(&format!("Source: {}", source_path(file_name)))
+    }
+
+    /// Records a row skipped at line `line` for `reason`, as soon as it is read.
+    fn skipped(&mut self, line: u64, reason: SkipReason) -> io::Result<()> {
+        self.line(&format!("Skipped line {}: {}", line, reason.describe()))
+    }
+
+    /// Records the summary of `parsed` once the whole file is read.
+    fn summary(&mut self, parsed: &ParsedMovies) -> io::Result<()> {
+        for line in parsed.delimiter_note().into_iter().chain(parsed.summary()) {
+            self.line(&line)?;
+        }
+        Ok(())
+    }
+
+    /// Records each file in `files` with its number of lines.
+    fn files(&mut self, files: &[ManifestFile]) -> io::Result<()> {
+        for file in files {
+            let noun = if file.lines == 1 { "line" } else { "lines" };
+            self.line(&format!("Wrote {} with {} {}", file.name, file.lines, noun))?;
+        }
+        Ok(())
+    }
+
+    /// Records when the run finished and syncs the log to disk.
+    fn finish(mut self) -> io::Result<()> {
+        self.line(&format!(
+            "Finished at {}",
+            Local::now().naive_local().format(LOG_TIME_FORMAT)
+        ))?;
+        self.file.map_or(Ok(()), |file| file.sync_all())
+    }
+}
+
+/// How `process.log` writes its timestamps, such as `2024-03-09 14:05:01`.
+const LOG_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// The most year files `--stream` keeps open at once, to stay well below the limit on open
+/// file descriptors.
+const MAX_OPEN_YEAR_FILES: usize = 64;
+
+/// The year files `--stream` appends titles to. Each file is created, truncated, and given
+/// its permissions the first time a title is written to it; after that it is reopened for
+/// appending whenever it was closed to make room for another year.
+struct YearWriters {
+    /// The directory the year files are written in.
+    dir: PathBuf,
+    /// The permission bits of each year file.
+    file_mode: u32,
+    /// The permission bits of the decade subdirectories, if year files are nested in them.
+    decade_mode: Option<u32>,
+    /// The most files kept open at once.
+    capacity: usize,
+    /// The open files by year.
+    open: HashMap<String, BufWriter<File>>,
+    /// The open years, least recently written first.
+    recent: VecDeque<String>,
+    /// How many titles were written for each year.
+    counts: BTreeMap<String, usize>,
+    /// The most files that were open at once.
+    peak_open: usize,
+}
+
+impl YearWriters {
+    /// Creates writers for year files in `dir`, keeping at most `capacity` files open.
+    fn new(dir: &Path, file_mode: u32, capacity: usize) -> YearWriters {
+        YearWriters {
+            dir: dir.to_path_buf(),
+            file_mode,
+            decade_mode: None,
+            capacity: capacity.max(1),
+            open: HashMap::new(),
+            recent: VecDeque::new(),
+            counts: BTreeMap::new(),
+            peak_open: 0,
+        }
+    }
+
+    /// Nests the year files in decade subdirectories created with `dir_mode`.
+    fn by_decade(mut self, dir_mode: u32) -> YearWriters {
+        self.decade_mode = Some(dir_mode);
+        self
+    }
+
+    /// Appends `title` to the file of `year`, opening it if needed.
+    fn write(&mut self, year: &str, title: &str) -> io::Result<()> {
+        if self.open.contains_key(year) {
+            // Mark the year as the most recently written
+            if let Some(index) = self.recent.iter().position(|open_year| open_year == year) {
+                self.recent.remove(index);
+            }
+        } else {
+            // Close the least recently written file if too many are open
+            if self.open.len() >= self.capacity {
+                if let Some(oldest) = self.recent.pop_front() {
+                    if let Some(writer) = self.open.remove(&oldest) {
+                        close_synced(writer)?;
+                    }
+                }
+            }
+            let first_time = !self.counts.contains_key(year);
+            let year_dir = year_dir(&self.dir, year, self.decade_mode.is_some());
+            if let (Some(mode), true) = (self.decade_mode, first_time) {
+                create_decade_dir(&year_dir, mode)?;
+            }
+            let path = year_file_path(&year_dir, year);
+            let file = if first_time {
+                OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&path)?
+            } else {
+                OpenOptions::new().append(true).open(&path)?
+            };
+            if first_time {
+                // Set permissions, by default rw-r----- (owner: read, write; group: read; others: none)
+                set_mode(&path, self.file_mode)?;
+            }
+            self.open.insert(year.to_string(), BufWriter::new(file));
+            self.peak_open = self.peak_open.max(self.open.len());
+        }
+        self.recent.push_back(year.to_string());
+
+        let writer = self
+            .open
+            .get_mut(year)
+            .expect("The year file was just opened");
+        writeln!(writer, "{}", title)?; // Write the title followed by a newline
+        *self.counts.entry(year.to_string()).or_default() += 1;
+        Ok(())
+    }
+
+    /// Flushes, syncs, and closes every open file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok` with the number of titles written for each year, or the
+    /// error from flushing a file.
+    fn finish(mut self) -> io::Result<BTreeMap<String, usize>> {
+        for (_, writer) in self.open.drain() {
+            close_synced(writer)?;
+        }
+        Ok(self.counts)
+    }
+}
+
+/// Flushes `writer` and syncs its file to disk before closing it.
+fn close_synced(writer: BufWriter<File>) -> io::Result<()> {
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()
+}
+
+/// The number of titles written to one year file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct YearCount {
+    /// The release year.
+    year: String,
+    /// How many titles the year file holds.
+    titles: usize,
+}
+
+/// The number of titles written to one year file, and where the file is.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct YearFileCount {
+    /// The release year.
+    year: String,
+    /// How many titles the year file holds.
+    titles: usize,
+    /// The path of the year file, inside the new directory.
+    path: PathBuf,
+}
+
+/// What `process_file` wrote, or would write in a dry run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct ProcessReport {
+    /// The path of the new directory.
+    dir: PathBuf,
+    /// How many year files were written.
+    years: usize,
+    /// How many titles were written across all year files.
+    titles: usize,
+    /// The year file with the fewest titles; the earliest year wins a tie.
+    smallest: Option<YearCount>,
+    /// The year file with the most titles; the earliest year wins a tie.
+    largest: Option<YearCount>,
+    /// How many titles each year file holds, by year.
+    #[serde(skip)]
+    counts: BTreeMap<String, usize>,
+    /// Each year file with its number of titles, in ascending year order.
+    year_files: Vec<YearFileCount>,
+    /// The manifest written in the new directory; none for a dry run.
+    #[serde(skip)]
+    manifest: Option<Manifest>,
+    /// Whether this is the plan of a dry run, whose directory was never created.
+    dry_run: bool,
+}
+
+impl ProcessReport {
+    /// Builds the report for writing `counts`, the number of titles of each year, into `dir`.
+    fn new(dir: PathBuf, counts: &BTreeMap<String, usize>) -> ProcessReport {
+        let years = counts.iter().map(|(year, &titles)| YearCount {
+            year: year.clone(),
+            titles,
+        });
+        ProcessReport {
+            dir,
+            years: counts.len(),
+            titles: counts.values().sum(),
+            smallest: years.clone().reduce(|smallest, count| {
+                if count.titles < smallest.titles {
+                    count
+                } else {
+                    smallest
+                }
+            }),
+            largest: years.reduce(|largest, count| {
+                if count.titles > largest.titles {
+                    count
+                } else {
+                    largest
+                }
+            }),
+            counts: counts.clone(),
+            year_files: Vec::new(),
+            manifest: None,
+            dry_run: false,
+        }
+    }
+
+    /// Fills in `year_files` with the path of each year file `options` writes, in ascending
+    /// year order.
+    fn with_year_files(mut self, options: &ProcessOptions) -> ProcessReport {
+        let mut years: Vec<_> = self.counts.iter().collect();
+        years.sort_by_key(|(year, _)| (year.parse::<u32>().ok(), year.to_string()));
+        self.year_files = years
+            .into_iter()
+            .map(|(year, &titles)| YearFileCount {
+                year: year.clone(),
+                titles,
+                path: year_output_path(
+                    &year_dir(&self.dir, year, options.by_decade),
+                    year,
+                    options.output_format,
+                ),
+            })
+            .collect();
+        self
+    }
+
+    /// Lists each year file with its number of titles, such as `1994: 12 titles -> dir/1994.txt`.
+    fn year_file_lines(&self) -> Vec<String> {
+        self.year_files
+            .iter()
+            .map(|file| {
+                format!(
+                    "{}: {} {} -> {}",
+                    file.year,
+                    file.titles,
+                    if file.titles == 1 { "title" } else { "titles" },
+                    file.path.display()
+                )
+            })
+            .collect()
+    }
+
+    /// Writes the line of each year file to `out`, unless `options.quiet` is set. With
+    /// `--format json`, `out` is stderr, so these lines never mix with the JSON report.
+    fn print_year_files(&self, options: &ProcessOptions, out: &mut dyn Write) -> io::Result<()> {
+        if !options.quiet {
+            for line in self.year_file_lines() {
+                writeln!(out, "{}", line)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The full path of the new directory, for showing to the user.
+    fn full_path(&self) -> PathBuf {
+        std::path::absolute(&self.dir).unwrap_or_else(|_| self.dir.clone())
+    }
+
+    /// Describes the report over several lines, for the interactive menu.
+    fn lines(&self) -> Vec<String> {
+        let describe = |count: &Option<YearCount>| match count {
+            Some(count) => format!(
+                "{} ({} {})",
+                count.year,
+                count.titles,
+                if count.titles == 1 { "title" } else { "titles" }
+            ),
+            None => "none".to_string(),
+        };
+        // A dry run describes what would be written, in a directory that does not exist
+        let (heading, titles, directory) = if self.dry_run {
+            (
+                "Dry run summary:",
+                "Titles that would be written",
+                "Directory that would be created",
+            )
+        } else {
+            ("Summary:", "Titles written", "Output directory")
+        };
+        vec![
+            heading.to_string(),
+            format!("  Years: {}", self.years),
+            format!("  {}: {}", titles, self.titles),
+            format!("  Smallest year file: {}", describe(&self.smallest)),
+            format!("  Largest year file: {}", describe(&self.largest)),
+            format!("  {}: {}\n", directory, self.full_path().display()),
+        ]
+    }
+
+    /// Describes the report on one line, for `--select`.
+    fn one_line(&self) -> String {
+        let describe = |count: &Option<YearCount>| match count {
+            Some(count) => format!("{} with {}", count.year, count.titles),
+            None => "none".to_string(),
+        };
+        format!(
+            "{} {} titles in {} year files to {} (smallest: {}, largest: {})",
+            if self.dry_run { "Would write" } else { "Wrote" },
+            self.titles,
+            self.years,
+            self.full_path().display(),
+            describe(&self.smallest),
+            describe(&self.largest)
+        )
+    }
+
+    /// Formats the report as a JSON object, with the full path of the new directory.
+    fn to_json(&self) -> String {
+        let report = ProcessReport {
+            dir: self.full_path(),
+            ..self.clone()
+        };
+        serde_json::to_string(&report).expect("A report always serializes to JSON")
+    }
+}
+
+/// A file of a batch that could not be processed.
+#[derive(Debug)]
+struct FailedFile {
+    /// The path of the file relative to the search root.
+    name: String,
+    /// Why it failed.
+    failure: Failure,
+    /// The error message.
+    error: String,
+}
+
+/// The outcome of processing every file matching a search.
+#[derive(Debug, Default)]
+struct BatchReport {
+    /// The files processed successfully, by path relative to the search root, with their reports.
+    succeeded: Vec<(String, ProcessReport)>,
+    /// The files that failed, by path relative to the search root, with their errors.
+    failed: Vec<FailedFile>,
+}
+
+impl BatchReport {
+    /// Counts the files that were processed, successfully or not.
+    fn files(&self) -> usize {
+        self.succeeded.len() + self.failed.len()
+    }
+
+    /// The failure that sets the exit status of the batch: no matching files, or the first
+    /// file that failed, in name order.
+    fn failure(&self) -> Option<Failure> {
+        if self.files() == 0 {
+            return Some(Failure::NoMatchingCsv);
+        }
+        self.failed.first().map(|failed| failed.failure)
+    }
+}
+
+/// Processes every file matching `search`, in name order, into its own new directory,
+/// using up to `jobs` worker threads. Each file's messages are printed together once the
+/// file is done, every line prefixed with the file name, followed by a final summary. A
+/// failed file does not stop the batch unless `fail_fast` is set, in which case no further
+/// files are started; files already being processed by other workers still finish.
+///
+/// # Returns
+///
+/// A `BatchReport` with the files that succeeded and failed, in name order.
+fn process_batch(
+    search: &CsvSearch,
+    options: &ProcessOptions,
+    fail_fast: bool,
+    jobs: usize,
+) -> BatchReport {
+    let mut batch = BatchReport::default();
+    let files = match list_matching_csvs(search) {
+        Ok(files) => files,
+        Err(e) => {
+            // Report the unreadable search root as the failure of the whole batch
+            println!("Error: {}", e);
+            let name = search.root.display().to_string();
+            batch.failed.push(FailedFile {
+                name,
+                failure: Failure::FileSystem,
+                error: e.to_string(),
+            });
+            return batch;
+        }
+    };
+    if files.is_empty() {
+        println!("No files matching {} were found.", search.describe());
+        return batch;
+    }
+
+    // Each worker takes the next unprocessed file until none are left
+    let next = AtomicUsize::new(0);
+    let stopped = AtomicBool::new(false);
+    let results = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, files.len()) {
+            scope.spawn(|| {
+                while !stopped.load(AtomicOrdering::SeqCst) {
+                    let index = next.fetch_add(1, AtomicOrdering::SeqCst);
+                    let Some(file) = files.get(index) else { break };
+
+                    // Collect the file's messages so they are printed together
+                    let mut output = Vec::new();
+                    let path = search.root.join(&file.name);
+                    let result = process_file_to(&path.to_string_lossy(), options, &mut output)
+                        .map_err(|e| (Failure::of(&*e), e.to_string()));
+                    match &result {
+                        Ok(report) => output.extend(format!("{}\n", report.one_line()).bytes()),
+                        Err((_, e)) => output.extend(format!("Failed: {}\n", e).bytes()),
+                    }
+                    print_prefixed(&file.name, &output);
+
+                    if result.is_err() && fail_fast {
+                        stopped.store(true, AtomicOrdering::SeqCst);
+                    }
+                    results.lock().unwrap().push((index, result));
+                }
+            });
+        }
+    });
+
+    // Report the files in name order, whichever worker finished first
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    for (index, result) in results {
+        let name = files[index].name.clone();
+        match result {
+            Ok(report) => batch.succeeded.push((name, report)),
+            Err((failure, error)) => batch.failed.push(FailedFile {
+                name,
+                failure,
+                error,
+            }),
+        }
+    }
+
+    if stopped.load(AtomicOrdering::SeqCst) {
+        println!("Stopped the batch because --fail-fast is set.");
+    }
+    println!(
+        "Processed {} of {} files: {} succeeded, {} failed",
+        batch.files(),
+        files.len(),
+        batch.succeeded.len(),
+        batch.failed.len()
+    );
+    for failed in &batch.failed {
+        println!("  {}: {}", failed.name, failed.error);
+    }
+    batch
+}
+
+/// Prints each non-blank line of `output` prefixed with `[name]`, holding the standard output lock
+/// so lines from other files cannot come in between.
+fn print_prefixed(name: &str, output: &[u8]) {
+    let mut stdout = io::stdout().lock();
+    for line in String::from_utf8_lossy(output)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+    {
+        let _ = writeln!(stdout, "[{}] {}", name, line); // A closed terminal is not worth failing the batch over
+    }
+}
+
+/// An output directory found by `--clean`.
+#[derive(Debug, Clone, PartialEq)]
+struct OutputDir {
+    /// The path of the directory.
+    path: PathBuf,
+    /// When the directory was created, or last modified where creation times are not recorded.
+    created: Option<SystemTime>,
+    /// The total size of the files inside the directory.
+    bytes: u64,
+}
+
+/// What `--clean` removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct CleanReport {
+    /// How many directories were removed.
+    dirs: usize,
+    /// The total size of the files they held.
+    bytes: u64,
+}
+
+/// Checks whether `name` is exactly `onid.movies.digits` or `onid.movies.YYYYMMDD-HHMMSS`, with an
+/// optional `-N` suffix on the timestamp, the names of the output directories of `onid`.
+fn is_output_dir_name(name: &str, onid: &str) -> bool {
+    let digits = |text: &str| !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit());
+    name.strip_prefix(onid)
+        .and_then(|rest| rest.strip_prefix(".movies."))
+        .is_some_and(|number| {
+            let parts: Vec<&str> = number.split('-').collect();
+            parts.iter().all(|part| digits(part))
+                && match parts[..] {
+                    [_] => true,
+                    [date, time] | [date, time, _] => date.len() == 8 && time.len() == 6,
+                    _ => false,
+                }
+        })
+}
+
+/// Adds up the size of every file under `path`, without following symbolic links.
+fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut bytes = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.path().symlink_metadata()?;
+        bytes += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(bytes)
+}
+
+/// Finds the output directories of `onid` directly inside `dir`. Symbolic links, files, and
+/// directories whose names only contain the pattern are never included.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the directories sorted by name, or the error from reading `dir`.
+fn find_output_dirs(dir: &Path, onid: &str) -> io::Result<Vec<OutputDir>> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if !name
+            .to_str()
+            .is_some_and(|name| is_output_dir_name(name, onid))
+        {
+            continue;
+        }
+        let metadata = entry.path().symlink_metadata()?;
+        if !metadata.is_dir() {
+            continue;
+        }
+        found.push(OutputDir {
+            path: entry.path(),
+            created: metadata.created().or_else(|_| metadata.modified()).ok(),
+            bytes: dir_size(&entry.path())?,
+        });
+    }
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(found)
+}
+
+/// Lists the output directories of `onid` in `dir` with their creation time and size, then
+/// removes them once `confirm` agrees to remove that many, or right away with `yes`.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok(CleanReport)` with what was removed, which is nothing if there were
+/// no directories or the removal was declined, or the first error from finding or removing them.
+fn clean_output_dirs(
+    dir: &Path,
+    onid: &str,
+    yes: bool,
+    confirm: impl FnOnce(usize) -> bool,
+    out: &mut dyn Write,
+) -> io::Result<CleanReport> {
+    let found = find_output_dirs(dir, onid)?;
+    if found.is_empty() {
+        writeln!(
+            out,
+            "No {}.movies.* directories were found in {}",
+            onid,
+            dir.display()
+        )?;
+        return Ok(CleanReport::default());
+    }
+    for output_dir in &found {
+        let created = output_dir
+            .created
+            .map_or_else(|| "unknown".to_string(), format_date);
+        writeln!(
+            out,
+            "{:<16}  {:>10}  {}",
+            created,
+            format_size(output_dir.bytes),
+            output_dir.path.display()
+        )?;
+    }
+    if !yes && !confirm(found.len()) {
+        writeln!(out, "Nothing was removed.")?;
+        return Ok(CleanReport::default());
+    }
+
+    let mut report = CleanReport::default();
+    for output_dir in found {
+        fs::remove_dir_all(&output_dir.path)?;
+        report.dirs += 1;
+        report.bytes += output_dir.bytes;
+    }
+    let noun = if report.dirs == 1 {
+        "directory"
+    } else {
+        "directories"
+    };
+    writeln!(
+        out,
+        "Removed {} {} holding {} bytes ({})",
+        report.dirs,
+        noun,
+        report.bytes,
+        format_size(report.bytes)
+    )?;
+    Ok(report)
+}
+
+/// The name of the file `--skip-report` writes in the new directory.
+const SKIP_REPORT_NAME: &str = "skipped.txt";
+
+/// Why a row of the CSV file was not written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum SkipReason {
+    /// The Title column is empty.
+    MissingTitle,
+    /// The Year column is empty.
+    MissingYear,
+    /// The year is not a whole number in the accepted range, or contains a path separator.
+    InvalidYear,
+    /// The row is too short to hold the column given with `--title-col` or `--year-col`.
+    ColumnOutOfRange,
+}
+
+impl SkipReason {
+    /// Describes the reason, such as `missing title`.
+    fn describe(self) -> &'static str {
+        match self {
+            SkipReason::MissingTitle => "missing title",
+            SkipReason::MissingYear => "missing year",
+            SkipReason::InvalidYear => "invalid year",
+            SkipReason::ColumnOutOfRange => "column out of range",
+        }
+    }
+}
+
+/// The number of example line numbers shown for each skip reason in the summary.
+const SKIP_EXAMPLES: usize = 5;
+
+/// The movie titles read from a CSV file.
+#[derive(Debug, Default)]
+struct ParsedMovies {
+    /// The titles of each release year, keyed by the year without leading zeros or signs.
+    by_year: BTreeMap<String, Vec<String>>,
+    /// How many data rows were read.
+    rows_read: usize,
+    /// The line numbers of the skipped rows, by why they were skipped.
+    skipped: BTreeMap<SkipReason, Vec<u64>>,
+    /// The delimiter found in the header row, or `None` if it was given with `--delimiter`.
+    detected_delimiter: Option<u8>,
+    /// How many rows had a valid year outside `--from-year` and `--to-year`.
+    filtered: usize,
+    /// How many rows had a valid year listed in `--exclude-years`.
+    excluded: usize,
+    /// How many written titles had control characters replaced with spaces.
+    sanitized: usize,
+}
+
+impl ParsedMovies {
+    /// Counts the rows that were skipped for any reason.
+    fn skipped_count(&self) -> usize {
+        self.skipped.values().map(Vec::len).sum()
+    }
+
+    /// Counts the rows that were written, neither skipped, filtered, nor excluded.
+    fn written_count(&self) -> usize {
+        self.rows_read - self.skipped_count() - self.filtered - self.excluded
+    }
+
+    /// Says which delimiter was detected, if it was not given with `--delimiter` and is not
+    /// the usual comma.
+    fn delimiter_note(&self) -> Option<String> {
+        self.detected_delimiter
+            .filter(|&delimiter| delimiter != b',')
+            .map(|delimiter| {
+                format!(
+                    "Detected {} as the column delimiter",
+                    describe_delimiter(delimiter)
+                )
+            })
+    }
+
+    /// Summarizes the rows read, written, excluded, filtered, and skipped, with up to five
+    /// example line numbers for each reason a row was skipped.
+    fn summary(&self) -> Vec<String> {
+        let mut counts = vec![format!("{} written", self.written_count())];
+        if self.excluded > 0 {
+            counts.push(format!("{} excluded", self.excluded));
+        }
+        if self.filtered > 0 {
+            counts.push(format!("{} filtered", self.filtered));
+        }
+        counts.push(format!("{} skipped", self.skipped_count()));
+        let mut lines = vec![format!(
+            "Read {} rows: {}",
+            self.rows_read,
+            counts.join(", ")
+        )];
+        for (reason, line_numbers) in &self.skipped {
+            let examples: Vec<String> = line_numbers
+                .iter()
+                .take(SKIP_EXAMPLES)
+                .map(u64::to_string)
+                .collect();
+            let more = if line_numbers.len() > SKIP_EXAMPLES {
+                ", ..."
+            } else {
+                ""
+            };
+            let noun = if line_numbers.len() == 1 {
+                "line"
+            } else {
+                "lines"
+            };
+            lines.push(format!(
+                "  {}: {} ({} {}{})",
+                reason.describe(),
+                line_numbers.len(),
+                noun,
+                examples.join(", "),
+                more
+            ));
+        }
+        if self.sanitized > 0 {
+            let noun = if self.sanitized == 1 {
+                "title"
+            } else {
+                "titles"
+            };
+            lines.push(format!(
+                "Replaced line breaks or control characters with spaces in {} {}",
+                self.sanitized, noun
+            ));
+        }
+        lines
+    }
+
+    /// Lists every skipped row's line number, one reason per line, for `skipped.txt`.
+    fn skip_report(&self) -> String {
+        self.skipped
+            .iter()
+            .map(|(reason, line_numbers)| {
+                let numbers: Vec<String> = line_numbers.iter().map(u64::to_string).collect();
+                format!("{}: {}\n", reason.describe(), numbers.join(", "))
+            })
+            .collect()
+    }
+}
+
+/// Reads the CSV file `file_name` and groups the movie titles by release year, reading the
+/// columns named Title and Year, or the first two columns if the headers do not name both.
+/// Rows missing a title or a year, and rows whose year is not a whole number within
+/// `read.years`, are skipped and their line numbers recorded. Each title is the tab-separated
+/// values of `read.columns`, which is just the title by default.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the titles of each year, or the error that stopped parsing.
+fn read_movies_by_year(
+    file_name: &str,
+    read: &ReadOptions,
+) -> Result<ParsedMovies, Box<dyn std::error::Error>> {
+    read_movies_by_year_with(file_name, read, |_, _| Ok(()))
+}
+
+/// Reads the CSV file `file_name` like `read_movies_by_year`, also passing the line number
+/// and reason of each skipped row to `skip_row` as it is read.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the titles of each year, or the error that stopped parsing
+/// or that `skip_row` returned.
+fn read_movies_by_year_with(
+    file_name: &str,
+    read: &ReadOptions,
+    mut skip_row: impl FnMut(u64, SkipReason) -> io::Result<()>,
+) -> Result<ParsedMovies, Box<dyn std::error::Error>> {
+    let mut by_year: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut parsed = read_movies(file_name, read, |row| match row {
+        Row::Title { year, title } => {
+            by_year.entry(year).or_default().push(title);
+            Ok(())
+        }
+        Row::Skipped { line, reason } => skip_row(line, reason),
+    })?;
+    parsed.by_year = by_year;
+    Ok(parsed)
+}
+
+/// A data row as `read_movies` passes it on while reading.
+enum Row {
+    /// An accepted row, with its year and the title to write.
+    Title { year: String, title: String },
+    /// A skipped row, with its line number and the reason it was skipped.
+    Skipped { line: u64, reason: SkipReason },
+}
+
+/// Reads the CSV file `file_name` like `read_movies_by_year`, but passes each accepted row
+/// with its year and title, and each skipped row with its line number and reason, to
+/// `on_row` as it is read instead of keeping the titles.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the rows read and skipped, and no titles, or the error
+/// that stopped parsing or that `on_row` returned.
+fn read_movies(
+    file_name: &str,
+    read: &ReadOptions,
+    mut on_row: impl FnMut(Row) -> io::Result<()>,
+) -> Result<ParsedMovies, Box<dyn std::error::Error>> {
+    // Open the specified CSV file, or the standard input, for reading
+    let decoded = DecodingReader::new(
+        open_input(file_name)?,
+        input_name(file_name),
+        read.encoding,
+        read.lossy,
+    );
+    let mut input = BufReader::new(decoded);
+    let compressed = is_compressed(file_name);
+    let file_name = input_name(file_name);
+    // Invalid text is a problem with the data; any other read error in a compressed file
+    // means its data could not be decompressed
+    let describe_io_error = |e: &io::Error| -> Option<Box<dyn std::error::Error>> {
+        if let Some(invalid) = e
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<InvalidUtf8>())
+        {
+            return Some(format!("Cannot process {}: {}", file_name, invalid).into());
+        }
+        compressed.then(|| format!("Cannot decompress {}: {}", file_name, e).into())
+    };
+    let read_error = |e: csv::Error| -> Box<dyn std::error::Error> {
+        if let csv::ErrorKind::Io(io_error) = e.kind() {
+            if let Some(error) = describe_io_error(io_error) {
+                return error;
+            }
+        }
+        e.into()
+    };
+
+    // Read the header line first, so the delimiter can be detected from it
+    let mut header_line = Vec::new();
+    input
+        .read_until(b'\n', &mut header_line)
+        .map_err(|e| describe_io_error(&e).unwrap_or_else(|| e.into()))?;
+    // Drop the byte order mark Excel writes at the start, so it is not part of the first header
+    if header_line.starts_with(UTF8_BOM) {
+        header_line.drain(..UTF8_BOM.len());
+    }
+    // An empty file has no columns to find, and simply has no rows
+    if header_line.is_empty() {
+        return Ok(ParsedMovies::default());
+    }
+    let detected_delimiter = match read.delimiter {
+        Some(_) => None,
+        None => Some(detect_delimiter(&header_line)),
+    };
+    let delimiter = read.delimiter.or(detected_delimiter).unwrap_or(b',');
+
+    // Initialize a CSV reader, reading the first line again before the rest; rows of given
+    // columns may have any length, since a short one is skipped instead
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(read.headers)
+        .flexible(read.title_col.is_some() || read.year_col.is_some())
+        .delimiter(delimiter)
+        .from_reader(io::Cursor::new(header_line).chain(input));
+
+    // Find the Title and Year columns from the header row; without one, this is the first data row
+    let headers = rdr.headers().map_err(read_error)?.clone();
+    let cannot_process = |reason: String| format!("Cannot process {}: {}", file_name, reason);
+    if !read.headers {
+        if let Some(name) = read
+            .columns
+            .iter()
+            .find(|name| !name.trim().eq_ignore_ascii_case("title"))
+        {
+            return Err(cannot_process(format!(
+                "--no-header has no column named {}; only the title can be written",
+                name
+            ))
+            .into());
+        }
+    }
+    let columns = match (read.title_col, read.year_col) {
+        // Both columns were given, so the headers are not searched at all
+        (Some(title), Some(year)) => Columns { title, year },
+        (title_col, year_col) => {
+            let found = match find_columns(&headers).map_err(cannot_process)? {
+                _ if !read.headers => Columns { title: 0, year: 1 },
+                Some(columns) => columns,
+                None => {
+                    eprintln!(
+                        "Warning: {} has no Title and Year headers; using the first two columns.",
+                        file_name
+                    );
+                    if header_looks_like_data(&headers) {
+                        eprintln!("Warning: the first row of {} looks like a movie, not headers; pass --no-header to read it as data.", file_name);
+                    }
+                    Columns { title: 0, year: 1 }
+                }
+            };
+            Columns {
+                title: title_col.unwrap_or(found.title),
+                year: year_col.unwrap_or(found.year),
+            }
+        }
+    };
+    if columns.title == columns.year {
+        return Err(cannot_process(format!(
+            "the title and the year would both be read from column {}",
+            columns.title
+        ))
+        .into());
+    }
+    // Find the columns to write before reading any row, so a missing one fails right away
+    let output_columns =
+        find_output_columns(&headers, &read.columns, columns.title).map_err(cannot_process)?;
+
+    // Initialize the movie titles organized by their release year
+    let mut parsed = ParsedMovies {
+        detected_delimiter,
+        ..ParsedMovies::default()
+    };
+
+    // Iterate over each record (row) in the CSV file
+    for result in rdr.records() {
+        let record = result.map_err(read_error)?; // Unwrap the result or return an error
+
+        // Extract the 'Title' and 'Year' fields from the record, without a stray carriage return
+        let title = record
+            .get(columns.title)
+            .unwrap_or("")
+            .trim_end_matches('\r'); // Get the Title column
+        let year = record
+            .get(columns.year)
+            .unwrap_or("")
+            .trim_end_matches('\r')
+            .to_string(); // Get the Year column
+
+        // If both title and year are present, add the title to the corresponding year's list
+        parsed.rows_read += 1;
+        let skip = if record.len() <= columns.title.max(columns.year) {
+            SkipReason::ColumnOutOfRange
+        } else if title.trim().is_empty() {
+            SkipReason::MissingTitle
+        } else if year.trim().is_empty() {
+            SkipReason::MissingYear
+        } else {
+            // The year names a file, so only a plausible whole number may be used
+            match validate_year(&year, &read.years) {
+                // An excluded year stays excluded even inside the year range
+                Ok(year) if read.excluded_years.contains(&year) => {
+                    parsed.excluded += 1;
+                    continue;
+                }
+                Ok(year) if !read.wanted_year(year) => {
+                    parsed.filtered += 1;
+                    continue;
+                }
+                Ok(year) => {
+                    let (line, sanitized) = output_line(&record, &output_columns);
+                    parsed.sanitized += usize::from(sanitized);
+                    on_row(Row::Title {
+                        year: year.to_string(),
+                        title: line,
+                    })?;
+                    continue;
+                }
+                Err(_) => SkipReason::InvalidYear,
+            }
+        };
+        let line = record.position().map_or(0, |position| position.line());
+        parsed.skipped.entry(skip).or_default().push(line);
+        on_row(Row::Skipped { line, reason: skip })?;
+    }
+
+    Ok(parsed)
+}
+
+/// The UTF-8 byte order mark that Excel writes at the start of a CSV file.
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// The delimiters `detect_delimiter` chooses between, in the order ties are broken.
+const DELIMITER_CANDIDATES: [u8; 4] = [b',', b';', b'\t', b'|'];
+
+/// Detects the column delimiter of a CSV file from its header line: the candidate that
+/// splits the line into the most columns, ignoring delimiters inside double quotes. A line
+/// without any candidate, or a tie, is read with the earlier candidate, so commas win.
+fn detect_delimiter(header_line: &[u8]) -> u8 {
+    let mut counts = [0usize; DELIMITER_CANDIDATES.len()];
+    let mut quoted = false;
+    for &byte in header_line {
+        if byte == b'"' {
+            quoted = !quoted;
+        } else if let (false, Some(index)) = (
+            quoted,
+            DELIMITER_CANDIDATES
+                .iter()
+                .position(|&candidate| candidate == byte),
+        ) {
+            counts[index] += 1;
+        }
+    }
+    // The first of the candidates with the most separators, so ties go to the earlier one
+    let best = (0..counts.len()).fold(0, |best, index| {
+        if counts[index] > counts[best] {
+            index
+        } else {
+            best
+        }
+    });
+    DELIMITER_CANDIDATES[best]
+}
+
+/// Names a delimiter in messages, such as `';'`, or `tab` for a tab.
+fn describe_delimiter(delimiter: u8) -> String {
+    match delimiter {
+        b'\t' => "tab".to_string(),
+        _ => format!("'{}'", char::from(delimiter)),
+    }
+}
+
+/// The file name that stands for the standard input.
+const STDIN_NAME: &str = "-";
+
+/// The extension of gzip-compressed files.
+const GZIP_SUFFIX: &str = ".gz";
+
+/// Returns true if `file_name` names a gzip-compressed file.
+fn is_compressed(file_name: &str) -> bool {
+    file_name.ends_with(GZIP_SUFFIX)
+}
+
+/// The error for a CSV file without a single valid movie record, which is not worth a directory.
+#[derive(Debug)]
+struct NoMovieRecords {
+    /// The name of the CSV file.
+    file: String,
+}
+
+impl fmt::Display for NoMovieRecords {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "No valid movie records found in {}; nothing to do",
+            self.file
+        )
+    }
+}
+
+impl std::error::Error for NoMovieRecords {}
+
+/// The error `DecodingReader` returns for bytes that are not valid UTF-8.
+#[derive(Debug)]
+struct InvalidUtf8 {
+    /// The position of the first invalid byte in the input.
+    offset: u64,
+}
+
+impl fmt::Display for InvalidUtf8 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the byte at offset {} is not valid UTF-8; try --encoding latin1 or --lossy",
+            self.offset
+        )
+    }
+}
+
+impl std::error::Error for InvalidUtf8 {}
+
+/// Reads the text of another reader in `encoding` and passes it on as UTF-8, so the CSV reader
+/// always gets valid UTF-8. Invalid UTF-8 fails with `InvalidUtf8` once the text before it has
+/// been read, becomes U+FFFD if `lossy`
+/// is set, or, with `InputEncoding::Auto`, switches the rest of the input to Latin-1 after a
+/// warning. The input is read in pieces, so memory does not grow with the size of the file.
+struct DecodingReader<R> {
+    /// The reader of the undecoded bytes.
+    inner: R,
+    /// Names the input in the warning of `InputEncoding::Auto`.
+    name: String,
+    /// The encoding the rest of the input is read in.
+    encoding: InputEncoding,
+    /// Whether invalid UTF-8 becomes U+FFFD instead of failing.
+    lossy: bool,
+    /// Bytes read but not decoded yet, such as the start of a character split between reads.
+    pending: Vec<u8>,
+    /// How many bytes before `pending` were decoded.
+    offset: u64,
+    /// Decoded text not passed on yet.
+    decoded: Vec<u8>,
+    /// How much of `decoded` was already passed on.
+    position: usize,
+}
+
+impl<R: Read> DecodingReader<R> {
+    /// Decodes the bytes of `inner`, which `name` describes in messages.
+    fn new(inner: R, name: &str, encoding: InputEncoding, lossy: bool) -> DecodingReader<R> {
+        DecodingReader {
+            inner,
+            name: name.to_string(),
+            encoding,
+            lossy,
+            pending: Vec::new(),
+            offset: 0,
+            decoded: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Decodes as much of `pending` as can be, leaving a character split at the end for the
+    /// next read unless `at_end` says there is none.
+    fn decode(&mut self, at_end: bool) -> io::Result<()> {
+        loop {
+            if self.encoding == InputEncoding::Latin1 {
+                // Every byte is a character in Latin-1, so nothing is left over
+                let (text, _) = WINDOWS_1252.decode_without_bom_handling(&self.pending);
+                self.decoded.extend_from_slice(text.as_bytes());
+                self.offset += self.pending.len() as u64;
+                self.pending.clear();
+                return Ok(());
+            }
+            let error = match std::str::from_utf8(&self.pending) {
+                Ok(text) => {
+                    self.decoded.extend_from_slice(text.as_bytes());
+                    self.offset += self.pending.len() as u64;
+                    self.pending.clear();
+                    return Ok(());
+                }
+                Err(error) => error,
+            };
+            let valid = error.valid_up_to();
+            self.decoded.extend_from_slice(&self.pending[..valid]);
+            self.offset += valid as u64;
+            self.pending.drain(..valid);
+            let invalid = match error.error_len() {
+                None if !at_end => return Ok(()), // Wait for the rest of the character
+                None => self.pending.len(),
+                Some(invalid) => invalid,
+            };
+            if self.encoding == InputEncoding::Auto {
+                eprintln!(
+                    "Warning: {} is not valid UTF-8 at byte {}; reading the rest as Latin-1.",
+                    self.name, self.offset
+                );
+                self.encoding = InputEncoding::Latin1;
+            } else if self.lossy {
+                self.decoded
+                    .extend_from_slice(char::REPLACEMENT_CHARACTER.to_string().as_bytes());
+                self.offset += invalid as u64;
+                self.pending.drain(..invalid);
+            } else if !self.decoded.is_empty() {
+                // Hand over the text before the invalid bytes first, so its rows are still read
+                return Ok(());
+            } else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    InvalidUtf8 {
+                        offset: self.offset,
+                    },
+                ));
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.decoded.len() {
+            self.decoded.clear();
+            self.position = 0;
+            let mut chunk = [0; 8192];
+            let read = self.inner.read(&mut chunk)?;
+            if read == 0 && self.pending.is_empty() {
+                return Ok(0);
+            }
+            self.pending.extend_from_slice(&chunk[..read]);
+            self.decode(read == 0)?;
+        }
+        let count = buf.len().min(self.decoded.len() - self.position);
+        buf[..count].copy_from_slice(&self.decoded[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+/// Opens `file_name` for reading, or the standard input if it is `-`. Gzip-compressed
+/// files are decompressed while they are read.
+fn open_input(file_name: &str) -> io::Result<Box<dyn Read>> {
+    if file_name == STDIN_NAME {
+        Ok(Box::new(io::stdin().lock()))
+    } else if is_compressed(file_name) {
+        Ok(Box::new(GzDecoder::new(File::open(file_name)?)))
+    } else {
+        Ok(Box::new(File::open(file_name)?))
+    }
+}
+
+/// Names `file_name` in messages, calling `-` the standard input.
+fn input_name(file_name: &str) -> &str {
+    if file_name == STDIN_NAME {
+        "the standard input"
+    } else {
+        file_name
+    }
+}
+
+/// The positions of the columns read from each row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Columns {
+    /// The index of the Title column.
+    title: usize,
+    /// The index of the Year column.
+    year: usize,
+}
+
+/// Finds the Title and Year columns in a header row, ignoring case, surrounding whitespace,
+/// and a leading byte order mark.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok(Some(columns))` if both headers were found, `Ok(None)` if they
+/// were not, or a message if the row has fewer than two columns.
+fn find_columns(headers: &StringRecord) -> Result<Option<Columns>, String> {
+    if headers.len() < 2 {
+        return Err(format!(
+            "it has {} column(s), but a title and a year column are needed",
+            headers.len()
+        ));
+    }
+    let position = |name: &str| {
+        headers.iter().position(|header| {
+            header
+                .trim_start_matches('\u{feff}')
+                .trim()
+                .eq_ignore_ascii_case(name)
+        })
+    };
+    Ok(match (position("title"), position("year")) {
+        (Some(title), Some(year)) => Some(Columns { title, year }),
+        _ => None,
+    })
+}
+
+/// Returns true if the header row looks like a data row: its second field is a plausible
+/// release year.
+fn header_looks_like_data(headers: &StringRecord) -> bool {
+    headers
+        .get(1)
+        .is_some_and(|year| validate_year(year, &(DEFAULT_MIN_YEAR..=DEFAULT_MAX_YEAR)).is_ok())
+}
+
+/// Finds the index of each column named in `names`, ignoring case, for `--columns`. A name
+/// also matches a header that starts with it as a whole word, so `rating` finds `Rating Value`
+/// when no header is exactly `rating`. The name `title` always means the column the titles
+/// are read from, `title_index`.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the column indices in the order of `names`, or a message
+/// naming the missing columns and listing the available ones.
+fn find_output_columns(
+    headers: &StringRecord,
+    names: &[String],
+    title_index: usize,
+) -> Result<Vec<usize>, String> {
+    let header_names: Vec<&str> = headers
+        .iter()
+        .map(|header| header.trim_start_matches('\u{feff}').trim())
+        .collect();
+    let lowercase: Vec<String> = header_names
+        .iter()
+        .map(|header| header.to_lowercase())
+        .collect();
+    let position = |name: &str| {
+        let name = name.trim().to_lowercase();
+        if name == "title" {
+            return Some(title_index);
+        }
+        let first_word = |header: &String| {
+            header
+                .strip_prefix(&name)
+                .is_some_and(|rest| rest.starts_with(' '))
+        };
+        lowercase
+            .iter()
+            .position(|header| *header == name)
+            .or_else(|| lowercase.iter().position(first_word))
+    };
+    let missing: Vec<&str> = names
+        .iter()
+        .map(String::as_str)
+        .filter(|name| position(name).is_none())
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "no column named {}; the available columns are {}",
+            missing.join(", "),
+            header_names.join(", ")
+        ));
+    }
+    Ok(names.iter().filter_map(|name| position(name)).collect())
+}
+
+/// Joins the values of `columns` in `record` with tabs, cleaning each value with
+/// `sanitize_text` so each movie stays on one line.
+///
+/// # Returns
+///
+/// The line, and whether any value had control characters replaced.
+fn output_line(record: &StringRecord, columns: &[usize]) -> (String, bool) {
+    let mut sanitized = false;
+    let values: Vec<String> = columns
+        .iter()
+        .map(|&index| {
+            let value = sanitize_text(record.get(index).unwrap_or("").trim_end_matches('\r'));
+            sanitized |= matches!(value, Cow::Owned(_));
+            value.into_owned()
+        })
+        .collect();
+    (values.join("\t"), sanitized)
+}
+
+/// Replaces each run of control characters in `text`, such as the line break of a
+/// multi-line quoted title or a tab, with a single space.
+///
+/// # Returns
+///
+/// The text itself if it has no control characters, or a cleaned copy.
+fn sanitize_text(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(char::is_control) {
+        return Cow::Borrowed(text);
+    }
+    let mut clean = String::with_capacity(text.len());
+    let mut in_run = false;
+    for c in text.chars() {
+        if !c.is_control() {
+            clean.push(c);
+        } else if !in_run {
+            clean.push(' ');
+        }
+        in_run = c.is_control();
+    }
+    Cow::Owned(clean)
+}
+
+/// Checks that `year` is a whole number within `years`. A year containing a path
+/// separator is rejected outright, since the year names the file it is written to.
+///
+/// # Returns
+///
+/// A `Result` which is `Ok` with the parsed year, or a message saying what is wrong.
+fn validate_year(year: &str, years: &RangeInclusive<u32>) -> Result<u32, String> {
+    if year.contains(['/', '\\']) {
+        return Err("it contains a path separator".to_string());
+    }
+    let parsed: u32 = year
+        .trim()
+        .parse()
+        .map_err(|_| "it is not a whole number".to_string())?;
+    if !years.contains(&parsed) {
+        return Err(format!("it is outside {}-{}", years.start(), years.end()));
+    }
+    Ok(parsed)
+}
+
+/// Trims the surrounding whitespace from each title, sorts the titles by their bytes, and
+/// removes repeated titles.
+///
+/// # Returns
+///
+/// The number of titles removed.
+fn dedupe_titles(titles: &mut Vec<String>) -> usize {
+    for title in titles.iter_mut() {
+        *title = title.trim().to_string();
+    }
+    titles.sort();
+    let before = titles.len();
+    titles.dedup();
+    before - titles.len()
+}
+
+/// Describes what processing would create: the directory `dir_name`, then one line per
+/// year file, in ascending year order, with the number of titles it would hold.
+fn dry_run_plan(
+    dir_name: &Path,
+    movies_by_year: &BTreeMap<String, Vec<String>>,
+    format: OutputFormat,
+    by_decade: bool,
+) -> Vec<String> {
+    let mut plan = vec![format!(
+        "Dry run: would create directory {}",
+        dir_name.display()
+    )];
+    for (year, titles) in movies_by_year {
+        let noun = if titles.len() == 1 { "title" } else { "titles" };
+        plan.push(format!(
+            "Would write {} with {} {}",
+            year_output_path(&year_dir(dir_name, year, by_decade), year, format).display(),
+            titles.len(),
+            noun
+        ));
+    }
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_onid_precedence() {
+        // The flag wins over the environment, which wins over the constant
+        assert_eq!(
+            resolve_onid(Some("flagname"), Some("envname")),
+            Ok("flagname".to_string())
+        );
+        assert_eq!(
+            resolve_onid(None, Some("envname")),
+            Ok("envname".to_string())
+        );
+        assert_eq!(resolve_onid(None, None), Ok(ONID.to_string()));
+    }
+
+    #[test]
+    fn test_resolve_onid_rejects_invalid_values() {
+        assert_eq!(
+            resolve_onid(Some("My Name!"), Some("envname")),
+            Err("Invalid ONID 'My Name!' from --onid: it may only contain lowercase letters and digits, not '!'".to_string())
+        );
+        assert_eq!(
+            resolve_onid(None, Some("ab")),
+            Err("Invalid ONID 'ab' from the ONID environment variable: it must be 3 to 30 characters long, not 2".to_string())
+        );
+        // The ONID is normalized before it is used
+        assert_eq!(
+            resolve_onid(Some(" My Name "), None),
+            Ok("myname".to_string())
+        );
+        // A valid flag is used even when the environment variable is invalid
+        assert_eq!(
+            resolve_onid(Some("abc123"), Some("../etc")),
+            Ok("abc123".to_string())
+        );
+    }
+
+    /// Creates a temporary directory holding files of the given names and sizes.
+    fn dir_with_files(files: &[(&str, usize)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, size) in files {
+            fs::write(dir.path().join(name), "x".repeat(*size)).unwrap();
+        }
+        dir
+    }
+
+    /// Creates a search of `root` for names from `prefix` to `suffix`, `max_depth` levels deep.
+    fn search(root: &Path, prefix: &str, suffix: &str, max_depth: usize) -> CsvSearch {
+        CsvSearch {
+            root: root.to_path_buf(),
+            pattern: FilePattern {
+                prefix: prefix.to_string(),
+                suffix: suffix.to_string(),
+                compressed: false,
+            },
+            max_depth,
+            follow_symlinks: true,
+            glob: None,
+            max_size: None,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unreadable_subdirectory_is_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("movies_top.csv"), "x").unwrap();
+        let locked = dir.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::write(locked.join("movies_hidden.csv"), "xx").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        let files = matching_csv_files(&search(dir.path(), "movies_", ".csv", 1));
+        let readable = fs::read_dir(&locked).is_ok(); // Permissions do not stop the superuser
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o750)).unwrap();
+        let names: Vec<_> = files.unwrap().into_iter().map(|file| file.name).collect();
+        if readable {
+            assert_eq!(names.len(), 2);
+        } else {
+            assert_eq!(names, ["movies_top.csv"]);
+        }
+    }
+
+    #[test]
+    fn test_unreadable_search_root_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = search(&dir.path().join("missing"), "movies_", ".csv", 0);
+        let error = matching_csv_files(&missing).unwrap_err();
+        assert!(
+            error.to_string().starts_with(&format!(
+                "Cannot read directory {}: ",
+                missing.root.display()
+            )),
+            "{}",
+            error
+        );
+        assert!(pick_file(&missing, Selection::Largest).is_err());
+
+        // A batch reports the search root as its failure
+        let batch = process_batch(
+            &missing,
+            &process_options("roottest", dir.path().to_path_buf()),
+            false,
+            1,
+        );
+        assert_eq!(batch.failure(), Some(Failure::FileSystem));
+    }
+
+    #[test]
+    fn test_recursive_search_finds_nested_files() {
+        let dir = dir_with_files(&[("movies_top.csv", 10)]);
+        let nested = dir.path().join("drops").join("2024-01");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(nested.join("movies_1.csv"), "x".repeat(100)).unwrap();
+        fs::write(nested.join("notes.txt"), "x".repeat(500)).unwrap();
+        let deep_name = Path::new("drops").join("2024-01").join("movies_1.csv");
+
+        // Only the top level is searched without --recursive
+        assert_eq!(
+            find_extreme_csv(&search(dir.path(), "movies_", ".csv", 0), Ordering::Greater).unwrap(),
+            Some("movies_top.csv".to_string())
+        );
+        // The nested file is reported relative to the search root
+        let recursive = search(dir.path(), "movies_", ".csv", 5);
+        assert_eq!(
+            find_extreme_csv(&recursive, Ordering::Greater).unwrap(),
+            Some(deep_name.to_str().unwrap().to_string())
+        );
+        assert_eq!(
+            find_extreme_csv(&recursive, Ordering::Less).unwrap(),
+            Some("movies_top.csv".to_string())
+        );
+        // A depth limit of 1 stops above drops/2024-01
+        assert_eq!(
+            find_extreme_csv(&search(dir.path(), "movies_", ".csv", 1), Ordering::Greater).unwrap(),
+            Some("movies_top.csv".to_string())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_recursive_search_does_not_follow_directory_links() {
+        let dir = dir_with_files(&[("movies_top.csv", 10)]);
+        let outside = dir_with_files(&[("movies_outside.csv", 100)]);
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("linked")).unwrap();
+        // A link back to the root would loop forever if links were followed
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("cycle")).unwrap();
+        let recursive = search(dir.path(), "movies_", ".csv", 5);
+        let names: Vec<_> = matching_csv_files(&recursive)
+            .unwrap()
+            .into_iter()
+            .map(|file| (file.name, file.size))
+            .collect();
+        assert_eq!(names, vec![("movies_top.csv".to_string(), 10)]);
+    }
+
+    /// Creates a search of `root` for the files matching the glob `pattern`.
+    fn glob_search(root: &Path, pattern: &str) -> CsvSearch {
+        CsvSearch {
+            glob: Some(pattern.to_string()),
+            ..search(root, "", "", 0)
+        }
+    }
+
+    #[test]
+    fn test_glob_lists_the_matching_files() {
+        let dir = dir_with_files(&[
+            ("movies_2024_a.csv", 5),
+            ("movies_2024_b.csv", 7),
+            ("movies_2023.csv", 9),
+            ("notes.txt", 1),
+        ]);
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/movies_2024_c.csv"), "x").unwrap();
+        let names = |pattern: &str| -> Vec<String> {
+            list_matching_csvs(&glob_search(dir.path(), pattern))
+                .unwrap()
+                .into_iter()
+                .map(|file| file.name)
+                .collect()
+        };
+
+        // A single match, several matches, and none
+        assert_eq!(names("movies_2023.csv"), ["movies_2023.csv"]);
+        assert_eq!(
+            names("movies_2024_*.csv"),
+            ["movies_2024_a.csv", "movies_2024_b.csv"]
+        );
+        assert!(names("movies_1999_*.csv").is_empty());
+
+        // `*` stops at a separator, so subdirectories are only searched when named
+        assert_eq!(
+            names("*/movies_*.csv"),
+            [Path::new("sub").join("movies_2024_c.csv").to_str().unwrap()]
+        );
+        assert_eq!(
+            glob_search(dir.path(), "movies_2024_*.csv").describe(),
+            "movies_2024_*.csv"
+        );
+    }
+
+    #[test]
+    fn test_glob_patterns_must_stay_in_the_search_root() {
+        assert_eq!(check_glob_pattern("movies_*.csv"), Ok(()));
+        assert_eq!(check_glob_pattern("./sub/*.csv"), Ok(()));
+        assert!(check_glob_pattern("../movies_*.csv")
+            .unwrap_err()
+            .contains("inside the search directory"));
+        assert!(check_glob_pattern("sub/../../*.csv").is_err());
+        assert!(check_glob_pattern("/etc/*.csv").is_err());
+        assert!(check_glob_pattern("movies_[.csv")
+            .unwrap_err()
+            .contains("not a valid pattern"));
+
+        let dir = dir_with_files(&[("movies_a.csv", 1)]);
+        let search = glob_search(&dir.path().join("sub"), "../movies_*.csv");
+        assert_eq!(
+            list_matching_csvs(&search).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        let search = glob_search(dir.path(), "../*/movies_*.csv");
+        assert_eq!(
+            list_matching_csvs(&search).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_links_are_followed_unless_disabled() {
+        let dir = dir_with_files(&[("movies_small.csv", 10)]);
+        let outside = dir_with_files(&[("target.csv", 500)]);
+        std::os::unix::fs::symlink(
+            outside.path().join("target.csv"),
+            dir.path().join("movies_linked.csv"),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(
+            dir.path().join("gone.csv"),
+            dir.path().join("movies_broken.csv"),
+        )
+        .unwrap();
+
+        // The valid link counts with its target's size and the broken link is skipped
+        let following = search(dir.path(), "movies_", ".csv", 0);
+        let names: Vec<_> = list_matching_csvs(&following)
+            .unwrap()
+            .into_iter()
+            .map(|file| (file.name, file.size))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("movies_linked.csv".to_string(), 500),
+                ("movies_small.csv".to_string(), 10)
+            ]
+        );
+        assert_eq!(
+            pick_file(&following, Selection::Largest).unwrap(),
+            Some("movies_linked.csv".to_string())
+        );
+
+        let ignoring = CsvSearch {
+            follow_symlinks: false,
+            ..search(dir.path(), "movies_", ".csv", 0)
+        };
+        let names: Vec<_> = list_matching_csvs(&ignoring)
+            .unwrap()
+            .into_iter()
+            .map(|file| file.name)
+            .collect();
+        assert_eq!(names, ["movies_small.csv"]);
+        assert_eq!(
+            pick_file(&ignoring, Selection::Largest).unwrap(),
+            Some("movies_small.csv".to_string())
+        );
+    }
+
+    #[test]
+    fn test_list_matching_csvs_sorts_by_name() {
+        let dir = dir_with_files(&[
+            ("movies_b.csv", 2048),
+            ("movies_a.csv", 10),
+            ("notes.txt", 1),
+            ("movies_c.csv", 0),
+        ]);
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_710_000_000);
+        fs::File::options()
+            .write(true)
+            .open(dir.path().join("movies_b.csv"))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let candidates = list_matching_csvs(&search(dir.path(), "movies_", ".csv", 0)).unwrap();
+        let names: Vec<_> = candidates.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["movies_a.csv", "movies_b.csv", "movies_c.csv"]);
+        assert_eq!(candidates[1].size, 2048);
+        assert_eq!(candidates[1].modified, Some(modified));
+        assert_eq!(
+            format_candidate_row(2, &candidates[1]),
+            format!(
+                "  2  {:<40} {:>10}  2024-03-09 16:00",
+                "movies_b.csv", "2.0 KiB"
+            )
+        );
+    }
+
+    #[test]
+    fn test_format_size_and_date() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+        assert_eq!(format_date(SystemTime::UNIX_EPOCH), "1970-01-01 00:00");
+        // 2000-02-29 is a leap day
+        assert_eq!(
+            format_date(
+                SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(951_782_400 + 3_661)
+            ),
+            "2000-02-29 01:01"
+        );
+    }
+
+    #[test]
+    fn test_pick_newest_and_oldest_by_modification_time() {
+        let dir = dir_with_files(&[
+            ("movies_a.csv", 10),
+            ("movies_b.csv", 20),
+            ("movies_c.csv", 30),
+            ("other.csv", 1),
+        ]);
+        let day = std::time::Duration::from_secs(24 * 60 * 60);
+        let now = SystemTime::now();
+        // Give each file a controlled modification time; other.csv is newest but does not match
+        for (name, age_in_days) in [
+            ("movies_a.csv", 5),
+            ("movies_b.csv", 1),
+            ("movies_c.csv", 9),
+            ("other.csv", 0),
+        ] {
+            let file = fs::File::options()
+                .write(true)
+                .open(dir.path().join(name))
+                .unwrap();
+            file.set_modified(now - day * age_in_days).unwrap();
+        }
+        let search = search(dir.path(), "movies_", ".csv", 0);
+        assert_eq!(
+            pick_file(&search, Selection::Newest).unwrap(),
+            Some("movies_b.csv".to_string())
+        );
+        assert_eq!(
+            pick_file(&search, Selection::Oldest).unwrap(),
+            Some("movies_c.csv".to_string())
+        );
+        assert_eq!(
+            pick_file(&search, Selection::Largest).unwrap(),
+            Some("movies_c.csv".to_string())
+        );
+        assert_eq!(
+            pick_file(&search, Selection::Smallest).unwrap(),
+            Some("movies_a.csv".to_string())
+        );
+    }
+
+    #[test]
+    fn test_equal_sizes_pick_the_smallest_name() {
+        // Created in reverse order so the directory is unlikely to list the winner first
+        let dir = dir_with_files(&[
+            ("movies_d.csv", 10),
+            ("movies_b.csv", 50),
+            ("movies_c.csv", 10),
+            ("movies_a.csv", 50),
+        ]);
+        let search = search(dir.path(), "movies_", ".csv", 0);
+        for _ in 0..3 {
+            assert_eq!(
+                find_extreme_csv(&search, Ordering::Greater).unwrap(),
+                Some("movies_a.csv".to_string())
+            );
+            assert_eq!(
+                find_extreme_csv(&search, Ordering::Less).unwrap(),
+                Some("movies_c.csv".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_pick_by_rows_disagrees_with_pick_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        // Few rows with long quoted descriptions, against many short rows
+        let long = format!(
+            "\"{}\"",
+            "A very long description, with commas. ".repeat(50)
+        );
+        fs::write(
+            dir.path().join("movies_wordy.csv"),
+            format!(
+                "Title,Year,Notes\nAlpha,2001,{}\nBravo,2002,{}\n",
+                long, long
+            ),
+        )
+        .unwrap();
+        let short: String = (0..20).map(|i| format!("T{},2001,x\n", i)).collect();
+        fs::write(
+            dir.path().join("movies_terse.csv"),
+            format!("Title,Year,Notes\n{}", short),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("movies_same.csv"),
+            format!("Title,Year,Notes\n{}", short),
+        )
+        .unwrap();
+        assert_eq!(
+            count_csv_rows(&dir.path().join("movies_wordy.csv")).unwrap(),
+            2
+        );
+        assert_eq!(
+            count_csv_rows(&dir.path().join("movies_terse.csv")).unwrap(),
+            20
+        );
+
+        let search = search(dir.path(), "movies_", ".csv", 0);
+        assert_eq!(
+            pick_file(&search, Selection::Largest).unwrap(),
+            Some("movies_wordy.csv".to_string())
+        );
+        assert_eq!(
+            pick_file(&search, Selection::FewestRows).unwrap(),
+            Some("movies_wordy.csv".to_string())
+        );
+        // movies_same.csv ties with movies_terse.csv on rows, and wins by name
+        assert_eq!(
+            pick_file(&search, Selection::Smallest).unwrap(),
+            Some("movies_same.csv".to_string())
+        );
+        assert_eq!(
+            pick_file(&search, Selection::MostRows).unwrap(),
+            Some("movies_same.csv".to_string())
+        );
+        assert_eq!(
+            picked_message(Selection::MostRows, &search.pattern),
+            "Picked the file with the most data rows matching movies_*.csv"
+        );
+    }
+
+    #[test]
+    fn test_pick_extreme_breaks_ties_by_name() {
+        let files = || {
+            vec![
+                ("b".to_string(), 1),
+                ("c".to_string(), 2),
+                ("a".to_string(), 1),
+                ("d".to_string(), 2),
+            ]
+        };
+        assert_eq!(
+            pick_extreme(files(), Ordering::Greater),
+            Some("c".to_string())
+        );
+        assert_eq!(pick_extreme(files(), Ordering::Less), Some("a".to_string()));
+        assert_eq!(
+            pick_extreme(Vec::<(String, u64)>::new(), Ordering::Less),
+            None
+        );
+    }
+
+    #[test]
+    fn test_default_pattern_picks_largest_and_smallest() {
+        let dir = dir_with_files(&[
+            ("movies_1.csv", 30),
+            ("movies_2.csv", 10),
+            ("other.csv", 99),
+            ("movies_3.txt", 1),
+        ]);
+        fs::create_dir(dir.path().join("movies_dir.csv")).unwrap(); // Directories never match
+        let search = search(dir.path(), "movies_", ".csv", 0);
+        assert_eq!(
+            find_extreme_csv(&search, Ordering::Greater).unwrap(),
+            Some("movies_1.csv".to_string())
+        );
+        assert_eq!(
+            find_extreme_csv(&search, Ordering::Less).unwrap(),
+            Some("movies_2.csv".to_string())
+        );
+        assert_eq!(search.pattern.to_string(), "movies_*.csv");
+    }
+
+    #[test]
+    fn test_compressed_files_match_only_when_included() {
+        let dir = dir_with_files(&[
+            ("movies_1.csv", 30),
+            ("movies_2019.csv.gz", 100),
+            ("movies_3.gz", 200),
+        ]);
+        let mut search = search(dir.path(), "movies_", ".csv", 0);
+        assert_eq!(
+            find_extreme_csv(&search, Ordering::Greater).unwrap(),
+            Some("movies_1.csv".to_string())
+        );
+
+        search.pattern.compressed = true;
+        // The compressed size on disk is compared; movies_3.gz lacks the .csv suffix
+        assert_eq!(
+            find_extreme_csv(&search, Ordering::Greater).unwrap(),
+            Some("movies_2019.csv.gz".to_string())
+        );
+        assert_eq!(search.pattern.to_string(), "movies_*.csv[.gz]");
+        assert_eq!(
+            picked_message(Selection::Largest, &search.pattern),
+            "Picked the largest file matching movies_*.csv[.gz] (compressed files are compared by their compressed size on disk)"
+        );
+        assert_eq!(
+            picked_message(Selection::Newest, &search.pattern),
+            "Picked the most recently modified file matching movies_*.csv[.gz]"
+        );
+    }
+
+    #[test]
+    fn test_compressed_file_matches_uncompressed_output() {
+        let base = tempfile::tempdir().unwrap();
+        let compressed = base.path().join("movies_sample_1.csv.gz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&compressed).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder
+            .write_all(&fs::read("movies_sample_1.csv").unwrap())
+            .unwrap();
+        encoder.finish().unwrap();
+
+        let plain = process_file(
+            "movies_sample_1.csv",
+            &process_options("gziptest", base.path().join("plain")),
+        )
+        .unwrap();
+        let gzipped = process_file(
+            compressed.to_str().unwrap(),
+            &process_options("gziptest", base.path().join("gzipped")),
+        )
+        .unwrap();
+        assert_eq!((plain.years, plain.titles), (gzipped.years, gzipped.titles));
+        // The manifests differ in the source they record
+        for entry in fs::read_dir(&plain.dir).unwrap() {
+            let name = entry.unwrap().file_name();
+            if name == MANIFEST_NAME {
+                continue;
+            }
+            assert_eq!(
+                fs::read(plain.dir.join(&name)).unwrap(),
+                fs::read(gzipped.dir.join(&name)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_corrupt_compressed_file_names_the_file() {
+        let base = tempfile::tempdir().unwrap();
+        let corrupt = base.path().join("movies_corrupt.csv.gz");
+        fs::write(&corrupt, "Title,Year\nNot gzip,2001\n").unwrap();
+        let error = process_file(
+            corrupt.to_str().unwrap(),
+            &process_options("gziptest", base.path().join("out")),
+        )
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with(&format!("Cannot decompress {}: ", corrupt.display())),
+            "{}",
+            error
+        );
+        assert_eq!(fs::read_dir(base.path().join("out")).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_custom_pattern_ignores_non_matching_files() {
+        let dir = dir_with_files(&[
+            ("films-2021.csv", 20),
+            ("films-2022.csv", 5),
+            ("films-2023.txt", 50),
+            ("movies_1.csv", 100),
+            ("export_movies.csv", 1),
+        ]);
+        let films = search(dir.path(), "films-", ".csv", 0);
+        assert_eq!(
+            find_extreme_csv(&films, Ordering::Greater).unwrap(),
+            Some("films-2021.csv".to_string())
+        );
+        assert_eq!(
+            find_extreme_csv(&films, Ordering::Less).unwrap(),
+            Some("films-2022.csv".to_string())
+        );
+
+        assert_eq!(
+            find_extreme_csv(&search(dir.path(), "export_", ".csv", 0), Ordering::Greater).unwrap(),
+            Some("export_movies.csv".to_string())
+        );
+
+        let none = search(dir.path(), "none_", ".csv", 0);
+        assert_eq!(find_extreme_csv(&none, Ordering::Greater).unwrap(), None);
+        assert_eq!(find_extreme_csv(&none, Ordering::Less).unwrap(), None);
+    }
+
+    #[test]
+    fn test_year_file_path_uses_platform_separator() {
+        let dir = Path::new("clinicke.movies.123");
+        let path = year_file_path(dir, "1994");
+        assert_eq!(path.parent(), Some(dir));
+        assert_eq!(path.file_name().and_then(|n| n.to_str()), Some("1994.txt"));
+        assert_eq!(path, dir.join("1994.txt"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_set_mode_sets_permission_bits() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("2001.txt");
+        fs::write(&file, "title\n").unwrap();
+        set_mode(&file, 0o640).unwrap();
+        assert_eq!(
+            fs::metadata(&file).unwrap().permissions().mode() & 0o777,
+            0o640
+        );
+        set_mode(dir.path(), 0o750).unwrap();
+        assert_eq!(
+            fs::metadata(dir.path()).unwrap().permissions().mode() & 0o777,
+            0o750
+        );
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("500M"), Ok(500 * 1024 * 1024));
+        assert_eq!(parse_size("2G"), Ok(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("2g"), Ok(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("1.5KiB"), Ok(1536));
+        assert_eq!(parse_size("10KB"), Ok(10 * 1024));
+        assert_eq!(parse_size("1T"), Ok(1 << 40));
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(
+            parse_size("2X"),
+            Err("'2X' is not a size such as 500M or 2G".to_string())
+        );
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("-1").is_err());
+        assert!(parse_size("5iB").is_err());
+        assert_eq!(
+            parse_size("99999999999T"),
+            Err("'99999999999T' is too large".to_string())
+        );
+    }
+
+    #[test]
+    fn test_max_size_limits_picks_and_named_files() {
+        let dir = dir_with_files(&[
+            ("movies_small.csv", 10),
+            ("movies_medium.csv", 100),
+            ("movies_huge.csv", 5000),
+        ]);
+        let limited = CsvSearch {
+            max_size: Some(1000),
+            ..search(dir.path(), "movies_", ".csv", 0)
+        };
+
+        // The oversized file is left out of the largest pick but still listed
+        assert_eq!(
+            pick_file(&limited, Selection::Largest).unwrap(),
+            Some("movies_medium.csv".to_string())
+        );
+        assert_eq!(
+            pick_file(&limited, Selection::Smallest).unwrap(),
+            Some("movies_small.csv".to_string())
+        );
+        assert_eq!(list_matching_csvs(&limited).unwrap().len(), 3);
+
+        // A named file over the limit needs confirmation or force
+        let huge = dir.path().join("movies_huge.csv");
+        let mut asked = None;
+        assert!(!check_file_size(&huge, Some(1000), false, |prompt| {
+            asked = Some(prompt.to_string());
+            false
+        }));
+        assert!(asked
+            .unwrap()
+            .contains("is 4.9 KiB, over --max-size 1000 B. Process it anyway?"));
+        assert!(check_file_size(&huge, Some(1000), false, |_| true));
+        assert!(check_file_size(&huge, Some(1000), true, |_| panic!(
+            "--force should not ask"
+        )));
+        assert!(check_file_size(&huge, None, false, |_| panic!(
+            "no limit should not ask"
+        )));
+        assert!(check_file_size(
+            &dir.path().join("movies_small.csv"),
+            Some(1000),
+            false,
+            |_| panic!("small files should not ask")
+        ));
+    }
+
+    #[test]
+    fn test_detect_delimiter() {
+        assert_eq!(
+            detect_delimiter(b"Title,Year,Languages,Rating Value\n"),
+            b','
+        );
+        assert_eq!(detect_delimiter(b"Title;Year;Languages\r\n"), b';');
+        assert_eq!(detect_delimiter(b"Title\tYear\n"), b'\t');
+        assert_eq!(detect_delimiter(b"Title|Year|Rating"), b'|');
+        // Delimiters inside quotes do not count, and ties and lines without any go to the comma
+        assert_eq!(detect_delimiter(b"\"Title, Name, Other\";Year\n"), b';');
+        assert_eq!(detect_delimiter(b"Title;Year,Rating\n"), b',');
+        assert_eq!(detect_delimiter(b"Title\n"), b',');
+        assert_eq!(detect_delimiter(b""), b',');
+    }
+
+    #[test]
+    fn test_parse_delimiter() {
+        assert_eq!(parse_delimiter(";"), Ok(b';'));
+        assert_eq!(parse_delimiter("tab"), Ok(b'\t'));
+        assert_eq!(parse_delimiter("\\t"), Ok(b'\t'));
+        assert_eq!(
+            parse_delimiter(";;"),
+            Err("';;' is not a single character such as ; or tab".to_string())
+        );
+        assert!(parse_delimiter("").is_err());
+        assert!(parse_delimiter("\"").is_err());
+        assert!(parse_delimiter("é").is_err());
+    }
+
+    #[test]
+    fn test_semicolon_delimited_files_are_detected() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_semicolon.csv");
+        fs::write(&csv, "Title;Year;Languages\n\"Alpha, the Movie\";2001;[English]\nBeta;2001;[French]\nGamma;1999;[English]\n").unwrap();
+        let csv = csv.to_str().unwrap();
+
+        let parsed = read_movies_by_year(csv, &ReadOptions::default()).unwrap();
+        assert_eq!(parsed.detected_delimiter, Some(b';'));
+        assert_eq!(
+            parsed.delimiter_note().unwrap(),
+            "Detected ';' as the column delimiter"
+        );
+
+        let mut out = Vec::new();
+        let report = process_file_to(
+            csv,
+            &process_options("delimtest", base.path().join("out")),
+            &mut out,
+        )
+        .unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("Detected ';' as the column delimiter\nRead 3 rows: 3 written"));
+        assert_eq!(
+            fs::read_to_string(year_file_path(&report.dir, "2001")).unwrap(),
+            "Alpha, the Movie\nBeta\n"
+        );
+        assert_eq!(
+            fs::read_to_string(year_file_path(&report.dir, "1999")).unwrap(),
+            "Gamma\n"
+        );
+
+        // --delimiter overrides the detection, so a comma reads each line as one column
+        let comma = ReadOptions {
+            delimiter: Some(b','),
+            ..ReadOptions::default()
+        };
+        assert!(read_movies_by_year(csv, &comma)
+            .unwrap_err()
+            .to_string()
+            .contains("it has 1 column(s)"));
+
+        // A detected comma is not worth a message
+        let parsed = read_movies_by_year("movies_sample_1.csv", &ReadOptions::default()).unwrap();
+        assert_eq!(parsed.detected_delimiter, Some(b','));
+        assert_eq!(parsed.delimiter_note(), None);
+    }
+
+    #[test]
+    fn test_bom_and_crlf_are_ignored() {
+        // The fixture starts with a byte order mark and ends its lines with \r\n
+        let fixture = fs::read("movies_sample_bom_crlf.csv").unwrap();
+        assert!(fixture.starts_with(UTF8_BOM) && fixture.ends_with(b"\r\n"));
+
+        let parsed = read_movies_by_year(
+            "movies_sample_bom_crlf.csv",
+            &read_with(&["title", "rating"]),
+        )
+        .unwrap();
+        assert_eq!(parsed.skipped_count(), 0);
+        assert_eq!(
+            parsed.by_year.keys().collect::<Vec<_>>(),
+            ["1995", "2008", "2009"]
+        );
+        assert_eq!(parsed.by_year["2009"], ["Up\t8.3"]);
+
+        let base = tempfile::tempdir().unwrap();
+        let options = ProcessOptions {
+            stream: true,
+            ..process_options("bomtest", base.path().to_path_buf())
+        };
+        let report = process_file("movies_sample_bom_crlf.csv", &options).unwrap();
+        let mut names: Vec<_> = fs::read_dir(&report.dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["1995.txt", "2008.txt", "2009.txt", MANIFEST_NAME]);
+        assert_eq!(
+            fs::read_to_string(year_file_path(&report.dir, "2008")).unwrap(),
+            "The Dark Knight\nIron Man\n"
+        );
+    }
+
+    #[test]
+    fn test_latin1_files_need_an_encoding() {
+        let encoded = |encoding, lossy| ReadOptions {
+            encoding,
+            lossy,
+            ..ReadOptions::default()
+        };
+
+        // Read as UTF-8, the first accented title stops the run
+        let error =
+            read_movies_by_year("movies_sample_latin1.csv", &ReadOptions::default()).unwrap_err();
+        assert_eq!(Failure::of(&*error), Failure::Parse);
+        assert!(
+            error.to_string().contains(
+                "the byte at offset 36 is not valid UTF-8; try --encoding latin1 or --lossy"
+            ),
+            "{}",
+            error
+        );
+
+        // Latin-1 and auto detection both read the titles correctly
+        for encoding in [InputEncoding::Latin1, InputEncoding::Auto] {
+            let parsed =
+                read_movies_by_year("movies_sample_latin1.csv", &encoded(encoding, false)).unwrap();
+            assert_eq!(parsed.by_year["2001"], ["Amélie"]);
+            assert_eq!(parsed.by_year["1994"], ["Léon: The Professional"]);
+        }
+        let base = tempfile::tempdir().unwrap();
+        let options = ProcessOptions {
+            read: encoded(InputEncoding::Latin1, false),
+            ..process_options("latintest", base.path().to_path_buf())
+        };
+        let report = process_file("movies_sample_latin1.csv", &options).unwrap();
+        assert_eq!(
+            fs::read_to_string(year_file_path(&report.dir, "2001")).unwrap(),
+            "Amélie\n"
+        );
+
+        // --lossy keeps going with a replacement character
+        let parsed = read_movies_by_year(
+            "movies_sample_latin1.csv",
+            &encoded(InputEncoding::Utf8, true),
+        )
+        .unwrap();
+        assert_eq!(parsed.by_year["2001"], ["Am\u{FFFD}lie"]);
+    }
+
+    #[test]
+    fn test_decoding_reader_keeps_characters_split_between_reads() {
+        let decode = |bytes: &[u8], encoding, lossy| {
+            let mut text = String::new();
+            DecodingReader::new(bytes, "test", encoding, lossy)
+                .read_to_string(&mut text)
+                .map(|_| text)
+        };
+        // "é" straddles the end of the first 8192-byte read
+        let text = format!("{}é and ü", "a".repeat(8191));
+        assert_eq!(
+            decode(text.as_bytes(), InputEncoding::Utf8, false).unwrap(),
+            text
+        );
+        assert_eq!(
+            decode(text.as_bytes(), InputEncoding::Auto, false).unwrap(),
+            text
+        );
+
+        // A character cut off at the end of the input is invalid
+        assert!(decode(b"caf\xc3", InputEncoding::Utf8, false).is_err());
+        assert_eq!(
+            decode(b"caf\xc3", InputEncoding::Utf8, true).unwrap(),
+            "caf\u{FFFD}"
+        );
+        assert_eq!(
+            decode(b"caf\xe9 cr\xe8me", InputEncoding::Latin1, false).unwrap(),
+            "café crème"
+        );
+    }
+
+    #[test]
+    fn test_headerless_files_need_no_header() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_headerless.csv");
+        fs::write(&csv, "Alpha,2001\nBeta,2001\nGamma,1999\n").unwrap();
+        let csv = csv.to_str().unwrap();
+
+        // With headers, the first movie is taken as the header row and lost
+        let with_headers = read_movies_by_year(csv, &ReadOptions::default()).unwrap();
+        assert_eq!(with_headers.rows_read, 2);
+        assert_eq!(with_headers.by_year["2001"], ["Beta"]);
+
+        let no_header = ReadOptions {
+            headers: false,
+            ..ReadOptions::default()
+        };
+        let without_headers = read_movies_by_year(csv, &no_header).unwrap();
+        assert_eq!(without_headers.rows_read, with_headers.rows_read + 1);
+        assert_eq!(without_headers.by_year["2001"], ["Alpha", "Beta"]);
+
+        let report = process_file(
+            csv,
+            &ProcessOptions {
+                read: no_header.clone(),
+                ..process_options("headertest", base.path().join("out"))
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(year_file_path(&report.dir, "2001")).unwrap(),
+            "Alpha\nBeta\n"
+        );
+
+        // Only the title can be written without column names
+        let with_columns = ReadOptions {
+            columns: vec!["title".to_string(), "rating".to_string()],
+            ..no_header
+        };
+        assert!(read_movies_by_year(csv, &with_columns)
+            .unwrap_err()
+            .to_string()
+            .contains("--no-header has no column named rating"));
+    }
+
+    #[test]
+    fn test_header_looks_like_data() {
+        assert!(header_looks_like_data(&StringRecord::from(vec![
+            "Alpha", "2001"
+        ])));
+        assert!(!header_looks_like_data(&StringRecord::from(vec![
+            "Title", "Year"
+        ])));
+        assert!(!header_looks_like_data(&StringRecord::from(vec![
+            "Name", "Released"
+        ])));
+        assert!(!header_looks_like_data(&StringRecord::from(vec![
+            "Alpha", "12"
+        ])));
+        assert!(!header_looks_like_data(&StringRecord::from(vec!["Alpha"])));
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("750"), Ok(0o750));
+        assert_eq!(parse_mode("0750"), Ok(0o750));
+        assert_eq!(parse_mode("0o600"), Ok(0o600));
+        assert_eq!(parse_mode("777"), Ok(0o777));
+        assert_eq!(
+            parse_mode("1000"),
+            Err("mode 1000 is larger than 777".to_string())
+        );
+        assert_eq!(
+            parse_mode("789"),
+            Err("'789' is not an octal mode such as 750".to_string())
+        );
+        assert!(parse_mode("rwx").is_err());
+        assert!(parse_mode("").is_err());
+        assert!(parse_mode("-1").is_err());
+    }
+
+    #[test]
+    fn test_invalid_mode_is_rejected_while_parsing_options() {
+        assert!(Options::try_parse_from(["files_and_directories", "--dir-mode", "800"]).is_err());
+        assert!(Options::try_parse_from(["files_and_directories", "--file-mode", "1777"]).is_err());
+    }
+
+    /// Creates the default options for processing into `output_dir` with the given ONID, but
+    /// without `process.log`, so the directory holds only the files a test checks.
+    fn process_options(onid: &str, output_dir: PathBuf) -> ProcessOptions {
+        ProcessOptions {
+            onid: onid.to_string(),
+            dir_mode: DEFAULT_DIR_MODE,
+            file_mode: DEFAULT_FILE_MODE,
+            output_dir,
+            dry_run: false,
+            keep_duplicates: false,
+            read: ReadOptions::default(),
+            skip_report: false,
+            strict: false,
+            stream: false,
+            output_format: OutputFormat::Txt,
+            by_decade: false,
+            dir_template: DEFAULT_DIR_TEMPLATE.to_string(),
+            name_scheme: NameScheme::Random,
+            quiet: false,
+            checksums: false,
+            log: false,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_process_file_applies_modes() {
+        let options = Options::try_parse_from([
+            "files_and_directories",
+            "--dir-mode",
+            "700",
+            "--file-mode",
+            "600",
+        ])
+        .unwrap();
+        let options = ProcessOptions {
+            dir_mode: options.dir_mode.unwrap(),
+            file_mode: options.file_mode.unwrap(),
+            ..process_options("modetest", PathBuf::new())
+        };
+        let dir = process_file("movies_sample_1.csv", &options).unwrap().dir;
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let dir_mode = mode(&dir);
+        let file_modes: Vec<u32> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| mode(&entry.unwrap().path()))
+            .collect();
+        // Restore write access so the directory can always be removed
+        set_mode(&dir, 0o700).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(dir_mode, 0o700);
+        assert!(!file_modes.is_empty());
+        assert!(file_modes.iter().all(|&mode| mode == 0o600));
+    }
+
+    /// Processes the first sample file into `output_dir` and checks that the new
+    /// directory and its year files are inside it.
+    fn assert_processed_into(output_dir: PathBuf, base: &Path) {
+        let dir = process_file(
+            "movies_sample_1.csv",
+            &process_options("outputtest", output_dir),
+        )
+        .unwrap()
+        .dir;
+        assert_eq!(
+            dir.parent()
+                .map(|parent| parent.components().collect::<Vec<_>>()),
+            Some(base.components().collect())
+        );
+        assert!(dir
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("outputtest.movies."));
+        assert!(year_file_path(&dir, "2008").is_file());
+    }
+
+    #[test]
+    fn test_output_dir_absolute_path() {
+        let base = tempfile::tempdir().unwrap();
+        assert_processed_into(base.path().to_path_buf(), base.path());
+    }
+
+    #[test]
+    fn test_output_dir_relative_path() {
+        let base = tempfile::tempdir_in(".").unwrap();
+        let relative = PathBuf::from(base.path().file_name().unwrap());
+        assert_processed_into(relative.clone(), &relative);
+    }
+
+    #[test]
+    fn test_output_dir_trailing_slash_and_missing_parents() {
+        let base = tempfile::tempdir().unwrap();
+        let nested = base.path().join("runs").join("latest");
+        assert_processed_into(PathBuf::from(format!("{}/", nested.display())), &nested);
+    }
+
+    #[test]
+    fn test_output_dir_that_is_a_file_fails() {
+        let base = tempfile::tempdir().unwrap();
+        let file = base.path().join("not_a_dir");
+        fs::write(&file, "").unwrap();
+        let error = process_file(
+            "movies_sample_1.csv",
+            &process_options("outputtest", file.clone()),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("Output directory {} is a file", file.display())
+        );
+        assert_eq!(Failure::of(&*error), Failure::FileSystem);
+    }
+
+    #[test]
+    fn test_year_files_are_sorted_and_reproducible() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_order.csv");
+        fs::write(
+            &csv,
+            "Title,Year\nZulu,2001\nalpha,2001\nÉclair,2001\nBravo,1999\nAlpha,2001\n",
+        )
+        .unwrap();
+        let csv = csv.to_str().unwrap();
+
+        let first = process_file(
+            csv,
+            &process_options("ordertest", base.path().join("first")),
+        )
+        .unwrap()
+        .dir;
+        let second = process_file(
+            csv,
+            &process_options("ordertest", base.path().join("second")),
+        )
+        .unwrap()
+        .dir;
+        let read = |dir: &Path, year: &str| fs::read(year_file_path(dir, year)).unwrap();
+        for year in ["1999", "2001"] {
+            assert_eq!(read(&first, year), read(&second, year));
+        }
+        assert_eq!(
+            String::from_utf8(read(&first, "2001")).unwrap(),
+            "Alpha\nZulu\nalpha\nÉclair\n"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_titles_are_dropped_unless_kept() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_dupes.csv");
+        fs::write(
+            &csv,
+            "Title,Year\nThe Matrix,1999\n  The Matrix ,1999\nThe Matrix,1999\nGladiator,2000\n",
+        )
+        .unwrap();
+        let csv = csv.to_str().unwrap();
+        let read = |dir: &Path, year: &str| fs::read_to_string(year_file_path(dir, year)).unwrap();
+
+        let deduped = process_file(
+            csv,
+            &process_options("dupetest", base.path().join("deduped")),
+        )
+        .unwrap()
+        .dir;
+        assert_eq!(read(&deduped, "1999"), "The Matrix\n");
+        assert_eq!(read(&deduped, "2000"), "Gladiator\n");
+
+        let options = ProcessOptions {
+            keep_duplicates: true,
+            ..process_options("dupetest", base.path().join("kept"))
+        };
+        let kept = process_file(csv, &options).unwrap().dir;
+        assert_eq!(
+            read(&kept, "1999"),
+            "  The Matrix \nThe Matrix\nThe Matrix\n"
+        );
+        assert_eq!(read(&kept, "2000"), "Gladiator\n");
+    }
+
+    #[test]
+    fn test_dedupe_titles_counts_dropped_titles() {
+        let mut titles = vec![
+            "b".to_string(),
+            " a".to_string(),
+            "a ".to_string(),
+            "b".to_string(),
+            "c".to_string(),
+        ];
+        assert_eq!(dedupe_titles(&mut titles), 2);
+        assert_eq!(titles, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_validate_year() {
+        let years = DEFAULT_MIN_YEAR..=DEFAULT_MAX_YEAR;
+        assert_eq!(validate_year("1999", &years), Ok(1999));
+        assert_eq!(validate_year(" 2008 ", &years), Ok(2008));
+        assert_eq!(validate_year("1800", &years), Ok(1800));
+        assert_eq!(validate_year("2100", &years), Ok(2100));
+        assert_eq!(
+            validate_year("1799", &years),
+            Err("it is outside 1800-2100".to_string())
+        );
+        assert_eq!(
+            validate_year("19x7", &years),
+            Err("it is not a whole number".to_string())
+        );
+        assert_eq!(
+            validate_year("-1999", &years),
+            Err("it is not a whole number".to_string())
+        );
+        assert_eq!(
+            validate_year("../etc", &years),
+            Err("it contains a path separator".to_string())
+        );
+        assert_eq!(
+            validate_year("..\\2000", &years),
+            Err("it contains a path separator".to_string())
+        );
+        assert_eq!(validate_year("1950", &(1900..=1960)), Ok(1950));
+        assert!(validate_year("1999", &(1900..=1960)).is_err());
+    }
+
+    #[test]
+    fn test_invalid_years_never_name_files() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_escape.csv");
+        let rows =
+            "Title,Year\nGood,2001\nEvil,../../escape\nTypo,19x7\nAncient,1066\nPadded,+02001\n";
+        fs::write(&csv, rows).unwrap();
+        let output_dir = base.path().join("nested").join("out");
+
+        let dir = process_file(
+            csv.to_str().unwrap(),
+            &process_options("yeartest", output_dir.clone()),
+        )
+        .unwrap()
+        .dir;
+        let mut written: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        written.sort();
+        assert_eq!(written, ["2001.txt", MANIFEST_NAME]);
+        assert_eq!(
+            fs::read_to_string(year_file_path(&dir, "2001")).unwrap(),
+            "Good\nPadded\n"
+        );
+        // Nothing escaped the output directory
+        assert!(!base.path().join("escape.txt").exists());
+        assert!(!base.path().join("nested").join("escape.txt").exists());
+        assert_eq!(fs::read_dir(base.path()).unwrap().count(), 2);
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 1);
+
+        let parsed = read_movies_by_year(csv.to_str().unwrap(), &ReadOptions::default()).unwrap();
+        assert_eq!(parsed.skipped[&SkipReason::InvalidYear], [3, 4, 5]);
+    }
+
+    #[test]
+    fn test_find_columns() {
+        let headers = |fields: &[&str]| StringRecord::from(fields.to_vec());
+        assert_eq!(
+            find_columns(&headers(&["\u{feff}Title", "Year", "Languages"])),
+            Ok(Some(Columns { title: 0, year: 1 }))
+        );
+        assert_eq!(
+            find_columns(&headers(&["Rating", " YEAR ", "title"])),
+            Ok(Some(Columns { title: 2, year: 1 }))
+        );
+        assert_eq!(find_columns(&headers(&["Name", "Released"])), Ok(None));
+        assert_eq!(find_columns(&headers(&["Year", "Name"])), Ok(None));
+        assert!(find_columns(&headers(&["Title"])).is_err());
+        assert!(find_columns(&headers(&[])).is_err());
+    }
+
+    #[test]
+    fn test_columns_are_found_by_header_name() {
+        let base = tempfile::tempdir().unwrap();
+        let swapped = base.path().join("movies_swapped.csv");
+        fs::write(
+            &swapped,
+            "Year,Title,Rating\n1999,The Matrix,8.7\n2000,Gladiator,8.5\n",
+        )
+        .unwrap();
+        let dir = process_file(
+            swapped.to_str().unwrap(),
+            &process_options("columntest", base.path().join("out")),
+        )
+        .unwrap()
+        .dir;
+        assert_eq!(
+            fs::read_to_string(year_file_path(&dir, "1999")).unwrap(),
+            "The Matrix\n"
+        );
+        assert_eq!(
+            fs::read_to_string(year_file_path(&dir, "2000")).unwrap(),
+            "Gladiator\n"
+        );
+
+        // Without recognizable headers the first two columns are used
+        let unnamed = base.path().join("movies_unnamed.csv");
+        fs::write(&unnamed, "Name,Released\nAlien,1979\n").unwrap();
+        let dir = process_file(
+            unnamed.to_str().unwrap(),
+            &process_options("columntest", base.path().join("out")),
+        )
+        .unwrap()
+        .dir;
+        assert_eq!(
+            fs::read_to_string(year_file_path(&dir, "1979")).unwrap(),
+            "Alien\n"
+        );
+
+        let narrow = base.path().join("movies_narrow.csv");
+        fs::write(&narrow, "Title\nAlien\n").unwrap();
+        let error = process_file(
+            narrow.to_str().unwrap(),
+            &process_options("columntest", base.path().join("out")),
+        )
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("a title and a year column are needed"),
+            "{}",
+            error
+        );
+    }
+
+    /// Writes a CSV file with one row of each kind of skipped row, and more invalid years
+    /// than the summary shows, and returns its path.
+    fn csv_with_bad_rows(base: &Path) -> String {
+        let csv = base.join("movies_bad_rows.csv");
+        let mut rows = String::from("Title,Year\nGood,2001\n,1999\nNo Year,\n   ,2002\n");
+        for year in ["19x7", "1066", "../x", "3000", "abc", "-1"] {
+            rows.push_str(&format!("Bad Year,{}\n", year));
+        }
+        rows.push_str("Also Good,2001\n");
+        fs::write(&csv, rows).unwrap();
+        csv.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_skipped_rows_are_counted_by_reason() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = csv_with_bad_rows(base.path());
+        let parsed = read_movies_by_year(&csv, &ReadOptions::default()).unwrap();
+        assert_eq!(parsed.rows_read, 11);
+        assert_eq!(parsed.skipped_count(), 9);
+        assert_eq!(parsed.skipped[&SkipReason::MissingTitle], [3, 5]);
+        assert_eq!(parsed.skipped[&SkipReason::MissingYear], [4]);
+        assert_eq!(
+            parsed.skipped[&SkipReason::InvalidYear],
+            [6, 7, 8, 9, 10, 11]
+        );
+        assert_eq!(
+            parsed.summary(),
+            [
+                "Read 11 rows: 2 written, 9 skipped",
+                "  missing title: 2 (lines 3, 5)",
+                "  missing year: 1 (line 4)",
+                "  invalid year: 6 (lines 6, 7, 8, 9, 10, ...)",
+            ]
+        );
+
+        let options = ProcessOptions {
+            skip_report: true,
+            ..process_options("skiptest", base.path().join("out"))
+        };
+        let dir = process_file(&csv, &options).unwrap().dir;
+        assert_eq!(
+            fs::read_to_string(year_file_path(&dir, "2001")).unwrap(),
+            "Also Good\nGood\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join(SKIP_REPORT_NAME)).unwrap(),
+            "missing title: 3, 5\nmissing year: 4\ninvalid year: 6, 7, 8, 9, 10, 11\n"
+        );
+    }
+
+    #[test]
+    fn test_strict_fails_without_writing_when_rows_are_skipped() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = csv_with_bad_rows(base.path());
+        let output_dir = base.path().join("out");
+        let options = ProcessOptions {
+            strict: true,
+            ..process_options("skiptest", output_dir.clone())
+        };
+        assert!(process_file(&csv, &options).is_err());
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 0);
+
+        // A file without skipped rows still succeeds under --strict
+        let clean = base.path().join("movies_clean.csv");
+        fs::write(&clean, "Title,Year\nGood,2001\n").unwrap();
+        assert!(process_file(clean.to_str().unwrap(), &options).is_ok());
+    }
+
+    #[test]
+    fn test_process_report_counts_year_files() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_report.csv");
+        fs::write(
+            &csv,
+            "Title,Year\nA,2001\nB,2001\nC,1999\nD,2003\nE,2003\nF,2002\n",
+        )
+        .unwrap();
+        let report = process_file(
+            csv.to_str().unwrap(),
+            &process_options("reporttest", base.path().join("out")),
+        )
+        .unwrap();
+
+        assert_eq!(report.years, 4);
+        assert_eq!(report.titles, 6);
+        assert_eq!(
+            report.smallest,
+            Some(YearCount {
+                year: "1999".to_string(),
+                titles: 1
+            })
+        );
+        assert_eq!(
+            report.largest,
+            Some(YearCount {
+                year: "2001".to_string(),
+                titles: 2
+            })
+        );
+        assert_eq!(fs::read_dir(&report.dir).unwrap().count(), 5); // The year files and the manifest
+
+        let full_path = report.dir.display().to_string();
+        assert_eq!(
+            report.one_line(),
+            format!("Wrote 6 titles in 4 year files to {} (smallest: 1999 with 1, largest: 2001 with 2)", full_path)
+        );
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["dir"], full_path.as_str());
+        assert_eq!(json["titles"], 6);
+        assert_eq!(json["largest"]["year"], "2001");
+        assert_eq!(json["year_files"][1]["titles"], 2);
+        assert_eq!(
+            json["year_files"][1]["path"],
+            year_file_path(&report.dir, "2001").to_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_year_file_counts_are_printed_in_year_order() {
+        let base = tempfile::tempdir().unwrap();
+        let mut output = Vec::new();
+        let options = process_options("counttest", base.path().join("out"));
+        let report = process_file_to("movies_sample_2.csv", &options, &mut output).unwrap();
+
+        // The counts match the titles of each year in the fixture
+        let parsed = read_movies_by_year("movies_sample_2.csv", &ReadOptions::default()).unwrap();
+        let expected: Vec<String> = parsed
+            .by_year
+            .iter()
+            .map(|(year, titles)| {
+                let noun = if titles.len() == 1 { "title" } else { "titles" };
+                format!(
+                    "{}: {} {} -> {}",
+                    year,
+                    titles.len(),
+                    noun,
+                    year_file_path(&report.dir, year).display()
+                )
+            })
+            .collect();
+        assert_eq!(report.year_file_lines(), expected);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(&format!(
+            "2008: 2 titles -> {}\n",
+            year_file_path(&report.dir, "2008").display()
+        )));
+
+        // Years sort by their value, and --quiet leaves the lines out
+        let counts = BTreeMap::from([("999".to_string(), 1), ("1994".to_string(), 3)]);
+        let report = ProcessReport::new(PathBuf::from("dir"), &counts).with_year_files(&options);
+        assert_eq!(
+            report.year_file_lines(),
+            [
+                "999: 1 title -> dir/999.txt",
+                "1994: 3 titles -> dir/1994.txt"
+            ]
+        );
+        let mut quiet_output = Vec::new();
+        report
+            .print_year_files(
+                &ProcessOptions {
+                    quiet: true,
+                    ..options
+                },
+                &mut quiet_output,
+            )
+            .unwrap();
+        assert!(quiet_output.is_empty());
+    }
+
+    #[test]
+    fn test_process_report_without_titles() {
+        let report = ProcessReport::new(PathBuf::from("empty"), &BTreeMap::new());
+        assert_eq!(
+            (report.years, report.titles, report.smallest, report.largest),
+            (0, 0, None, None)
+        );
+    }
+
+    #[test]
+    fn test_stream_writes_titles_in_csv_order() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_stream.csv");
+        fs::write(
+            &csv,
+            "Title,Year\nZulu,2001\nAlpha,1999\n,2000\nZulu,2001\nBravo,2001\n",
+        )
+        .unwrap();
+        let options = ProcessOptions {
+            stream: true,
+            skip_report: true,
+            ..process_options("streamtest", base.path().join("out"))
+        };
+        let report = process_file(csv.to_str().unwrap(), &options).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(year_file_path(&report.dir, "2001")).unwrap(),
+            "Zulu\nZulu\nBravo\n"
+        );
+        assert_eq!(
+            fs::read_to_string(year_file_path(&report.dir, "1999")).unwrap(),
+            "Alpha\n"
+        );
+        assert_eq!(
+            fs::read_to_string(report.dir.join(SKIP_REPORT_NAME)).unwrap(),
+            "missing title: 4\n"
+        );
+        assert_eq!((report.years, report.titles), (2, 4));
+
+        // Under --strict, the streamed output is removed again
+        let output_dir = base.path().join("strict");
+        let options = ProcessOptions {
+            stream: true,
+            strict: true,
+            ..process_options("streamtest", output_dir.clone())
+        };
+        assert!(process_file(csv.to_str().unwrap(), &options).is_err());
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_failure_mid_write_leaves_no_directory() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_broken.csv");
+        // The invalid UTF-8 row fails after the first titles were already streamed to disk
+        fs::write(
+            &csv,
+            b"Title,Year\nAlpha,2001\nBravo,2002\n\xff\xfe,2003\nCharlie,2004\n",
+        )
+        .unwrap();
+        let output_dir = base.path().join("out");
+        let options = ProcessOptions {
+            stream: true,
+            ..process_options("atomictest", output_dir.clone())
+        };
+        let mut output = Vec::new();
+        assert!(process_file_to(csv.to_str().unwrap(), &options, &mut output).is_err());
+
+        // Neither the final directory nor the hidden staging directory is left behind
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 0);
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.contains("Removed the partial output in "),
+            "{}",
+            output
+        );
+        assert!(output.contains(".tmp.atomictest.movies."), "{}", output);
+        assert!(!output.contains("Created directory"), "{}", output);
+    }
+
+    #[test]
+    fn test_json_year_files_match_the_text_files() {
+        let base = tempfile::tempdir().unwrap();
+        let text = process_file(
+            "movies_sample_1.csv",
+            &process_options("jsontest", base.path().to_path_buf()),
+        )
+        .unwrap();
+        let options = ProcessOptions {
+            output_format: OutputFormat::Json,
+            ..process_options("jsontest", base.path().to_path_buf())
+        };
+        let json = process_file("movies_sample_1.csv", &options).unwrap();
+        assert_eq!((json.years, json.titles), (text.years, text.titles));
+
+        let mut files: Vec<_> = fs::read_dir(&json.dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| !path.ends_with(MANIFEST_NAME))
+            .collect();
+        files.sort();
+        assert_eq!(files.len(), json.years);
+        for path in files {
+            assert_eq!(path.extension().unwrap(), "json");
+            let year = path.file_stem().unwrap().to_str().unwrap();
+            let parsed: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            let titles: Vec<String> = fs::read_to_string(year_file_path(&text.dir, year))
+                .unwrap()
+                .lines()
+                .map(String::from)
+                .collect();
+            assert_eq!(parsed["year"], year.parse::<u32>().unwrap());
+            assert_eq!(parsed["count"], titles.len());
+            assert_eq!(parsed["titles"], serde_json::json!(titles));
+        }
+    }
+
+    #[test]
+    fn test_manifest_matches_the_directory() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_manifest.csv");
+        fs::write(
+            &csv,
+            "Title,Year\nAlpha,2001\nBravo,2001\n,2002\nCharlie,1999\n",
+        )
+        .unwrap();
+        let options = ProcessOptions {
+            skip_report: true,
+            ..process_options("manifesttest", base.path().join("out"))
+        };
+        let report = process_file(csv.to_str().unwrap(), &options).unwrap();
+
+        let text = fs::read_to_string(report.dir.join(MANIFEST_NAME)).unwrap();
+        let manifest: Manifest = serde_json::from_str(&text).unwrap();
+        assert_eq!(Some(&manifest), report.manifest.as_ref());
+        assert_eq!(
+            manifest.source,
+            std::path::absolute(&csv).unwrap().display().to_string()
+        );
+        assert_eq!(
+            manifest.source_size,
+            Some(fs::metadata(&csv).unwrap().len())
+        );
+        assert!(manifest.source_modified.is_some());
+        assert_eq!(
+            (
+                manifest.rows_read,
+                manifest.rows_written,
+                manifest.rows_skipped
+            ),
+            (4, 3, 1)
+        );
+
+        // Every other file in the directory is listed with its actual line count
+        let mut names: Vec<_> = fs::read_dir(&report.dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["1999.txt", "2001.txt", MANIFEST_NAME, SKIP_REPORT_NAME]
+        );
+        let listed: Vec<_> = manifest
+            .files
+            .iter()
+            .map(|file| file.name.as_str())
+            .collect();
+        assert_eq!(listed, ["1999.txt", "2001.txt", SKIP_REPORT_NAME]);
+        for file in &manifest.files {
+            assert_eq!(
+                fs::read_to_string(report.dir.join(&file.name))
+                    .unwrap()
+                    .lines()
+                    .count(),
+                file.lines
+            );
+        }
+
+        // Streaming records the same files and rows
+        let options = ProcessOptions {
+            stream: true,
+            ..options
+        };
+        let streamed = process_file(csv.to_str().unwrap(), &options)
+            .unwrap()
+            .manifest
+            .unwrap();
+        assert_eq!(
+            (streamed.files, streamed.rows_written),
+            (manifest.files, manifest.rows_written)
+        );
+    }
+
+    #[test]
+    fn test_checksums_match_the_files() {
+        // The published test vector of SHA-256 for "abc"
+        let base = tempfile::tempdir().unwrap();
+        fs::write(base.path().join("abc.txt"), "abc").unwrap();
+        assert_eq!(
+            sha256_file(&base.path().join("abc.txt")).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let options = ProcessOptions {
+            checksums: true,
+            by_decade: true,
+            ..process_options("sumstest", base.path().join("out"))
+        };
+        let report = process_file("movies_sample_2.csv", &options).unwrap();
+        let manifest = report.manifest.unwrap();
+        assert!(manifest.checksums);
+        assert_eq!(manifest.files.last().unwrap().name, CHECKSUMS_NAME);
+
+        // One line per year file, each hash matching the file
+        let sums = fs::read_to_string(report.dir.join(CHECKSUMS_NAME)).unwrap();
+        assert_eq!(sums.lines().count(), manifest.files.len() - 1);
+        for (line, file) in sums.lines().zip(&manifest.files) {
+            assert_eq!(
+                line,
+                format!(
+                    "{}  {}",
+                    sha256_file(&report.dir.join(&file.name)).unwrap(),
+                    file.name
+                )
+            );
+        }
+        assert!(sums.contains("  2000s/2008.txt\n"));
+        #[cfg(unix)]
+        assert_eq!(
+            fs::metadata(report.dir.join(CHECKSUMS_NAME))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            0o640
+        );
+
+        // A second implementation agrees, where coreutils is installed
+        if let Ok(output) = process::Command::new("sha256sum")
+            .arg("-c")
+            .arg(CHECKSUMS_NAME)
+            .current_dir(&report.dir)
+            .output()
+        {
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stdout)
+            );
+        }
+    }
+
+    #[test]
+    fn test_year_file_contents() {
+        let titles = ["Alien".to_string(), "Say \"Hi\"".to_string()];
+        let columns = ["title".to_string()];
+        assert_eq!(
+            year_file_contents("1979", &titles, &columns, OutputFormat::Txt),
+            "Alien\nSay \"Hi\"\n"
+        );
+        assert_eq!(
+            year_file_contents("1979", &titles, &columns, OutputFormat::Csv),
+            "title\nAlien\n\"Say \"\"Hi\"\"\"\n"
+        );
+        let json = year_file_contents("1979", &titles, &columns, OutputFormat::Json);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+            serde_json::json!({"year": 1979, "count": 2, "titles": titles})
+        );
+        assert_eq!(
+            year_output_path(Path::new("out"), "1979", OutputFormat::Json),
+            Path::new("out").join("1979.json")
+        );
+        assert!(Options::try_parse_from([
+            "files_and_directories",
+            "--output-format",
+            "json",
+            "--stream"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_csv_year_files_read_back_as_the_fixture_titles() {
+        let base = tempfile::tempdir().unwrap();
+        let csv_path = base.path().join("movies_quoted.csv");
+        fs::write(&csv_path, "Title,Year,Rating\n\"Crouching Tiger, Hidden Dragon\",2000,7.9\n\"The \"\"Burbs\"\"\",1989,6.9\nMemento,2000,8.4\n").unwrap();
+        let options = ProcessOptions {
+            output_format: OutputFormat::Csv,
+            read: read_with(&["title", "rating"]),
+            ..process_options("csvtest", base.path().join("out"))
+        };
+        let report = process_file(csv_path.to_str().unwrap(), &options).unwrap();
+        assert_eq!((report.years, report.titles), (2, 3));
+        #[cfg(unix)]
+        assert_eq!(
+            fs::metadata(report.dir.join("2000.csv"))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            DEFAULT_FILE_MODE
+        );
+
+        // Reading each year file back with the csv crate gives the titles of the fixture
+        let read_back = |year: &str| {
+            let mut reader =
+                csv::Reader::from_path(report.dir.join(format!("{}.csv", year))).unwrap();
+            assert_eq!(reader.headers().unwrap(), vec!["title", "rating"]);
+            reader
+                .records()
+                .map(|record| record.unwrap().iter().map(String::from).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            read_back("2000"),
+            [
+                ["Crouching Tiger, Hidden Dragon", "7.9"],
+                ["Memento", "8.4"]
+            ]
+        );
+        assert_eq!(read_back("1989"), [["The \"Burbs\"", "6.9"]]);
+    }
+
+    #[test]
+    fn test_columns_add_the_rating() {
+        let base = tempfile::tempdir().unwrap();
+        let options = ProcessOptions {
+            read: read_with(&["title", "rating"]),
+            ..process_options("columnstest", base.path().to_path_buf())
+        };
+        let report = process_file("movies_sample_1.csv", &options).unwrap();
+        assert_eq!(
+            fs::read_to_string(year_file_path(&report.dir, "2008")).unwrap(),
+            "Iron Man\t7.9\nThe Incredible Hulk\t6.8\n"
+        );
+
+        // The order of --columns is the order of the values
+        let parsed = read_movies_by_year(
+            "movies_sample_1.csv",
+            &read_with(&["Rating Value", "TITLE"]),
+        )
+        .unwrap();
+        assert_eq!(
+            parsed.by_year["2008"],
+            ["6.8\tThe Incredible Hulk", "7.9\tIron Man"]
+        );
+
+        // A file without the column fails before any row is read, listing what it has
+        let csv = base.path().join("movies_no_rating.csv");
+        fs::write(&csv, "Title,Year\nAlpha,2001\n").unwrap();
+        let error = read_movies_by_year(
+            csv.to_str().unwrap(),
+            &read_with(&["title", "rating", "genre"]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("Cannot process {}: no column named rating, genre; the available columns are Title, Year", csv.display())
+        );
+        assert!(read_movies_by_year(csv.to_str().unwrap(), &ReadOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_columns_replace_tabs_and_line_breaks() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_messy.csv");
+        fs::write(&csv, "Title,Year,Rating\n\"Tab\there\",2001,\"7\n.5\"\n").unwrap();
+        let parsed =
+            read_movies_by_year(csv.to_str().unwrap(), &read_with(&["title", "rating"])).unwrap();
+        assert_eq!(parsed.by_year["2001"], ["Tab here\t7 .5"]);
+        assert_eq!(parsed.sanitized, 1);
+    }
+
+    #[test]
+    fn test_year_range_filters_rows() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_range.csv");
+        fs::write(&csv, "Title,Year\nMetropolis,1927\nMatrix,1999\nGladiator,2000\nCars,2006\nInception,2010\nBogus,1200\nTypo,19x7\n").unwrap();
+        let csv = csv.to_str().unwrap();
+        let ranged = |from_year, to_year| ReadOptions {
+            from_year,
+            to_year,
+            ..ReadOptions::default()
+        };
+
+        // Lower bound only
+        let parsed = read_movies_by_year(csv, &ranged(Some(2000), None)).unwrap();
+        assert_eq!(
+            parsed.by_year.keys().collect::<Vec<_>>(),
+            ["2000", "2006", "2010"]
+        );
+        assert_eq!((parsed.filtered, parsed.skipped_count()), (2, 2));
+        assert_eq!(
+            parsed.summary()[0],
+            "Read 7 rows: 3 written, 2 filtered, 2 skipped"
+        );
+
+        // Upper bound only
+        let parsed = read_movies_by_year(csv, &ranged(None, Some(1999))).unwrap();
+        assert_eq!(parsed.by_year.keys().collect::<Vec<_>>(), ["1927", "1999"]);
+        assert_eq!((parsed.filtered, parsed.written_count()), (3, 2));
+
+        // Both bounds; a bogus year inside them is still invalid, not written
+        let parsed = read_movies_by_year(csv, &ranged(Some(1000), Some(2005))).unwrap();
+        assert_eq!(
+            parsed.by_year.keys().collect::<Vec<_>>(),
+            ["1927", "1999", "2000"]
+        );
+        assert_eq!(parsed.skipped[&SkipReason::InvalidYear], [7, 8]);
+        assert_eq!(parsed.filtered, 2);
+
+        // Filtered rows do not fail --strict, and the manifest counts them apart
+        let options = ProcessOptions {
+            read: ranged(Some(2000), Some(2006)),
+            ..process_options("rangetest", base.path().join("out"))
+        };
+        let csv = base.path().join("movies_clean.csv");
+        fs::write(&csv, "Title,Year\nMatrix,1999\nGladiator,2000\nCars,2006\n").unwrap();
+        for stream in [false, true] {
+            let report = process_file(
+                csv.to_str().unwrap(),
+                &ProcessOptions {
+                    stream,
+                    strict: true,
+                    ..options.clone()
+                },
+            )
+            .unwrap();
+            let manifest = report.manifest.unwrap();
+            assert_eq!(
+                (
+                    manifest.rows_written,
+                    manifest.rows_filtered,
+                    manifest.rows_skipped
+                ),
+                (2, 1, 0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_excluded_years_win_over_the_year_range() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_excluded.csv");
+        fs::write(&csv, "Title,Year\nGravity,2013\nHer,2013\nInterstellar,2014\nSpotlight,2015\nArrival,2016\nHeat,1995\n").unwrap();
+        let read = ReadOptions {
+            from_year: Some(2010),
+            to_year: Some(2016),
+            excluded_years: vec![2013, 2015, 1995],
+            ..ReadOptions::default()
+        };
+        let parsed = read_movies_by_year(csv.to_str().unwrap(), &read).unwrap();
+        assert_eq!(parsed.by_year.keys().collect::<Vec<_>>(), ["2014", "2016"]);
+        // 1995 is outside the range too, but its exclusion wins
+        assert_eq!((parsed.excluded, parsed.filtered), (4, 0));
+        assert_eq!(
+            parsed.summary()[0],
+            "Read 6 rows: 2 written, 4 excluded, 0 skipped"
+        );
+
+        let report = process_file(
+            csv.to_str().unwrap(),
+            &ProcessOptions {
+                read,
+                ..process_options("excludetest", base.path().join("out"))
+            },
+        )
+        .unwrap();
+        let manifest = report.manifest.unwrap();
+        assert_eq!(
+            (
+                manifest.rows_written,
+                manifest.rows_excluded,
+                manifest.rows_filtered
+            ),
+            (2, 4, 0)
+        );
+    }
+
+    #[test]
+    fn test_parse_excluded_year() {
+        assert_eq!(parse_excluded_year("2013"), Ok(2013));
+        assert_eq!(parse_excluded_year(" 2015"), Ok(2015));
+        assert_eq!(
+            parse_excluded_year("20x3"),
+            Err("'20x3' is not a year; list whole numbers such as 2013,2015".to_string())
+        );
+        assert!(parse_excluded_year("").is_err());
+        assert!(
+            Options::try_parse_from(["files_and_directories", "--exclude-years", "2013,2015"])
+                .is_ok()
+        );
+        assert!(Options::try_parse_from([
+            "files_and_directories",
+            "--exclude-years",
+            "2013,latest"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_title_and_year_columns_can_be_given() {
+        // The fixture has no header row, and its titles are in column 3 and years in column 1
+        let no_header = ReadOptions {
+            headers: false,
+            ..ReadOptions::default()
+        };
+        let parsed = read_movies_by_year("movies_sample_columns.csv", &no_header).unwrap();
+        assert_eq!(parsed.by_year["2008"], ["tt0468569"]);
+
+        let given = ReadOptions {
+            title_col: Some(3),
+            year_col: Some(1),
+            ..no_header.clone()
+        };
+        let parsed = read_movies_by_year("movies_sample_columns.csv", &given).unwrap();
+        assert_eq!(parsed.skipped_count(), 0);
+        assert_eq!(
+            parsed.by_year["1994"],
+            ["The Shawshank Redemption", "Pulp Fiction"]
+        );
+        assert_eq!(parsed.by_year["2008"], ["The Dark Knight"]);
+
+        let base = tempfile::tempdir().unwrap();
+        let report = process_file(
+            "movies_sample_columns.csv",
+            &ProcessOptions {
+                read: given,
+                ..process_options("columntest", base.path().join("out"))
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(year_file_path(&report.dir, "1994")).unwrap(),
+            "Pulp Fiction\nThe Shawshank Redemption\n"
+        );
+
+        // A given column wins over the headers, and the other one is still found by name
+        let csv = base.path().join("movies_named.csv");
+        fs::write(
+            &csv,
+            "Year,Title,Original Title\n1997,Princess Mononoke,Mononoke-hime\n",
+        )
+        .unwrap();
+        let parsed = read_movies_by_year(
+            csv.to_str().unwrap(),
+            &ReadOptions {
+                title_col: Some(2),
+                ..ReadOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(parsed.by_year["1997"], ["Mononoke-hime"]);
+        let error = read_movies_by_year(
+            csv.to_str().unwrap(),
+            &ReadOptions {
+                title_col: Some(0),
+                ..ReadOptions::default()
+            },
+        )
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .ends_with("the title and the year would both be read from column 0"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_short_rows_are_skipped_when_columns_are_given() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_ragged.csv");
+        fs::write(
+            &csv,
+            "tt1,1999,Drama,Magnolia\ntt2,2001\ntt3\ntt4,2001,Drama,Amelie,extra\n",
+        )
+        .unwrap();
+        let read = ReadOptions {
+            headers: false,
+            title_col: Some(3),
+            year_col: Some(1),
+            ..ReadOptions::default()
+        };
+        let parsed = read_movies_by_year(csv.to_str().unwrap(), &read).unwrap();
+        assert_eq!(
+            parsed.by_year.values().flatten().collect::<Vec<_>>(),
+            ["Magnolia", "Amelie"]
+        );
+        assert_eq!(parsed.skipped[&SkipReason::ColumnOutOfRange], [2, 3]);
+        assert_eq!(parsed.skip_report(), "column out of range: 2, 3\n");
+    }
+
+    #[test]
+    fn test_process_log_records_the_run() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_messy.csv");
+        fs::write(
+            &csv,
+            "Title,Year\nAlpha,2001\n,1999\nBeta,\nGamma,19x7\nDelta,1999\n",
+        )
+        .unwrap();
+        let csv = csv.to_str().unwrap();
+        let options = ProcessOptions {
+            log: true,
+            ..process_options("logtest", base.path().join("out"))
+        };
+
+        for stream in [false, true] {
+            let report = process_file(
+                csv,
+                &ProcessOptions {
+                    stream,
+                    ..options.clone()
+                },
+            )
+            .unwrap();
+            let log_path = report.dir.join(LOG_NAME);
+            let log = fs::read_to_string(&log_path).unwrap();
+            let lines: Vec<&str> = log.lines().collect();
+            assert!(lines[0].starts_with("Started at "), "{}", log);
+            assert_eq!(lines[1], format!("Source: {}", source_path(csv)));
+            assert_eq!(
+                lines[2..5],
+                [
+                    "Skipped line 3: missing title",
+                    "Skipped line 4: missing year",
+                    "Skipped line 5: invalid year"
+                ]
+            );
+            assert_eq!(lines[5], "Read 5 rows: 2 written, 3 skipped");
+            assert!(
+                lines.contains(&"Wrote 1999.txt with 1 line")
+                    && lines.contains(&"Wrote 2001.txt with 1 line"),
+                "{}",
+                log
+            );
+            assert!(lines.last().unwrap().starts_with("Finished at "), "{}", log);
+            #[cfg(unix)]
+            assert_eq!(
+                fs::metadata(&log_path).unwrap().permissions().mode() & 0o777,
+                0o640
+            );
+            // The log is not part of the manifest, which was written before the log finished
+            assert!(report
+                .manifest
+                .unwrap()
+                .files
+                .iter()
+                .all(|file| file.name != LOG_NAME));
+        }
+
+        // --no-log leaves it out
+        let report = process_file(
+            csv,
+            &ProcessOptions {
+                log: false,
+                ..options
+            },
+        )
+        .unwrap();
+        assert!(!report.dir.join(LOG_NAME).exists());
+        assert!(
+            Options::try_parse_from(["files_and_directories", "--no-log"])
+                .unwrap()
+                .no_log
+        );
+    }
+
+    #[test]
+    fn test_failed_run_keeps_its_log() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_broken.csv");
+        // The skipped row is read before the invalid UTF-8 stops the run
+        fs::write(
+            &csv,
+            b"Title,Year\nAlpha,2001\n,1999\nBravo,2002\n\xff\xfe,2003\n",
+        )
+        .unwrap();
+
+        for stream in [false, true] {
+            let output_dir = base.path().join(format!("out_{}", stream));
+            let options = ProcessOptions {
+                stream,
+                log: true,
+                ..process_options("failtest", output_dir.clone())
+            };
+            let mut output = Vec::new();
+            let error = process_file_to(csv.to_str().unwrap(), &options, &mut output).unwrap_err();
+            assert_eq!(Failure::of(&*error), Failure::Parse);
+
+            // The staging directory is gone, but its log was moved out first
+            let kept: Vec<PathBuf> = fs::read_dir(&output_dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            assert_eq!(kept.len(), 1);
+            let name = kept[0].file_name().unwrap().to_str().unwrap();
+            assert!(
+                name.starts_with("failtest.movies.") && name.ends_with(".process.log"),
+                "{}",
+                name
+            );
+            let log = fs::read_to_string(&kept[0]).unwrap();
+            let lines: Vec<&str> = log.lines().collect();
+            assert!(lines[0].starts_with("Started at "), "{}", log);
+            assert_eq!(lines[2], "Skipped line 3: missing title");
+            assert_eq!(lines.last().unwrap(), &format!("Failed: {}", error));
+            let output = String::from_utf8(output).unwrap();
+            assert!(
+                output.contains(&format!(
+                    "Kept the log of the failed run in {}",
+                    kept[0].display()
+                )),
+                "{}",
+                output
+            );
+        }
+
+        // Without a log, nothing is left
+        let output_dir = base.path().join("out_no_log");
+        assert!(process_file(
+            csv.to_str().unwrap(),
+            &process_options("failtest", output_dir.clone())
+        )
+        .is_err());
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_multiline_titles_are_written_on_one_line() {
+        let parsed =
+            read_movies_by_year("movies_sample_multiline.csv", &ReadOptions::default()).unwrap();
+        assert_eq!(parsed.sanitized, 2);
+        assert_eq!(
+            parsed.summary()[1],
+            "Replaced line breaks or control characters with spaces in 2 titles"
+        );
+
+        let base = tempfile::tempdir().unwrap();
+        let report = process_file(
+            "movies_sample_multiline.csv",
+            &process_options("multilinetest", base.path().to_path_buf()),
+        )
+        .unwrap();
+        let titles = fs::read_to_string(year_file_path(&report.dir, "2003")).unwrap();
+        assert_eq!(
+            titles,
+            "Finding Nemo\nThe Lord of the Rings: The Return of the King\n"
+        );
+        assert_eq!(
+            fs::read_to_string(year_file_path(&report.dir, "2009")).unwrap(),
+            "Up\n"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_text() {
+        assert!(matches!(sanitize_text("Heat"), Cow::Borrowed("Heat")));
+        assert_eq!(sanitize_text("Two\r\nLines"), "Two Lines");
+        assert_eq!(
+            sanitize_text("Bell\u{7}\tand\u{1b}escape"),
+            "Bell and escape"
+        );
+    }
+
+    /// Read options that write the columns `names`.
+    fn read_with(names: &[&str]) -> ReadOptions {
+        ReadOptions {
+            columns: names.iter().map(|name| name.to_string()).collect(),
+            ..ReadOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_expand_dir_template() {
+        let values = TemplateValues {
+            onid: "templatetest",
+            rand: "42",
+            source: "movies_2",
+            date: "20240309",
+        };
+        let cases: [(&str, Result<&str, &str>); 12] = [
+            (DEFAULT_DIR_TEMPLATE, Ok("templatetest.movies.42")),
+            ("{source}-{date}", Ok("movies_2-20240309")),
+            ("{onid}_{source}_{rand}_{date}", Ok("templatetest_movies_2_42_20240309")),
+            ("fixed", Ok("fixed")),
+            ("{rand}{rand}", Ok("4242")),
+            ("", Err("'' expands to '', which cannot name a directory")),
+            ("..", Err("'..' expands to '..', which cannot name a directory")),
+            ("{onid}/{rand}", Err("'{onid}/{rand}' expands to 'templatetest/42', which contains a path separator")),
+            ("a\\b", Err("'a\\b' expands to 'a\\b', which contains a path separator")),
+            ("{name}", Err("'{name}' has an unknown placeholder {name}; use {onid}, {rand}, {source}, or {date}")),
+            ("{onid", Err("'{onid' has a '{' without a matching '}'")),
+            ("onid}", Err("'onid}' has a '}' without a matching '{'")),
+        ];
+        for (template, expected) in cases {
+            assert_eq!(
+                expand_dir_template(template, &values).as_deref(),
+                expected.map_err(str::to_string).as_deref(),
+                "{}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn test_source_stem() {
+        assert_eq!(source_stem("movies_2.csv"), "movies_2");
+        assert_eq!(source_stem("data/movies_2.csv.gz"), "movies_2");
+        assert_eq!(source_stem(STDIN_NAME), "stdin");
+    }
+
+    #[test]
+    fn test_template_without_rand_gets_a_suffix() {
+        let base = tempfile::tempdir().unwrap();
+        let options = ProcessOptions {
+            dir_template: "{source}-fixed".to_string(),
+            ..process_options("templatetest", base.path().to_path_buf())
+        };
+        let dirs: Vec<_> = (0..3)
+            .map(|_| {
+                process_file_to("movies_sample_1.csv", &options, &mut io::sink())
+                    .unwrap()
+                    .dir
+            })
+            .collect();
+        assert_eq!(
+            dirs,
+            [
+                "movies_sample_1-fixed",
+                "movies_sample_1-fixed.1",
+                "movies_sample_1-fixed.2"
+            ]
+            .map(|name| base.path().join(name))
+        );
+    }
+
+    #[test]
+    fn test_decade_name_boundaries() {
+        assert_eq!(decade_name("1899"), "1890s");
+        assert_eq!(decade_name("1900"), "1900s");
+        assert_eq!(decade_name("1909"), "1900s");
+        assert_eq!(decade_name("1999"), "1990s");
+        assert_eq!(decade_name("2000"), "2000s");
+        assert_eq!(decade_name("2100"), "2100s");
+        assert_eq!(decade_name("1800"), "1800s");
+    }
+
+    #[test]
+    fn test_by_decade_nests_year_files() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_decades.csv");
+        fs::write(
+            &csv,
+            "Title,Year\nAlpha,1899\nBravo,1900\nCharlie,1999\nDelta,2000\nEcho,2000\n",
+        )
+        .unwrap();
+        for stream in [false, true] {
+            let options = ProcessOptions {
+                by_decade: true,
+                stream,
+                ..process_options("decadetest", base.path().join("out"))
+            };
+            let report = process_file(csv.to_str().unwrap(), &options).unwrap();
+
+            let manifest = report.manifest.unwrap();
+            let names: Vec<_> = manifest
+                .files
+                .iter()
+                .map(|file| file.name.as_str())
+                .collect();
+            let nested = |decade: &str, year: &str| {
+                Path::new(decade)
+                    .join(format!("{}.txt", year))
+                    .to_string_lossy()
+                    .into_owned()
+            };
+            assert_eq!(
+                names,
+                [
+                    nested("1890s", "1899"),
+                    nested("1900s", "1900"),
+                    nested("1990s", "1999"),
+                    nested("2000s", "2000")
+                ]
+            );
+            assert_eq!(
+                fs::read_to_string(report.dir.join("2000s").join("2000.txt")).unwrap(),
+                "Delta\nEcho\n"
+            );
+            assert_eq!(
+                fs::read_to_string(report.dir.join("1900s").join("1900.txt")).unwrap(),
+                "Bravo\n"
+            );
+            assert!(!year_file_path(&report.dir, "1900").exists());
+            #[cfg(unix)]
+            assert_eq!(
+                fs::metadata(report.dir.join("1990s"))
+                    .unwrap()
+                    .permissions()
+                    .mode()
+                    & 0o777,
+                DEFAULT_DIR_MODE
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_output_dir_name() {
+        assert!(is_output_dir_name("cleantest.movies.0", "cleantest"));
+        assert!(is_output_dir_name("cleantest.movies.12345", "cleantest"));
+        assert!(!is_output_dir_name("cleantest.movies.", "cleantest"));
+        assert!(!is_output_dir_name("cleantest.movies.12a", "cleantest"));
+        assert!(!is_output_dir_name("other.movies.12", "cleantest"));
+        assert!(!is_output_dir_name("xcleantest.movies.12", "cleantest"));
+        assert!(!is_output_dir_name("cleantest.movies.12.bak", "cleantest"));
+        assert!(!is_output_dir_name(".tmp.cleantest.movies.12", "cleantest"));
+
+        // The names of --name-scheme timestamp, with the suffix of a taken name
+        assert!(is_output_dir_name(
+            "cleantest.movies.20240309-140501",
+            "cleantest"
+        ));
+        assert!(is_output_dir_name(
+            "cleantest.movies.20240309-140501-2",
+            "cleantest"
+        ));
+        assert!(is_output_dir_name(
+            "cleantest.movies.20240309-140501-12",
+            "cleantest"
+        ));
+        assert!(!is_output_dir_name(
+            "cleantest.movies.20240309-140501-",
+            "cleantest"
+        ));
+        assert!(!is_output_dir_name(
+            "cleantest.movies.20240309-140501-x",
+            "cleantest"
+        ));
+        assert!(!is_output_dir_name(
+            "cleantest.movies.20240309-140501-1-2",
+            "cleantest"
+        ));
+        assert!(!is_output_dir_name(
+            "cleantest.movies.2024039-140501",
+            "cleantest"
+        ));
+        assert!(!is_output_dir_name(
+            "cleantest.movies.20240309-1405",
+            "cleantest"
+        ));
+        assert!(!is_output_dir_name(
+            "cleantest.movies.20240309-",
+            "cleantest"
+        ));
+        assert!(!is_output_dir_name("cleantest.movies.-140501", "cleantest"));
+    }
+
+    #[test]
+    fn test_clean_removes_only_matching_directories() {
+        let base = tempfile::tempdir().unwrap();
+        let removed = [
+            "cleantest.movies.1",
+            "cleantest.movies.20240309-140501",
+            "cleantest.movies.20240309-140501-1",
+            "cleantest.movies.22",
+        ];
+        for name in removed {
+            fs::create_dir(base.path().join(name)).unwrap();
+            fs::write(base.path().join(name).join("2001.txt"), "Alpha\n").unwrap();
+        }
+        fs::create_dir_all(base.path().join("cleantest.movies.22").join("nested")).unwrap();
+        fs::write(
+            base.path()
+                .join("cleantest.movies.22")
+                .join("nested")
+                .join("x"),
+            "abcd",
+        )
+        .unwrap();
+        let kept = [
+            "other.movies.3",
+            "my.cleantest.movies.4",
+            "cleantest.movies.5.old",
+            "cleantest.movies.abc",
+            "cleantest.movies.20240309-1405",
+            "cleantest.movies.20240309-140501-x",
+        ];
+        for name in kept {
+            fs::create_dir(base.path().join(name)).unwrap();
+        }
+        fs::write(
+            base.path().join("cleantest.movies.6"),
+            "a file, not a directory",
+        )
+        .unwrap();
+
+        let found = find_output_dirs(base.path(), "cleantest").unwrap();
+        let names: Vec<_> = found
+            .iter()
+            .map(|dir| dir.path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, removed);
+        assert_eq!(found[3].bytes, 10);
+
+        // Declining the confirmation removes nothing
+        let mut output = Vec::new();
+        let report = clean_output_dirs(
+            base.path(),
+            "cleantest",
+            false,
+            |count| count == 5,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(report, CleanReport::default());
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .ends_with("Nothing was removed.\n"));
+        assert_eq!(find_output_dirs(base.path(), "cleantest").unwrap().len(), 4);
+
+        let mut output = Vec::new();
+        let report = clean_output_dirs(
+            base.path(),
+            "cleantest",
+            false,
+            |count| count == 4,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(report, CleanReport { dirs: 4, bytes: 28 });
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .ends_with("Removed 4 directories holding 28 bytes (28 B)\n"));
+        for name in removed {
+            assert!(!base.path().join(name).exists());
+        }
+        for name in kept {
+            assert!(base.path().join(name).is_dir());
+        }
+        assert!(base.path().join("cleantest.movies.6").is_file());
+    }
+
+    #[test]
+    fn test_clean_with_yes_skips_the_confirmation() {
+        let base = tempfile::tempdir().unwrap();
+        fs::create_dir(base.path().join("cleantest.movies.7")).unwrap();
+        let report = clean_output_dirs(
+            base.path(),
+            "cleantest",
+            true,
+            |_| panic!("asked for confirmation"),
+            &mut io::sink(),
+        )
+        .unwrap();
+        assert_eq!(report.dirs, 1);
+        assert!(!base.path().join("cleantest.movies.7").exists());
+
+        // With nothing to remove, nobody is asked either
+        let report = clean_output_dirs(
+            base.path(),
+            "cleantest",
+            false,
+            |_| panic!("asked for confirmation"),
+            &mut io::sink(),
+        )
+        .unwrap();
+        assert_eq!(report, CleanReport::default());
+    }
+
+    #[test]
+    fn test_staging_dir_is_renamed_when_complete() {
+        let base = tempfile::tempdir().unwrap();
+        let staging = StagingDir::create(
+            &process_options("renametest", base.path().to_path_buf()),
+            "movies_sample_1.csv",
+            &mut NameSource::new(&mut rand::thread_rng()),
+        )
+        .unwrap();
+        let hidden = staging.path().to_path_buf();
+        assert!(hidden
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with(".tmp.renametest.movies."));
+        fs::write(hidden.join("2001.txt"), "Alpha\n").unwrap();
+
+        let (dir_name, ()) = staging.finish(Ok(()), &mut io::sink()).unwrap();
+        assert_eq!(staging_name(&dir_name), hidden);
+        assert!(!hidden.exists());
+        assert_eq!(
+            fs::read_to_string(year_file_path(&dir_name, "2001")).unwrap(),
+            "Alpha\n"
+        );
+    }
+
+    #[test]
+    fn test_year_writers_reopen_closed_files_for_appending() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writers = YearWriters::new(dir.path(), DEFAULT_FILE_MODE, 2);
+        for (year, title) in [
+            ("2001", "a"),
+            ("2002", "b"),
+            ("2003", "c"),
+            ("2001", "d"),
+            ("2002", "e"),
+            ("2001", "f"),
+        ] {
+            writers.write(year, title).unwrap();
+            assert!(writers.open.len() <= 2);
+        }
+        assert_eq!(writers.peak_open, 2);
+        let counts = writers.finish().unwrap();
+        assert_eq!(
+            counts.into_iter().collect::<Vec<_>>(),
+            [
+                ("2001".to_string(), 3),
+                ("2002".to_string(), 2),
+                ("2003".to_string(), 1)
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(year_file_path(dir.path(), "2001")).unwrap(),
+            "a\nd\nf\n"
+        );
+        assert_eq!(
+            fs::read_to_string(year_file_path(dir.path(), "2002")).unwrap(),
+            "b\ne\n"
+        );
+    }
+
+    #[test]
+    fn test_stream_large_file_keeps_no_titles_in_memory() {
+        const ROWS: usize = 500_000;
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_large.csv");
+        let mut rows = io::BufWriter::new(File::create(&csv).unwrap());
+        writeln!(rows, "Title,Year").unwrap();
+        for i in 0..ROWS {
+            writeln!(rows, "Movie {},{}", i, 1950 + i % 60).unwrap();
+        }
+        rows.into_inner().unwrap().sync_all().unwrap();
+
+        let mut writers = YearWriters::new(base.path(), DEFAULT_FILE_MODE, MAX_OPEN_YEAR_FILES);
+        let parsed = read_movies(
+            csv.to_str().unwrap(),
+            &ReadOptions::default(),
+            |row| match row {
+                Row::Title { year, title } => writers.write(&year, &title),
+                Row::Skipped { .. } => Ok(()),
+            },
+        )
+        .unwrap();
+        // The titles went straight to the files; nothing was buffered
+        assert!(parsed.by_year.is_empty());
+        assert_eq!(parsed.rows_read, ROWS);
+        assert!(writers.peak_open <= MAX_OPEN_YEAR_FILES);
+        let counts = writers.finish().unwrap();
+        assert_eq!(counts.len(), 60);
+        assert_eq!(counts.values().sum::<usize>(), ROWS);
+        let lines = fs::read_to_string(year_file_path(base.path(), "1950"))
+            .unwrap()
+            .lines()
+            .count();
+        assert_eq!(lines, ROWS / 60 + 1);
+    }
+
+    /// Writes three CSV files into `dir`; `movies_b.csv` is malformed.
+    fn batch_fixtures(dir: &Path) {
+        fs::write(dir.join("movies_a.csv"), "Title,Year\nAlpha,2001\n").unwrap();
+        fs::write(
+            dir.join("movies_b.csv"),
+            "Title,Year\nBravo,2002\nBroken,2002,extra\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("movies_c.csv"),
+            "Title,Year\nCharlie,2003\nDelta,2004\n",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_batch_continues_past_a_failed_file() {
+        let base = tempfile::tempdir().unwrap();
+        batch_fixtures(base.path());
+        let output_dir = base.path().join("out");
+        let batch = process_batch(
+            &search(base.path(), "movies_", ".csv", 0),
+            &process_options("batchtest", output_dir.clone()),
+            false,
+            1,
+        );
+
+        let succeeded: Vec<_> = batch
+            .succeeded
+            .iter()
+            .map(|(name, report)| (name.as_str(), report.titles))
+            .collect();
+        assert_eq!(succeeded, [("movies_a.csv", 1), ("movies_c.csv", 2)]);
+        assert_eq!(batch.failed.len(), 1);
+        assert_eq!(batch.failed[0].name, "movies_b.csv");
+        assert_eq!(batch.failure(), Some(Failure::Parse));
+        // One output directory per successful file
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 2);
+        assert_eq!(
+            fs::read_to_string(year_file_path(&batch.succeeded[1].1.dir, "2004")).unwrap(),
+            "Delta\n"
+        );
+    }
+
+    #[test]
+    fn test_batch_fail_fast_stops_at_the_first_failure() {
+        let base = tempfile::tempdir().unwrap();
+        batch_fixtures(base.path());
+        let batch = process_batch(
+            &search(base.path(), "movies_", ".csv", 0),
+            &process_options("batchtest", base.path().join("out")),
+            true,
+            1,
+        );
+        assert_eq!(batch.succeeded.len(), 1);
+        assert_eq!(batch.failed.len(), 1);
+        assert_eq!(batch.files(), 2); // movies_c.csv was never processed
+    }
+
+    #[test]
+    fn test_batch_with_two_jobs() {
+        let base = tempfile::tempdir().unwrap();
+        fs::write(
+            base.path().join("movies_a.csv"),
+            "Title,Year\nAlpha,2001\nAlso,2001\n",
+        )
+        .unwrap();
+        fs::write(base.path().join("movies_b.csv"), "Title,Year\nBravo,2002\n").unwrap();
+        let output_dir = base.path().join("out");
+        let batch = process_batch(
+            &search(base.path(), "movies_", ".csv", 0),
+            &process_options("jobstest", output_dir.clone()),
+            false,
+            2,
+        );
+
+        assert!(batch.failed.is_empty());
+        let names: Vec<_> = batch
+            .succeeded
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, ["movies_a.csv", "movies_b.csv"]);
+        let (a, b) = (&batch.succeeded[0].1.dir, &batch.succeeded[1].1.dir);
+        assert_ne!(a, b);
+        assert_eq!(
+            fs::read_to_string(year_file_path(a, "2001")).unwrap(),
+            "Alpha\nAlso\n"
+        );
+        assert_eq!(
+            fs::read_to_string(year_file_path(b, "2002")).unwrap(),
+            "Bravo\n"
+        );
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_staging_dir_never_reuses_a_name() {
+        let base = tempfile::tempdir().unwrap();
+        let options = process_options("uniquetest", base.path().to_path_buf());
+        // 1,600 names out of 100,000 make random collisions all but certain
+        let dirs: Vec<PathBuf> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..200)
+                            .map(|_| {
+                                StagingDir::create(
+                                    &options,
+                                    "movies_sample_1.csv",
+                                    &mut NameSource::new(&mut rand::thread_rng()),
+                                )
+                                .unwrap()
+                                .finish(Ok(()), &mut io::sink())
+                                .unwrap()
+                                .0
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        let unique: std::collections::HashSet<_> = dirs.iter().collect();
+        assert_eq!(unique.len(), 1600);
+        assert_eq!(fs::read_dir(base.path()).unwrap().count(), 1600);
+    }
+
+    #[test]
+    fn test_process_file_happy_path() {
+        use rand::SeedableRng;
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_happy.csv");
+        fs::write(&csv, "Title,Year\nHeat,1995\nAlien,1979\nCasino,1995\n").unwrap();
+        let output_dir = base.path().join("out");
+        let options = process_options("happytest", output_dir.clone());
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let expected = dir_name_for(
+            &options,
+            csv.to_str().unwrap(),
+            &mut NameSource::new(&mut rng.clone()),
+            0,
+        )
+        .unwrap();
+
+        let report = process_file_with(
+            csv.to_str().unwrap(),
+            &options,
+            &mut NameSource::new(&mut rng),
+            &mut io::sink(),
+        )
+        .unwrap();
+        assert_eq!(report.dir, expected);
+        assert!(report.dir.starts_with(&output_dir));
+        assert_eq!(
+            report.counts.into_iter().collect::<Vec<_>>(),
+            [("1979".to_string(), 1), ("1995".to_string(), 2)]
+        );
+        assert_eq!(
+            fs::read_to_string(year_file_path(&expected, "1979")).unwrap(),
+            "Alien\n"
+        );
+        assert_eq!(
+            fs::read_to_string(year_file_path(&expected, "1995")).unwrap(),
+            "Casino\nHeat\n"
+        );
+    }
+
+    #[test]
+    fn test_taken_names_are_retried() {
+        use rand::SeedableRng;
+        let base = tempfile::tempdir().unwrap();
+        let options = process_options("retrytest", base.path().to_path_buf());
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2);
+        let mut upcoming = rng.clone();
+        let mut next_name = |attempt| {
+            dir_name_for(
+                &options,
+                "movies_sample_1.csv",
+                &mut NameSource::new(&mut upcoming),
+                attempt,
+            )
+            .unwrap()
+        };
+        let taken_final = next_name(0);
+        let taken_staging = staging_name(&next_name(1));
+        let free = next_name(2);
+        fs::create_dir(&taken_final).unwrap();
+        fs::create_dir(&taken_staging).unwrap();
+
+        // The finished directory and the staging directory of another run are both left alone
+        let report = process_file_with(
+            "movies_sample_1.csv",
+            &options,
+            &mut NameSource::new(&mut rng),
+            &mut io::sink(),
+        )
+        .unwrap();
+        assert_eq!(report.dir, free);
+        assert_eq!(fs::read_dir(&taken_final).unwrap().count(), 0);
+        assert_eq!(fs::read_dir(&taken_staging).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_timestamp_names_get_a_suffix_within_the_same_second() {
+        let base = tempfile::tempdir().unwrap();
+        let options = ProcessOptions {
+            name_scheme: NameScheme::Timestamp,
+            ..process_options("stamptest", base.path().to_path_buf())
+        };
+        let mut rng = rand::thread_rng();
+        let mut names = NameSource {
+            rng: &mut rng,
+            clock: || {
+                chrono::NaiveDate::from_ymd_opt(2024, 3, 9)
+                    .unwrap()
+                    .and_hms_opt(14, 5, 1)
+                    .unwrap()
+            },
+        };
+
+        // Every run within the same second gets the next free suffix
+        let dirs: Vec<PathBuf> = (0..3)
+            .map(|_| {
+                process_file_with("movies_sample_1.csv", &options, &mut names, &mut io::sink())
+                    .unwrap()
+                    .dir
+            })
+            .collect();
+        assert_eq!(
+            dirs,
+            [
+                "stamptest.movies.20240309-140501",
+                "stamptest.movies.20240309-140501-1",
+                "stamptest.movies.20240309-140501-2"
+            ]
+            .map(|name| base.path().join(name))
+        );
+
+        // The suffix follows the attempt, so a taken suffix is skipped like a taken random number
+        fs::create_dir(base.path().join("stamptest.movies.20240309-140501-4")).unwrap();
+        assert_eq!(
+            dir_name_for(&options, "movies_sample_1.csv", &mut names, 3).unwrap(),
+            base.path().join("stamptest.movies.20240309-140501-3")
+        );
+        let report =
+            process_file_with("movies_sample_1.csv", &options, &mut names, &mut io::sink())
+                .unwrap();
+        assert_eq!(
+            report.dir,
+            base.path().join("stamptest.movies.20240309-140501-3")
+        );
+        let report =
+            process_file_with("movies_sample_1.csv", &options, &mut names, &mut io::sink())
+                .unwrap();
+        assert_eq!(
+            report.dir,
+            base.path().join("stamptest.movies.20240309-140501-5")
+        );
+    }
+
+    #[test]
+    fn test_rows_missing_a_column_write_nothing() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_short.csv");
+        fs::write(&csv, "Title,Year\nAlpha,2001\nBravo\n").unwrap();
+        let output_dir = base.path().join("out");
+        let error = process_file(
+            csv.to_str().unwrap(),
+            &process_options("shorttest", output_dir.clone()),
+        )
+        .unwrap_err();
+        assert_eq!(Failure::of(&*error), Failure::Parse);
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_empty_csv_writes_nothing() {
+        let base = tempfile::tempdir().unwrap();
+        let output_dir = base.path().join("out");
+        let cases = [
+            ("movies_zero.csv", ""),
+            ("movies_header.csv", "\u{feff}Title,Year\r\n"),
+            (
+                "movies_invalid.csv",
+                "Title,Year\nAlpha,\n,2001\nBeta,19x7\n",
+            ),
+        ];
+        for (name, contents) in cases {
+            let csv = base.path().join(name);
+            fs::write(&csv, contents).unwrap();
+            for stream in [false, true] {
+                let options = ProcessOptions {
+                    stream,
+                    ..process_options("emptytest", output_dir.clone())
+                };
+                let mut output = Vec::new();
+                let error =
+                    process_file_to(csv.to_str().unwrap(), &options, &mut output).unwrap_err();
+                assert_eq!(Failure::of(&*error), Failure::NoMovieRecords);
+                assert_eq!(
+                    error.to_string(),
+                    format!(
+                        "No valid movie records found in {}; nothing to do",
+                        csv.display()
+                    )
+                );
+                // The streamed output had a staging directory, which is removed again
+                assert!(
+                    !output_dir.exists() || fs::read_dir(&output_dir).unwrap().next().is_none(),
+                    "{}",
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_file_to_writes_messages_to_out() {
+        let base = tempfile::tempdir().unwrap();
+        let mut output = Vec::new();
+        let report = process_file_to(
+            "movies_sample_1.csv",
+            &process_options("outtest", base.path().to_path_buf()),
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(&format!(
+            "Created directory with name {}",
+            report.dir.display()
+        )));
+        assert!(output.starts_with("Read "));
+    }
+
+    #[test]
+    fn test_dry_run_writes_nothing() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_dry.csv");
+        fs::write(&csv, "Title,Year\nAlpha,2001\nBeta,1999\nGamma,2001\n").unwrap();
+        let entries = || {
+            fs::read_dir(base.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect::<Vec<_>>()
+        };
+        let before = entries();
+
+        let options = ProcessOptions {
+            dry_run: true,
+            ..process_options("drytest", base.path().join("missing"))
+        };
+        let report = process_file(csv.to_str().unwrap(), &options).unwrap();
+        let dir = report.dir.clone();
+        assert_eq!(entries(), before);
+        assert!(!dir.exists());
+
+        // The report says what would be written, not what was
+        assert!(report.dry_run);
+        assert!(
+            report
+                .one_line()
+                .starts_with("Would write 3 titles in 2 year files to "),
+            "{}",
+            report.one_line()
+        );
+        let lines = report.lines();
+        assert_eq!(lines[0], "Dry run summary:");
+        assert_eq!(lines[2], "  Titles that would be written: 3");
+        assert!(
+            lines[5].starts_with("  Directory that would be created: "),
+            "{}",
+            lines[5]
+        );
+        assert!(
+            serde_json::from_str::<serde_json::Value>(&report.to_json()).unwrap()["dry_run"]
+                .as_bool()
+                .unwrap()
+        );
+        let written = process_file(
+            csv.to_str().unwrap(),
+            &ProcessOptions {
+                dry_run: false,
+                ..options
+            },
+        )
+        .unwrap();
+        assert!(written.one_line().starts_with("Wrote 3 titles"));
+        assert_eq!(written.lines()[0], "Summary:");
+
+        let parsed = read_movies_by_year(csv.to_str().unwrap(), &ReadOptions::default()).unwrap();
+        let plan = dry_run_plan(&dir, &parsed.by_year, OutputFormat::Txt, false);
+        assert_eq!(
+            plan,
+            [
+                format!("Dry run: would create directory {}", dir.display()),
+                format!(
+                    "Would write {} with 1 title",
+                    year_file_path(&dir, "1999").display()
+                ),
+                format!(
+                    "Would write {} with 2 titles",
+                    year_file_path(&dir, "2001").display()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dry_run_reports_parse_errors() {
+        let base = tempfile::tempdir().unwrap();
+        let csv = base.path().join("movies_bad.csv");
+        fs::write(&csv, "Title,Year\nAlpha,2001\nBeta,1999,extra\n").unwrap(); // Uneven row lengths
+        let options = ProcessOptions {
+            dry_run: true,
+            ..process_options("drytest", base.path().to_path_buf())
+        };
+        assert!(process_file(csv.to_str().unwrap(), &options).is_err());
+        assert_eq!(fs::read_dir(base.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_validate_onid() {
+        let accepted = [
+            ("clinicke", "clinicke"),
+            ("abc", "abc"),
+            ("UPPER", "upper"),
+            ("  padded\t", "padded"),
+            ("My Name", "myname"),
+            ("a b c", "abc"),
+        ];
+        for (onid, normalized) in accepted {
+            assert_eq!(
+                validate_onid(onid),
+                Ok(normalized.to_string()),
+                "{:?}",
+                onid
+            );
+        }
+        assert_eq!(validate_onid(&"a".repeat(30)), Ok("a".repeat(30)));
+
+        let rejected = [
+            ("", OnidError::Empty),
+            (" \t\n ", OnidError::Empty),
+            ("../etc", OnidError::PathSeparator('/')),
+            ("back\\slash", OnidError::PathSeparator('\\')),
+            (".hidden", OnidError::LeadingDot),
+            (" .hidden", OnidError::LeadingDot),
+            ("ab", OnidError::Length(2)),
+            ("A B", OnidError::Length(2)),
+            (&"a".repeat(31), OnidError::Length(31)),
+            ("My Name!", OnidError::InvalidCharacter('!')),
+            ("dots.here", OnidError::InvalidCharacter('.')),
+            ("ünïcode", OnidError::InvalidCharacter('ü')),
+        ];
+        for (onid, error) in rejected {
+            assert_eq!(validate_onid(onid), Err(error), "{:?}", onid);
+        }
+    }
 }
@@ -0,0 +1,511 @@
+/*
+ * Command-line tests for the files_and_directories binary.
+ *
+ * Each test runs the compiled program with the non-interactive flags and writes its
+ * output directories into a temporary directory.
+ */
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::fs;
+
+/// Creates a command for the files_and_directories binary.
+fn files_and_directories() -> Command {
+    Command::cargo_bin("files_and_directories").unwrap()
+}
+
+#[test]
+fn test_file_from_stdin() {
+    let output_dir = tempfile::tempdir().unwrap();
+    files_and_directories()
+        .args(["--file", "-", "--onid", "stdintest", "--output-dir"])
+        .arg(output_dir.path())
+        .write_stdin(fs::read("movies_sample_2.csv").unwrap())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Read 9 rows: 9 written, 0 skipped",
+        ))
+        .stdout(predicate::str::contains("Wrote 9 titles in 8 year files"));
+
+    // The output directory is named as usual and holds the year files
+    let dirs: Vec<_> = fs::read_dir(output_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(dirs.len(), 1);
+    assert!(dirs[0]
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("stdintest.movies."));
+    assert_eq!(
+        fs::read_to_string(dirs[0].join("2008.txt"))
+            .unwrap()
+            .lines()
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn test_file_from_stdin_never_shows_the_menu() {
+    // Menu answers on the standard input are read as CSV data, not as choices
+    let output_dir = tempfile::tempdir().unwrap();
+    files_and_directories()
+        .args(["--file", "-", "--onid", "stdintest", "--output-dir"])
+        .arg(output_dir.path())
+        .write_stdin("1\n2\n")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Select file to process").not())
+        .stderr(predicate::str::contains(
+            "Cannot process the standard input",
+        ));
+    // Only the log of the failed run is left
+    let kept: Vec<_> = fs::read_dir(output_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(kept.len(), 1);
+    assert!(
+        kept[0].is_file() && kept[0].to_str().unwrap().ends_with(".process.log"),
+        "{}",
+        kept[0].display()
+    );
+
+    // --file cannot be combined with the other ways of choosing files
+    files_and_directories()
+        .args(["--file", "-", "--all"])
+        .assert()
+        .failure();
+    files_and_directories()
+        .args(["--file", "-", "--select", "largest"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_clean_asks_before_removing() {
+    let output_dir = tempfile::tempdir().unwrap();
+    fs::create_dir(output_dir.path().join("cleantest.movies.42")).unwrap();
+    fs::create_dir(output_dir.path().join("other.movies.42")).unwrap();
+
+    files_and_directories()
+        .args(["--clean", "--onid", "cleantest", "--output-dir"])
+        .arg(output_dir.path())
+        .write_stdin("n\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Remove 1 directory? [y/N]"))
+        .stdout(predicate::str::contains("Nothing was removed."));
+    assert!(output_dir.path().join("cleantest.movies.42").is_dir());
+
+    files_and_directories()
+        .args(["--clean", "--yes", "--onid", "cleantest", "--output-dir"])
+        .arg(output_dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Removed 1 directory holding 0 bytes",
+        ));
+    assert!(!output_dir.path().join("cleantest.movies.42").exists());
+    assert!(output_dir.path().join("other.movies.42").is_dir());
+}
+
+#[test]
+fn test_format_json_prints_only_the_report_on_stdout() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("movies_json.csv"),
+        "Title,Year\nAlpha,2001\nBeta,1999\nGamma,2001\n",
+    )
+    .unwrap();
+    let output = files_and_directories()
+        .current_dir(dir.path())
+        .args([
+            "--file",
+            "movies_json.csv",
+            "--format",
+            "json",
+            "--quiet",
+            "--onid",
+            "jsontest",
+            "--output-dir",
+            "out",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // The whole of stdout is one JSON object, and the progress messages went to stderr
+    let report: serde_json::Value =
+        serde_json::from_str(&String::from_utf8(output.stdout).unwrap()).unwrap();
+    assert_eq!(
+        (report["years"].as_u64(), report["titles"].as_u64()),
+        (Some(2), Some(3))
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Read 3 rows: 3 written, 0 skipped")
+            && stderr.contains("Created directory with name"),
+        "{}",
+        stderr
+    );
+
+    // Without --quiet, the line of each year file goes to stderr too
+    let output = files_and_directories()
+        .current_dir(dir.path())
+        .args([
+            "--file",
+            "movies_json.csv",
+            "--format",
+            "json",
+            "--onid",
+            "jsontest",
+            "--output-dir",
+            "out",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value =
+        serde_json::from_str(&String::from_utf8(output.stdout).unwrap()).unwrap();
+    assert_eq!(report["year_files"].as_array().map(Vec::len), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("1999: 1 title -> ") && stderr.contains("2001: 2 titles -> "),
+        "{}",
+        stderr
+    );
+
+    // --select prints which file it picked on stderr as well
+    let output = files_and_directories()
+        .current_dir(dir.path())
+        .args([
+            "--select",
+            "largest",
+            "--format",
+            "json",
+            "--quiet",
+            "--onid",
+            "jsontest",
+            "--output-dir",
+            "out",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(
+        serde_json::from_str::<serde_json::Value>(&String::from_utf8(output.stdout).unwrap())
+            .is_ok()
+    );
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Now processing the chosen file named"));
+
+    // So does the notice for each file left out by --max-size
+    fs::write(
+        dir.path().join("movies_json_small.csv"),
+        "Title,Year\nAlpha,2001\n",
+    )
+    .unwrap();
+    let output = files_and_directories()
+        .current_dir(dir.path())
+        .args([
+            "--select",
+            "largest",
+            "--max-size",
+            "30",
+            "--format",
+            "json",
+            "--quiet",
+            "--onid",
+            "jsontest",
+            "--output-dir",
+            "out",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value =
+        serde_json::from_str(&String::from_utf8(output.stdout).unwrap()).unwrap();
+    assert_eq!(report["titles"].as_u64(), Some(1));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Leaving out movies_json.csv"));
+}
+
+#[test]
+fn test_exit_codes() {
+    let dir = tempfile::tempdir().unwrap();
+
+    // 0: success
+    fs::write(
+        dir.path().join("movies_good.csv"),
+        "Title,Year\nAlpha,2001\n",
+    )
+    .unwrap();
+    files_and_directories()
+        .current_dir(dir.path())
+        .args([
+            "--file",
+            "movies_good.csv",
+            "--onid",
+            "exittest",
+            "--output-dir",
+            "out",
+        ])
+        .assert()
+        .code(0);
+
+    // 2: no CSV file matches the search
+    for flag in ["--all", "--select=largest"] {
+        files_and_directories()
+            .current_dir(dir.path())
+            .args([flag, "--prefix", "nothing_"])
+            .assert()
+            .code(2);
+    }
+
+    files_and_directories()
+        .current_dir(dir.path())
+        .args(["--glob", "nothing_*.csv"])
+        .assert()
+        .code(2);
+
+    // 3: the CSV file cannot be parsed, or --strict found a skipped row
+    fs::write(
+        dir.path().join("movies_bad.csv"),
+        "Title,Year\nAlpha,2001\nBroken,2001,extra\n",
+    )
+    .unwrap();
+    files_and_directories()
+        .current_dir(dir.path())
+        .args(["--file", "movies_bad.csv", "--output-dir", "out"])
+        .assert()
+        .code(3);
+    fs::write(dir.path().join("movies_skip.csv"), "Title,Year\nAlpha,\n").unwrap();
+    files_and_directories()
+        .current_dir(dir.path())
+        .args([
+            "--file",
+            "movies_skip.csv",
+            "--strict",
+            "--output-dir",
+            "out",
+        ])
+        .assert()
+        .code(3);
+    files_and_directories()
+        .current_dir(dir.path())
+        .args(["--all", "--prefix", "movies_b", "--output-dir", "out"])
+        .assert()
+        .code(3);
+
+    // 4: a file or directory cannot be read or written
+    files_and_directories()
+        .current_dir(dir.path())
+        .args(["--file", "missing.csv"])
+        .assert()
+        .code(4);
+    files_and_directories()
+        .current_dir(dir.path())
+        .args([
+            "--file",
+            "movies_good.csv",
+            "--output-dir",
+            "movies_bad.csv",
+        ])
+        .assert()
+        .code(4);
+
+    // 5: invalid arguments or ONID
+    files_and_directories()
+        .args(["--no-such-flag"])
+        .assert()
+        .code(5);
+    files_and_directories()
+        .args(["--file", "-", "--all"])
+        .assert()
+        .code(5);
+    files_and_directories()
+        .args(["--onid", "Not Valid!", "--file", "-"])
+        .assert()
+        .code(5);
+    files_and_directories()
+        .args(["--min-year", "2000", "--max-year", "1990", "--file", "-"])
+        .assert()
+        .code(5);
+    files_and_directories()
+        .args(["--from-year", "2000", "--to-year", "1990", "--file", "-"])
+        .assert()
+        .code(5);
+    files_and_directories()
+        .args(["--title-col", "2", "--year-col", "2", "--file", "-"])
+        .assert()
+        .code(5);
+    files_and_directories()
+        .args(["--dir-template", "{onid}/{rand}", "--file", "-"])
+        .assert()
+        .code(5);
+    files_and_directories()
+        .args(["--glob", "../movies_*.csv"])
+        .assert()
+        .code(5);
+    let over_limit = [
+        "--file",
+        "movies_good.csv",
+        "--max-size",
+        "10",
+        "--output-dir",
+        "out",
+    ];
+    files_and_directories()
+        .current_dir(dir.path())
+        .args(over_limit)
+        .write_stdin("n\n")
+        .assert()
+        .code(5);
+    files_and_directories()
+        .current_dir(dir.path())
+        .args(over_limit)
+        .arg("--force")
+        .assert()
+        .code(0);
+    files_and_directories().arg("--help").assert().code(0);
+
+    // 6: the CSV file has no valid movie records
+    fs::write(dir.path().join("movies_empty.csv"), "Title,Year\n").unwrap();
+    files_and_directories()
+        .current_dir(dir.path())
+        .args([
+            "--file",
+            "movies_empty.csv",
+            "--onid",
+            "emptytest",
+            "--output-dir",
+            "empty_out",
+        ])
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains(
+            "No valid movie records found in movies_empty.csv; nothing to do",
+        ));
+    // No directory is created; only the log of the failed run is kept
+    let kept: Vec<_> = fs::read_dir(dir.path().join("empty_out"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(kept.len(), 1);
+    assert!(
+        kept[0].to_str().unwrap().ends_with(".process.log"),
+        "{}",
+        kept[0].display()
+    );
+    let log = fs::read_to_string(&kept[0]).unwrap();
+    assert!(
+        log.ends_with("Failed: No valid movie records found in movies_empty.csv; nothing to do\n"),
+        "{}",
+        log
+    );
+}
+
+#[test]
+fn test_glob_processes_one_match_or_asks_among_several() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("movies_2024_a.csv"),
+        "Title,Year\nAlpha,2001\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("movies_2024_b.csv"),
+        "Title,Year\nBeta,2002\nGamma,2003\n",
+    )
+    .unwrap();
+    let glob = |pattern: &str| {
+        files_and_directories()
+            .current_dir(dir.path())
+            .args([
+                "--onid",
+                "globtest",
+                "--output-dir",
+                "out",
+                "--glob",
+                pattern,
+            ])
+            .assert()
+    };
+
+    // A single match is processed without asking
+    glob("movies_2024_a*")
+        .success()
+        .stdout(predicate::str::contains(
+            "Now processing the chosen file named movies_2024_a.csv",
+        ));
+
+    // Several matches are listed to choose from
+    files_and_directories()
+        .current_dir(dir.path())
+        .args([
+            "--onid",
+            "globtest",
+            "--output-dir",
+            "out",
+            "--glob",
+            "movies_2024_*.csv",
+        ])
+        .write_stdin("2\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("movies_2024_a.csv"))
+        .stdout(predicate::str::contains(
+            "Now processing the chosen file named movies_2024_b.csv",
+        ));
+
+    // With --all, every match is processed
+    files_and_directories()
+        .current_dir(dir.path())
+        .args([
+            "--onid",
+            "globtest",
+            "--output-dir",
+            "out",
+            "--glob",
+            "movies_2024_*.csv",
+            "--all",
+        ])
+        .assert()
+        .success();
+    assert_eq!(fs::read_dir(dir.path().join("out")).unwrap().count(), 4);
+}
+
+#[test]
+fn test_menu_exit_code_follows_the_last_operation() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("movies_bad.csv"),
+        "Title,Year\nAlpha,2001\nBroken,2001,extra\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("movies_good.csv"),
+        "Title,Year\nAlpha,2001\n",
+    )
+    .unwrap();
+    let menu = |input: &str| {
+        files_and_directories()
+            .current_dir(dir.path())
+            .args(["--output-dir", "out"])
+            .write_stdin(input.to_string())
+            .assert()
+    };
+
+    // Exiting right after a failure returns its code
+    menu("1\n3\nmovies_bad.csv\n2\n").code(3);
+    // A later success clears it
+    menu("1\n3\nmovies_bad.csv\n1\n3\nmovies_good.csv\n2\n").code(0);
+    menu("2\n").code(0);
+}